@@ -0,0 +1,36 @@
+//! Bundles every `locales/*.lang` file into the binary, so adding a
+//! translation takes nothing but a new file.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("locales");
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut locales: Vec<_> = fs::read_dir(&dir)
+        .expect("locales directory")
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lang"))
+        .collect();
+    locales.sort();
+
+    let entries: String = locales
+        .iter()
+        .map(|path| {
+            let tag = path.file_stem().unwrap().to_string_lossy();
+            format!(
+                "    ({:?}, include_str!({:?})),\n",
+                tag,
+                path.display().to_string()
+            )
+        })
+        .collect();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("locales.rs");
+    fs::write(
+        out,
+        format!("pub const LOCALES: &[(&str, &str)] = &[\n{}];\n", entries),
+    )
+    .unwrap();
+}
@@ -0,0 +1,164 @@
+//! Keyboard focus and motion helpers for dialogs and popovers, so that they
+//! can be used without a mouse.
+
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, KeyboardEvent, ScrollBehavior, ScrollIntoViewOptions};
+
+/// Elements Tab can land on.
+const FOCUSABLE: &str = "a[href], button:not([disabled]), input:not([disabled]), \
+    select:not([disabled]), textarea:not([disabled]), [tabindex]:not([tabindex=\"-1\"])";
+
+/// Where Tab should send focus among `len` elements when it would otherwise
+/// leave them: around to the other end, or to the nearest end if focus is
+/// outside. `None` when the browser's own order stays inside.
+pub fn wrap_focus(current: Option<usize>, len: usize, backwards: bool) -> Option<usize> {
+    let last = len.checked_sub(1)?;
+    match current {
+        None if backwards => Some(last),
+        None => Some(0),
+        Some(0) if backwards => Some(last),
+        Some(i) if !backwards && i >= last => Some(0),
+        Some(_) => None,
+    }
+}
+
+fn focusable(root: &Element) -> Vec<HtmlElement> {
+    let nodes = match root.query_selector_all(FOCUSABLE) {
+        Ok(nodes) => nodes,
+        Err(_) => return Vec::new(),
+    };
+    (0..nodes.length())
+        .filter_map(|i| nodes.item(i))
+        .filter_map(|node| node.dyn_into::<HtmlElement>().ok())
+        .collect()
+}
+
+/// The element that has focus, unless nothing in particular does.
+pub fn active_element() -> Option<HtmlElement> {
+    let document = web_sys::window()?.document()?;
+    let active = document.active_element()?;
+    if document.body().is_some_and(|body| *body == active) {
+        return None;
+    }
+    active.dyn_into().ok()
+}
+
+/// Keydown handler keeping Tab and Shift+Tab inside `root`.
+pub fn trap_focus(root: &Element, e: &KeyboardEvent) {
+    if e.key() != "Tab" {
+        return;
+    }
+    let elements = focusable(root);
+    let active = active_element();
+    let current = elements.iter().position(|el| active.as_ref() == Some(el));
+    if let Some(target) = wrap_focus(current, elements.len(), e.shift_key()) {
+        e.prevent_default();
+        let _ = elements[target].focus();
+    }
+}
+
+/// Which of `len` menu items an arrow, Home or End `key` moves focus to
+/// from `current`, wrapping around at the ends. `None` for other keys.
+pub fn menu_step(current: Option<usize>, len: usize, key: &str) -> Option<usize> {
+    let last = len.checked_sub(1)?;
+    match (key, current) {
+        ("ArrowDown", Some(i)) if i < last => Some(i + 1),
+        ("ArrowDown", _) | ("Home", _) => Some(0),
+        ("ArrowUp", Some(i)) if i > 0 => Some(i - 1),
+        ("ArrowUp", _) | ("End", _) => Some(last),
+        _ => None,
+    }
+}
+
+/// Keydown handler moving focus between the items of the menu `root` with
+/// the arrow keys, Home and End.
+pub fn navigate_menu(root: &Element, e: &KeyboardEvent) {
+    let items = match root.query_selector_all("[role=\"menuitem\"]") {
+        Ok(nodes) => (0..nodes.length())
+            .filter_map(|i| nodes.item(i))
+            .filter_map(|node| node.dyn_into::<HtmlElement>().ok())
+            .collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+    let active = active_element();
+    let current = items.iter().position(|el| active.as_ref() == Some(el));
+    if let Some(target) = menu_step(current, items.len(), &e.key()) {
+        e.prevent_default();
+        let _ = items[target].focus();
+    }
+}
+
+/// Moves focus to the first thing in `root` that can take it.
+pub fn focus_first(root: &Element) {
+    if let Some(first) = focusable(root).first() {
+        let _ = first.focus();
+    }
+}
+
+/// Whether the user asked the system for less animation.
+pub fn prefers_reduced_motion() -> bool {
+    web_sys::window()
+        .and_then(|w| {
+            w.match_media("(prefers-reduced-motion: reduce)")
+                .ok()
+                .flatten()
+        })
+        .is_some_and(|query| query.matches())
+}
+
+/// Scrolls `element` to the middle of its scroll container, gliding there
+/// unless reduced motion is preferred.
+pub fn scroll_into_view(element: &Element) {
+    let mut options = ScrollIntoViewOptions::new();
+    options.block(web_sys::ScrollLogicalPosition::Center);
+    if !prefers_reduced_motion() {
+        options.behavior(ScrollBehavior::Smooth);
+    }
+    element.scroll_into_view_with_scroll_into_view_options(&options);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tab_wraps_at_the_ends() {
+        assert_eq!(wrap_focus(Some(2), 3, false), Some(0));
+        assert_eq!(wrap_focus(Some(0), 3, true), Some(2));
+        assert_eq!(wrap_focus(Some(0), 3, false), None);
+        assert_eq!(wrap_focus(Some(1), 3, true), None);
+        assert_eq!(wrap_focus(Some(0), 1, false), Some(0));
+        assert_eq!(wrap_focus(Some(0), 1, true), Some(0));
+    }
+
+    #[test]
+    fn tab_from_outside_comes_back_in() {
+        assert_eq!(wrap_focus(None, 3, false), Some(0));
+        assert_eq!(wrap_focus(None, 3, true), Some(2));
+        assert_eq!(wrap_focus(None, 0, false), None);
+        assert_eq!(wrap_focus(Some(0), 0, true), None);
+    }
+
+    #[test]
+    fn arrows_move_through_the_menu() {
+        for (current, key, expected) in [
+            (Some(0), "ArrowDown", Some(1)),
+            (Some(2), "ArrowDown", Some(0)),
+            (None, "ArrowDown", Some(0)),
+            (Some(2), "ArrowUp", Some(1)),
+            (Some(0), "ArrowUp", Some(2)),
+            (None, "ArrowUp", Some(2)),
+            (Some(1), "Home", Some(0)),
+            (Some(1), "End", Some(2)),
+            (Some(1), "Tab", None),
+            (Some(1), "Enter", None),
+        ] {
+            assert_eq!(
+                menu_step(current, 3, key),
+                expected,
+                "{key} from {current:?}"
+            );
+        }
+        assert_eq!(menu_step(None, 0, "ArrowDown"), None);
+    }
+}
@@ -0,0 +1,248 @@
+//! Files sent as messages: small ones travel inline, base64-encoded, larger
+//! ones as a link once uploaded.
+
+use crate::protocol::FileData;
+
+/// Largest file sent or shown inline unless configured otherwise.
+pub const DEFAULT_INLINE_LIMIT: usize = 256 * 1024;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard, padded base64 of `bytes`.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &b)| group | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The bytes of standard base64 `text`, or `None` if it isn't valid.
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let padding = text.iter().rev().take_while(|&&b| b == b'=').count();
+    if padding > 2 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (n, chunk) in text.chunks(4).enumerate() {
+        let last = n == text.len() / 4 - 1;
+        let mut group = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = match c {
+                b'=' if last && i >= 4 - padding => 0,
+                _ => BASE64_ALPHABET.iter().position(|&a| a == c)? as u32,
+            };
+            group |= value << (18 - 6 * i);
+        }
+        let len = if last { 3 - padding } else { 3 };
+        bytes.extend((0..len).map(|i| (group >> (16 - 8 * i)) as u8));
+    }
+    Some(bytes)
+}
+
+/// `bytes` as a file sent inline, unless they are over `limit`.
+pub fn encode_file(name: &str, mime: &str, bytes: &[u8], limit: usize) -> Option<FileData> {
+    (bytes.len() <= limit).then(|| FileData {
+        name: name.to_string(),
+        mime: mime.to_string(),
+        size: bytes.len() as u64,
+        body: Some(encode_base64(bytes)),
+        url: None,
+    })
+}
+
+/// The contents of a file sent inline, if they are there, decode, are no
+/// bigger than `limit` and are as long as announced.
+pub fn decode_file(file: &FileData, limit: usize) -> Option<Vec<u8>> {
+    let body = file.body.as_deref()?;
+    // Checked before decoding, so oversized payloads cost nothing.
+    if body.len() / 4 * 3 > limit + 2 {
+        return None;
+    }
+    let bytes = decode_base64(body)?;
+    (bytes.len() <= limit && bytes.len() as u64 == file.size).then_some(bytes)
+}
+
+/// `bytes` in the largest unit that keeps the number at least 1, with one
+/// decimal below 10 (`512 B`, `1.5 KB`, `23 MB`).
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    // Compared against what would be shown once rounded, so 1023.7 KB is
+    // "1.0 MB" rather than "1024 KB", and 9.97 KB is "10 KB".
+    while size >= 1023.5 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else if size < 9.95 {
+        format!("{:.1} {}", size, UNITS[unit])
+    } else {
+        format!("{:.0} {}", size, UNITS[unit])
+    }
+}
+
+/// Icon for a file of type `mime`.
+pub fn icon_for(mime: &str) -> &'static str {
+    let (kind, subtype) = mime.split_once('/').unwrap_or((mime, ""));
+    match (kind, subtype) {
+        ("image", _) => "🖼",
+        ("audio", _) => "🎵",
+        ("video", _) => "🎞",
+        ("text", _) => "📄",
+        (_, "pdf") => "📕",
+        (_, "zip" | "gzip" | "x-tar" | "x-7z-compressed" | "x-rar-compressed") => "🗜",
+        (_, "json" | "xml" | "javascript") => "📄",
+        _ => "📎",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_matches_the_rfc_vectors() {
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode_base64(plain.as_bytes()), encoded);
+            assert_eq!(decode_base64(encoded).as_deref(), Some(plain.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn base64_round_trips_every_byte() {
+        let bytes: Vec<u8> = (0..=255).rev().collect();
+        for len in 0..bytes.len() {
+            let encoded = encode_base64(&bytes[..len]);
+            assert_eq!(encoded.len(), len.div_ceil(3) * 4);
+            assert_eq!(decode_base64(&encoded).as_deref(), Some(&bytes[..len]));
+        }
+        assert_eq!(encode_base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn bad_base64_is_refused() {
+        for text in [
+            "Zg", "Zg=", "Zm9vY", "Zg===", "====", "Z===", "Zg==Zm8=", "Zm=v", "Zm9v!A==",
+            "Zm9v\n", "Zm 9v", "Zm9v-_==",
+        ] {
+            assert_eq!(decode_base64(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn files_round_trip_under_the_limit() {
+        let bytes = b"%PDF-1.7 not really";
+        let file = encode_file("report.pdf", "application/pdf", bytes, 64).unwrap();
+        assert_eq!(file.name, "report.pdf");
+        assert_eq!(file.mime, "application/pdf");
+        assert_eq!(file.size, bytes.len() as u64);
+        assert_eq!(file.url, None);
+        assert_eq!(decode_file(&file, 64).as_deref(), Some(&bytes[..]));
+
+        let exact = encode_file("a.log", "text/plain", &[b'x'; 64], 64).unwrap();
+        assert_eq!(decode_file(&exact, 64).map(|b| b.len()), Some(64));
+        assert!(encode_file("a.log", "text/plain", &[b'x'; 65], 64).is_none());
+        let empty = encode_file("empty", "", &[], 64).unwrap();
+        assert_eq!(decode_file(&empty, 64), Some(Vec::new()));
+    }
+
+    #[test]
+    fn suspect_files_are_not_decoded() {
+        let file = encode_file("a.log", "text/plain", &[b'x'; 100], DEFAULT_INLINE_LIMIT).unwrap();
+        assert_eq!(decode_file(&file, 99), None, "over the receiver's limit");
+
+        let lying = FileData {
+            size: 3,
+            ..file.clone()
+        };
+        assert_eq!(
+            decode_file(&lying, DEFAULT_INLINE_LIMIT),
+            None,
+            "size mismatch"
+        );
+
+        let garbled = FileData {
+            body: Some("not base64!".to_string()),
+            ..file.clone()
+        };
+        assert_eq!(decode_file(&garbled, DEFAULT_INLINE_LIMIT), None);
+
+        let linked = FileData {
+            body: None,
+            url: Some("https://files.example/a.log".to_string()),
+            ..file
+        };
+        assert_eq!(decode_file(&linked, DEFAULT_INLINE_LIMIT), None);
+    }
+
+    #[test]
+    fn sizes_are_human_readable() {
+        const KB: u64 = 1024;
+        const MB: u64 = 1024 * KB;
+        for (bytes, expected) in [
+            (0, "0 B"),
+            (1, "1 B"),
+            (512, "512 B"),
+            (1023, "1023 B"),
+            (KB, "1.0 KB"),
+            (1536, "1.5 KB"),
+            (10 * KB - 1, "10 KB"),
+            (10 * KB, "10 KB"),
+            (256 * KB, "256 KB"),
+            (MB - 1, "1.0 MB"),
+            (MB, "1.0 MB"),
+            (23 * MB, "23 MB"),
+            (5 * 1024 * MB, "5.0 GB"),
+            (2048 * 1024 * 1024 * MB, "2048 TB"),
+            (u64::MAX, "16777216 TB"),
+        ] {
+            assert_eq!(format_size(bytes), expected, "{bytes}");
+        }
+    }
+
+    #[test]
+    fn icons_follow_the_type() {
+        for (mime, icon) in [
+            ("image/png", "🖼"),
+            ("audio/ogg", "🎵"),
+            ("video/mp4", "🎞"),
+            ("text/plain", "📄"),
+            ("application/json", "📄"),
+            ("application/pdf", "📕"),
+            ("application/zip", "🗜"),
+            ("application/x-7z-compressed", "🗜"),
+            ("application/octet-stream", "📎"),
+            ("", "📎"),
+            ("nonsense", "📎"),
+        ] {
+            assert_eq!(icon_for(mime), icon, "{mime:?}");
+        }
+    }
+}
@@ -0,0 +1,645 @@
+//! Code in messages: fenced blocks and inline spans split out of the text,
+//! and a small highlighter for the languages we paste at each other most.
+//! Everything here works on plain text and hands back slices of it; turning
+//! them into elements is left to the view, so nothing is ever injected as
+//! markup.
+
+/// A part of a message, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Block<'a> {
+    Text(&'a str),
+    /// A fenced block, with the language tag after the opening fence. A
+    /// fence never closed runs to the end of the message.
+    Code {
+        lang: Option<&'a str>,
+        code: &'a str,
+    },
+}
+
+/// The opening fence `line` starts, as its length in backticks and its
+/// language tag.
+fn opening_fence(line: &str) -> Option<(usize, Option<&str>)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let ticks = trimmed.len() - trimmed.trim_start_matches('`').len();
+    let info = trimmed[ticks..].trim();
+    // A backtick after the fence makes it a one-line inline span instead.
+    if ticks < 3 || info.contains('`') {
+        return None;
+    }
+    Some((ticks, info.split_whitespace().next()))
+}
+
+/// Whether `line` closes a fence opened with `ticks` backticks.
+fn closes_fence(line: &str, ticks: usize) -> bool {
+    let trimmed = line.trim();
+    trimmed.len() >= ticks && trimmed.bytes().all(|b| b == b'`')
+}
+
+/// Splits `text` into runs of prose and fenced code blocks. The newlines
+/// around fences are left out of both.
+pub fn blocks(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut text_start = 0;
+    let mut at = 0;
+    while at < text.len() {
+        let line_end = text[at..].find('\n').map_or(text.len(), |i| at + i);
+        let (ticks, lang) = match opening_fence(&text[at..line_end]) {
+            Some(fence) => fence,
+            None => {
+                at = line_end + 1;
+                continue;
+            }
+        };
+        let before = text[text_start..at].trim_end_matches(['\n', '\r']);
+        if !before.trim().is_empty() {
+            blocks.push(Block::Text(before));
+        }
+
+        let code_start = (line_end + 1).min(text.len());
+        let mut line_start = code_start;
+        let (code_end, next) = loop {
+            if line_start >= text.len() {
+                break (text.len(), text.len());
+            }
+            let end = text[line_start..]
+                .find('\n')
+                .map_or(text.len(), |i| line_start + i);
+            if closes_fence(&text[line_start..end], ticks) {
+                break (line_start, end + 1);
+            }
+            line_start = end + 1;
+        };
+        let code = text[code_start..code_end.max(code_start)].trim_end_matches(['\n', '\r']);
+        blocks.push(Block::Code { lang, code });
+        at = next;
+        text_start = next.min(text.len());
+    }
+    if text_start < text.len() && !text[text_start..].trim().is_empty() {
+        blocks.push(Block::Text(&text[text_start..]));
+    }
+    blocks
+}
+
+/// A piece of prose, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inline<'a> {
+    Text(&'a str),
+    /// What is between a run of backticks and the next run as long.
+    Code(&'a str),
+}
+
+/// Splits `text` into plain runs and backtick code spans. Backticks left
+/// unmatched are plain text.
+pub fn inline_spans(text: &str) -> Vec<Inline<'_>> {
+    let mut spans = Vec::new();
+    let mut plain_start = 0;
+    let mut at = 0;
+    while let Some(found) = text[at..].find('`') {
+        let open = at + found;
+        let ticks = text[open..].len() - text[open..].trim_start_matches('`').len();
+        let content_start = open + ticks;
+        let mut search = content_start;
+        let close = loop {
+            match text[search..].find('`') {
+                Some(i) => {
+                    let run = search + i;
+                    let len = text[run..].len() - text[run..].trim_start_matches('`').len();
+                    if len == ticks {
+                        break Some(run);
+                    }
+                    search = run + len;
+                }
+                None => break None,
+            }
+        };
+        match close {
+            Some(close) if close > content_start => {
+                if open > plain_start {
+                    spans.push(Inline::Text(&text[plain_start..open]));
+                }
+                spans.push(Inline::Code(&text[content_start..close]));
+                at = close + ticks;
+                plain_start = at;
+            }
+            _ => at = content_start,
+        }
+    }
+    if plain_start < text.len() {
+        spans.push(Inline::Text(&text[plain_start..]));
+    }
+    spans
+}
+
+/// Byte offset of `inner`, a slice of `outer`, within it: where a piece
+/// [`blocks`] or [`inline_spans`] returned starts in the text.
+pub(crate) fn offset_in(outer: &str, inner: &str) -> usize {
+    inner.as_ptr() as usize - outer.as_ptr() as usize
+}
+
+/// The languages we highlight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    JavaScript,
+    Python,
+    Json,
+}
+
+impl Language {
+    /// The language a fence's tag names, if we know it.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        Some(match tag.to_ascii_lowercase().as_str() {
+            "rust" | "rs" => Language::Rust,
+            "javascript" | "js" | "jsx" | "mjs" | "typescript" | "ts" | "tsx" => {
+                Language::JavaScript
+            }
+            "python" | "py" | "python3" => Language::Python,
+            "json" => Language::Json,
+            _ => return None,
+        })
+    }
+
+    fn syntax(self) -> &'static Syntax {
+        match self {
+            Language::Rust => &RUST,
+            Language::JavaScript => &JAVASCRIPT,
+            Language::Python => &PYTHON,
+            Language::Json => &JSON,
+        }
+    }
+}
+
+/// What a piece of highlighted code is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Plain,
+    Keyword,
+    /// `true`, `null`, `self` and the like.
+    Literal,
+    /// Capitalized names, which are types in every language we highlight.
+    Type,
+    String,
+    Number,
+    Comment,
+}
+
+struct Syntax {
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    /// Quotes that open strings; backtick strings may span lines.
+    quotes: &'static [char],
+    /// Whether `'` opens character literals, and otherwise lifetimes.
+    char_literals: bool,
+    /// Whether `"""` and `'''` open strings that span lines.
+    triple_quotes: bool,
+}
+
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "super", "trait", "type", "unsafe", "use",
+        "where", "while",
+    ],
+    literals: &["true", "false", "self", "Self", "None", "Some", "Ok", "Err"],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+    char_literals: true,
+    triple_quotes: false,
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "of",
+        "return",
+        "switch",
+        "throw",
+        "try",
+        "type",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    literals: &[
+        "true",
+        "false",
+        "null",
+        "undefined",
+        "this",
+        "NaN",
+        "Infinity",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    char_literals: false,
+    triple_quotes: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield",
+    ],
+    literals: &["True", "False", "None", "self"],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &['"', '\''],
+    char_literals: false,
+    triple_quotes: true,
+};
+
+const JSON: Syntax = Syntax {
+    keywords: &[],
+    literals: &["true", "false", "null"],
+    line_comment: None,
+    block_comment: None,
+    quotes: &['"'],
+    char_literals: false,
+    triple_quotes: false,
+};
+
+/// Length of the string starting at `rest`, which opens with `quote`: up to
+/// the closing quote, the end of the line, or the end of the code if it is
+/// never closed. Backslashes escape the character after them.
+fn string_len(rest: &str, quote: char, multiline: bool) -> usize {
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' if !multiline => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+/// Length of the Rust raw string (`r"…"`, `r#"…"#`) starting at `rest`, if
+/// one does.
+fn raw_string_len(rest: &str) -> Option<usize> {
+    let after_prefix = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    let hashes = after_prefix.len() - after_prefix.trim_start_matches('#').len();
+    let body = after_prefix[hashes..].strip_prefix('"')?;
+    let closing = format!("\"{}", "#".repeat(hashes));
+    let opening = rest.len() - body.len();
+    Some(
+        body.find(&closing)
+            .map_or(rest.len(), |i| opening + i + closing.len()),
+    )
+}
+
+/// Length of the Rust character literal starting at `rest`, or `None` for
+/// a lifetime.
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    match chars.next()? {
+        (_, '\\') => {
+            let end = rest[1..].find('\'').filter(|&i| i > 1 && i < 12)?;
+            Some(end + 2)
+        }
+        (_, '\'') => None,
+        _ => match chars.next()? {
+            (i, '\'') => Some(i + 1),
+            _ => None,
+        },
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// The next token at the start of `rest`, as its kind and length.
+fn next_token(syntax: &Syntax, rest: &str) -> (Kind, usize) {
+    let first = match rest.chars().next() {
+        Some(c) => c,
+        None => return (Kind::Plain, 0),
+    };
+    let line_len = rest.find('\n').unwrap_or(rest.len());
+
+    if let Some(start) = syntax.line_comment {
+        if rest.starts_with(start) {
+            return (Kind::Comment, line_len);
+        }
+    }
+    if let Some((start, end)) = syntax.block_comment {
+        if let Some(body) = rest.strip_prefix(start) {
+            let len = body
+                .find(end)
+                .map_or(rest.len(), |i| start.len() + i + end.len());
+            return (Kind::Comment, len);
+        }
+    }
+    if syntax.triple_quotes {
+        for quotes in ["\"\"\"", "'''"] {
+            if rest.starts_with(quotes) {
+                let len = rest[3..].find(quotes).map_or(rest.len(), |i| 3 + i + 3);
+                return (Kind::String, len);
+            }
+        }
+    }
+    if syntax.char_literals {
+        if first == '\'' {
+            return match char_literal_len(rest) {
+                Some(len) => (Kind::String, len),
+                // A lifetime: the quote and the name after it.
+                None => (
+                    Kind::Plain,
+                    1 + rest[1..]
+                        .find(|c: char| !is_ident_char(c))
+                        .unwrap_or(rest.len() - 1),
+                ),
+            };
+        }
+        if let Some(len) = raw_string_len(rest) {
+            return (Kind::String, len);
+        }
+    }
+    if syntax.quotes.contains(&first) {
+        return (Kind::String, string_len(rest, first, first == '`'));
+    }
+    if first.is_ascii_digit() {
+        let mut len = 0;
+        let mut chars = rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let decimal_point =
+                c == '.' && chars.peek().is_some_and(|&(_, next)| next.is_ascii_digit());
+            if !(c.is_ascii_alphanumeric() || c == '_' || decimal_point) {
+                break;
+            }
+            len = i + c.len_utf8();
+        }
+        return (Kind::Number, len);
+    }
+    if is_ident_start(first) {
+        let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+        let word = &rest[..len];
+        let kind = if syntax.keywords.contains(&word) {
+            Kind::Keyword
+        } else if syntax.literals.contains(&word) {
+            Kind::Literal
+        } else if !syntax.keywords.is_empty() && first.is_uppercase() {
+            Kind::Type
+        } else {
+            Kind::Plain
+        };
+        return (kind, len);
+    }
+    (Kind::Plain, first.len_utf8())
+}
+
+/// `code` split into highlighted pieces, which put back together give
+/// `code` again. Languages we don't know come back as one plain piece.
+pub fn highlight(lang: Option<Language>, code: &str) -> Vec<(Kind, &str)> {
+    let syntax = match lang {
+        Some(lang) => lang.syntax(),
+        None => return vec![(Kind::Plain, code)],
+    };
+    let mut pieces: Vec<(Kind, &str)> = Vec::new();
+    let mut at = 0;
+    while at < code.len() {
+        let (kind, len) = next_token(syntax, &code[at..]);
+        let len = len.max(1);
+        let len = (at + len..=code.len())
+            .find(|&end| code.is_char_boundary(end))
+            .unwrap_or(code.len())
+            - at;
+        match pieces.last_mut() {
+            // Neighbouring plain pieces are merged, to keep the element
+            // count down.
+            Some((Kind::Plain, piece)) if kind == Kind::Plain => {
+                let start = at - piece.len();
+                *piece = &code[start..at + len];
+            }
+            _ => pieces.push((kind, &code[at..at + len])),
+        }
+        at += len;
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{match_ranges, split_matches};
+
+    #[test]
+    fn fences_split_prose_from_code() {
+        let text = "look:\n```rust\nfn main() {}\n```\nneat";
+        assert_eq!(
+            blocks(text),
+            [
+                Block::Text("look:"),
+                Block::Code {
+                    lang: Some("rust"),
+                    code: "fn main() {}",
+                },
+                Block::Text("neat"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_fence_runs_to_the_end() {
+        assert_eq!(
+            blocks("```py\nprint(1)\nprint(2)\n"),
+            [Block::Code {
+                lang: Some("py"),
+                code: "print(1)\nprint(2)",
+            }]
+        );
+        assert_eq!(
+            blocks("```"),
+            [Block::Code {
+                lang: None,
+                code: "",
+            }]
+        );
+    }
+
+    #[test]
+    fn longer_fences_need_as_long_a_close() {
+        let text = "````\n```\nstill code\n````";
+        assert_eq!(
+            blocks(text),
+            [Block::Code {
+                lang: None,
+                code: "```\nstill code",
+            }]
+        );
+    }
+
+    #[test]
+    fn not_fences() {
+        for text in ["```inline``` span", "    ```\nindented", "``two``"] {
+            assert_eq!(blocks(text), [Block::Text(text)], "{}", text);
+        }
+    }
+
+    #[test]
+    fn inline_spans_pair_equal_runs_of_backticks() {
+        assert_eq!(
+            inline_spans("use `Vec` or ``a`b`` but ` alone"),
+            [
+                Inline::Text("use "),
+                Inline::Code("Vec"),
+                Inline::Text(" or "),
+                Inline::Code("a`b"),
+                Inline::Text(" but ` alone"),
+            ]
+        );
+        assert_eq!(inline_spans("``"), [Inline::Text("``")]);
+    }
+
+    fn kinds(lang: Language, code: &str) -> Vec<(Kind, &str)> {
+        let pieces = highlight(Some(lang), code);
+        let joined: String = pieces.iter().map(|(_, piece)| *piece).collect();
+        assert_eq!(joined, code, "pieces must put back the code");
+        pieces
+            .into_iter()
+            .filter(|(kind, _)| *kind != Kind::Plain)
+            .collect()
+    }
+
+    #[test]
+    fn highlights_rust() {
+        let code = "fn f<'a>(s: &'a str) -> Option<char> { /* x */ Some('\\n') } // done";
+        assert_eq!(
+            kinds(Language::Rust, code),
+            [
+                (Kind::Keyword, "fn"),
+                (Kind::Type, "Option"),
+                (Kind::Comment, "/* x */"),
+                (Kind::Literal, "Some"),
+                (Kind::String, "'\\n'"),
+                (Kind::Comment, "// done"),
+            ]
+        );
+        assert_eq!(
+            kinds(Language::Rust, r##"let s = r#"a "q" b"#; 1_000.5"##),
+            [
+                (Kind::Keyword, "let"),
+                (Kind::String, r##"r#"a "q" b"#"##),
+                (Kind::Number, "1_000.5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_javascript_python_and_json() {
+        assert_eq!(
+            kinds(Language::JavaScript, "const t = `a\nb`; // c"),
+            [
+                (Kind::Keyword, "const"),
+                (Kind::String, "`a\nb`"),
+                (Kind::Comment, "// c"),
+            ]
+        );
+        assert_eq!(
+            kinds(Language::Python, "def f():\n    \"\"\"doc\n\"\"\"  # x"),
+            [
+                (Kind::Keyword, "def"),
+                (Kind::String, "\"\"\"doc\n\"\"\""),
+                (Kind::Comment, "# x"),
+            ]
+        );
+        assert_eq!(
+            kinds(Language::Json, r#"{"a": [1, true, null]}"#),
+            [
+                (Kind::String, "\"a\""),
+                (Kind::Number, "1"),
+                (Kind::Literal, "true"),
+                (Kind::Literal, "null"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_strings_and_comments_run_out() {
+        assert_eq!(
+            kinds(Language::Rust, "let s = \"open\nx"),
+            [(Kind::Keyword, "let"), (Kind::String, "\"open")]
+        );
+        assert_eq!(
+            kinds(Language::JavaScript, "a /* never"),
+            [(Kind::Comment, "/* never")]
+        );
+    }
+
+    #[test]
+    fn non_ascii_code_stays_whole() {
+        let code = "let café = \"naïve ☕\"; // ünï";
+        assert_eq!(
+            kinds(Language::Rust, code),
+            [
+                (Kind::Keyword, "let"),
+                (Kind::String, "\"naïve ☕\""),
+                (Kind::Comment, "// ünï"),
+            ]
+        );
+        assert_eq!(highlight(None, code), [(Kind::Plain, code)]);
+    }
+
+    #[test]
+    fn search_matches_line_up_inside_non_ascii_code() {
+        let text = "Ça marche ?\n```rust\nlet CAFÉ = \"café\";\n```";
+        let ranges = match_ranges(text, "café");
+        let code = match blocks(text)[1] {
+            Block::Code { code, .. } => code,
+            other => panic!("expected code, got {:?}", other),
+        };
+
+        let matched: Vec<&str> = split_matches(code, offset_in(text, code), &ranges)
+            .into_iter()
+            .filter_map(|(run, matched)| matched.then_some(run))
+            .collect();
+        assert_eq!(matched, ["CAFÉ", "café"]);
+
+        // A piece cut inside a match only marks its own part of it.
+        let piece = &code[6..];
+        let runs = split_matches(piece, offset_in(text, piece), &ranges);
+        assert_eq!(runs[0], ("FÉ", true));
+    }
+}
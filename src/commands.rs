@@ -0,0 +1,194 @@
+//! Slash commands typed into the message input.
+
+use crate::i18n::I18n;
+
+/// Text prepended to `/me` messages so that any client can render them as
+/// actions, whether or not the server knows about them.
+pub const ACTION_PREFIX: &str = "/me ";
+
+const SHRUG: &str = r"¯\_(ツ)_/¯";
+
+/// Commands listed by `/help`: name and arguments. Add an entry here, a
+/// match arm in [`parse_command`] and a `command.<name>` line describing it
+/// in the locale files to introduce a new one.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("me", "<action>"),
+    ("shrug", "[message]"),
+    ("nick", "<name>"),
+    ("clear", ""),
+    ("retention", "[count]"),
+    ("help", ""),
+];
+
+/// What the user asked for when submitting the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'a> {
+    /// Plain text, sent as is.
+    Message(&'a str),
+    Me(&'a str),
+    Shrug(&'a str),
+    Nick(&'a str),
+    Clear,
+    /// Keep this many messages per conversation, or say how many are kept.
+    Retention(Option<usize>),
+    Help,
+    /// A known command used with the wrong arguments, by name.
+    Usage(&'a str),
+    /// A `/word` we don't recognise, by name.
+    Unknown(&'a str),
+}
+
+impl Command<'_> {
+    /// The message text this command sends, or `None` if it is handled
+    /// locally.
+    pub fn message_text(&self) -> Option<String> {
+        match *self {
+            Command::Message(text) => Some(text.to_string()),
+            Command::Me(action) => Some(format!("{}{}", ACTION_PREFIX, action)),
+            Command::Shrug("") => Some(SHRUG.to_string()),
+            Command::Shrug(text) => Some(format!("{} {}", text, SHRUG)),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_command(input: &str) -> Command<'_> {
+    let input = input.trim();
+    let rest = match input.strip_prefix('/') {
+        Some(rest) => rest,
+        None => return Command::Message(input),
+    };
+    let (name, args) = match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (rest, ""),
+    };
+    match name {
+        "me" if args.is_empty() => Command::Usage(name),
+        "me" => Command::Me(args),
+        "shrug" => Command::Shrug(args),
+        "nick" if args.is_empty() || args.contains(char::is_whitespace) => Command::Usage(name),
+        "nick" => Command::Nick(args),
+        "clear" => Command::Clear,
+        "retention" if args.is_empty() => Command::Retention(None),
+        "retention" => match args.parse() {
+            Ok(count) if count > 0 => Command::Retention(Some(count)),
+            _ => Command::Usage(name),
+        },
+        "help" => Command::Help,
+        _ => Command::Unknown(name),
+    }
+}
+
+/// The action of a `/me` message, without the prefix.
+pub fn action_text(message: &str) -> Option<&str> {
+    message.strip_prefix(ACTION_PREFIX)
+}
+
+/// How to call the command `name`, e.g. "/me <action>".
+pub fn usage(name: &str) -> String {
+    match COMMANDS.iter().find(|(command, _)| *command == name) {
+        Some((_, "")) => format!("/{}", name),
+        Some((_, args)) => format!("/{} {}", name, args),
+        None => format!("/{}", name),
+    }
+}
+
+/// One line per command, for `/help`.
+pub fn help_text(i18n: &I18n) -> String {
+    COMMANDS
+        .iter()
+        .map(|(name, _)| {
+            let description = i18n.t(&format!("command.{}", name));
+            format!("{} — {}", usage(name), description)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let cases = [
+            ("hello", Command::Message("hello")),
+            ("  hello there  ", Command::Message("hello there")),
+            ("not /a command", Command::Message("not /a command")),
+            ("", Command::Message("")),
+            ("/me waves", Command::Me("waves")),
+            ("/me   waves at  you  ", Command::Me("waves at  you")),
+            ("/me", Command::Usage("me")),
+            ("/me   ", Command::Usage("me")),
+            ("/shrug", Command::Shrug("")),
+            ("/shrug oh well", Command::Shrug("oh well")),
+            ("/nick bob", Command::Nick("bob")),
+            ("/nick", Command::Usage("nick")),
+            ("/nick two words", Command::Usage("nick")),
+            ("/clear", Command::Clear),
+            ("/clear everything", Command::Clear),
+            ("/retention", Command::Retention(None)),
+            ("/retention 500", Command::Retention(Some(500))),
+            ("/retention 0", Command::Usage("retention")),
+            ("/retention -5", Command::Usage("retention")),
+            ("/retention lots", Command::Usage("retention")),
+            ("/help", Command::Help),
+            ("/help\tme", Command::Help),
+            ("/HELP", Command::Unknown("HELP")),
+            ("/dance", Command::Unknown("dance")),
+            ("/", Command::Unknown("")),
+        ];
+        for (input, command) in cases {
+            assert_eq!(parse_command(input), command, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn sent_text() {
+        assert_eq!(parse_command("hi").message_text().as_deref(), Some("hi"));
+        assert_eq!(
+            parse_command("/me waves").message_text().as_deref(),
+            Some("/me waves")
+        );
+        assert_eq!(
+            parse_command("/shrug").message_text().as_deref(),
+            Some(SHRUG)
+        );
+        assert_eq!(
+            parse_command("/shrug fine").message_text(),
+            Some(format!("fine {}", SHRUG))
+        );
+        for local in [
+            "/nick bob",
+            "/clear",
+            "/retention",
+            "/help",
+            "/me",
+            "/dance",
+        ] {
+            assert_eq!(parse_command(local).message_text(), None, "{}", local);
+        }
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        let sent = parse_command("/me waves").message_text().unwrap();
+        assert_eq!(action_text(&sent), Some("waves"));
+        assert_eq!(action_text("waves"), None);
+    }
+
+    #[test]
+    fn usage_and_help() {
+        assert_eq!(usage("me"), "/me <action>");
+        assert_eq!(usage("clear"), "/clear");
+        assert_eq!(usage("dance"), "/dance");
+
+        let help = help_text(&I18n::default());
+        let lines: Vec<&str> = help.lines().collect();
+        assert_eq!(lines.len(), COMMANDS.len());
+        for ((name, _), line) in COMMANDS.iter().zip(&lines) {
+            assert!(line.starts_with(&usage(name)), "{}", line);
+            assert!(!line.contains("command."), "{} is not described", name);
+        }
+    }
+}
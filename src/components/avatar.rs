@@ -0,0 +1,151 @@
+use yew::prelude::*;
+
+use crate::i18n::use_i18n;
+
+/// Backgrounds for generated avatars, picked by name.
+const COLORS: &[&str] = &[
+    "bg-red-500",
+    "bg-orange-500",
+    "bg-amber-500",
+    "bg-lime-600",
+    "bg-green-600",
+    "bg-teal-600",
+    "bg-cyan-600",
+    "bg-blue-600",
+    "bg-indigo-500",
+    "bg-violet-500",
+    "bg-fuchsia-500",
+    "bg-pink-500",
+];
+
+/// Background class for `name`'s generated avatar. Derived from an FNV-1a
+/// hash of the name, so it is the same in every session and on every client.
+pub fn avatar_color(name: &str) -> &'static str {
+    let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    COLORS[hash as usize % COLORS.len()]
+}
+
+/// Up to two letters standing in for `name`: the first letters of its first
+/// two words ("Ada Lovelace" → "AL", "bob_smith" → "BS"), or just its first
+/// letter.
+pub fn initials(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+        .filter_map(|word| word.chars().next())
+        .take(2)
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub name: String,
+    /// Image to show; left empty, or failing to load, the initials are shown
+    /// instead.
+    #[prop_or_default]
+    pub url: String,
+    /// Width and height in pixels.
+    pub size: u32,
+    #[prop_or_default]
+    pub class: Classes,
+    #[prop_or_default]
+    pub onclick: Option<Callback<MouseEvent>>,
+}
+
+/// A user's round avatar image, which falls back to their initials on a
+/// coloured circle when the image can't be loaded.
+#[function_component(Avatar)]
+pub fn avatar(props: &Props) -> Html {
+    let i18n = use_i18n();
+    let failed = use_state(|| false);
+    {
+        // A new image deserves a new attempt.
+        let failed = failed.clone();
+        use_effect_with_deps(
+            move |_| {
+                failed.set(false);
+                || ()
+            },
+            props.url.clone(),
+        );
+    }
+
+    let style = format!("width: {0}px; height: {0}px", props.size);
+    let label = i18n.t_with("avatar.label", &[("name", &props.name)]);
+    if *failed || props.url.is_empty() {
+        return html! {
+            <div
+                onclick={props.onclick.clone()}
+                {style}
+                class={classes!(
+                    "flex-none", "flex", "items-center", "justify-center", "rounded-full",
+                    "text-white", "font-semibold", "select-none",
+                    avatar_color(&props.name),
+                    props.class.clone(),
+                )}
+                role="img"
+                aria-label={label}
+            >
+                <span style={format!("font-size: {}px", props.size * 2 / 5)}>{ initials(&props.name) }</span>
+            </div>
+        };
+    }
+
+    let onerror = Callback::from(move |_| failed.set(true));
+    html! {
+        <img
+            onclick={props.onclick.clone()}
+            {onerror}
+            {style}
+            class={classes!("flex-none", "rounded-full", props.class.clone())}
+            src={props.url.clone()}
+            alt={label}
+        />
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_stable() {
+        // Pinned: every client must agree on these, so changing the hash or
+        // reordering `COLORS` recolours everyone.
+        for (name, color) in [
+            ("alice", "bg-pink-500"),
+            ("bob", "bg-indigo-500"),
+            ("carol", "bg-amber-500"),
+            ("ada lovelace", "bg-amber-500"),
+            ("ゆき", "bg-teal-600"),
+        ] {
+            assert_eq!(avatar_color(name), color, "{name}");
+        }
+    }
+
+    #[test]
+    fn colors_are_spread_out() {
+        let names: Vec<_> = (0..1200).map(|i| format!("user{i}")).collect();
+        for color in COLORS {
+            let count = names.iter().filter(|n| avatar_color(n) == *color).count();
+            assert!((70..=130).contains(&count), "{color}: {count} of 1200");
+        }
+        assert_ne!(avatar_color("alice"), avatar_color("bob"));
+    }
+
+    #[test]
+    fn initials_are_up_to_two_letters() {
+        for (name, expected) in [
+            ("Ada Lovelace", "AL"),
+            ("bob_smith", "BS"),
+            ("mary-jane watson", "MJ"),
+            ("alice", "A"),
+            ("  spaced  out ", "SO"),
+            ("élodie", "É"),
+            ("", ""),
+        ] {
+            assert_eq!(initials(name), expected, "{name:?}");
+        }
+    }
+}
@@ -1,230 +1,4852 @@
-use serde::{Deserialize, Serialize};
-use web_sys::HtmlInputElement;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+
+use gloo_events::EventListener;
+use gloo_render::{request_animation_frame, AnimationFrame};
+use gloo_timers::callback::{Interval, Timeout};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{DataTransfer, File, HtmlElement, HtmlInputElement, HtmlSelectElement};
+use yew::context::ContextHandle;
 use yew::prelude::*;
 use yew_agent::{Bridge, Bridged};
+use yew_router::prelude::*;
+
+use crate::a11y;
+use crate::attachments::decode_file;
+use crate::attachments::{format_size, icon_for, DEFAULT_INLINE_LIMIT};
+use crate::code::{blocks, inline_spans, offset_in, Block, Inline};
+use crate::commands::{self, action_text, parse_command, Command};
+use crate::components::avatar::Avatar;
+use crate::components::code_block::CodeBlock;
+use crate::components::emoji_picker::EmojiPicker;
+#[cfg(feature = "frame-log")]
+use crate::components::frame_log::FrameLogPanel;
+#[cfg(feature = "gif-picker")]
+use crate::components::gif_picker::{GifPicker, GifSearch};
+use crate::components::login::validate_username;
+use crate::components::message_menu::{MessageAction, MessageMenu};
+use crate::components::message_row::MessageRow;
+use crate::components::toast::ToastStack;
+use crate::components::user_popover::{UserAction, UserPopover};
+use crate::config;
+use crate::emoji::expand_shortcodes;
+use crate::filter::WordFilter;
+use crate::fold::{fold, short_count};
+use crate::i18n::{self, I18n, I18nContext};
+use crate::idle::{IdleTracker, HIDDEN_AFTER_MS, IDLE_AFTER_MS};
+use crate::input_history::{InputHistory, INPUT_HISTORY_CAP};
+use crate::messages::{
+    day_starts, excerpt, group_bursts, image_messages, split_message, typing_label, ChatMessage,
+    MessageData, MessageId, MessageStore, ReceiptLevel, Receipts, Row, SendStatus,
+    MAX_MESSAGE_CHARS, QUOTE_EXCERPT_CHARS,
+};
+use crate::protocol::{
+    DeleteData, EditData, FileData, MsgTypes, Presence, ReactionData, WebSocketMessage,
+    PROTOCOL_VERSION,
+};
+use crate::resync::ResyncPlan;
+use crate::rich_text::{image_url, tokenize, Token};
+use crate::sanitize::{is_image_data_url, safe_image_url, safe_web_url};
+use crate::search::{match_ranges, matching_messages, split_matches};
+use crate::services::event_bus::{ChatEvent, EventBus};
+use crate::services::files::{self, FileError};
+use crate::services::images::{self, ImageError};
+use crate::services::theme::{Theme, ThemeContext};
+use crate::services::transport::{MessageTransport, TransportFactory};
+use crate::services::websocket::{validate_ws_url, CloseReason, ConnState};
+use crate::services::{clipboard, notifications, sound, storage};
+use crate::shortcuts::{shortcut_for, Direction, Shortcut, CHEATSHEET};
+use crate::state::{
+    filter_users, ChatState, ConversationId, Effect, Pin, UserProfile, DEFAULT_ROOM,
+    PENDING_TIMEOUT_MS,
+};
+use crate::time::{self, format_day, format_full, format_timestamp, Day, RELATIVE_FOR_MS};
+use crate::toasts::{ErrorKind, ToastId};
+use crate::viewport::{offset_of, visible_window, Window, ESTIMATED_ROW_PX, OVERSCAN_ROWS};
+use crate::{Route, User};
+
+#[allow(clippy::enum_variant_names)]
+pub enum Msg {
+    HandleEvent(ChatEvent),
+    ConnectionState(ConnState),
+    ConnectionError(String),
+    FlushPending,
+    Input(String),
+    StopTyping,
+    /// Periodic cleanup of typing indicators and unmatched reactions.
+    Sweep,
+    /// Relative timestamps may need a new label.
+    Tick,
+    /// The user typed or clicked somewhere on the page.
+    Activity,
+    /// The tab was hidden or shown.
+    VisibilityChanged,
+    KeyDown(KeyboardEvent),
+    Scrolled,
+    JumpToLatest,
+    ToggleBurst(String),
+    /// Shows the rest of a long message, or folds it again.
+    ToggleFold(MessageId),
+    SubmitMessage,
+    ToggleSettings,
+    ToggleNotifications,
+    ToggleSound,
+    /// Turns masking rude words on or off.
+    ToggleFilter,
+    /// Replaces the words masked on top of the built-in ones with those
+    /// listed, separated by commas.
+    SetFilterWords(String),
+    StartRename,
+    Logout,
+    /// Leaves a session the server ended, for the login page.
+    BackToLogin,
+    ImageFailed(String),
+    /// Shows the image of message `id` full size.
+    OpenLightbox(MessageId),
+    /// Moves the lightbox to the previous (`Up`) or next image.
+    StepLightbox(Direction),
+    WindowFocus(bool),
+    ReconnectNow,
+    SendTimedOut(MessageId),
+    RetrySend(MessageId),
+    DrainThrottled,
+    FlushReads,
+    Paste(web_sys::Event),
+    /// Image files pasted or dropped, to be sent one after another.
+    AttachImages(Vec<File>),
+    ImagePrepared(Result<String, ImageError>),
+    /// Sends the previewed image.
+    SendImage,
+    /// Drops the previewed image, and the error about the last one.
+    CancelImage,
+    /// Files picked or dropped, to be sent one after another.
+    AttachFiles(Vec<File>),
+    FilePrepared(Result<FileData, FileError>),
+    /// Saves the file attached to the message with this id.
+    DownloadFile(MessageId),
+    SendNextChunk,
+    ShowError(ErrorKind),
+    DismissToast(ToastId),
+    ToggleDrawer,
+    /// Takes down the "new messages" line of the open conversation.
+    ClearNewDivider,
+    /// Shrinks the sidebar to a rail of avatars, or widens it back.
+    ToggleRail,
+    /// The visible part of the page changed height, e.g. as the on-screen
+    /// keyboard opened.
+    ViewportResized(f64),
+    ExpireToasts,
+    RemoveUser(String),
+    ReplyTo(MessageId),
+    StartEdit(MessageId),
+    CancelEdit,
+    Delete(MessageId),
+    CancelDelete,
+    /// Toggles our reaction with an emoji on a message.
+    React(MessageId, String),
+    CancelReply,
+    JumpToMessage(MessageId),
+    ClearHighlight,
+    /// Opens the transcript search, or moves focus back to it.
+    OpenSearch,
+    /// Closes the search and scrolls back to where it was opened.
+    CloseSearch,
+    Search(String),
+    /// Jumps to the previous (older) or next match.
+    SearchStep(Direction),
+    /// Copies the text of the message with this id.
+    Copy(MessageId),
+    /// The message with this id was copied; show so for a moment.
+    CopiedFeedback(MessageId),
+    CopiedFeedbackEnded(MessageId),
+    /// Shows a message collapsed because its sender is muted.
+    ShowMuted(MessageId),
+    /// Typing paused; write the drafts to storage.
+    SaveDrafts,
+    /// Pins the message with this id, or unpins it.
+    TogglePin(MessageId),
+    /// Folds the pinned strip away, or opens it back up.
+    TogglePins,
+    OpenConversation(ConversationId),
+    JoinRoom,
+    LeaveRoom(String),
+    ToggleEmojiPicker,
+    CloseEmojiPicker,
+    InsertEmoji(String),
+    #[cfg(feature = "gif-picker")]
+    ToggleGifPicker,
+    #[cfg(feature = "gif-picker")]
+    SendGif(String),
+    Shortcut(Shortcut),
+    FilterUsers(String),
+    ToggleTheme,
+    ThemeChanged(ThemeContext),
+    /// Switches the UI to the locale with this tag.
+    SetLocale(String),
+    LocaleChanged(I18nContext),
+    /// Opens the profile card of a user, at the position clicked.
+    ShowUser {
+        name: String,
+        x: i32,
+        y: i32,
+    },
+    CloseUserPopover,
+    UserAction(String, UserAction),
+    ShowMessageMenu {
+        id: MessageId,
+        x: i32,
+        y: i32,
+    },
+    CloseMessageMenu,
+    MessageAction(MessageId, MessageAction),
+}
+
+/// One entry of the rendered transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TranscriptItem {
+    Row(Row),
+    /// Divider between cached history and what arrived live.
+    EarlierDivider,
+    /// Line above the first message that arrived while we were away.
+    NewDivider,
+    /// Heading for the day on which the message at this index, and those
+    /// after it, were sent.
+    DaySeparator(usize),
+}
+
+/// The transcript search bar's state while it is open.
+struct Search {
+    query: String,
+    /// The match last jumped to.
+    current: Option<MessageId>,
+    /// Where the transcript was scrolled to when the search opened.
+    scroll_top: i32,
+    at_bottom: bool,
+}
+
+/// Panels that float above the transcript. Only one is open at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Overlay {
+    Settings,
+    EmojiPicker,
+    #[cfg(feature = "gif-picker")]
+    GifPicker,
+    Shortcuts,
+    /// The sidebar slid in over the conversation, on narrow screens.
+    Drawer,
+    /// The image of message `id`, full size.
+    Lightbox(MessageId),
+    /// Actions for message `id`, opened by a right-click or long press at
+    /// `x`, `y`.
+    MessageMenu {
+        id: MessageId,
+        x: i32,
+        y: i32,
+    },
+    /// Profile card of `name`, opened by a click at `x`, `y`.
+    UserPopover {
+        name: String,
+        x: i32,
+        y: i32,
+    },
+}
+
+/// Distance from the bottom of the transcript, in pixels, within which new
+/// messages keep the view pinned to the latest one.
+const NEAR_BOTTOM_PX: i32 = 100;
+
+/// Distance from the top of the transcript, in pixels, within which the
+/// previous page of history is fetched.
+const NEAR_TOP_PX: i32 = 200;
+/// Transcript height assumed until it has been measured.
+const DEFAULT_VIEWPORT_PX: i32 = 800;
+
+/// How long a user who left stays in the sidebar while fading out.
+const DEPARTURE_FADE_MS: u32 = 800;
+
+/// How long the latest messages stay in view, with the window focused,
+/// before the "new messages" line goes.
+const NEW_DIVIDER_CLEAR_MS: u32 = 4_000;
+
+/// Reactions offered on every message.
+const QUICK_REACTIONS: &[&str] = &["👍", "❤️", "😂"];
+/// How long reactions and deletes for a message we haven't received yet are
+/// kept.
+const ORPHAN_BUFFER_MS: u64 = 10_000;
+
+/// Pause between the pieces of a split-up paste, so they arrive in order
+/// without bursting.
+const CHUNK_SEND_DELAY_MS: u32 = 150;
+
+/// Read receipts are collected for this long and sent as one frame.
+const READ_BATCH_MS: u32 = 1_000;
+
+/// How long the delete button waits for the confirming second click.
+const DELETE_CONFIRM_MS: u32 = 3_000;
+
+/// Pause in typing after which drafts are saved.
+const DRAFT_SAVE_MS: u32 = 500;
+
+/// Messages per conversation kept in localStorage across reloads.
+const HISTORY_LIMIT: usize = 200;
+
+/// How long a message stays highlighted after jumping to it.
+const HIGHLIGHT_MS: u32 = 1_500;
+/// How long a copy button shows that it worked.
+pub const COPIED_FEEDBACK_MS: u32 = 1_500;
+
+/// Inactivity after which we tell others we stopped typing.
+const TYPING_IDLE_MS: u32 = 5_000;
+/// Someone we haven't heard from for this long is no longer shown as typing.
+const TYPING_EXPIRE_MS: u64 = 6_000;
+/// How often relative timestamps ("4 min ago") are brought up to date.
+const TICK_MS: u32 = 30_000;
+
+#[derive(Properties, PartialEq)]
+pub struct ChatProps {
+    /// How to reach the server; a real websocket unless overridden.
+    #[prop_or_default]
+    pub transport: TransportFactory,
+    /// Tailwind colour used for buttons, badges and the selected
+    /// conversation, such as `"violet"` or `"emerald"`.
+    #[prop_or_else(|| "blue".to_string())]
+    pub accent: String,
+    /// Any CSS width for the sidebar.
+    #[prop_or_else(|| "15rem".to_string())]
+    pub sidebar_width: String,
+    /// Hides the sidebar, leaving the whole width to the conversation.
+    #[prop_or_default]
+    pub sidebar_collapsed: bool,
+    /// Whether the sidebar lists the users online.
+    #[prop_or(true)]
+    pub show_users: bool,
+    /// Shown in the header of rooms instead of the room name.
+    #[prop_or_default]
+    pub title: Option<String>,
+    /// Largest file, in bytes, sent inline or accepted from others. Bigger
+    /// ones need an upload endpoint.
+    #[prop_or(DEFAULT_INLINE_LIMIT)]
+    pub inline_file_limit: usize,
+    /// Told the text of every message we send.
+    #[prop_or_default]
+    pub on_message_sent: Option<Callback<String>>,
+}
+
+/// The chat screen. What the chat knows, and how that changes, is in
+/// [`ChatState`]; this keeps the DOM, the timers and the socket.
+pub struct Chat {
+    state: ChatState,
+    chat_input: NodeRef,
+    emoji_button: NodeRef,
+    input_text: String,
+    transcript: NodeRef,
+    scroll_to_bottom: bool,
+    /// Distance from the bottom of the transcript to restore after content
+    /// was inserted above the viewport.
+    scroll_anchor: Option<i32>,
+    /// Last known scroll offset and height of the transcript.
+    scroll_top: i32,
+    viewport_height: i32,
+    /// Measured heights of transcript rows, by row key.
+    row_heights: HashMap<String, i32>,
+    /// Rows in the DOM as of the last render.
+    rendered_rows: Cell<(usize, usize)>,
+    /// Message to scroll to once its row has been rendered.
+    pending_jump: Option<MessageId>,
+    search: Option<Search>,
+    search_input: NodeRef,
+    /// The search just opened and its input should get focus.
+    focus_search: bool,
+    /// Scroll offset to go back to after the next render.
+    restore_scroll: Option<i32>,
+    _producer: Box<dyn Bridge<EventBus>>,
+    /// The factory `wss` came from, to notice when the property changes.
+    transport: TransportFactory,
+    wss: Option<Box<dyn MessageTransport>>,
+    /// Text typed into the user list's search box.
+    user_filter: String,
+    theme: Option<ThemeContext>,
+    _theme_listener: Option<ContextHandle<ThemeContext>>,
+    locale: Option<I18nContext>,
+    _locale_listener: Option<ContextHandle<I18nContext>>,
+    room_input: NodeRef,
+    server_url: String,
+    overlay: Option<Overlay>,
+    /// The settings panel or shortcut dialog, for keeping focus inside.
+    overlay_root: NodeRef,
+    /// What had focus before an overlay opened, to go back to on closing.
+    return_focus: Option<HtmlElement>,
+    /// An overlay opened and should get focus after rendering.
+    focus_overlay: bool,
+    /// The page behind the lightbox is kept from scrolling while it is open.
+    scroll_locked: bool,
+    /// Whether the websocket traffic panel is showing.
+    #[cfg(feature = "frame-log")]
+    frame_log_open: bool,
+    /// An overlay closed and focus may need to go back.
+    restore_focus: bool,
+    settings_input: NodeRef,
+    settings_error: Option<String>,
+    toast_timer: Option<Timeout>,
+    /// Timeouts after which our messages not yet echoed back are shown as
+    /// failed.
+    pending_timeouts: HashMap<MessageId, Timeout>,
+    read_flush: Option<Timeout>,
+    drain_timer: Option<Timeout>,
+    chunk_timer: Option<Timeout>,
+    incoming: Vec<ChatEvent>,
+    flush_handle: Option<AnimationFrame>,
+    expanded_bursts: HashSet<String>,
+    /// Long messages shown whole rather than folded.
+    unfolded: HashSet<MessageId>,
+    departures: HashMap<String, Timeout>,
+    confirm_delete_timeout: Option<Timeout>,
+    highlighted: Option<MessageId>,
+    highlight_timeout: Option<Timeout>,
+    /// Messages just copied, each with the timeout that resets its button.
+    copied: HashMap<MessageId, Timeout>,
+    pins_collapsed: bool,
+    /// Pending write of the drafts to storage.
+    draft_timer: Option<Timeout>,
+    notification_permission_asked: bool,
+    /// Image URLs that failed to load and are shown as text instead.
+    broken_images: HashSet<String>,
+    /// Pasted or dropped files still to be prepared, in order.
+    image_queue: VecDeque<File>,
+    /// An image is being read and scaled down.
+    preparing_image: bool,
+    /// The prepared image, shown for confirmation before it is sent.
+    image_preview: Option<String>,
+    /// Files being read or uploaded.
+    preparing_files: usize,
+    file_input: NodeRef,
+    /// Why the last image or file couldn't be sent.
+    attach_error: Option<String>,
+    /// Takes down the "new messages" line once the latest messages have
+    /// been in view for a while.
+    new_divider_timer: Option<Timeout>,
+    typing_idle: Option<Timeout>,
+    _typing_sweep: Interval,
+    _tick: Interval,
+    /// Decides when we count as away.
+    idle: IdleTracker,
+    _presence_listeners: Vec<EventListener>,
+    /// Whether the sidebar is shrunk to a rail on wide screens.
+    rail: bool,
+    /// Height of the visual viewport, where the browser reports it.
+    visual_height: Option<f64>,
+    _viewport_listener: Option<EventListener>,
+    _keydown: Option<EventListener>,
+    base_title: String,
+    _focus_listeners: Vec<EventListener>,
+}
+
+impl Chat {
+    /// Opens a connection to `self.server_url` through the transport given
+    /// in the properties, registering the current user every time it
+    /// (re)opens. Failures are kept in `settings_error` so the panel can show
+    /// them.
+    fn connect(&mut self, ctx: &Context<Self>) {
+        match ctx.props().transport.open(&self.server_url) {
+            Ok(wss) => {
+                wss.set_handshake(self.handshake_frames());
+                self.state.conn_state = wss.state();
+                self.wss = Some(wss);
+                self.settings_error = None;
+            }
+            Err(e) => {
+                log::error!("ws: {}", e);
+                self.wss = None;
+                self.state.conn_state = ConnState::Closed;
+                self.state.conn_error = Some(e.clone());
+                self.settings_error = Some(e);
+            }
+        }
+        self.state.sync_connection_toast();
+    }
+
+    /// Shows an error notice for `kind`, counting it on the one already up
+    /// if it is the same failure again.
+    fn show_error(&mut self, ctx: &Context<Self>, kind: ErrorKind) {
+        self.state.show_error(kind);
+        self.schedule_toast_expiry(ctx);
+    }
+
+    /// Arms the timeout taking down the next notice to expire.
+    fn schedule_toast_expiry(&mut self, ctx: &Context<Self>) {
+        self.toast_timer = self.state.toasts.next_expiry().map(|at| {
+            let link = ctx.link().clone();
+            let delay = at.saturating_sub(time::now());
+            Timeout::new(delay as u32, move || link.send_message(Msg::ExpireToasts))
+        });
+    }
+
+    /// Frames sent on every (re)connect, from the [`ResyncPlan`] for where
+    /// we are now. Servers that don't keep history simply never answer its
+    /// requests.
+    fn handshake_frames(&self) -> Vec<WebSocketMessage> {
+        ResyncPlan::new(&self.state, self.idle.presence()).into_frames()
+    }
+
+    /// Sends a join or leave request for `room` and keeps the handshake in
+    /// sync so the membership survives reconnects.
+    fn send_membership(&self, message_type: MsgTypes, room: &str) {
+        let message = WebSocketMessage {
+            room: Some(room.to_string()),
+            ..WebSocketMessage::new(message_type, None)
+        };
+        if let Err(e) = self.send(&message) {
+            log::debug!("error sending to channel: {}", e);
+        }
+        if let Some(wss) = &self.wss {
+            wss.set_handshake(self.handshake_frames());
+        }
+    }
+
+    /// Tells the others we went away or came back, if we did. Returns
+    /// whether our own entry in the sidebar changed.
+    fn presence_changed(&mut self, changed: Option<Presence>) -> bool {
+        let presence = match changed {
+            Some(presence) => presence,
+            None => return false,
+        };
+        match WebSocketMessage::status(&self.state.username, presence) {
+            Ok(frame) => {
+                if let Err(e) = self.send(&frame) {
+                    log::debug!("error sending status: {}", e);
+                }
+            }
+            Err(e) => log::error!("could not write our status: {}", e),
+        }
+        if let Some(wss) = &self.wss {
+            wss.set_handshake(self.handshake_frames());
+        }
+        self.state
+            .presence
+            .insert(self.state.username.clone(), presence);
+        true
+    }
+
+    /// Tells the others whether we are typing, if
+    /// [`ChatState::typing_notice`] says a notice is due.
+    fn send_typing(&mut self, typing: bool) {
+        // Held until we are back, it would only be stale.
+        if self.state.conn_state != ConnState::Open {
+            return;
+        }
+        if let Some(message) = self.state.typing_notice(typing) {
+            if let Err(e) = self.send(&message) {
+                log::debug!("error sending typing notice: {}", e);
+            }
+        }
+    }
+
+    /// Starts waiting for the echo of our message `id`.
+    fn arm_pending_timeout(&mut self, ctx: &Context<Self>, id: &str) {
+        if self.state.outgoing.contains_key(id) {
+            let link = ctx.link().clone();
+            let timed_out = id.to_string();
+            let timeout = Timeout::new(PENDING_TIMEOUT_MS as u32, move || {
+                link.send_message(Msg::SendTimedOut(timed_out))
+            });
+            self.pending_timeouts.insert(id.to_string(), timeout);
+        }
+    }
+
+    /// Sends our message `id` if the rate limit allows, otherwise holds it
+    /// back behind any messages already waiting.
+    fn send_or_throttle(&mut self, ctx: &Context<Self>, id: MessageId) {
+        match self.state.admit(id.clone()) {
+            Ok(()) => self.send_outgoing(ctx, &id),
+            Err(Some(ms)) => self.arm_drain(ctx, ms),
+            Err(None) => {}
+        }
+    }
+
+    fn arm_drain(&mut self, ctx: &Context<Self>, ms: u64) {
+        let link = ctx.link().clone();
+        self.drain_timer = Some(Timeout::new(ms as u32, move || {
+            link.send_message(Msg::DrainThrottled)
+        }));
+    }
+
+    /// Hands our message `id` to the websocket service, marking it failed
+    /// right away if that doesn't work.
+    fn send_outgoing(&mut self, ctx: &Context<Self>, id: &MessageId) {
+        let frame = match self.state.outgoing.get(id) {
+            Some(frame) => frame.clone(),
+            None => return,
+        };
+        let accepted = self
+            .send(&frame)
+            .map_err(|e| log::debug!("error sending to channel: {}", e))
+            .is_ok();
+        match self.state.handed_over(id, accepted) {
+            SendStatus::Pending => self.arm_pending_timeout(ctx, id),
+            SendStatus::Failed => self.schedule_toast_expiry(ctx),
+            _ => {}
+        }
+    }
+
+    /// Loads the conversations cached by [`Chat::save_history`]. Rooms found
+    /// there are rejoined on connect.
+    fn restore_history(&mut self) {
+        let history: Vec<(ConversationId, Vec<MessageData>)> = match storage::load_history() {
+            Some(history) => history,
+            None => return,
+        };
+        for (conversation, messages) in history {
+            if let ConversationId::Room(room) = &conversation {
+                if !self.state.rooms.contains(room) {
+                    self.state.rooms.push(room.clone());
+                }
+            }
+            let messages: Vec<ChatMessage> = messages
+                .into_iter()
+                .map(|data| {
+                    if !data.id.is_empty() {
+                        self.state.seen_ids.insert(&data.id);
+                    }
+                    ChatMessage {
+                        data,
+                        restored: true,
+                        ..Default::default()
+                    }
+                })
+                .collect();
+            self.state
+                .conversations
+                .entry(conversation)
+                .or_default()
+                .messages = messages.into();
+        }
+        // The cap may have been lowered since they were saved.
+        let max_messages = self.state.max_messages;
+        self.state.set_max_messages(max_messages);
+    }
+
+    /// Keeps what is in the input as the open conversation's draft, saved
+    /// once typing pauses for [`DRAFT_SAVE_MS`]. Text being edited is not a
+    /// draft.
+    fn update_draft(&mut self, ctx: &Context<Self>) {
+        if !self.state.set_draft(&self.input_text) {
+            return;
+        }
+        let link = ctx.link().clone();
+        self.draft_timer = Some(Timeout::new(DRAFT_SAVE_MS, move || {
+            link.send_message(Msg::SaveDrafts)
+        }));
+    }
+
+    /// Forgets the open conversation's draft once it has been sent.
+    fn clear_draft(&mut self) {
+        if self.state.drafts.remove(&self.state.active).is_some() {
+            self.draft_timer = None;
+            self.save_drafts();
+        }
+    }
+
+    fn save_drafts(&self) {
+        let drafts: Vec<(&ConversationId, &String)> = self.state.drafts.iter().collect();
+        storage::save_drafts(&drafts);
+    }
+
+    /// Puts the open conversation's draft, if it has one, into the input.
+    /// Drafts over the length limit come back whole, for the counter to
+    /// flag.
+    fn restore_draft(&mut self) {
+        let text = self
+            .state
+            .drafts
+            .get(&self.state.active)
+            .cloned()
+            .unwrap_or_default();
+        if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+            input.set_value(&text);
+        }
+        self.input_text = text;
+    }
+
+    /// Pencil shown on sidebar entries with an unsent draft, other than the
+    /// one open.
+    fn view_draft_marker(&self, conversation: &ConversationId) -> Html {
+        if *conversation == self.state.active || !self.state.drafts.contains_key(conversation) {
+            return html! {};
+        }
+        html! {
+            <span class="ml-1 text-gray-400" title={self.t("draft.marker")} aria-label={self.t("draft.marker")}>{"✏️"}</span>
+        }
+    }
+
+    /// Caches the last [`HISTORY_LIMIT`] delivered messages of every
+    /// conversation.
+    fn save_history(&self) {
+        let history: Vec<(&ConversationId, Vec<&MessageData>)> = self
+            .state
+            .conversations
+            .iter()
+            .map(|(id, c)| {
+                let limit = HISTORY_LIMIT.min(self.state.max_messages);
+                (id, c.messages.persisted(limit))
+            })
+            .collect();
+        storage::save_history(&history);
+    }
+
+    /// Sends the edited text of our message `message_id` and shows it right
+    /// away.
+    fn send_edit(&mut self, ctx: &Context<Self>, message_id: MessageId, text: String) {
+        let edit = EditData {
+            message_id,
+            from: self.state.username.clone(),
+            message: text,
+        };
+        let message =
+            WebSocketMessage::new(MsgTypes::Edit, Some(serde_json::to_string(&edit).unwrap()));
+        if let Err(e) = self.send(&message) {
+            log::debug!("error sending edit: {}", e);
+            self.show_error(ctx, ErrorKind::ActionFailed);
+        }
+        self.state.apply_edit(edit);
+    }
+
+    /// Queues Read receipts for the open conversation if its latest messages
+    /// are on screen. Frames go out at most once per [`READ_BATCH_MS`].
+    fn mark_read(&mut self, ctx: &Context<Self>) {
+        if self.state.mark_read() && self.read_flush.is_none() {
+            let link = ctx.link().clone();
+            self.read_flush = Some(Timeout::new(READ_BATCH_MS, move || {
+                link.send_message(Msg::FlushReads)
+            }));
+        }
+    }
+
+    fn send_delete(&mut self, ctx: &Context<Self>, message_id: MessageId) {
+        let delete = DeleteData {
+            message_id,
+            from: self.state.username.clone(),
+        };
+        let message = WebSocketMessage::new(
+            MsgTypes::Delete,
+            Some(serde_json::to_string(&delete).unwrap()),
+        );
+        if let Err(e) = self.send(&message) {
+            log::debug!("error sending delete: {}", e);
+            self.show_error(ctx, ErrorKind::ActionFailed);
+        }
+        self.state.apply_delete(delete);
+    }
+
+    /// Prefixes the document title with the number of messages that arrived
+    /// while the window was in the background.
+    fn update_title(&self) {
+        let document = match web_sys::window().and_then(|w| w.document()) {
+            Some(document) => document,
+            None => return,
+        };
+        if self.state.title_unread == 0 {
+            document.set_title(&self.base_title);
+        } else {
+            document.set_title(&format!(
+                "({}) {}",
+                self.state.title_unread, self.base_title
+            ));
+        }
+    }
+
+    /// Starts the countdown to taking down the "new messages" line while
+    /// the latest messages are in view and the window is focused, and stops
+    /// it otherwise.
+    fn arm_new_divider_clear(&mut self, ctx: &Context<Self>) {
+        let shown = self
+            .state
+            .conversations
+            .get(&self.state.active)
+            .is_some_and(|c| c.new_since.is_some());
+        if !(shown && self.state.at_bottom && self.state.window_focused) {
+            self.new_divider_timer = None;
+        } else if self.new_divider_timer.is_none() {
+            let link = ctx.link().clone();
+            self.new_divider_timer = Some(Timeout::new(NEW_DIVIDER_CLEAR_MS, move || {
+                link.send_message(Msg::ClearNewDivider)
+            }));
+        }
+    }
+
+    fn clear_title_unread(&mut self) {
+        if self.state.title_unread > 0 {
+            self.state.title_unread = 0;
+            self.update_title();
+        }
+    }
+
+    /// Returns to the login screen with `reason` shown there. The name stays
+    /// saved so the form comes back filled in.
+    fn reject_login(&mut self, ctx: &Context<Self>, reason: String) {
+        self.disconnect();
+        if let Some((user, _)) = ctx.link().context::<User>(Callback::noop()) {
+            *user.login_error.borrow_mut() = Some(reason);
+        }
+        if let Some(history) = ctx.link().history() {
+            history.push(Route::Login);
+        }
+    }
+
+    /// Rows of the open conversation in display order, headed by the day
+    /// they were sent on, with cached history separated from what arrived
+    /// live.
+    fn transcript_items(&self) -> Vec<TranscriptItem> {
+        let messages = self.state.messages();
+        let restored = messages.iter().take_while(|m| m.restored).count();
+        let days: Vec<Day> = messages.iter().map(|m| Day::of(m.timestamp)).collect();
+        let day_starts = day_starts(&days);
+        let divider = restored > 0 && restored < messages.len();
+        let new_since = self
+            .state
+            .conversations
+            .get(&self.state.active)
+            .and_then(|c| c.new_since.as_ref())
+            .and_then(|id| messages.iter().position(|m| &m.id == id));
+
+        // Bursts never run across a divider or into the next day.
+        let mut boundaries = day_starts.clone();
+        if divider {
+            boundaries.push(restored);
+        }
+        boundaries.extend(new_since);
+        boundaries.push(messages.len());
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut items = Vec::new();
+        for segment in boundaries.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+            if divider && start == restored {
+                items.push(TranscriptItem::EarlierDivider);
+            }
+            if day_starts.contains(&start) {
+                items.push(TranscriptItem::DaySeparator(start));
+            }
+            if new_since == Some(start) {
+                items.push(TranscriptItem::NewDivider);
+            }
+            let slice = &messages[start..end];
+            items.extend(
+                group_bursts(slice.iter().map(|m| (m.from.as_str(), m.timestamp)))
+                    .into_iter()
+                    .map(|row| TranscriptItem::Row(row.offset(start))),
+            );
+        }
+        items
+    }
+
+    /// Stable key of `item`, used for diffing and for remembering its height.
+    fn item_key(&self, item: TranscriptItem) -> String {
+        match item {
+            TranscriptItem::Row(Row::Single(i) | Row::Burst { start: i, .. }) => {
+                self.state.messages()[i].id.clone()
+            }
+            TranscriptItem::EarlierDivider => "earlier".to_string(),
+            TranscriptItem::NewDivider => "new-since".to_string(),
+            TranscriptItem::DaySeparator(i) => {
+                let day = Day::of(self.state.messages()[i].timestamp);
+                format!("day-{}-{}-{}", day.year, day.month, day.day)
+            }
+        }
+    }
+
+    fn item_heights(&self, items: &[TranscriptItem]) -> Vec<i32> {
+        items
+            .iter()
+            .map(|&item| {
+                self.row_heights
+                    .get(&self.item_key(item))
+                    .copied()
+                    .unwrap_or(ESTIMATED_ROW_PX)
+            })
+            .collect()
+    }
+
+    /// Which of `items` to render. Follows the latest messages while the
+    /// view is pinned to the bottom, and centres on a message being jumped
+    /// to.
+    fn transcript_window(&self, items: &[TranscriptItem], heights: &[i32]) -> Window {
+        let total: i32 = heights.iter().sum();
+        let jump_target = self.pending_jump.as_ref().and_then(|id| {
+            let index = self.state.messages().iter().position(|m| &m.id == id)?;
+            items.iter().position(|item| match item {
+                TranscriptItem::Row(Row::Single(i)) => *i == index,
+                TranscriptItem::Row(Row::Burst { start, len }) => {
+                    (*start..start + len).contains(&index)
+                }
+                TranscriptItem::EarlierDivider
+                | TranscriptItem::NewDivider
+                | TranscriptItem::DaySeparator(_) => false,
+            })
+        });
+        let scroll_top = if let Some(target) = jump_target {
+            offset_of(heights, target) - self.viewport_height / 2
+        } else if let Some(anchor) = self.scroll_anchor {
+            total - anchor
+        } else if self.scroll_to_bottom || self.state.at_bottom {
+            total - self.viewport_height
+        } else {
+            self.scroll_top
+        };
+        visible_window(heights, scroll_top, self.viewport_height, OVERSCAN_ROWS)
+    }
+
+    /// The rows of the open conversation around the visible part of the
+    /// transcript, with spacers standing in for the rest.
+    fn view_transcript(&self, ctx: &Context<Self>) -> Html {
+        let items = self.transcript_items();
+        let heights = self.item_heights(&items);
+        let window = self.transcript_window(&items, &heights);
+        self.rendered_rows.set((window.start, window.end));
+
+        let spacer = |height: i32| {
+            html! { <div aria-hidden="true" style={format!("height: {}px", height)}></div> }
+        };
+        html! {
+            <>
+                { spacer(window.space_above) }
+                {
+                    for items[window.start..window.end].iter().map(|&item| {
+                        let key = self.item_key(item);
+                        html! {
+                            <div key={key.clone()} data-row={key} class="pb-4">
+                                {
+                                    match item {
+                                        TranscriptItem::Row(row) => self.view_row(ctx, row),
+                                        TranscriptItem::EarlierDivider => html! {
+                                            <div class="text-center text-xs text-gray-400 dark:text-gray-500">{ self.t("chat.earlier") }</div>
+                                        },
+                                        TranscriptItem::NewDivider => html! {
+                                            <div role="separator" class="flex items-center gap-3 text-xs font-semibold text-red-600 dark:text-red-400">
+                                                <div class="flex-grow border-t border-red-400 dark:border-red-500"></div>
+                                                <span>{ self.t("chat.new_divider") }</span>
+                                                <div class="flex-grow border-t border-red-400 dark:border-red-500"></div>
+                                            </div>
+                                        },
+                                        TranscriptItem::DaySeparator(i) => self.view_day_separator(i),
+                                    }
+                                }
+                            </div>
+                        }
+                    })
+                }
+                { spacer(window.space_below) }
+            </>
+        }
+    }
+
+    fn view_day_separator(&self, index: usize) -> Html {
+        let timestamp = self.state.messages()[index].timestamp;
+        html! {
+            <div role="separator" class="flex items-center gap-3 text-xs font-medium text-gray-400 dark:text-gray-500">
+                <div class="flex-grow border-t border-gray-200 dark:border-gray-700"></div>
+                <span>{ format_day(&self.state.i18n, timestamp, time::now()) }</span>
+                <div class="flex-grow border-t border-gray-200 dark:border-gray-700"></div>
+            </div>
+        }
+    }
+
+    /// Debug builds only: seeds the open conversation with `count` generated
+    /// messages (`?fill=10000`), to check how the transcript copes with long
+    /// sessions.
+    #[cfg(debug_assertions)]
+    fn fill_transcript(&mut self, count: usize) {
+        self.state.max_messages = self.state.max_messages.max(count);
+        let conversation = self.state.active.clone();
+        let start = time::now().saturating_sub(count as u64 * 1_000);
+        for i in 0..count {
+            let from = format!("user{}", i % 7);
+            let timestamp = start + i as u64 * 1_000;
+            let message = MessageData {
+                id: self.state.next_id(&from, timestamp),
+                message: format!(
+                    "Message {} of {}.{}",
+                    i + 1,
+                    count,
+                    " Lorem ipsum.".repeat(i % 12)
+                ),
+                from,
+                timestamp,
+                ..Default::default()
+            };
+            self.state.push_message(&conversation, message.into());
+        }
+        self.scroll_to_bottom = true;
+    }
+
+    /// Records the heights of the rows currently in the DOM.
+    fn measure_rows(&mut self) {
+        let rows = match self
+            .transcript
+            .cast::<web_sys::Element>()
+            .and_then(|el| el.query_selector_all("[data-row]").ok())
+        {
+            Some(rows) => rows,
+            None => return,
+        };
+        for i in 0..rows.length() {
+            let row = match rows
+                .item(i)
+                .and_then(|n| n.dyn_into::<web_sys::HtmlElement>().ok())
+            {
+                Some(row) => row,
+                None => continue,
+            };
+            if let Some(key) = row.get_attribute("data-row") {
+                self.row_heights.insert(key, row.offset_height());
+            }
+        }
+    }
+
+    fn view_row(&self, ctx: &Context<Self>, row: Row) -> Html {
+        let (start, len) = match row {
+            Row::Single(i) => return self.view_message(ctx, &self.state.messages()[i]),
+            Row::Burst { start, len } => (start, len),
+        };
+        let messages = self.state.messages();
+        let first = &messages[start];
+        let key = burst_key(first);
+        let expanded = self.expanded_bursts.contains(&key);
+        let label = if expanded {
+            self.t("chat.collapse")
+        } else {
+            self.state
+                .i18n
+                .plural("chat.burst_more", len - 1, &[("name", &first.from)])
+        };
+        let toggle = ctx.link().callback(move |_| Msg::ToggleBurst(key.clone()));
+        let shown = if expanded { len } else { 1 };
+        // Lined up under the avatar, which is on the right for our own.
+        let side = if self.state.is_own(first) {
+            "self-end mr-14"
+        } else {
+            "self-start ml-14"
+        };
+
+        html! {
+            <div class="flex flex-col space-y-4">
+                { for messages[start..start + shown].iter().map(|m| self.view_message(ctx, m)) }
+                <button onclick={toggle} class={classes!(side, "text-xs", "text-blue-600", "hover:underline", "dark:text-blue-400")}>
+                    { label }
+                </button>
+            </div>
+        }
+    }
+
+    fn view_emoji_picker(&self, ctx: &Context<Self>) -> Html {
+        let toggle = ctx.link().callback(|_| Msg::ToggleEmojiPicker);
+
+        html! {
+            <>
+                <button
+                    ref={self.emoji_button.clone()}
+                    onclick={toggle}
+                    class="w-12 h-12 rounded-full border border-gray-300 text-xl hover:bg-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:hover:bg-gray-700"
+                    aria-label={self.t("chat.insert_emoji")}
+                    aria-expanded={(self.overlay == Some(Overlay::EmojiPicker)).to_string()}
+                >
+                    {"😊"}
+                </button>
+                {
+                    if self.overlay == Some(Overlay::EmojiPicker) {
+                        html! {
+                            <EmojiPicker
+                                on_select={ctx.link().callback(Msg::InsertEmoji)}
+                                on_close={ctx.link().callback(|_| Msg::CloseEmojiPicker)}
+                                anchor={self.emoji_button.clone()}
+                            />
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </>
+        }
+    }
+
+    #[cfg(feature = "gif-picker")]
+    fn view_gif_picker(&self, ctx: &Context<Self>) -> Html {
+        let search = match GifSearch::configured() {
+            Some(search) => search,
+            None => return html! {},
+        };
+        let toggle = ctx.link().callback(|_| Msg::ToggleGifPicker);
+
+        html! {
+            <>
+                <button
+                    onclick={toggle}
+                    class="px-3 h-12 rounded-full border border-gray-300 text-xs font-bold text-gray-600 hover:bg-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:text-gray-300 dark:hover:bg-gray-700 dark:bg-gray-700"
+                    aria-label={self.t("chat.send_gif")}
+                >
+                    {"GIF"}
+                </button>
+                {
+                    if self.overlay == Some(Overlay::GifPicker) {
+                        html! {
+                            <GifPicker
+                                {search}
+                                on_select={ctx.link().callback(Msg::SendGif)}
+                                on_close={ctx.link().callback(|_| Msg::ToggleGifPicker)}
+                            />
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </>
+        }
+    }
+
+    #[cfg(not(feature = "gif-picker"))]
+    fn view_gif_picker(&self, _ctx: &Context<Self>) -> Html {
+        html! {}
+    }
+
+    /// Blocks the page once the server turned out to speak a protocol
+    /// version newer than ours, rather than misreading what it sends.
+    fn view_version_banner(&self, ctx: &Context<Self>) -> Html {
+        let version = match self.state.unsupported_version {
+            Some(version) => version,
+            None => return html! {},
+        };
+        let refresh = Callback::from(|_: MouseEvent| {
+            if let Some(window) = web_sys::window() {
+                let _ = window.location().reload();
+            }
+        });
+
+        html! {
+            <div class="fixed inset-0 z-50 flex items-center justify-center bg-black bg-opacity-50">
+                <div
+                    class="w-96 p-6 bg-white rounded-md shadow-lg text-center dark:bg-gray-800"
+                    role="alertdialog"
+                    aria-modal="true"
+                    aria-label={self.t("version.title")}
+                >
+                    <h2 class="font-semibold mb-2">{ self.t("version.title") }</h2>
+                    <p class="text-sm text-gray-600 mb-4 dark:text-gray-300">
+                        {
+                            self.state.i18n.t_with(
+                                "version.body",
+                                &[("server", &version.to_string()), ("client", &PROTOCOL_VERSION.to_string())],
+                            )
+                        }
+                    </p>
+                    <button
+                        onclick={refresh}
+                        class={self.accent(ctx, "px-4 py-2 rounded-md bg-blue-600 hover:bg-blue-700 text-white text-sm focus:outline-none focus:ring-2 focus:ring-blue-400")}
+                    >
+                        { self.t("version.refresh") }
+                    </button>
+                </div>
+            </div>
+        }
+    }
+
+    /// Covers the page once the server has ended our session for good, in
+    /// place of reconnecting.
+    fn view_refused(&self, ctx: &Context<Self>) -> Html {
+        let reason = match self.state.refused {
+            Some(reason) => refused_text(&self.state.i18n, reason),
+            None => return html! {},
+        };
+        let title = self.t("refused.title");
+
+        html! {
+            <div class="fixed inset-0 z-50 flex items-center justify-center bg-black bg-opacity-50">
+                <div
+                    class="w-96 p-6 bg-white rounded-md shadow-lg text-center dark:bg-gray-800"
+                    role="alertdialog"
+                    aria-modal="true"
+                    aria-label={title.clone()}
+                >
+                    <h2 class="font-semibold mb-2">{ title }</h2>
+                    <p class="text-sm text-gray-600 mb-4 dark:text-gray-300">{ reason }</p>
+                    <button
+                        onclick={ctx.link().callback(|_| Msg::BackToLogin)}
+                        class={self.accent(ctx, "px-4 py-2 rounded-md bg-blue-600 hover:bg-blue-700 text-white text-sm focus:outline-none focus:ring-2 focus:ring-blue-400")}
+                    >
+                        { self.t("refused.login") }
+                    </button>
+                </div>
+            </div>
+        }
+    }
+
+    /// Counts of frames the server sent that we couldn't use, for telling
+    /// a protocol mismatch from a bug.
+    fn view_diagnostics(&self) -> Html {
+        let stats = &self.state.protocol_stats;
+        let i18n = &self.state.i18n;
+
+        html! {
+            <details class="mt-3 text-xs text-gray-600 dark:text-gray-300">
+                <summary class="cursor-pointer font-medium">{ self.t("diagnostics.title") }</summary>
+                <dl class="mt-1 space-y-0.5">
+                    <div class="flex justify-between">
+                        <dt>{ self.t("diagnostics.protocol") }</dt>
+                        <dd>{ PROTOCOL_VERSION }</dd>
+                    </div>
+                    <div class="flex justify-between">
+                        <dt>{ self.t("diagnostics.unknown") }</dt>
+                        <dd>{ stats.unknown }</dd>
+                    </div>
+                    <div class="flex justify-between">
+                        <dt>{ self.t("diagnostics.malformed") }</dt>
+                        <dd>{ stats.malformed }</dd>
+                    </div>
+                </dl>
+                {
+                    match &stats.last {
+                        Some(last) => html! {
+                            <p class="mt-1 break-words">{ i18n.t_with("diagnostics.last", &[("reason", last)]) }</p>
+                        },
+                        None => html! {},
+                    }
+                }
+            </details>
+        }
+    }
+
+    fn view_shortcuts(&self, ctx: &Context<Self>) -> Html {
+        if self.overlay != Some(Overlay::Shortcuts) {
+            return html! {};
+        }
+        let close = ctx.link().callback(|_| Msg::Shortcut(Shortcut::Dismiss));
+
+        html! {
+            <div class="fixed inset-0 z-20 flex items-center justify-center bg-black bg-opacity-30">
+                <div
+                    ref={self.overlay_root.clone()}
+                    onkeydown={self.trap_focus()}
+                    class="w-80 p-4 bg-white rounded-md shadow-lg dark:bg-gray-800"
+                    role="dialog"
+                    aria-modal="true"
+                    aria-label={self.t("shortcuts.title")}
+                >
+                    <div class="flex items-center justify-between mb-3">
+                        <h2 class="font-semibold">{ self.t("shortcuts.title") }</h2>
+                        <button
+                            onclick={close}
+                            class="text-gray-400 hover:text-gray-700 focus:outline-none focus:ring-2 focus:ring-blue-400 rounded dark:text-gray-500 dark:hover:text-gray-200"
+                            aria-label={self.t("shortcuts.close")}
+                        >
+                            {"✕"}
+                        </button>
+                    </div>
+                    <dl class="space-y-2 text-sm">
+                        { for CHEATSHEET.iter().map(|(keys, action)| html! {
+                            <div class="flex justify-between">
+                                <dt><kbd class="px-2 py-0.5 rounded border border-gray-300 bg-gray-50 text-xs dark:border-gray-600 dark:bg-gray-900">{ *keys }</kbd></dt>
+                                <dd class="text-gray-600 dark:text-gray-300">{ self.t(action) }</dd>
+                            </div>
+                        })}
+                    </dl>
+                </div>
+            </div>
+        }
+    }
+
+    fn view_unseen_pill(&self, ctx: &Context<Self>) -> Html {
+        if self.state.unseen == 0 {
+            return html! {};
+        }
+        let jump = ctx.link().callback(|_| Msg::JumpToLatest);
+        let label = self
+            .state
+            .i18n
+            .plural("chat.new_messages", self.state.unseen, &[]);
+
+        html! {
+            <button
+                onclick={jump}
+                class="absolute bottom-24 left-1/2 transform -translate-x-1/2 px-4 py-1 rounded-full bg-blue-600 text-white text-xs shadow-md hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-blue-400"
+            >
+                { label }{" ↓"}
+            </button>
+        }
+    }
+
+    fn view_rooms(&self, ctx: &Context<Self>) -> Html {
+        let join = ctx.link().callback(|e: FocusEvent| {
+            e.prevent_default();
+            Msg::JoinRoom
+        });
+
+        html! {
+            <>
+                <h2 class="text-2xl font-semibold p-4 border-b border-gray-200 dark:border-gray-700">{ self.t("rooms.title") }</h2>
+                <ul class="divide-y divide-gray-200 dark:divide-gray-700">
+                    { for self.state.rooms.iter().map(|room| {
+                        let conversation = ConversationId::Room(room.clone());
+                        let unread = self
+                            .state.conversations
+                            .get(&conversation)
+                            .map(|c| c.unread)
+                            .unwrap_or_default();
+                        let selected = self.state.active == conversation;
+                        let draft = self.view_draft_marker(&conversation);
+                        let open = ctx
+                            .link()
+                            .callback(move |_| Msg::OpenConversation(conversation.clone()));
+                        let leave = {
+                            let room = room.clone();
+                            ctx.link().callback(move |_| Msg::LeaveRoom(room.clone()))
+                        };
+                        html! {
+                            <li class={classes!(
+                                "flex", "items-center", "pr-4", "hover:bg-gray-100", "dark:hover:bg-gray-700",
+                                selected.then(|| self.accent(ctx, "bg-blue-50 dark:bg-blue-900")),
+                            )}>
+                                <button
+                                    onclick={open}
+                                    class="flex flex-grow items-center pl-4 py-2 text-left rounded focus:outline-none focus:ring-2 focus:ring-inset focus:ring-blue-400"
+                                    aria-current={selected.then_some("true")}
+                                >
+                                    <span class="flex-grow font-medium">
+                                        { format!("#{}", room) }
+                                        { draft }
+                                    </span>
+                                    {
+                                        if unread > 0 {
+                                            html! {
+                                                <span class={self.accent(ctx, "px-2 py-0.5 rounded-full bg-blue-600 text-white text-xs")}>{ unread }</span>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
+                                </button>
+                                {
+                                    if room != DEFAULT_ROOM {
+                                        html! {
+                                            <button
+                                                onclick={leave}
+                                                class="ml-2 text-gray-400 hover:text-gray-700 rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-500 dark:hover:text-gray-200"
+                                                aria-label={self.state.i18n.t_with("rooms.leave", &[("room", room)])}
+                                            >
+                                                {"✕"}
+                                            </button>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                            </li>
+                        }
+                    })}
+                </ul>
+                <form onsubmit={join} class="p-3 border-b border-gray-200 dark:border-gray-700">
+                    <input
+                        ref={self.room_input.clone()}
+                        type="text"
+                        placeholder={self.t("rooms.join")}
+                        class="w-full px-3 py-1 rounded-md border border-gray-300 text-sm focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                    />
+                </form>
+            </>
+        }
+    }
+
+    /// Rooms and users: a column beside the conversation on wide screens,
+    /// optionally shrunk to a rail, and a drawer over it on narrow ones.
+    fn view_sidebar(&self, ctx: &Context<Self>) -> Html {
+        if ctx.props().sidebar_collapsed {
+            return html! {};
+        }
+        let drawer = self.overlay == Some(Overlay::Drawer);
+        // The drawer always shows everything; a rail is too narrow to use.
+        let rail = self.rail && !drawer;
+        let close = ctx.link().callback(|_| Msg::ToggleDrawer);
+
+        html! {
+            <>
+                {
+                    if drawer {
+                        html! { <div onclick={close} class="fixed inset-0 z-30 bg-black bg-opacity-40 md:hidden" aria-hidden="true"></div> }
+                    } else {
+                        html! {}
+                    }
+                }
+                <aside
+                    id="chat-sidebar"
+                    ref={if drawer { self.overlay_root.clone() } else { NodeRef::default() }}
+                    onkeydown={drawer.then(|| self.trap_focus())}
+                    role={drawer.then_some("dialog")}
+                    aria-modal={drawer.then_some("true")}
+                    aria-label={self.t("sidebar.label")}
+                    class={classes!(
+                        "fixed", "inset-y-0", "left-0", "z-40", "w-72", "max-w-full",
+                        "md:static", "md:z-auto", "md:visible", "md:translate-x-0",
+                        "flex-none", "bg-gray-50", "border-r", "border-gray-200", "overflow-y-auto",
+                        "transition-transform", "motion-reduce:transition-none",
+                        "dark:bg-gray-900", "dark:border-gray-700",
+                        if drawer { "translate-x-0" } else { "-translate-x-full invisible" },
+                        if rail { "md:w-16" } else { "md:w-[var(--sidebar-width)]" },
+                    )}
+                    style={format!("--sidebar-width: {}", ctx.props().sidebar_width)}
+                >
+                    <div class={classes!("hidden", "md:flex", "p-2", if rail { "justify-center" } else { "justify-end" })}>
+                        <button
+                            onclick={ctx.link().callback(|_| Msg::ToggleRail)}
+                            class="px-1 text-gray-400 hover:text-gray-700 rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-500 dark:hover:text-gray-200"
+                            aria-label={self.t(if rail { "sidebar.expand" } else { "sidebar.collapse" })}
+                            aria-expanded={(!rail).to_string()}
+                        >
+                            { if rail { "»" } else { "«" } }
+                        </button>
+                    </div>
+                    {
+                        if rail {
+                            self.view_rail(ctx)
+                        } else {
+                            html! {
+                                <>
+                                    { self.view_rooms(ctx) }
+                                    { if ctx.props().show_users { self.view_users(ctx) } else { html! {} } }
+                                </>
+                            }
+                        }
+                    }
+                </aside>
+            </>
+        }
+    }
+
+    /// The sidebar shrunk to a column of icons: a tile per room, then an
+    /// avatar per user, each opening its conversation.
+    fn view_rail(&self, ctx: &Context<Self>) -> Html {
+        let rooms = self.state.rooms.iter().map(|room| {
+            let conversation = ConversationId::Room(room.clone());
+            let label = format!("#{}", room);
+            let initial: String = room.chars().take(1).flat_map(char::to_uppercase).collect();
+            self.view_rail_entry(ctx, conversation, label, html! {
+                <span class="flex items-center justify-center w-10 h-10 rounded-md bg-gray-200 font-semibold dark:bg-gray-700">
+                    { initial }
+                </span>
+            })
+        });
+        let users = self
+            .state
+            .users
+            .iter()
+            .filter(|u| ctx.props().show_users && u.name != self.state.username)
+            .map(|u| {
+                let away = self.state.presence_of(&u.name) == Presence::Away;
+                let conversation = ConversationId::Direct(u.name.clone());
+                self.view_rail_entry(ctx, conversation, u.name.clone(), html! {
+                    <span class={classes!("relative", "block", u.departing.then_some("opacity-50"))}>
+                        <Avatar name={u.name.clone()} url={u.avatar.clone()} size={40} />
+                        <span
+                            class={classes!(
+                                "absolute", "bottom-0", "right-0", "w-3", "h-3", "rounded-full",
+                                "border-2", "border-gray-50", "dark:border-gray-900",
+                                if away { "bg-yellow-400" } else { "bg-green-500" },
+                            )}
+                            aria-hidden="true"
+                        />
+                    </span>
+                })
+            });
+
+        html! {
+            <ul class="flex flex-col items-center gap-2 pb-2">
+                { for rooms }
+                { for users }
+            </ul>
+        }
+    }
+
+    fn view_rail_entry(
+        &self,
+        ctx: &Context<Self>,
+        conversation: ConversationId,
+        label: String,
+        icon: Html,
+    ) -> Html {
+        let unread = self
+            .state
+            .conversations
+            .get(&conversation)
+            .map(|c| c.unread)
+            .unwrap_or_default();
+        let selected = self.state.active == conversation;
+        let onclick = ctx
+            .link()
+            .callback(move |_| Msg::OpenConversation(conversation.clone()));
+        let title = label.clone();
+
+        html! {
+            <li class="relative">
+                <button
+                    {onclick}
+                    class={classes!(
+                        "block", "rounded-md", "p-0.5", "focus:outline-none", "focus:ring-2", "focus:ring-blue-400",
+                        selected.then(|| self.accent(ctx, "ring-2 ring-blue-600")),
+                    )}
+                    {title}
+                    aria-label={label}
+                    aria-current={selected.then_some("true")}
+                >
+                    { icon }
+                </button>
+                {
+                    if unread > 0 {
+                        html! {
+                            <span class={self.accent(ctx, "absolute -top-1 -right-1 px-1.5 rounded-full bg-blue-600 text-white text-xs")}>{ unread }</span>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </li>
+        }
+    }
+
+    /// The user list, narrowed down by the search box under its heading.
+    fn view_users(&self, ctx: &Context<Self>) -> Html {
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::FilterUsers(input.value())
+        });
+        let onkeydown = ctx.link().batch_callback(|e: KeyboardEvent| {
+            (e.key() == "Escape").then(|| {
+                // Clearing the filter is all Escape should do here.
+                e.stop_propagation();
+                Msg::FilterUsers(String::new())
+            })
+        });
+        let users = filter_users(&self.state.users, self.user_filter.trim());
+
+        html! {
+            <>
+                <h2 class="text-2xl font-semibold p-4 border-b border-gray-200 dark:border-gray-700" title={self.peak_label()}>
+                    { self.state.i18n.t_with("users.title", &[("count", &self.online_label(false))]) }
+                </h2>
+                <div class="p-2 border-b border-gray-200 dark:border-gray-700">
+                    <input
+                        {oninput}
+                        {onkeydown}
+                        type="search"
+                        value={self.user_filter.clone()}
+                        placeholder={self.t("users.find")}
+                        aria-label={self.t("users.find")}
+                        class="w-full px-3 py-1 rounded-md border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                    />
+                </div>
+                {
+                    if users.is_empty() && !self.state.users.is_empty() {
+                        html! { <p class="p-4 text-sm text-gray-500 dark:text-gray-400">{ self.t("users.no_match") }</p> }
+                    } else {
+                        html! {
+                            <ul class="divide-y divide-gray-200 dark:divide-gray-700">
+                                { for users.into_iter().map(|(u, matched)| self.view_user(ctx, u, matched)) }
+                            </ul>
+                        }
+                    }
+                }
+            </>
+        }
+    }
+
+    /// Callback opening the profile card of `name` where the click landed,
+    /// or under the clicked element when it came from the keyboard. The
+    /// click goes no further, so rows it is inside don't also react.
+    fn show_user(&self, ctx: &Context<Self>, name: &str) -> Callback<MouseEvent> {
+        let name = name.to_string();
+        ctx.link().callback(move |e: MouseEvent| {
+            e.stop_propagation();
+            let (mut x, mut y) = (e.client_x(), e.client_y());
+            if e.detail() == 0 {
+                if let Some(target) = e.target_dyn_into::<web_sys::Element>() {
+                    let rect = target.get_bounding_client_rect();
+                    x = rect.left() as i32;
+                    y = rect.bottom() as i32;
+                }
+            }
+            Msg::ShowUser {
+                name: name.clone(),
+                x,
+                y,
+            }
+        })
+    }
+
+    /// The actions that apply to message `m`, as offered by its menu.
+    fn message_actions(&self, m: &ChatMessage) -> Vec<MessageAction> {
+        let own = self.state.is_own(m);
+        let can_reply = !m.id.is_empty() && m.status == SendStatus::Sent;
+        let mut actions = Vec::new();
+        if can_reply && !m.deleted {
+            actions.extend(QUICK_REACTIONS.iter().map(|&e| MessageAction::React(e)));
+        }
+        if can_reply {
+            actions.push(MessageAction::Reply);
+        }
+        if !m.deleted {
+            actions.push(MessageAction::Copy);
+        }
+        if can_reply && !m.deleted {
+            actions.push(MessageAction::TogglePin);
+        }
+        if !own {
+            actions.push(MessageAction::MentionAuthor);
+        }
+        if own && can_reply && !m.deleted && m.file.is_none() {
+            actions.extend([MessageAction::Edit, MessageAction::Delete]);
+        }
+        actions
+    }
+
+    fn view_message_menu(&self, ctx: &Context<Self>) -> Html {
+        let (id, x, y) = match &self.overlay {
+            Some(Overlay::MessageMenu { id, x, y }) => (id.clone(), *x, *y),
+            _ => return html! {},
+        };
+        let actions = match self.state.messages().iter().find(|m| m.id == id) {
+            Some(m) => self.message_actions(m),
+            None => return html! {},
+        };
+        let pinned = self.state.is_pinned(&id);
+        let on_action = {
+            let id = id.clone();
+            ctx.link()
+                .callback(move |action| Msg::MessageAction(id.clone(), action))
+        };
+
+        html! {
+            <MessageMenu
+                key={id}
+                {actions}
+                {pinned}
+                {x}
+                {y}
+                {on_action}
+                on_close={ctx.link().callback(|_| Msg::CloseMessageMenu)}
+            />
+        }
+    }
+
+    /// The images in the open conversation that can be shown, oldest first,
+    /// for stepping through in the lightbox.
+    fn lightbox_images(&self) -> Vec<(&ChatMessage, String)> {
+        image_messages(self.state.messages())
+            .filter_map(|(m, url)| safe_image_url(url).map(|url| (m, url)))
+            .filter(|(_, url)| !self.broken_images.contains(url))
+            .collect()
+    }
+
+    fn view_lightbox(&self, ctx: &Context<Self>) -> Html {
+        let id = match &self.overlay {
+            Some(Overlay::Lightbox(id)) => id,
+            _ => return html! {},
+        };
+        let images = self.lightbox_images();
+        let index = match images.iter().position(|(m, _)| &m.id == id) {
+            Some(index) => index,
+            None => return html! {},
+        };
+        let (m, url) = &images[index];
+        let close = ctx.link().callback(|_| Msg::Shortcut(Shortcut::Dismiss));
+        let onbackdrop = ctx.link().batch_callback(|e: MouseEvent| {
+            // Clicks on the image and controls bubble up here too.
+            (e.target() == e.current_target()).then_some(Msg::Shortcut(Shortcut::Dismiss))
+        });
+        let onkeydown = {
+            let root = self.overlay_root.clone();
+            let link = ctx.link().clone();
+            Callback::from(move |e: KeyboardEvent| {
+                let direction = match e.key().as_str() {
+                    "ArrowLeft" => Direction::Up,
+                    "ArrowRight" => Direction::Down,
+                    _ => {
+                        if let Some(root) = root.cast::<web_sys::Element>() {
+                            a11y::trap_focus(&root, &e);
+                        }
+                        return;
+                    }
+                };
+                e.prevent_default();
+                link.send_message(Msg::StepLightbox(direction));
+            })
+        };
+        let step = |direction: Direction, enabled: bool, label: &str, arrow: &'static str| {
+            html! {
+                <button
+                    onclick={ctx.link().callback(move |_| Msg::StepLightbox(direction))}
+                    disabled={!enabled}
+                    class="p-3 rounded-full text-2xl text-white bg-black bg-opacity-40 hover:bg-opacity-60 disabled:opacity-30 focus:outline-none focus:ring-2 focus:ring-white"
+                    aria-label={self.t(label)}
+                >
+                    { arrow }
+                </button>
+            }
+        };
+        let pasted = is_image_data_url(url);
+
+        html! {
+            <div
+                onclick={onbackdrop}
+                class="fixed inset-0 z-40 flex items-center justify-between gap-2 p-2 bg-black bg-opacity-80 md:p-6"
+            >
+                <div
+                    ref={self.overlay_root.clone()}
+                    {onkeydown}
+                    class="contents"
+                    role="dialog"
+                    aria-modal="true"
+                    aria-label={self.t("lightbox.label")}
+                >
+                    { step(Direction::Up, index > 0, "lightbox.previous", "‹") }
+                    <figure class="flex flex-col items-center min-w-0 max-h-full">
+                        <img
+                            class="max-w-full object-contain rounded-md"
+                            style="max-height: calc(100dvh - 8rem)"
+                            src={url.clone()}
+                            alt={self.t("message.image")}
+                        />
+                        <figcaption class="mt-2 flex flex-wrap items-center justify-center gap-x-3 text-sm text-gray-200">
+                            <span class="font-semibold">{ &m.from }</span>
+                            <time>{ format_full(m.timestamp) }</time>
+                            <span class="tabular-nums text-gray-400">
+                                {
+                                    self.state.i18n.t_with(
+                                        "lightbox.position",
+                                        &[("current", &(index + 1)), ("total", &images.len())],
+                                    )
+                                }
+                            </span>
+                            <a
+                                href={url.clone()}
+                                target="_blank"
+                                rel="noopener noreferrer"
+                                download={pasted.then_some("")}
+                                class="underline hover:no-underline focus:outline-none focus:ring-2 focus:ring-white rounded"
+                            >
+                                { self.t(if pasted { "lightbox.download" } else { "lightbox.open_original" }) }
+                            </a>
+                            <button
+                                onclick={close}
+                                class="underline hover:no-underline focus:outline-none focus:ring-2 focus:ring-white rounded"
+                            >
+                                { self.t("lightbox.close") }
+                            </button>
+                        </figcaption>
+                    </figure>
+                    { step(Direction::Down, index + 1 < images.len(), "lightbox.next", "›") }
+                </div>
+            </div>
+        }
+    }
+
+    fn view_user_popover(&self, ctx: &Context<Self>) -> Html {
+        let (name, x, y) = match &self.overlay {
+            Some(Overlay::UserPopover { name, x, y }) => (name.clone(), *x, *y),
+            _ => return html! {},
+        };
+        let avatar = self
+            .state
+            .users
+            .iter()
+            .find(|u| u.name == name)
+            .map(|u| u.avatar.clone())
+            .unwrap_or_default();
+        let online_since = self.state.first_seen.get(&name).copied();
+        let is_self = name == self.state.username;
+        let muted = self.state.is_muted(&name);
+        let on_action = {
+            let name = name.clone();
+            ctx.link()
+                .callback(move |action| Msg::UserAction(name.clone(), action))
+        };
+
+        html! {
+            <UserPopover
+                key={name.clone()}
+                {online_since}
+                {is_self}
+                {muted}
+                name={name.clone()}
+                {avatar}
+                {x}
+                {y}
+                {on_action}
+                on_close={ctx.link().callback(|_| Msg::CloseUserPopover)}
+            />
+        }
+    }
+
+    /// One entry of the user list, with the part of the name matching the
+    /// search box highlighted.
+    fn view_user(&self, ctx: &Context<Self>, u: &UserProfile, matched: Range<usize>) -> Html {
+        let conversation = ConversationId::Direct(u.name.clone());
+        let unread = self
+            .state
+            .conversations
+            .get(&conversation)
+            .map(|c| c.unread)
+            .unwrap_or_default();
+        let selected = self.state.active == conversation;
+        let me = u.name == self.state.username;
+        let away = self.state.presence_of(&u.name) == Presence::Away;
+        let draft = self.view_draft_marker(&conversation);
+        let onclick = ctx
+            .link()
+            .callback(move |_| Msg::OpenConversation(conversation.clone()));
+
+        html! {
+            <li class={classes!(
+                "flex", "items-center", "p-3",
+                if me { "bg-gray-100 dark:bg-gray-700" } else { "hover:bg-gray-100 dark:hover:bg-gray-700" },
+                selected.then(|| self.accent(ctx, "bg-blue-50 dark:bg-blue-900")),
+                u.departing.then_some("opacity-50 transition-opacity duration-700 motion-reduce:transition-none"),
+            )}>
+                <button
+                    onclick={self.show_user(ctx, &u.name)}
+                    class="mr-4 rounded-full focus:outline-none focus:ring-2 focus:ring-blue-400"
+                    aria-label={self.state.i18n.t_with("popover.label", &[("name", &u.name)])}
+                >
+                    <div class={classes!(away.then_some("opacity-60"))}>
+                        <Avatar name={u.name.clone()} url={u.avatar.clone()} size={48} />
+                    </div>
+                </button>
+                <button
+                    {onclick}
+                    disabled={me}
+                    class="flex flex-grow items-center text-left rounded focus:outline-none focus:ring-2 focus:ring-blue-400 disabled:cursor-default"
+                    aria-current={selected.then_some("true")}
+                >
+                <div class="flex flex-col flex-grow">
+                    <span class="font-medium">
+                        { &u.name[..matched.start] }
+                        {
+                            if matched.is_empty() {
+                                html! {}
+                            } else {
+                                html! { <mark class="bg-yellow-200 rounded-sm dark:bg-yellow-700">{ &u.name[matched.clone()] }</mark> }
+                            }
+                        }
+                        { &u.name[matched.end..] }
+                        { if me { html! { <>{" "}{ self.t("users.you") }</> } } else { html! {} } }
+                        { draft }
+                        {
+                            if self.state.is_muted(&u.name) {
+                                html! {
+                                    <span class="ml-1 text-gray-400" title={self.t("users.muted")} aria-label={self.t("users.muted")}>{"🔇"}</span>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                    </span>
+                    <span class="flex items-center text-xs text-gray-500 dark:text-gray-400">
+                        <span
+                            class={classes!("inline-block", "w-2", "h-2", "mr-1", "rounded-full", if away { "bg-yellow-400" } else { "bg-green-500" })}
+                            aria-hidden="true"
+                        />
+                        { self.t(if away { "users.away" } else { "users.online" }) }
+                    </span>
+                </div>
+                {
+                    if unread > 0 {
+                        html! {
+                            <span class={self.accent(ctx, "px-2 py-0.5 rounded-full bg-blue-600 text-white text-xs")}>{ unread }</span>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                </button>
+            </li>
+        }
+    }
+
+    /// Header title: the room name, or the peer of the open direct
+    /// conversation with a way back to the room.
+    fn view_title(&self, ctx: &Context<Self>) -> Html {
+        match &self.state.active {
+            ConversationId::Room(room) => html! {
+                <h1 class="text-xl font-semibold">
+                    { ctx.props().title.clone().unwrap_or_else(|| format!("💬 #{}", room)) }
+                </h1>
+            },
+            ConversationId::Direct(peer) => {
+                let back = ctx.link().callback(|_| {
+                    Msg::OpenConversation(ConversationId::Room(DEFAULT_ROOM.to_string()))
+                });
+                html! {
+                    <>
+                        <button
+                            onclick={back}
+                            class="text-gray-500 hover:text-gray-800 rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                            aria-label={self.t("chat.back_to_room")}
+                        >
+                            {"←"}
+                        </button>
+                        <h1 class="text-xl font-semibold">{ self.state.i18n.t_with("chat.direct_title", &[("name", peer)]) }</h1>
+                    </>
+                }
+            }
+        }
+    }
+
+    /// The current locale's text for `key`.
+    fn t(&self, key: &str) -> String {
+        self.state.i18n.t(key)
+    }
+
+    /// How many users are online: "12", or "12 online" when `long`. While
+    /// disconnected the last list is stale, so no number is shown.
+    fn online_label(&self, long: bool) -> String {
+        match (self.state.conn_state == ConnState::Open, long) {
+            (true, true) => {
+                self.state
+                    .i18n
+                    .plural("users.online_count", self.state.online_count(), &[])
+            }
+            (true, false) => self.state.online_count().to_string(),
+            (false, true) => self.t("users.offline"),
+            (false, false) => "–".to_string(),
+        }
+    }
+
+    fn peak_label(&self) -> String {
+        self.state
+            .i18n
+            .t_with("users.peak", &[("count", &self.state.peak_users)])
+    }
+
+    fn view_connection_state(&self) -> Html {
+        let (dot, label) = match self.state.conn_state {
+            ConnState::Connecting => ("bg-yellow-400", "connection.connecting"),
+            ConnState::Open => ("bg-green-500", "connection.open"),
+            ConnState::Reconnecting => ("bg-yellow-400", "connection.reconnecting"),
+            ConnState::Closed => ("bg-red-500", "connection.closed"),
+        };
+
+        html! {
+            <span class="flex items-center text-xs text-gray-500 dark:text-gray-400">
+                <span class={classes!("inline-block", "w-2", "h-2", "mr-1", "rounded-full", dot)}></span>
+                { self.t(label) }
+            </span>
+        }
+    }
+
+    fn view_typing(&self) -> Html {
+        let mut names: Vec<&str> = self.state.typing.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        match typing_label(&self.state.i18n, &names) {
+            Some(label) => html! {
+                <div class="px-4 py-1 text-xs italic text-gray-500 bg-gray-50 dark:text-gray-400 dark:bg-gray-900">{ label }</div>
+            },
+            None => html! {},
+        }
+    }
+
+    fn view_reply_preview(&self, ctx: &Context<Self>) -> Html {
+        if self.state.editing.is_some() {
+            let cancel = ctx.link().callback(|_| Msg::CancelEdit);
+            return html! {
+                <div class="flex items-center justify-between px-4 py-2 border-t border-gray-200 bg-gray-50 text-xs text-gray-600 dark:border-gray-700 dark:bg-gray-900 dark:text-gray-300">
+                    <span>{ self.t("compose.editing") }</span>
+                    <button onclick={cancel} class="ml-2 text-gray-400 hover:text-gray-700 dark:text-gray-500 dark:hover:text-gray-200" aria-label={self.t("compose.cancel_edit")}>
+                        {"✕"}
+                    </button>
+                </div>
+            };
+        }
+        let original = match &self.state.replying_to {
+            Some(id) => self.state.messages().iter().find(|m| &m.id == id),
+            None => return html! {},
+        };
+        let cancel = ctx.link().callback(|_| Msg::CancelReply);
+        let text = match original {
+            Some(m) => self.state.i18n.t_with(
+                "compose.replying_to",
+                &[
+                    ("name", &m.from),
+                    ("text", &excerpt(&m.message, QUOTE_EXCERPT_CHARS)),
+                ],
+            ),
+            None => self.t("compose.replying"),
+        };
+
+        html! {
+            <div class="flex items-center justify-between px-4 py-2 border-t border-gray-200 bg-gray-50 text-xs text-gray-600 dark:border-gray-700 dark:bg-gray-900 dark:text-gray-300">
+                <span class="truncate">{ text }</span>
+                <button onclick={cancel} class="ml-2 text-gray-400 hover:text-gray-700 dark:text-gray-500 dark:hover:text-gray-200" aria-label={self.t("compose.cancel_reply")}>
+                    {"✕"}
+                </button>
+            </div>
+        }
+    }
+
+    /// Compact block quoting the message `id` refers to. If we don't have it
+    /// the excerpt sent along with the reply is shown instead.
+    fn view_quote(&self, ctx: &Context<Self>, id: &str, fallback: Option<&str>) -> Html {
+        match self.state.messages().iter().find(|m| m.id == id) {
+            Some(original) => {
+                let id = id.to_string();
+                let jump = ctx.link().callback(move |_| Msg::JumpToMessage(id.clone()));
+                html! {
+                    <div
+                        onclick={jump}
+                        class="mt-1 pl-2 border-l-4 border-gray-300 text-xs text-gray-500 cursor-pointer hover:text-gray-700 dark:border-gray-600 dark:text-gray-400 dark:hover:text-gray-200"
+                    >
+                        <span class="font-semibold">{ &original.from }</span>
+                        {": "}
+                        { self.censor(&excerpt(original.display_text(), QUOTE_EXCERPT_CHARS)).into_owned() }
+                    </div>
+                }
+            }
+            None => html! {
+                <div class="mt-1 pl-2 border-l-4 border-gray-200 text-xs italic text-gray-400 dark:border-gray-700 dark:text-gray-500">
+                    { fallback.map_or_else(|| self.t("message.unavailable"), |text| self.censor(text).into_owned()) }
+                </div>
+            },
+        }
+    }
+
+    /// Counters under a message, one per emoji. Clicking one toggles our own
+    /// reaction.
+    fn view_reactions(&self, ctx: &Context<Self>, id: &str) -> Html {
+        let by_emoji = match self.state.reactions.get(id) {
+            Some(by_emoji) if !by_emoji.is_empty() => by_emoji,
+            _ => return html! {},
+        };
+        let mut pills: Vec<(&String, &Vec<String>)> = by_emoji.iter().collect();
+        pills.sort();
+
+        html! {
+            <div class="mt-1 flex flex-wrap gap-1">
+                { for pills.into_iter().map(|(emoji, users)| {
+                    let mine = users.contains(&self.state.username);
+                    let onclick = {
+                        let id = id.to_string();
+                        let emoji = emoji.clone();
+                        ctx.link().callback(move |_| Msg::React(id.clone(), emoji.clone()))
+                    };
+                    html! {
+                        <button
+                            {onclick}
+                            title={users.join(", ")}
+                            aria-label={self.state.i18n.t_with("message.reacted", &[("emoji", emoji), ("names", &users.join(", "))])}
+                            aria-pressed={mine.to_string()}
+                            class={classes!(
+                                "px-2", "py-0.5", "rounded-full", "border", "text-xs",
+                                if mine { "border-blue-400 bg-blue-50 dark:bg-blue-900" } else { "border-gray-200 bg-white dark:border-gray-700 dark:bg-gray-800" },
+                            )}
+                        >
+                            { format!("{} {}", emoji, users.len()) }
+                        </button>
+                    }
+                })}
+            </div>
+        }
+    }
+
+    /// Message text: fenced code as code blocks, and the prose around it
+    /// with its code spans set apart and its links made clickable.
+    fn view_text(&self, message: &str) -> Html {
+        let message = self.censor(message);
+        let message = message.as_ref();
+        let matches = self.search_matches(message);
+        self.view_blocks(message, &blocks(message), &matches)
+    }
+
+    /// Where the open search matches `message`, as shown.
+    fn search_matches(&self, message: &str) -> Vec<Range<usize>> {
+        match &self.search {
+            Some(search) => match_ranges(message, search.query.trim()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Message `id`'s text, only its head if it is long, with a toggle
+    /// showing the rest. Messages matching the search are shown whole.
+    fn view_folded(&self, ctx: &Context<Self>, id: &str, message: &str) -> Html {
+        let message = self.censor(message);
+        let message = message.as_ref();
+        let matches = self.search_matches(message);
+        let blocks = blocks(message);
+        let head = match fold(&blocks) {
+            Some(head) if matches.is_empty() => head,
+            _ => return self.view_blocks(message, &blocks, &matches),
+        };
+        let unfolded = self.unfolded.contains(id);
+        let label = if unfolded {
+            self.t("message.show_less")
+        } else {
+            let count = short_count(message.chars().count());
+            self.state
+                .i18n
+                .t_with("message.show_more", &[("count", &count)])
+        };
+        let toggle = {
+            let id = id.to_string();
+            ctx.link().callback(move |_| Msg::ToggleFold(id.clone()))
+        };
+
+        html! {
+            <>
+                {
+                    if unfolded {
+                        self.view_blocks(message, &blocks, &matches)
+                    } else {
+                        html! {
+                            // Fades out whatever the bubble's background is.
+                            <div style="-webkit-mask-image: linear-gradient(to bottom, black 60%, transparent); mask-image: linear-gradient(to bottom, black 60%, transparent)">
+                                { self.view_blocks(message, &head, &matches) }
+                            </div>
+                        }
+                    }
+                }
+                <button
+                    onclick={toggle}
+                    class={self.accent(ctx, "mt-1 text-xs font-medium text-blue-600 hover:underline focus:outline-none focus:ring-2 focus:ring-blue-400 rounded dark:text-blue-400")}
+                    aria-expanded={unfolded.to_string()}
+                >
+                    { label }
+                </button>
+            </>
+        }
+    }
+
+    /// `blocks` of `message` as elements, with the search `matches` in
+    /// `message` marked.
+    fn view_blocks(&self, message: &str, blocks: &[Block], matches: &[Range<usize>]) -> Html {
+        html! {
+            <>
+            { for blocks.iter().map(|&block| match block {
+                Block::Text(text) => html! {
+                    <p>
+                        { for inline_spans(text).into_iter().map(|span| match span {
+                            Inline::Text(text) => self.view_prose(message, text, matches),
+                            Inline::Code(code) => html! {
+                                <code class="px-1 rounded bg-gray-100 font-mono text-xs dark:bg-gray-700">
+                                    { view_matches(code, offset_in(message, code), matches, false) }
+                                </code>
+                            },
+                        }) }
+                    </p>
+                },
+                Block::Code { lang, code } => html! {
+                    <CodeBlock lang={lang.map(str::to_string)} code={code.to_string()} />
+                },
+            }) }
+            </>
+        }
+    }
+
+    /// `text`, a slice of `message`, with its links made clickable and the
+    /// search `matches` in `message` marked.
+    fn view_prose(&self, message: &str, text: &str, matches: &[Range<usize>]) -> Html {
+        html! {
+            <>
+                { for tokenize(text).into_iter().map(|token| {
+                    let piece = match token {
+                        Token::Text(text) | Token::Link(text) => text,
+                    };
+                    let content = view_matches(piece, offset_in(message, piece), matches, true);
+                    match token {
+                        Token::Text(_) => content,
+                        Token::Link(url) => html! {
+                            <a
+                                href={url.to_string()}
+                                target="_blank"
+                                rel="noopener noreferrer"
+                                class="text-blue-600 hover:underline break-all dark:text-blue-400"
+                            >
+                                { content }
+                            </a>
+                        },
+                    }
+                }) }
+            </>
+        }
+    }
+
+    /// `text` as shown, with rude words masked if the filter is on. What is
+    /// stored, copied and exported is left as sent.
+    fn censor<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.word_filter() {
+            Some(filter) => filter.censor(text),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// The word filter, if it is on.
+    fn word_filter(&self) -> Option<&WordFilter> {
+        self.state.filter_enabled.then_some(&self.state.word_filter)
+    }
+
+    /// The pasted or dropped image about to be sent, with what else is
+    /// queued, files on their way, or why the last one can't be sent.
+    fn view_attachments(&self, ctx: &Context<Self>) -> Html {
+        let error = self.attach_error.clone();
+        let preparing = self.preparing_image || self.preparing_files > 0;
+        if error.is_none() && self.image_preview.is_none() && !preparing {
+            return html! {};
+        }
+        let queued = self.image_queue.len();
+        let button =
+            "px-3 py-1 rounded-md text-xs focus:outline-none focus:ring-2 focus:ring-blue-400";
+
+        html! {
+            <div class="flex items-center gap-3 px-4 py-2 border-t border-gray-200 bg-gray-50 text-xs text-gray-600 dark:border-gray-700 dark:bg-gray-900 dark:text-gray-300">
+                {
+                    match &self.image_preview {
+                        Some(url) => html! {
+                            <img class="h-16 rounded-md" src={url.clone()} alt={self.t("image.preview")} />
+                        },
+                        None if self.preparing_image => html! { <span aria-live="polite">{ self.t("image.preparing") }</span> },
+                        None if self.preparing_files > 0 => html! {
+                            <span aria-live="polite">{ self.state.i18n.plural("file.sending", self.preparing_files, &[]) }</span>
+                        },
+                        None => html! {},
+                    }
+                }
+                <div class="flex flex-col flex-grow">
+                    {
+                        if let Some(error) = error {
+                            html! { <span class="text-red-600 dark:text-red-400" role="alert">{ error }</span> }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    {
+                        if queued > 0 {
+                            html! { <span>{ self.state.i18n.plural("image.queued", queued, &[]) }</span> }
+                        } else {
+                            html! {}
+                        }
+                    }
+                </div>
+                {
+                    if self.image_preview.is_some() {
+                        html! {
+                            <button
+                                onclick={ctx.link().callback(|_| Msg::SendImage)}
+                                disabled={self.wss.is_none()}
+                                class={self.accent(ctx, &format!("{} bg-blue-600 hover:bg-blue-700 text-white disabled:opacity-50", button))}
+                            >
+                                { self.t("image.send") }
+                            </button>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                <button
+                    onclick={ctx.link().callback(|_| Msg::CancelImage)}
+                    class={classes!("text-gray-400", "hover:text-gray-700", "dark:text-gray-500", "dark:hover:text-gray-200", button)}
+                    aria-label={self.t("image.cancel")}
+                >
+                    {"✕"}
+                </button>
+            </div>
+        }
+    }
+
+    /// Strip under the header listing what is pinned in this conversation.
+    /// A pin jumps to its message while that is still in memory.
+    fn view_pins(&self, ctx: &Context<Self>) -> Html {
+        let pins: Vec<&Pin> = self
+            .state
+            .pins
+            .iter()
+            .filter(|pin| pin.conversation == self.state.active)
+            .collect();
+        if pins.is_empty() {
+            return html! {};
+        }
+        let summary = self.state.i18n.plural("pins.summary", pins.len(), &[]);
+        let button = "rounded focus:outline-none focus:ring-2 focus:ring-blue-400";
+
+        html! {
+            <div class="px-4 py-1 border-b border-gray-200 bg-amber-50 text-sm dark:border-gray-700 dark:bg-gray-900">
+                <button
+                    onclick={ctx.link().callback(|_| Msg::TogglePins)}
+                    class={classes!("text-xs", "font-medium", "text-gray-600", "hover:text-gray-900", "dark:text-gray-300", "dark:hover:text-gray-100", button)}
+                    aria-expanded={(!self.pins_collapsed).to_string()}
+                >
+                    { if self.pins_collapsed { "▸ " } else { "▾ " } }{ summary }
+                </button>
+                {
+                    if self.pins_collapsed {
+                        html! {}
+                    } else {
+                        html! {
+                            <ul class="mt-1 space-y-1">
+                                { for pins.into_iter().map(|pin| {
+                                    let loaded = self.state.messages().iter().any(|m| m.id == pin.id);
+                                    let id = pin.id.clone();
+                                    let jump = ctx.link().batch_callback(move |_| {
+                                        loaded.then(|| Msg::JumpToMessage(id.clone()))
+                                    });
+                                    let id = pin.id.clone();
+                                    let unpin = ctx.link().callback(move |_| Msg::TogglePin(id.clone()));
+                                    html! {
+                                        <li class="flex items-center gap-2">
+                                            <button
+                                                onclick={jump}
+                                                disabled={!loaded}
+                                                title={(!loaded).then(|| self.t("pins.not_loaded"))}
+                                                class={classes!("flex-grow", "text-left", "truncate", "hover:underline", "disabled:no-underline", "disabled:opacity-60", button)}
+                                            >
+                                                <span class="font-semibold">{ &pin.from }</span>{": "}{ self.censor(&pin.excerpt).into_owned() }
+                                            </button>
+                                            <button
+                                                onclick={unpin}
+                                                class={classes!("px-1", "text-gray-400", "hover:text-red-600", "dark:text-gray-500", button)}
+                                                aria-label={self.t("pins.unpin")}
+                                            >
+                                                {"✕"}
+                                            </button>
+                                        </li>
+                                    }
+                                }) }
+                            </ul>
+                        }
+                    }
+                }
+            </div>
+        }
+    }
+
+    /// Search bar under the header while searching the transcript.
+    fn view_search(&self, ctx: &Context<Self>) -> Html {
+        let search = match &self.search {
+            Some(search) => search,
+            None => return html! {},
+        };
+        let messages = self.state.messages();
+        let matches = matching_messages(messages, search.query.trim(), self.word_filter());
+        let position = matches
+            .iter()
+            .position(|&i| search.current.as_ref() == Some(&messages[i].id));
+        let counter = match position {
+            Some(p) => self.state.i18n.t_with(
+                "search.counter",
+                &[("current", &(p + 1)), ("total", &matches.len())],
+            ),
+            None if search.query.trim().is_empty() => String::new(),
+            None => self.t("search.no_matches"),
+        };
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::Search(input.value())
+        });
+        let onkeydown = ctx.link().batch_callback(|e: KeyboardEvent| {
+            let msg = match e.key().as_str() {
+                "Enter" if e.shift_key() => Msg::SearchStep(Direction::Down),
+                "Enter" | "ArrowUp" => Msg::SearchStep(Direction::Up),
+                "ArrowDown" => Msg::SearchStep(Direction::Down),
+                // Closing the search is all Escape should do here.
+                "Escape" => {
+                    e.stop_propagation();
+                    Msg::CloseSearch
+                }
+                _ => return None,
+            };
+            e.prevent_default();
+            Some(msg)
+        });
+        let step = |direction| ctx.link().callback(move |_| Msg::SearchStep(direction));
+        let button = "px-2 text-gray-500 hover:text-gray-800 rounded focus:outline-none focus:ring-2 focus:ring-blue-400 disabled:opacity-50 dark:text-gray-400 dark:hover:text-gray-100";
+
+        html! {
+            <div class="flex items-center gap-2 px-4 py-2 border-b border-gray-200 bg-gray-50 dark:border-gray-700 dark:bg-gray-900" role="search">
+                <input
+                    ref={self.search_input.clone()}
+                    {oninput}
+                    {onkeydown}
+                    type="search"
+                    value={search.query.clone()}
+                    placeholder={self.t("search.placeholder")}
+                    aria-label={self.t("search.placeholder")}
+                    class="flex-grow px-3 py-1 rounded-md border border-gray-300 text-sm focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                />
+                <span class="text-xs text-gray-500 tabular-nums dark:text-gray-400" aria-live="polite">{ counter }</span>
+                <button onclick={step(Direction::Up)} disabled={matches.is_empty()} class={button} aria-label={self.t("search.previous")}>
+                    {"↑"}
+                </button>
+                <button onclick={step(Direction::Down)} disabled={matches.is_empty()} class={button} aria-label={self.t("search.next")}>
+                    {"↓"}
+                </button>
+                <button onclick={ctx.link().callback(|_| Msg::CloseSearch)} class={button} aria-label={self.t("search.close")}>
+                    {"✕"}
+                </button>
+            </div>
+        }
+    }
+
+    #[cfg(feature = "frame-log")]
+    fn view_frame_log(&self, ctx: &Context<Self>) -> Html {
+        if !self.frame_log_open {
+            return html! {};
+        }
+        html! {
+            <FrameLogPanel on_close={ctx.link().callback(|_| Msg::Shortcut(Shortcut::ToggleFrameLog))} />
+        }
+    }
+
+    #[cfg(not(feature = "frame-log"))]
+    fn view_frame_log(&self, _ctx: &Context<Self>) -> Html {
+        html! {}
+    }
+
+    /// Message text, or the image it links to. Images that fail to load
+    /// fall back to the text.
+    fn view_body(&self, ctx: &Context<Self>, id: &str, message: &str) -> Html {
+        let url = match image_url(message).and_then(safe_image_url) {
+            Some(url) if !self.broken_images.contains(&url) => url,
+            _ => return self.view_folded(ctx, id, message),
+        };
+        let onerror = {
+            let url = url.clone();
+            ctx.link()
+                .callback(move |_: web_sys::Event| Msg::ImageFailed(url.clone()))
+        };
+        let id = id.to_string();
+
+        html! {
+            <div>
+                {
+                    // Pasted images have no address worth showing.
+                    if is_image_data_url(&url) {
+                        html! {}
+                    } else {
+                        html! {
+                            <a
+                                href={url.clone()}
+                                target="_blank"
+                                rel="noopener noreferrer"
+                                class="text-xs text-blue-600 hover:underline break-all dark:text-blue-400"
+                            >
+                                { &url }
+                            </a>
+                        }
+                    }
+                }
+                <button
+                    onclick={ctx.link().callback(move |_| Msg::OpenLightbox(id.clone()))}
+                    class="block mt-1 rounded-md focus:outline-none focus:ring-2 focus:ring-blue-400 cursor-zoom-in"
+                    aria-label={self.t("lightbox.open")}
+                >
+                    <img {onerror} class="rounded-md max-w-xs" src={url.clone()} alt={self.t("message.image")} />
+                </button>
+            </div>
+        }
+    }
+
+    /// Card for a file message: what it is, how big, and a button saving
+    /// it. Files whose contents didn't come through, or came through over
+    /// the inline limit, say so instead.
+    fn view_file(&self, ctx: &Context<Self>, id: &str, file: &FileData) -> Html {
+        let limit = ctx.props().inline_file_limit;
+        let available = file.url.as_deref().and_then(safe_web_url).is_some()
+            || decode_file(file, limit).is_some();
+        let name = if file.name.is_empty() {
+            self.t("file.unknown")
+        } else {
+            file.name.clone()
+        };
+        let id = id.to_string();
+
+        html! {
+            <div class="mt-1 flex items-center gap-3 max-w-xs p-3 rounded-md border border-gray-200 bg-gray-50 dark:border-gray-600 dark:bg-gray-700">
+                <span class="text-2xl" aria-hidden="true">{ icon_for(&file.mime) }</span>
+                <div class="flex flex-col flex-grow min-w-0">
+                    <span class="text-sm font-medium truncate" title={name.clone()}>{ name }</span>
+                    <span class="text-xs text-gray-500 dark:text-gray-400">
+                        {
+                            if available {
+                                format_size(file.size)
+                            } else {
+                                self.t("file.unavailable")
+                            }
+                        }
+                    </span>
+                </div>
+                {
+                    if available {
+                        html! {
+                            <button
+                                onclick={ctx.link().callback(move |_| Msg::DownloadFile(id.clone()))}
+                                class={self.accent(ctx, "px-3 py-1 rounded-md text-xs bg-blue-600 hover:bg-blue-700 text-white focus:outline-none focus:ring-2 focus:ring-blue-400")}
+                            >
+                                { self.t("file.download") }
+                            </button>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </div>
+        }
+    }
+
+    /// Check marks next to our own delivered messages: one when the server
+    /// has it, two once another client does, blue once someone has seen it.
+    fn view_receipt(&self, m: &ChatMessage) -> Html {
+        if !self.state.is_own(m) || m.status != SendStatus::Sent {
+            return html! {};
+        }
+        let receipts = self.state.receipts.get(&m.id);
+        let level = receipts.map_or(ReceiptLevel::Sent, Receipts::level);
+        let (marks, class, label) = match level {
+            ReceiptLevel::Sent => (
+                "✓",
+                "text-gray-400 dark:text-gray-500",
+                self.t("receipt.sent"),
+            ),
+            ReceiptLevel::Delivered => (
+                "✓✓",
+                "text-gray-400 dark:text-gray-500",
+                self.t("receipt.delivered"),
+            ),
+            ReceiptLevel::Read => (
+                "✓✓",
+                "text-blue-500",
+                self.state.i18n.t_with(
+                    "receipt.read",
+                    &[("names", &receipts.unwrap().readers().join(", "))],
+                ),
+            ),
+        };
+        html! {
+            <span class={classes!("text-xs", class)} title={label}>
+                { marks }
+            </span>
+        }
+    }
+
+    fn view_message(&self, ctx: &Context<Self>, m: &ChatMessage) -> Html {
+        if m.system {
+            return html! {
+                <div class="text-center text-xs text-gray-400 whitespace-pre-line dark:text-gray-500">{ &m.message }</div>
+            };
+        }
+        if self.state.is_collapsed(m) {
+            let id = m.id.clone();
+            return html! {
+                <div class="text-xs text-gray-400 dark:text-gray-500" data-message-id={m.id.clone()}>
+                    { self.state.i18n.t_with("message.muted", &[("name", &m.from)]) }{" — "}
+                    <button
+                        onclick={ctx.link().callback(move |_| Msg::ShowMuted(id.clone()))}
+                        class="hover:underline rounded focus:outline-none focus:ring-2 focus:ring-blue-400"
+                    >
+                        { self.t("message.show_muted") }
+                    </button>
+                </div>
+            };
+        }
+        let own = self.state.is_own(m);
+        let user = self.state.users.iter().find(|u| u.name == m.from);
+        let sent_class = if own {
+            self.accent(
+                ctx,
+                "mt-1 px-3 py-1.5 rounded-lg bg-blue-100 text-gray-800 text-sm max-w-prose break-words dark:bg-blue-900 dark:text-gray-100",
+            )
+        } else {
+            "mt-1 text-gray-700 text-sm max-w-prose break-words dark:text-gray-200".to_string()
+        };
+        let body_class = match m.status {
+            SendStatus::Failed => Classes::from(
+                "mt-1 px-2 py-1 rounded-md bg-red-100 text-red-700 text-sm max-w-prose break-words dark:bg-red-900 dark:text-red-300",
+            ),
+            SendStatus::Queued | SendStatus::Pending => classes!(sent_class, "opacity-60"),
+            SendStatus::Sent => classes!(sent_class),
+        };
+
+        let can_reply = !m.id.is_empty() && m.status == SendStatus::Sent;
+        let reply = {
+            let id = m.id.clone();
+            ctx.link().callback(move |_| Msg::ReplyTo(id.clone()))
+        };
+        let onmenu = (!m.id.is_empty()).then(|| {
+            let id = m.id.clone();
+            ctx.link().callback(move |(x, y)| Msg::ShowMessageMenu {
+                id: id.clone(),
+                x,
+                y,
+            })
+        });
+        let meta = html! {
+            <>
+                <span class="text-xs text-gray-400 dark:text-gray-500" title={format_full(m.timestamp)}>
+                    { format_timestamp(&self.state.i18n, m.timestamp, time::now()) }
+                </span>
+                { self.view_receipt(m) }
+                {
+                    if m.original_text.is_some() && !m.deleted {
+                        html! { <span class="text-xs text-gray-400 dark:text-gray-500">{ self.t("message.edited") }</span> }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if m.deleted {
+                        html! {}
+                    } else {
+                        let copied = self.copied.contains_key(&m.id);
+                        let id = m.id.clone();
+                        html! {
+                            <button
+                                onclick={ctx.link().callback(move |_| Msg::Copy(id.clone()))}
+                                class={classes!(
+                                    "text-xs", "rounded", "focus:outline-none", "focus:ring-2", "focus:ring-blue-400",
+                                    if copied {
+                                        "text-green-600 dark:text-green-400"
+                                    } else {
+                                        "opacity-0 group-hover:opacity-100 focus:opacity-100 text-gray-400 hover:text-blue-600 dark:text-gray-500"
+                                    },
+                                )}
+                                aria-label={self.t(if copied { "message.copied" } else { "message.copy" })}
+                            >
+                                { self.t(if copied { "message.copied_button" } else { "message.copy_button" }) }
+                            </button>
+                        }
+                    }
+                }
+                {
+                    if can_reply && !m.deleted {
+                        let pinned = self.state.is_pinned(&m.id);
+                        let id = m.id.clone();
+                        html! {
+                            <button
+                                onclick={ctx.link().callback(move |_| Msg::TogglePin(id.clone()))}
+                                class={classes!(
+                                    "text-xs", "rounded", "hover:text-blue-600",
+                                    "focus:outline-none", "focus:ring-2", "focus:ring-blue-400",
+                                    if pinned {
+                                        "text-amber-600 dark:text-amber-400"
+                                    } else {
+                                        "opacity-0 group-hover:opacity-100 focus:opacity-100 text-gray-400 dark:text-gray-500"
+                                    },
+                                )}
+                                aria-label={self.t(if pinned { "pins.unpin" } else { "pins.pin" })}
+                                aria-pressed={pinned.to_string()}
+                            >
+                                { self.t(if pinned { "pins.unpin_button" } else { "pins.pin_button" }) }
+                            </button>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if can_reply {
+                        html! {
+                            <>
+                                <button
+                                    onclick={reply}
+                                    class="opacity-0 group-hover:opacity-100 focus:opacity-100 text-xs text-gray-400 hover:text-blue-600 rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-500"
+                                    aria-label={self.t("message.reply")}
+                                >
+                                    { self.t("message.reply_button") }
+                                </button>
+                                {
+                                    if own && !m.deleted && m.file.is_none() {
+                                        let id = m.id.clone();
+                                        let edit = ctx.link().callback(move |_| Msg::StartEdit(id.clone()));
+                                        let id = m.id.clone();
+                                        let delete = ctx.link().callback(move |_| Msg::Delete(id.clone()));
+                                        let confirming = self.state.confirm_delete.as_ref() == Some(&m.id);
+                                        html! {
+                                            <>
+                                                <button
+                                                    onclick={edit}
+                                                    class="opacity-0 group-hover:opacity-100 focus:opacity-100 text-xs text-gray-400 hover:text-blue-600 rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-500"
+                                                    aria-label={self.t("message.edit")}
+                                                >
+                                                    { self.t("message.edit_button") }
+                                                </button>
+                                                <button
+                                                    onclick={delete}
+                                                    class={classes!(
+                                                        "text-xs", "rounded", "hover:text-red-600",
+                                                        "focus:outline-none", "focus:ring-2", "focus:ring-blue-400",
+                                                        if confirming {
+                                                            "text-red-600 dark:text-red-400"
+                                                        } else {
+                                                            "opacity-0 group-hover:opacity-100 focus:opacity-100 text-gray-400 dark:text-gray-500"
+                                                        },
+                                                    )}
+                                                    aria-label={self.t("message.delete")}
+                                                >
+                                                    { if confirming { self.t("message.confirm_delete") } else { self.t("message.delete_button") } }
+                                                </button>
+                                            </>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                                { for QUICK_REACTIONS.iter().map(|emoji| {
+                                    let id = m.id.clone();
+                                    let onclick = ctx.link().callback(move |_| Msg::React(id.clone(), emoji.to_string()));
+                                    html! {
+                                        <button
+                                            {onclick}
+                                            class="opacity-0 group-hover:opacity-100 focus:opacity-100 text-xs rounded hover:bg-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:hover:bg-gray-700"
+                                            aria-label={self.state.i18n.t_with("message.react", &[("emoji", emoji)])}
+                                        >
+                                            { *emoji }
+                                        </button>
+                                    }
+                                })}
+                            </>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </>
+        };
+
+        html! {
+            <MessageRow
+                id={m.id.clone()}
+                from={m.from.clone()}
+                is_own={own}
+                avatar={user.map(|u| u.avatar.clone()).unwrap_or_default()}
+                highlighted={self.highlighted.as_ref() == Some(&m.id)}
+                onuserclick={self.show_user(ctx, &m.from)}
+                onmenu={onmenu}
+                {meta}
+            >
+                {
+                    if let Some(reply_to) = &m.reply_to {
+                        self.view_quote(ctx, reply_to, m.reply_excerpt.as_deref())
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if m.deleted {
+                        html! {
+                            <div class="mt-1 text-sm italic text-gray-400 dark:text-gray-500">{ self.t("message.deleted") }</div>
+                        }
+                    } else if let Some(file) = &m.file {
+                        self.view_file(ctx, &m.id, file)
+                    } else if let Some(action) = action_text(&m.message) {
+                        html! {
+                            <div class="mt-1 text-sm italic text-gray-600 max-w-prose break-words dark:text-gray-300">
+                                { self.view_text(&format!("{} {}", m.from, action)) }
+                            </div>
+                        }
+                    } else {
+                        html! {
+                            <div class={body_class}>
+                                { self.view_body(ctx, &m.id, &m.message) }
+                            </div>
+                        }
+                    }
+                }
+                { self.view_reactions(ctx, &m.id) }
+                {
+                    match m.status {
+                        SendStatus::Failed => {
+                            let id = m.id.clone();
+                            let retry = ctx.link().callback(move |_| Msg::RetrySend(id.clone()));
+                            html! {
+                                <div class="mt-1 flex items-center space-x-2 text-xs text-red-600 dark:text-red-400">
+                                    <span>{ self.t("message.not_delivered") }</span>
+                                    <button
+                                        onclick={retry}
+                                        class="font-semibold hover:underline rounded focus:outline-none focus:ring-2 focus:ring-blue-400"
+                                    >
+                                        { self.t("message.retry") }
+                                    </button>
+                                </div>
+                            }
+                        }
+                        SendStatus::Queued | SendStatus::Pending => {
+                            html! { <div class="mt-1 text-xs text-gray-400 dark:text-gray-500">{ self.t("message.sending") }</div> }
+                        }
+                        SendStatus::Sent => html! {},
+                    }
+                }
+            </MessageRow>
+        }
+    }
+
+    /// Sends `message_text` to the open conversation and shows it
+    /// optimistically until the server echoes it back.
+    /// Starts preparing the next queued image, unless one is already being
+    /// prepared or waits to be sent.
+    fn prepare_next_image(&mut self, ctx: &Context<Self>) {
+        if self.preparing_image || self.image_preview.is_some() {
+            return;
+        }
+        let file = match self.image_queue.pop_front() {
+            Some(file) => file,
+            None => return,
+        };
+        self.preparing_image = true;
+        let link = ctx.link().clone();
+        spawn_local(async move {
+            link.send_message(Msg::ImagePrepared(images::prepare(file).await));
+        });
+    }
+
+    fn send_chat(&mut self, ctx: &Context<Self>, message_text: String) {
+        self.send_content(ctx, message_text, None);
+    }
+
+    /// Sends `file` to the open conversation, named by its file name.
+    fn send_file(&mut self, ctx: &Context<Self>, file: FileData) {
+        self.send_content(ctx, file.name.clone(), Some(file));
+    }
+
+    fn send_content(&mut self, ctx: &Context<Self>, message_text: String, file: Option<FileData>) {
+        // Asked here rather than on load so the prompt follows a user action.
+        if !self.notification_permission_asked {
+            self.notification_permission_asked = true;
+            notifications::request_permission();
+        }
+        // Answering means we have caught up.
+        self.new_divider_timer = None;
+        if let Some(c) = self.state.conversations.get_mut(&self.state.active) {
+            c.new_since = None;
+        }
+        let message = self.state.compose(message_text, file);
+        let id = message.id.clone().unwrap_or_default();
+        self.send_or_throttle(ctx, id);
+        self.scroll_to_bottom = true;
+        if let (Some(on_message_sent), MsgTypes::Message | MsgTypes::Private) =
+            (&ctx.props().on_message_sent, message.message_type)
+        {
+            on_message_sent.emit(message.data.unwrap_or_default());
+        }
+    }
+
+    /// `class` with its blues swapped for the accent colour.
+    fn accent(&self, ctx: &Context<Self>, class: &str) -> String {
+        class.replace("blue-", &format!("{}-", ctx.props().accent))
+    }
+
+    /// Expands the burst hiding message `id`, if it is in a collapsed one.
+    fn reveal(&mut self, id: &str) {
+        let messages = self.state.messages();
+        let index = match messages.iter().position(|m| m.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let burst = self
+            .transcript_items()
+            .into_iter()
+            .find_map(|item| match item {
+                TranscriptItem::Row(Row::Burst { start, len })
+                    if start < index && index < start + len =>
+                {
+                    Some(start)
+                }
+                _ => None,
+            });
+        if let Some(start) = burst {
+            let key = burst_key(&self.state.messages()[start]);
+            self.expanded_bursts.insert(key);
+        }
+    }
+
+    /// Moves focus into an overlay that just opened, or back to where it
+    /// was before one that just closed, unless it went somewhere else.
+    fn move_focus(&mut self) {
+        if std::mem::take(&mut self.focus_overlay) {
+            if let Some(root) = self.overlay_root.cast::<web_sys::Element>() {
+                a11y::focus_first(&root);
+            }
+        }
+        if std::mem::take(&mut self.restore_focus) && a11y::active_element().is_none() {
+            match self.return_focus.take().filter(|el| el.is_connected()) {
+                Some(el) => {
+                    let _ = el.focus();
+                }
+                None => self.focus_input(),
+            }
+        }
+    }
+
+    /// Keydown handler keeping Tab inside the open overlay.
+    fn trap_focus(&self) -> Callback<KeyboardEvent> {
+        let root = self.overlay_root.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            if let Some(root) = root.cast::<web_sys::Element>() {
+                a11y::trap_focus(&root, &e);
+            }
+        })
+    }
+
+    fn toggle_overlay(&mut self, overlay: Overlay) {
+        self.overlay = if self.overlay.as_ref() == Some(&overlay) {
+            None
+        } else {
+            Some(overlay)
+        };
+    }
+
+    /// Remembers `text` as sent, for recalling later.
+    fn remember_sent(&mut self, text: &str) {
+        self.state.sent.push(text.trim());
+        storage::save_sent_history(self.state.sent.entries());
+    }
+
+    /// The text an arrow key brings back into the message input, if it is
+    /// one recalling what we sent: Up with the input empty or the caret at
+    /// its start, then Up and Down while recalling. Left alone while a
+    /// panel is open or a message is being edited.
+    fn recall(&mut self, e: &KeyboardEvent) -> Option<String> {
+        if self.overlay.is_some()
+            || self.state.editing.is_some()
+            || e.alt_key()
+            || e.ctrl_key()
+            || e.meta_key()
+            || e.shift_key()
+        {
+            return None;
+        }
+        let input = self.chat_input.cast::<HtmlInputElement>()?;
+        match e.key().as_str() {
+            "ArrowUp" => {
+                let value = input.value();
+                let at_start = input.selection_start().ok().flatten() == Some(0)
+                    && input.selection_end().ok().flatten() == Some(0);
+                if !(self.state.sent.is_recalling() || value.is_empty() || at_start) {
+                    return None;
+                }
+                self.state.sent.older(&value).map(str::to_string)
+            }
+            "ArrowDown" => self.state.sent.newer(),
+            _ => None,
+        }
+    }
+
+    /// Puts `text` in the message input with the caret at its end.
+    fn set_input(&mut self, ctx: &Context<Self>, text: String) {
+        if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+            input.set_value(&text);
+            let end = input.value().encode_utf16().count() as u32;
+            let _ = input.set_selection_range(end, end);
+        }
+        ctx.link().send_message(Msg::Input(text));
+    }
+
+    /// Replaces the selection in the message input with `text`, leaving the
+    /// caret right after it.
+    fn insert_at_cursor(&mut self, text: &str) {
+        let input = match self.chat_input.cast::<HtmlInputElement>() {
+            Some(input) => input,
+            None => return,
+        };
+        let (updated, caret) = replace_selection(&input, text);
+        input.set_value(&updated);
+        let _ = input.set_selection_range(caret, caret);
+        let _ = input.focus();
+        self.input_text = updated;
+    }
+
+    /// Carries out a command that doesn't send anything.
+    fn run_command(&mut self, ctx: &Context<Self>, command: Command) {
+        let conversation = self.state.active.clone();
+        match command {
+            Command::Nick(name) if name == self.state.username => {}
+            Command::Nick(name) => {
+                if let Err(e) = validate_username(&self.state.i18n, name) {
+                    self.state.push_notice(conversation, e);
+                    return;
+                }
+                let message = WebSocketMessage::new(MsgTypes::Rename, Some(name.to_string()));
+                if let Err(e) = self.send(&message) {
+                    log::debug!("error sending rename: {}", e);
+                    self.show_error(ctx, ErrorKind::ActionFailed);
+                }
+                self.state.pending_rename = Some(name.to_string());
+            }
+            Command::Clear => {
+                if let Some(c) = self.state.conversations.get_mut(&conversation) {
+                    c.messages = MessageStore::default();
+                    c.unread = 0;
+                }
+                self.state.unseen = 0;
+                self.save_history();
+            }
+            Command::Retention(None) => {
+                let notice = self
+                    .state
+                    .i18n
+                    .t_with("notice.retention", &[("count", &self.state.max_messages)]);
+                self.state.push_notice(conversation, notice);
+            }
+            Command::Retention(Some(count)) => {
+                if self.state.set_max_messages(count) > 0 {
+                    self.save_history();
+                }
+                let notice = self
+                    .state
+                    .i18n
+                    .t_with("notice.retention_set", &[("count", &count)]);
+                self.state.push_notice(conversation, notice);
+            }
+            Command::Help => {
+                let help = commands::help_text(&self.state.i18n);
+                self.state.push_notice(conversation, help);
+            }
+            Command::Usage(name) => {
+                let usage = self
+                    .state
+                    .i18n
+                    .t_with("command.usage", &[("usage", &commands::usage(name))]);
+                self.state.push_notice(conversation, usage);
+            }
+            Command::Unknown(name) => {
+                let unknown = self
+                    .state
+                    .i18n
+                    .t_with("command.unknown", &[("name", &name)]);
+                self.state.push_notice(conversation, unknown);
+            }
+            Command::Message(_) | Command::Me(_) | Command::Shrug(_) => {}
+        }
+    }
+
+    /// Characters the input will send as, after shortcode expansion.
+    fn input_chars(&self) -> usize {
+        expand_shortcodes(self.input_text.trim()).chars().count()
+    }
+
+    fn focus_input(&self) {
+        if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+            let _ = input.focus();
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(wss) = self.wss.take() {
+            wss.close();
+        }
+    }
+
+    fn send(&self, message: &WebSocketMessage) -> Result<(), String> {
+        let wss = self
+            .wss
+            .as_ref()
+            .ok_or_else(|| "not connected".to_string())?;
+        wss.send(message.clone()).map_err(|e| e.to_string())
+    }
+
+    /// Carries out what the state asked for, returning whether to re-render.
+    fn run_effects(&mut self, ctx: &Context<Self>, effects: Vec<Effect>) -> bool {
+        let mut render = false;
+        for effect in effects {
+            match effect {
+                Effect::SendFrame(message) => {
+                    if let Err(e) = self.send(&message) {
+                        log::debug!("error sending to channel: {}", e);
+                    }
+                }
+                Effect::Render => render = true,
+                Effect::ScrollToBottom => self.scroll_to_bottom = true,
+                Effect::KeepScrollPosition => {
+                    if let Some(el) = self.transcript.cast::<web_sys::Element>() {
+                        // Keep whatever the user is reading where it is.
+                        self.scroll_anchor = Some(el.scroll_height() - el.scroll_top());
+                    }
+                }
+                Effect::Confirmed(id) => {
+                    // Dropping the timeout cancels it.
+                    self.pending_timeouts.remove(&id);
+                }
+                Effect::Notify { from, text } => {
+                    if self.state.notifications_enabled && notifications::page_hidden() {
+                        notifications::notify(&from, &text);
+                    }
+                    render = true;
+                }
+                Effect::PlaySound => {
+                    if self.state.sound_enabled {
+                        sound::play_ping();
+                    }
+                }
+                Effect::UpdateTitle => self.update_title(),
+                Effect::UserDeparted(name) => {
+                    let link = ctx.link().clone();
+                    let remove = Msg::RemoveUser(name.clone());
+                    let timeout =
+                        Timeout::new(DEPARTURE_FADE_MS, move || link.send_message(remove));
+                    self.departures.insert(name, timeout);
+                }
+                Effect::UserReturned(name) => {
+                    self.departures.remove(&name);
+                }
+                Effect::Renamed(name) => {
+                    // The login context, storage and the handshake for later
+                    // reconnects all pick up the new name.
+                    if let Some((user, _)) = ctx.link().context::<User>(Callback::noop()) {
+                        *user.username.borrow_mut() = name.clone();
+                    }
+                    storage::save_username(&name);
+                    if let Some(wss) = &self.wss {
+                        wss.set_handshake(self.handshake_frames());
+                    }
+                }
+                Effect::RejectLogin(reason) => self.reject_login(ctx, reason),
+                Effect::PinsChanged => storage::save_pins(&self.state.pins),
+                Effect::ShowError(kind) => ctx.link().send_message(Msg::ShowError(kind)),
+            }
+        }
+        render
+    }
+
+    /// Handles `msg` itself; effects queued on the state meanwhile are
+    /// carried out by the caller.
+    fn handle_msg(&mut self, ctx: &Context<Self>, msg: Msg) -> bool {
+        match msg {
+            Msg::HandleEvent(event) => {
+                self.incoming.push(event);
+                if self.flush_handle.is_none() {
+                    let link = ctx.link().clone();
+                    self.flush_handle = Some(request_animation_frame(move |_| {
+                        link.send_message(Msg::FlushPending)
+                    }));
+                }
+                false
+            }
+            Msg::ConnectionState(state) => {
+                // Nothing arrives until the socket is back, so the history
+                // cursors are as fresh now as they will be when it reopens.
+                if state == ConnState::Reconnecting && self.state.conn_state == ConnState::Open {
+                    if let Some(wss) = &self.wss {
+                        wss.set_handshake(self.handshake_frames());
+                    }
+                }
+                // The service flushes its queue right after the handshake,
+                // so queued messages start waiting for their echo now.
+                for id in self.state.connection_changed(state) {
+                    self.arm_pending_timeout(ctx, &id);
+                }
+                true
+            }
+            Msg::ConnectionError(error) => {
+                self.state.connection_error(error);
+                true
+            }
+            Msg::FlushPending => {
+                self.flush_handle = None;
+                let mut changed = false;
+                for event in std::mem::take(&mut self.incoming) {
+                    let effects = self.state.apply(event);
+                    changed |= self.run_effects(ctx, effects);
+                }
+                if changed {
+                    self.save_history();
+                    self.mark_read(ctx);
+                }
+                changed
+            }
+            Msg::ToggleFold(id) => {
+                if !self.unfolded.remove(&id) {
+                    self.unfolded.insert(id);
+                }
+                // Keep the message where it is: the text grows or shrinks
+                // under it, and the view no longer follows the bottom
+                // until the next scroll says it is there.
+                if let Some(el) = self.transcript.cast::<web_sys::Element>() {
+                    self.scroll_top = el.scroll_top();
+                    self.restore_scroll = Some(self.scroll_top);
+                }
+                self.state.at_bottom = false;
+                true
+            }
+            Msg::ToggleBurst(key) => {
+                if !self.expanded_bursts.remove(&key) {
+                    self.expanded_bursts.insert(key);
+                }
+                true
+            }
+            Msg::SubmitMessage => {
+                if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+                    let value = input.value();
+                    // Edits replace the text verbatim.
+                    let command = match self.state.editing {
+                        Some(_) => Command::Message(value.trim()),
+                        None => parse_command(&value),
+                    };
+                    let message_text = match command.message_text() {
+                        Some(text) => expand_shortcodes(&text),
+                        None => {
+                            self.remember_sent(&value);
+                            self.run_command(ctx, command);
+                            input.set_value("");
+                            self.input_text.clear();
+                            self.clear_draft();
+                            return true;
+                        }
+                    };
+                    if !message_text.is_empty()
+                        && message_text.chars().count() <= MAX_MESSAGE_CHARS
+                        && self.wss.is_some()
+                    {
+                        self.typing_idle = None;
+                        self.send_typing(false);
+                        input.set_value("");
+                        self.input_text.clear();
+                        match self.state.editing.take() {
+                            Some(id) => {
+                                self.send_edit(ctx, id, message_text);
+                                self.restore_draft();
+                            }
+                            None => {
+                                self.remember_sent(&value);
+                                self.send_chat(ctx, message_text);
+                                self.clear_draft();
+                            }
+                        }
+                        return true;
+                    }
+                }
+                false
+            }
+            Msg::Input(text) => {
+                let was_empty = self.input_text.trim().is_empty();
+                self.input_text = text;
+                self.update_draft(ctx);
+                if self.input_text.trim().is_empty() {
+                    self.typing_idle = None;
+                    self.send_typing(false);
+                } else {
+                    self.send_typing(true);
+                    let link = ctx.link().clone();
+                    self.typing_idle = Some(Timeout::new(TYPING_IDLE_MS, move || {
+                        link.send_message(Msg::StopTyping)
+                    }));
+                }
+                was_empty != self.input_text.trim().is_empty()
+            }
+            Msg::StopTyping => {
+                self.typing_idle = None;
+                self.send_typing(false);
+                false
+            }
+            Msg::Sweep => {
+                let now = time::now();
+                let before = self.state.typing.len();
+                self.state
+                    .typing
+                    .retain(|_, seen| now.saturating_sub(*seen) < TYPING_EXPIRE_MS);
+
+                let reactions = &mut self.state.reactions;
+                self.state.orphan_reactions.retain(|id, since| {
+                    let keep = now.saturating_sub(*since) < ORPHAN_BUFFER_MS;
+                    if !keep {
+                        log::debug!("dropping reactions for unknown message {}", id);
+                        reactions.remove(id);
+                    }
+                    keep
+                });
+                self.state.tombstones.retain(|id, (_, since)| {
+                    let keep = now.saturating_sub(*since) < ORPHAN_BUFFER_MS;
+                    if !keep {
+                        log::debug!("dropping delete for unknown message {}", id);
+                    }
+                    keep
+                });
+                self.state.typing.len() != before
+            }
+            Msg::Tick => {
+                let now = time::now();
+                let away = self.idle.tick(now);
+                // Otherwise only worth a render while the newest message is
+                // still labelled relative to now (or just stopped being), or
+                // when a new day turns "Today" into "Yesterday".
+                let cutoff = now.saturating_sub(RELATIVE_FOR_MS + TICK_MS as u64);
+                self.presence_changed(away)
+                    || Day::of(now) != Day::of(now.saturating_sub(TICK_MS as u64))
+                    || self
+                        .state
+                        .messages()
+                        .last()
+                        .is_some_and(|m| m.timestamp >= cutoff)
+            }
+            Msg::Activity => {
+                let back = self.idle.activity(time::now());
+                self.presence_changed(back)
+            }
+            Msg::VisibilityChanged => {
+                let changed = self
+                    .idle
+                    .visibility(notifications::page_hidden(), time::now());
+                self.presence_changed(changed)
+            }
+            Msg::Scrolled => {
+                let at_bottom = match self.transcript.cast::<web_sys::Element>() {
+                    Some(el) => {
+                        self.scroll_top = el.scroll_top();
+                        self.viewport_height = el.client_height();
+                        el.scroll_height() - el.scroll_top() - el.client_height() <= NEAR_BOTTOM_PX
+                    }
+                    None => true,
+                };
+                let mut changed =
+                    at_bottom != self.state.at_bottom || (at_bottom && self.state.unseen > 0);
+                if !changed {
+                    // Re-render when scrolling brings other rows into the window.
+                    let items = self.transcript_items();
+                    let heights = self.item_heights(&items);
+                    let window = self.transcript_window(&items, &heights);
+                    changed = (window.start, window.end) != self.rendered_rows.get();
+                }
+                self.state.at_bottom = at_bottom;
+                if at_bottom {
+                    self.state.unseen = 0;
+                    self.clear_title_unread();
+                    self.mark_read(ctx);
+                }
+                if self.scroll_top <= NEAR_TOP_PX && self.state.conn_state == ConnState::Open {
+                    changed |= self.state.request_older();
+                }
+                self.arm_new_divider_clear(ctx);
+                // The menu would be left pointing at nothing.
+                if matches!(self.overlay, Some(Overlay::MessageMenu { .. })) {
+                    self.overlay = None;
+                    changed = true;
+                }
+                changed
+            }
+            Msg::JumpToLatest => {
+                self.scroll_to_bottom = true;
+                self.state.unseen = 0;
+                self.clear_title_unread();
+                true
+            }
+            Msg::KeyDown(e) => {
+                if is_send_key(&e) {
+                    e.prevent_default();
+                    ctx.link().send_message(Msg::SubmitMessage);
+                } else if let Some(text) = self.recall(&e) {
+                    e.prevent_default();
+                    self.set_input(ctx, text);
+                }
+                false
+            }
+            Msg::SendTimedOut(id) => {
+                self.pending_timeouts.remove(&id);
+                let failed = self.state.send_timed_out(&id);
+                if failed {
+                    self.schedule_toast_expiry(ctx);
+                }
+                failed
+            }
+            Msg::Paste(e) => {
+                let input = match self.chat_input.cast::<HtmlInputElement>() {
+                    Some(input) => input,
+                    None => return false,
+                };
+                // `ClipboardEvent` is still behind web-sys' unstable APIs.
+                let data = js_sys::Reflect::get(&e, &"clipboardData".into())
+                    .ok()
+                    .and_then(|d| d.dyn_into::<DataTransfer>().ok());
+                // A pasted screenshot comes as a file; anything else is text.
+                let pasted_images: Vec<File> = data
+                    .as_ref()
+                    .map(transferred_files)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|file| file.type_().starts_with("image/"))
+                    .collect();
+                if !pasted_images.is_empty() {
+                    e.prevent_default();
+                    ctx.link().send_message(Msg::AttachImages(pasted_images));
+                    return false;
+                }
+                let pasted = data.and_then(|d| d.get_data("text").ok());
+                let pasted = match pasted {
+                    Some(pasted) => pasted,
+                    None => return false,
+                };
+                let (combined, _) = replace_selection(&input, &pasted);
+                let combined = expand_shortcodes(combined.trim());
+                // An edit can't be split; it just shows up as over the limit.
+                if combined.chars().count() <= MAX_MESSAGE_CHARS
+                    || self.state.editing.is_some()
+                    || self.wss.is_none()
+                {
+                    return false;
+                }
+                e.prevent_default();
+                input.set_value("");
+                self.input_text.clear();
+                self.clear_draft();
+                self.typing_idle = None;
+                self.send_typing(false);
+                self.state
+                    .chunks
+                    .extend(split_message(&combined, MAX_MESSAGE_CHARS));
+                if self.chunk_timer.is_none() {
+                    ctx.link().send_message(Msg::SendNextChunk);
+                }
+                true
+            }
+            Msg::SendNextChunk => {
+                self.chunk_timer = None;
+                let chunk = match self.state.chunks.pop_front() {
+                    Some(chunk) => chunk,
+                    None => return false,
+                };
+                self.send_chat(ctx, chunk);
+                if !self.state.chunks.is_empty() {
+                    let link = ctx.link().clone();
+                    self.chunk_timer = Some(Timeout::new(CHUNK_SEND_DELAY_MS, move || {
+                        link.send_message(Msg::SendNextChunk)
+                    }));
+                }
+                true
+            }
+            Msg::FlushReads => {
+                self.read_flush = None;
+                self.state.flush_reads();
+                false
+            }
+            Msg::RetrySend(id) => {
+                let kind = ErrorKind::SendFailed(id.clone());
+                self.state.toasts.dismiss_where(|t| t.kind == kind);
+                self.send_or_throttle(ctx, id);
+                true
+            }
+            Msg::DrainThrottled => {
+                self.drain_timer = None;
+                let (ready, drain_in) = self.state.drain_throttled();
+                for id in ready {
+                    self.send_outgoing(ctx, &id);
+                }
+                if let Some(ms) = drain_in {
+                    self.arm_drain(ctx, ms);
+                }
+                true
+            }
+            Msg::RemoveUser(name) => {
+                self.departures.remove(&name);
+                self.state.users.retain(|u| u.name != name);
+                true
+            }
+            Msg::React(message_id, emoji) => {
+                let retract = self
+                    .state
+                    .reactions
+                    .get(&message_id)
+                    .and_then(|r| r.get(&emoji))
+                    .is_some_and(|users| users.contains(&self.state.username));
+                let reaction = ReactionData {
+                    message_id,
+                    emoji,
+                    from: self.state.username.clone(),
+                    retract,
+                };
+                let message = WebSocketMessage::new(
+                    MsgTypes::Reaction,
+                    Some(serde_json::to_string(&reaction).unwrap()),
+                );
+                if let Err(e) = self.send(&message) {
+                    log::debug!("error sending reaction: {}", e);
+                    self.show_error(ctx, ErrorKind::ActionFailed);
+                }
+                self.state.apply_reaction(reaction)
+            }
+            Msg::BackToLogin => {
+                if let Some(reason) = self.state.refused {
+                    let reason = refused_text(&self.state.i18n, reason);
+                    self.reject_login(ctx, reason);
+                }
+                false
+            }
+            Msg::Logout => {
+                let message =
+                    WebSocketMessage::new(MsgTypes::Unregister, Some(self.state.username.clone()));
+                if let Err(e) = self.send(&message) {
+                    log::debug!("error sending unregister: {}", e);
+                }
+                self.disconnect();
+                storage::clear_username();
+                if let Some((user, _)) = ctx.link().context::<User>(Callback::noop()) {
+                    user.username.borrow_mut().clear();
+                }
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Login);
+                }
+                false
+            }
+            Msg::StartRename => {
+                let text = "/nick ".to_string();
+                if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+                    input.set_value(&text);
+                }
+                self.input_text = text;
+                self.focus_input();
+                true
+            }
+            Msg::StartEdit(id) => {
+                let text = match self.state.messages().iter().find(|m| m.id == id) {
+                    Some(m) => m.message.clone(),
+                    None => return false,
+                };
+                if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+                    input.set_value(&text);
+                }
+                self.input_text = text;
+                self.state.replying_to = None;
+                self.state.editing = Some(id);
+                self.focus_input();
+                true
+            }
+            Msg::CancelEdit => {
+                if self.state.editing.take().is_none() {
+                    return false;
+                }
+                self.restore_draft();
+                true
+            }
+            Msg::Delete(id) => {
+                if self.state.confirm_delete.as_ref() != Some(&id) {
+                    self.state.confirm_delete = Some(id);
+                    let link = ctx.link().clone();
+                    self.confirm_delete_timeout =
+                        Some(Timeout::new(DELETE_CONFIRM_MS, move || {
+                            link.send_message(Msg::CancelDelete)
+                        }));
+                    return true;
+                }
+                self.state.confirm_delete = None;
+                self.confirm_delete_timeout = None;
+                if self.state.editing.as_ref() == Some(&id) {
+                    ctx.link().send_message(Msg::CancelEdit);
+                }
+                self.send_delete(ctx, id);
+                true
+            }
+            Msg::CancelDelete => {
+                self.confirm_delete_timeout = None;
+                self.state.confirm_delete.take().is_some()
+            }
+            Msg::ReplyTo(id) => {
+                self.state.replying_to = Some(id);
+                self.focus_input();
+                true
+            }
+            Msg::ShowUser { name, x, y } => {
+                self.overlay = Some(Overlay::UserPopover { name, x, y });
+                true
+            }
+            Msg::CloseUserPopover => {
+                if !matches!(self.overlay, Some(Overlay::UserPopover { .. })) {
+                    return false;
+                }
+                self.overlay = None;
+                true
+            }
+            Msg::UserAction(name, action) => {
+                self.overlay = None;
+                match action {
+                    UserAction::Message => ctx
+                        .link()
+                        .send_message(Msg::OpenConversation(ConversationId::Direct(name))),
+                    UserAction::Mention => self.insert_at_cursor(&format!("@{} ", name)),
+                    UserAction::ToggleMute => {
+                        let muted = !self.state.is_muted(&name);
+                        if self.state.set_muted(&name, muted) {
+                            storage::save_muted(&self.state.muted);
+                        }
+                    }
+                }
+                true
+            }
+            Msg::ShowMessageMenu { id, x, y } => {
+                self.overlay = Some(Overlay::MessageMenu { id, x, y });
+                true
+            }
+            Msg::CloseMessageMenu => {
+                if !matches!(self.overlay, Some(Overlay::MessageMenu { .. })) {
+                    return false;
+                }
+                self.overlay = None;
+                true
+            }
+            Msg::MessageAction(id, action) => {
+                self.overlay = None;
+                let msg = match action {
+                    MessageAction::Reply => Msg::ReplyTo(id),
+                    MessageAction::Copy => Msg::Copy(id),
+                    MessageAction::TogglePin => Msg::TogglePin(id),
+                    MessageAction::Edit => Msg::StartEdit(id),
+                    MessageAction::Delete => Msg::Delete(id),
+                    MessageAction::React(emoji) => Msg::React(id, emoji.to_string()),
+                    MessageAction::MentionAuthor => {
+                        if let Some(m) = self.state.messages().iter().find(|m| m.id == id) {
+                            let mention = format!("@{} ", m.from);
+                            self.insert_at_cursor(&mention);
+                        }
+                        return true;
+                    }
+                };
+                ctx.link().send_message(msg);
+                true
+            }
+            Msg::FilterUsers(query) => {
+                self.user_filter = query;
+                true
+            }
+            Msg::Shortcut(Shortcut::Dismiss) => {
+                if self.overlay.take().is_some() {
+                    true
+                } else if let Some(draft) = self.state.sent.cancel() {
+                    self.set_input(ctx, draft);
+                    false
+                } else if self.state.editing.is_some() {
+                    ctx.link().send_message(Msg::CancelEdit);
+                    false
+                } else if self.state.replying_to.take().is_some() {
+                    true
+                } else {
+                    if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+                        if !input.value().is_empty() {
+                            input.set_value("");
+                            ctx.link().send_message(Msg::Input(String::new()));
+                        }
+                    }
+                    false
+                }
+            }
+            #[cfg(feature = "frame-log")]
+            Msg::Shortcut(Shortcut::ToggleFrameLog) => {
+                self.frame_log_open = !self.frame_log_open;
+                true
+            }
+            Msg::Shortcut(Shortcut::FocusInput) => {
+                self.focus_input();
+                false
+            }
+            Msg::Shortcut(Shortcut::ToggleHelp) => {
+                self.toggle_overlay(Overlay::Shortcuts);
+                true
+            }
+            Msg::Shortcut(Shortcut::CycleConversation(direction)) => {
+                let step = if direction == Direction::Up { -1 } else { 1 };
+                if let Some(conversation) = self.state.cycle_conversation(step) {
+                    ctx.link().send_message(Msg::OpenConversation(conversation));
+                }
+                false
+            }
+            Msg::Shortcut(Shortcut::ScrollPage(direction)) => {
+                if let Some(el) = self.transcript.cast::<web_sys::Element>() {
+                    // Keep a line of overlap so the reader doesn't lose their place.
+                    let page = el.client_height() * 9 / 10;
+                    let step = if direction == Direction::Up {
+                        -page
+                    } else {
+                        page
+                    };
+                    el.set_scroll_top(el.scroll_top() + step);
+                }
+                false
+            }
+            Msg::OpenConversation(conversation) => {
+                // On a phone, picking a conversation is what the drawer was
+                // opened for.
+                let drawer = self.overlay == Some(Overlay::Drawer);
+                if drawer {
+                    self.overlay = None;
+                }
+                if conversation == self.state.active {
+                    return drawer;
+                }
+                if let Some(c) = self.state.conversations.get_mut(&conversation) {
+                    c.unread = 0;
+                }
+                self.state.active = conversation;
+                self.state.replying_to = None;
+                self.search = None;
+                self.state.sent.cancel();
+                // Armed again once the new one is scrolled into view.
+                self.new_divider_timer = None;
+                if self.state.editing.is_some() {
+                    // Puts the new conversation's draft in once the edit
+                    // is dropped.
+                    ctx.link().send_message(Msg::CancelEdit);
+                } else {
+                    self.restore_draft();
+                }
+                self.state.unseen = 0;
+                self.scroll_to_bottom = true;
+                true
+            }
+            Msg::JoinRoom => {
+                let input = match self.room_input.cast::<HtmlInputElement>() {
+                    Some(input) => input,
+                    None => return false,
+                };
+                let room = input.value().trim().trim_start_matches('#').to_string();
+                if room.is_empty() {
+                    return false;
+                }
+                input.set_value("");
+                if !self.state.rooms.contains(&room) {
+                    self.state.rooms.push(room.clone());
+                    self.send_membership(MsgTypes::Join, &room);
+                }
+                ctx.link()
+                    .send_message(Msg::OpenConversation(ConversationId::Room(room)));
+                true
+            }
+            Msg::LeaveRoom(room) => {
+                if room == DEFAULT_ROOM {
+                    return false;
+                }
+                self.state.rooms.retain(|r| r != &room);
+                self.send_membership(MsgTypes::Leave, &room);
+                let conversation = ConversationId::Room(room);
+                self.state.conversations.remove(&conversation);
+                if self.state.active == conversation {
+                    ctx.link()
+                        .send_message(Msg::OpenConversation(ConversationId::Room(
+                            DEFAULT_ROOM.to_string(),
+                        )));
+                }
+                true
+            }
+            Msg::CancelReply => self.state.replying_to.take().is_some(),
+            Msg::JumpToMessage(id) => {
+                self.reveal(&id);
+                match find_message_element(&id) {
+                    Some(element) => a11y::scroll_into_view(&element),
+                    // Not rendered; bring its row into the window first.
+                    None => self.pending_jump = Some(id.clone()),
+                }
+                let link = ctx.link().clone();
+                self.highlight_timeout = Some(Timeout::new(HIGHLIGHT_MS, move || {
+                    link.send_message(Msg::ClearHighlight)
+                }));
+                self.highlighted = Some(id);
+                true
+            }
+            Msg::ClearHighlight => {
+                self.highlight_timeout = None;
+                self.highlighted.take().is_some()
+            }
+            Msg::OpenSearch => {
+                self.focus_search = true;
+                if self.search.is_none() {
+                    self.search = Some(Search {
+                        query: String::new(),
+                        current: None,
+                        scroll_top: self.scroll_top,
+                        at_bottom: self.state.at_bottom,
+                    });
+                }
+                // Focus moves to the search input once rendered.
+                true
+            }
+            Msg::CloseSearch => {
+                let search = match self.search.take() {
+                    Some(search) => search,
+                    None => return false,
+                };
+                if search.at_bottom {
+                    self.scroll_to_bottom = true;
+                } else {
+                    self.restore_scroll = Some(search.scroll_top);
+                }
+                self.highlighted = None;
+                self.focus_input();
+                true
+            }
+            Msg::Search(query) => {
+                let matches =
+                    matching_messages(self.state.messages(), query.trim(), self.word_filter());
+                let newest = matches.last().map(|&i| self.state.messages()[i].id.clone());
+                if let Some(search) = &mut self.search {
+                    search.query = query;
+                    search.current = newest.clone();
+                }
+                if let Some(id) = newest {
+                    ctx.link().send_message(Msg::JumpToMessage(id));
+                }
+                true
+            }
+            Msg::SearchStep(direction) => {
+                let search = match &self.search {
+                    Some(search) => search,
+                    None => return false,
+                };
+                let messages = self.state.messages();
+                let matches = matching_messages(messages, search.query.trim(), self.word_filter());
+                if matches.is_empty() {
+                    return false;
+                }
+                let last = matches.len() - 1;
+                let position = matches
+                    .iter()
+                    .position(|&i| search.current.as_ref() == Some(&messages[i].id));
+                let next = match (position, direction) {
+                    (Some(0), Direction::Up) | (None, _) => last,
+                    (Some(p), Direction::Up) => p - 1,
+                    (Some(p), Direction::Down) if p == last => 0,
+                    (Some(p), Direction::Down) => p + 1,
+                };
+                let id = messages[matches[next]].id.clone();
+                if let Some(search) = &mut self.search {
+                    search.current = Some(id.clone());
+                }
+                ctx.link().send_message(Msg::JumpToMessage(id));
+                true
+            }
+            Msg::Copy(id) => {
+                let text = match self.state.messages().iter().find(|m| m.id == id) {
+                    // Images are copied as their address.
+                    Some(m) => image_url(&m.message).unwrap_or(&m.message).to_string(),
+                    None => return false,
+                };
+                let link = ctx.link().clone();
+                clipboard::copy(text, move |copied| {
+                    if copied {
+                        link.send_message(Msg::CopiedFeedback(id));
+                    } else {
+                        log::warn!("could not copy message {}", id);
+                    }
+                });
+                false
+            }
+            Msg::CopiedFeedback(id) => {
+                let link = ctx.link().clone();
+                let ended = Msg::CopiedFeedbackEnded(id.clone());
+                let timeout = Timeout::new(COPIED_FEEDBACK_MS, move || link.send_message(ended));
+                self.copied.insert(id, timeout);
+                true
+            }
+            Msg::CopiedFeedbackEnded(id) => self.copied.remove(&id).is_some(),
+            Msg::ShowMuted(id) => self.state.show_muted(id),
+            Msg::SaveDrafts => {
+                self.draft_timer = None;
+                self.save_drafts();
+                false
+            }
+            Msg::TogglePin(id) => {
+                if !self.state.toggle_pin(&id) {
+                    return false;
+                }
+                storage::save_pins(&self.state.pins);
+                true
+            }
+            Msg::TogglePins => {
+                self.pins_collapsed = !self.pins_collapsed;
+                true
+            }
+            Msg::ToggleEmojiPicker => {
+                self.toggle_overlay(Overlay::EmojiPicker);
+                true
+            }
+            Msg::CloseEmojiPicker => {
+                if self.overlay != Some(Overlay::EmojiPicker) {
+                    return false;
+                }
+                self.overlay = None;
+                true
+            }
+            Msg::InsertEmoji(emoji) => {
+                self.insert_at_cursor(&emoji);
+                true
+            }
+            #[cfg(feature = "gif-picker")]
+            Msg::ToggleGifPicker => {
+                self.toggle_overlay(Overlay::GifPicker);
+                true
+            }
+            #[cfg(feature = "gif-picker")]
+            Msg::SendGif(url) => {
+                self.overlay = None;
+                self.send_chat(ctx, url);
+                true
+            }
+            Msg::ShowError(kind) => {
+                log::error!("ws: {:?}", kind);
+                self.show_error(ctx, kind);
+                true
+            }
+            Msg::DismissToast(id) => {
+                let dismissed = self.state.toasts.dismiss(id);
+                self.schedule_toast_expiry(ctx);
+                dismissed
+            }
+            Msg::ExpireToasts => {
+                let expired = self.state.toasts.expire(time::now());
+                self.schedule_toast_expiry(ctx);
+                expired
+            }
+            Msg::WindowFocus(focused) => {
+                self.state.window_focused = focused;
+                if focused {
+                    self.clear_title_unread();
+                    self.mark_read(ctx);
+                }
+                self.arm_new_divider_clear(ctx);
+                false
+            }
+            Msg::ClearNewDivider => {
+                self.new_divider_timer = None;
+                self.state
+                    .conversations
+                    .get_mut(&self.state.active)
+                    .and_then(|c| c.new_since.take())
+                    .is_some()
+            }
+            Msg::ImageFailed(url) => self.broken_images.insert(url),
+            Msg::OpenLightbox(id) => {
+                self.overlay = Some(Overlay::Lightbox(id));
+                true
+            }
+            Msg::StepLightbox(direction) => {
+                let images = self.lightbox_images();
+                let index = match &self.overlay {
+                    Some(Overlay::Lightbox(id)) => images.iter().position(|(m, _)| &m.id == id),
+                    _ => None,
+                };
+                let next = match (index, direction) {
+                    (Some(index), Direction::Up) => index.checked_sub(1),
+                    (Some(index), Direction::Down) => Some(index + 1),
+                    (None, _) => None,
+                };
+                match next.and_then(|next| images.get(next)) {
+                    Some((m, _)) => {
+                        self.overlay = Some(Overlay::Lightbox(m.id.clone()));
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Msg::AttachImages(files) => {
+                self.attach_error = None;
+                self.image_queue.extend(files);
+                self.prepare_next_image(ctx);
+                true
+            }
+            Msg::ImagePrepared(result) => {
+                self.preparing_image = false;
+                match result {
+                    Ok(url) => self.image_preview = Some(url),
+                    Err(e) => {
+                        self.attach_error = Some(match e {
+                            ImageError::TooLarge => self.state.i18n.t_with(
+                                "image.too_large",
+                                &[("max", &(images::MAX_IMAGE_BYTES / (1024 * 1024)))],
+                            ),
+                            e => self.t(e.key()),
+                        });
+                        self.prepare_next_image(ctx);
+                    }
+                }
+                true
+            }
+            Msg::AttachFiles(files) => {
+                self.attach_error = None;
+                self.preparing_files += files.len();
+                let inline_limit = ctx.props().inline_file_limit;
+                let upload_url = config::upload_url();
+                let link = ctx.link().clone();
+                // One at a time, so they arrive in the order picked.
+                spawn_local(async move {
+                    for file in files {
+                        let prepared = files::prepare(file, inline_limit, upload_url.clone()).await;
+                        link.send_message(Msg::FilePrepared(prepared));
+                    }
+                });
+                true
+            }
+            Msg::FilePrepared(result) => {
+                self.preparing_files = self.preparing_files.saturating_sub(1);
+                match result {
+                    Ok(file) => self.send_file(ctx, file),
+                    Err(e) => {
+                        self.attach_error = Some(match e {
+                            FileError::TooLarge => self.state.i18n.t_with(
+                                "file.too_large",
+                                &[("max", &format_size(ctx.props().inline_file_limit as u64))],
+                            ),
+                            e => self.t(e.key()),
+                        })
+                    }
+                }
+                true
+            }
+            Msg::DownloadFile(id) => {
+                let limit = ctx.props().inline_file_limit;
+                let saved = self
+                    .state
+                    .messages()
+                    .iter()
+                    .find(|m| m.id == id)
+                    .and_then(|m| m.file.as_ref())
+                    .is_some_and(|file| files::download(file, limit));
+                if !saved {
+                    log::warn!("nothing to save for message {}", id);
+                }
+                false
+            }
+            Msg::SendImage => {
+                if let Some(url) = self.image_preview.take() {
+                    self.send_chat(ctx, url);
+                }
+                self.prepare_next_image(ctx);
+                true
+            }
+            Msg::CancelImage => {
+                self.attach_error = None;
+                self.image_preview = None;
+                self.prepare_next_image(ctx);
+                true
+            }
+            Msg::ToggleTheme => {
+                if let Some(theme) = &self.theme {
+                    let next = theme.toggled();
+                    storage::save_theme(next);
+                    theme.set(next);
+                }
+                false
+            }
+            Msg::ThemeChanged(theme) => {
+                self.theme = Some(theme);
+                true
+            }
+            Msg::SetLocale(tag) => {
+                if let Some(locale) = &self.locale {
+                    storage::save_locale(&tag);
+                    locale.set(I18n::new(&tag));
+                }
+                false
+            }
+            Msg::LocaleChanged(locale) => {
+                self.state.i18n = (*locale).clone();
+                self.locale = Some(locale);
+                true
+            }
+            Msg::ToggleDrawer => {
+                self.toggle_overlay(Overlay::Drawer);
+                true
+            }
+            Msg::ToggleRail => {
+                self.rail = !self.rail;
+                storage::save_sidebar_rail(self.rail);
+                true
+            }
+            Msg::ViewportResized(height) => {
+                self.visual_height = Some(height);
+                true
+            }
+            Msg::ToggleSound => {
+                self.state.sound_enabled = !self.state.sound_enabled;
+                storage::save_sound_enabled(self.state.sound_enabled);
+                true
+            }
+            Msg::ToggleFilter => {
+                self.state.filter_enabled = !self.state.filter_enabled;
+                storage::save_filter_enabled(self.state.filter_enabled);
+                true
+            }
+            Msg::SetFilterWords(words) => {
+                self.state.filter_words = words
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|word| !word.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                self.state.word_filter = WordFilter::new(&self.state.filter_words);
+                storage::save_filter_words(&self.state.filter_words);
+                true
+            }
+            Msg::ToggleNotifications => {
+                self.state.notifications_enabled = !self.state.notifications_enabled;
+                storage::save_notifications_enabled(self.state.notifications_enabled);
+                true
+            }
+            Msg::ToggleSettings => {
+                self.toggle_overlay(Overlay::Settings);
+                true
+            }
+            Msg::ReconnectNow => {
+                let input = match self.settings_input.cast::<HtmlInputElement>() {
+                    Some(input) => input,
+                    None => return false,
+                };
+                match validate_ws_url(&input.value()) {
+                    Ok(url) => {
+                        self.disconnect();
+                        self.server_url = url;
+                        storage::save_server_url(&self.server_url);
+                        self.connect(ctx);
+                        if self.settings_error.is_none() {
+                            self.overlay = None;
+                        }
+                    }
+                    Err(e) => self.settings_error = Some(e),
+                }
+                true
+            }
+        }
+    }
+}
 
-use crate::services::event_bus::EventBus;
-use crate::{services::websocket::WebsocketService, User};
+/// The value of `input` with its selection replaced by `text`, and the caret
+/// position just after the insertion.
+fn replace_selection(input: &HtmlInputElement, text: &str) -> (String, u32) {
+    // Selection offsets are in UTF-16 code units.
+    let value: Vec<u16> = input.value().encode_utf16().collect();
+    let end = input
+        .selection_end()
+        .ok()
+        .flatten()
+        .map_or(value.len(), |end| (end as usize).min(value.len()));
+    let start = input
+        .selection_start()
+        .ok()
+        .flatten()
+        .map_or(end, |start| (start as usize).min(end));
 
-pub enum Msg {
-    HandleMsg(String),
-    SubmitMessage,
+    let inserted: Vec<u16> = text.encode_utf16().collect();
+    let mut updated = value[..start].to_vec();
+    updated.extend_from_slice(&inserted);
+    updated.extend_from_slice(&value[end..]);
+    (
+        String::from_utf16_lossy(&updated),
+        (start + inserted.len()) as u32,
+    )
+}
+
+/// `piece`, found at `offset` in the text `matches` were searched in, with
+/// the parts matching marked. Shortcodes are expanded if `shortcodes` is
+/// set, per run, so one cut by a match is left as typed.
+fn view_matches(piece: &str, offset: usize, matches: &[Range<usize>], shortcodes: bool) -> Html {
+    html! {
+        { for split_matches(piece, offset, matches).into_iter().map(|(run, matched)| {
+            let run = if shortcodes {
+                expand_shortcodes(run)
+            } else {
+                run.to_string()
+            };
+            if matched {
+                html! { <mark class="bg-yellow-200 rounded-sm dark:bg-yellow-700">{ run }</mark> }
+            } else {
+                html! { { run } }
+            }
+        }) }
+    }
 }
 
-#[derive(Deserialize)]
-struct MessageData {
-    from: String,
-    message: String,
+/// Enter sends; Shift+Enter is kept for newlines. Keys pressed while an IME
+/// composition is in progress (keyCode 229) only confirm the composition.
+fn is_send_key(e: &KeyboardEvent) -> bool {
+    e.key() == "Enter" && !e.shift_key() && !e.is_composing() && e.key_code() != 229
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum MsgTypes {
-    Users,
-    Register,
-    Message,
+/// Whether `e` was fired while the user is typing into a text field.
+fn is_typing(e: &KeyboardEvent) -> bool {
+    e.target()
+        .and_then(|t| t.dyn_into::<web_sys::HtmlElement>().ok())
+        .map(|el| {
+            matches!(el.tag_name().as_str(), "INPUT" | "TEXTAREA") || el.is_content_editable()
+        })
+        .unwrap_or(false)
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WebSocketMessage {
-    message_type: MsgTypes,
-    data_array: Option<Vec<String>>,
-    data: Option<String>,
+/// Key under which the burst starting with `first` is remembered as
+/// expanded.
+fn burst_key(first: &ChatMessage) -> String {
+    format!("{}-{}", first.from, first.timestamp)
 }
 
-#[derive(Clone)]
-struct UserProfile {
-    name: String,
-    avatar: String,
+/// The files carried by a paste or drop.
+fn transferred_files(data: &DataTransfer) -> Vec<File> {
+    let files = match data.files() {
+        Some(files) => files,
+        None => return Vec::new(),
+    };
+    (0..files.length()).filter_map(|i| files.get(i)).collect()
 }
 
-pub struct Chat {
-    users: Vec<UserProfile>,
-    chat_input: NodeRef,
-    _producer: Box<dyn Bridge<EventBus>>,
-    wss: WebsocketService,
-    messages: Vec<MessageData>,
+/// Why the server ended our session, for showing.
+fn refused_text(i18n: &I18n, reason: CloseReason) -> String {
+    i18n.t(match reason {
+        CloseReason::Kicked => "refused.kicked",
+        CloseReason::Banned => "refused.banned",
+        CloseReason::NameTaken => "refused.name_taken",
+    })
+}
+
+/// Stops or lets the page behind an overlay scroll.
+fn lock_page_scroll(lock: bool) {
+    if let Some(body) = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.body())
+    {
+        let _ = body.class_list().toggle_with_force("overflow-hidden", lock);
+    }
+}
+
+/// Looks up the rendered row for message `id` in the document.
+fn find_message_element(id: &str) -> Option<web_sys::Element> {
+    let document = web_sys::window()?.document()?;
+    document
+        .query_selector(&format!(
+            "[data-message-id=\"{}\"]",
+            id.replace('"', "\\\"")
+        ))
+        .ok()
+        .flatten()
 }
 
 impl Component for Chat {
     type Message = Msg;
-    type Properties = ();
+    type Properties = ChatProps;
 
     fn create(ctx: &Context<Self>) -> Self {
         let (user, _) = ctx
             .link()
             .context::<User>(Callback::noop())
             .expect("context to be set");
-        let wss = WebsocketService::new();
         let username = user.username.borrow().clone();
+        let server_url = config::ws_url();
 
-        let message = WebSocketMessage {
-            message_type: MsgTypes::Register,
-            data: Some(username.to_string()),
-            data_array: None,
+        let sweep = {
+            let link = ctx.link().clone();
+            Interval::new(1_000, move || link.send_message(Msg::Sweep))
+        };
+        let tick = {
+            let link = ctx.link().clone();
+            Interval::new(TICK_MS, move || link.send_message(Msg::Tick))
         };
 
-        if let Ok(_) = wss.tx.clone().try_send(serde_json::to_string(&message).unwrap()) {
-            log::debug!("message sent successfully");
-        }
+        let document = web_sys::window().and_then(|w| w.document());
+        let presence_listeners = match &document {
+            Some(document) => ["keydown", "mousedown", "touchstart", "visibilitychange"]
+                .into_iter()
+                .map(|event| {
+                    let link = ctx.link().clone();
+                    EventListener::new(document, event, move |_| {
+                        link.send_message(if event == "visibilitychange" {
+                            Msg::VisibilityChanged
+                        } else {
+                            Msg::Activity
+                        })
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        // Unlike `100dvh`, this shrinks for the on-screen keyboard everywhere,
+        // keeping the input above it.
+        // `VisualViewport` is missing from our version of web-sys.
+        let viewport_listener = web_sys::window()
+            .and_then(|w| js_sys::Reflect::get(&w, &"visualViewport".into()).ok())
+            .and_then(|v| v.dyn_into::<web_sys::EventTarget>().ok())
+            .map(|viewport| {
+                let link = ctx.link().clone();
+                let target = viewport.clone();
+                EventListener::new(&viewport, "resize", move |_| {
+                    if let Some(height) = js_sys::Reflect::get(&target, &"height".into())
+                        .ok()
+                        .and_then(|h| h.as_f64())
+                    {
+                        link.send_message(Msg::ViewportResized(height));
+                    }
+                })
+            });
+        let focus_listeners = match web_sys::window() {
+            Some(window) => ["focus", "blur"]
+                .into_iter()
+                .map(|event| {
+                    let link = ctx.link().clone();
+                    EventListener::new(&window, event, move |_| {
+                        link.send_message(Msg::WindowFocus(event == "focus"))
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        };
 
-        Self {
-            users: Vec::new(),
-            messages: Vec::new(),
+        let (theme, theme_listener) = ctx
+            .link()
+            .context::<ThemeContext>(ctx.link().callback(Msg::ThemeChanged))
+            .unzip();
+        let (locale, locale_listener) = ctx
+            .link()
+            .context::<I18nContext>(ctx.link().callback(Msg::LocaleChanged))
+            .unzip();
+        let mut state = ChatState::new(username, time::now);
+        if let Some(locale) = &locale {
+            state.i18n = (**locale).clone();
+        }
+        state.own_avatar = storage::load_avatar();
+        state.pins = storage::load_pins();
+        state.max_messages = config::max_messages();
+        state.muted = storage::load_muted();
+        state.window_focused = document
+            .as_ref()
+            .and_then(|d| d.has_focus().ok())
+            .unwrap_or(true);
+        state.sent = InputHistory::new(storage::load_sent_history(), INPUT_HISTORY_CAP);
+        state.notifications_enabled = storage::load_notifications_enabled();
+        state.sound_enabled = storage::load_sound_enabled();
+        state.filter_enabled = storage::load_filter_enabled();
+        state.filter_words = storage::load_filter_words();
+        state.word_filter = WordFilter::new(&state.filter_words);
+        state.drafts = storage::load_drafts().into_iter().collect();
+        let input_text = state.drafts.get(&state.active).cloned().unwrap_or_default();
+        let mut chat = Self {
+            state,
+            room_input: NodeRef::default(),
             chat_input: NodeRef::default(),
-            wss,
-            _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
+            emoji_button: NodeRef::default(),
+            input_text,
+            transcript: NodeRef::default(),
+            scroll_to_bottom: false,
+            scroll_anchor: None,
+            scroll_top: 0,
+            viewport_height: DEFAULT_VIEWPORT_PX,
+            row_heights: HashMap::new(),
+            rendered_rows: Cell::new((0, 0)),
+            pending_jump: None,
+            search: None,
+            search_input: NodeRef::default(),
+            focus_search: false,
+            restore_scroll: None,
+            transport: ctx.props().transport.clone(),
+            wss: None,
+            user_filter: String::new(),
+            theme,
+            _theme_listener: theme_listener,
+            locale,
+            _locale_listener: locale_listener,
+            _producer: EventBus::bridge(ctx.link().callback(|e| match e {
+                ChatEvent::ConnectionChanged(state) => Msg::ConnectionState(state),
+                ChatEvent::ConnectionError(error) => Msg::ConnectionError(error),
+                event => Msg::HandleEvent(event),
+            })),
+            server_url,
+            overlay: None,
+            overlay_root: NodeRef::default(),
+            return_focus: None,
+            focus_overlay: false,
+            scroll_locked: false,
+            #[cfg(feature = "frame-log")]
+            frame_log_open: config::query_param("debug").as_deref() == Some("1"),
+            restore_focus: false,
+            settings_input: NodeRef::default(),
+            settings_error: None,
+            toast_timer: None,
+            pending_timeouts: HashMap::new(),
+            read_flush: None,
+            drain_timer: None,
+            chunk_timer: None,
+            incoming: Vec::new(),
+            flush_handle: None,
+            expanded_bursts: HashSet::new(),
+            unfolded: HashSet::new(),
+            departures: HashMap::new(),
+            confirm_delete_timeout: None,
+            highlighted: None,
+            highlight_timeout: None,
+            copied: HashMap::new(),
+            pins_collapsed: false,
+            draft_timer: None,
+            notification_permission_asked: false,
+            broken_images: HashSet::new(),
+            image_queue: VecDeque::new(),
+            preparing_image: false,
+            image_preview: None,
+            preparing_files: 0,
+            file_input: NodeRef::default(),
+            attach_error: None,
+            new_divider_timer: None,
+            typing_idle: None,
+            _typing_sweep: sweep,
+            _tick: tick,
+            idle: IdleTracker::new(IDLE_AFTER_MS, HIDDEN_AFTER_MS, time::now()),
+            _presence_listeners: presence_listeners,
+            rail: storage::load_sidebar_rail(),
+            visual_height: None,
+            _viewport_listener: viewport_listener,
+            _keydown: None,
+            base_title: document.as_ref().map(|d| d.title()).unwrap_or_default(),
+            _focus_listeners: focus_listeners,
+        };
+        chat.restore_history();
+        #[cfg(debug_assertions)]
+        if let Some(count) = config::query_param("fill").and_then(|n| n.parse().ok()) {
+            chat.fill_transcript(count);
         }
+        chat.connect(ctx);
+        chat
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        match msg {
-            Msg::HandleMsg(s) => {
-                if let Ok(msg) = serde_json::from_str::<WebSocketMessage>(&s) {
-                    match msg.message_type {
-                        MsgTypes::Users => {
-                            let users_from_message = msg.data_array.unwrap_or_default();
-                            self.users = users_from_message
-                                .iter()
-                                .map(|u| UserProfile {
-                                    name: u.clone(),
-                                    avatar: format!(
-                                        "https://avatars.dicebear.com/api/adventurer-neutral/{}.svg",
-                                        u
-                                    ),
-                                })
-                                .collect();
-                            true
-                        }
-                        MsgTypes::Message => {
-                            if let Some(data) = msg.data {
-                                if let Ok(message_data) = serde_json::from_str::<MessageData>(&data) {
-                                    self.messages.push(message_data);
-                                    return true;
-                                }
-                            }
-                            false
-                        }
-                        _ => false,
-                    }
-                } else {
-                    false
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        let overlay = self.overlay.as_ref().map(std::mem::discriminant);
+        let render = self.handle_msg(ctx, msg);
+        let effects = self.state.take_effects();
+        let render = self.run_effects(ctx, effects) || render;
+        if let Some(Overlay::Lightbox(id)) = &self.overlay {
+            // Deleted, or gone with a conversation switch.
+            if !self.lightbox_images().iter().any(|(m, _)| &m.id == id) {
+                self.overlay = None;
+            }
+        }
+        let lightbox = matches!(self.overlay, Some(Overlay::Lightbox(_)));
+        if lightbox != self.scroll_locked {
+            self.scroll_locked = lightbox;
+            lock_page_scroll(lightbox);
+        }
+        match (overlay, self.overlay.as_ref().map(std::mem::discriminant)) {
+            (before, Some(after)) if before != Some(after) => {
+                if before.is_none() {
+                    self.return_focus = a11y::active_element();
                 }
+                self.focus_overlay = true;
             }
-            Msg::SubmitMessage => {
-                if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
-                    let message_text = input.value().trim().to_string();
-                    if !message_text.is_empty() {
-                        let message = WebSocketMessage {
-                            message_type: MsgTypes::Message,
-                            data: Some(message_text.clone()),
-                            data_array: None,
-                        };
-                        if let Err(e) = self
-                            .wss
-                            .tx
-                            .clone()
-                            .try_send(serde_json::to_string(&message).unwrap())
-                        {
-                            log::debug!("error sending to channel: {:?}", e);
-                        }
-                        input.set_value("");
-                    }
+            (Some(_), None) => self.restore_focus = true,
+            _ => {}
+        }
+        render
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        self.measure_rows();
+        self.move_focus();
+        if std::mem::take(&mut self.focus_search) {
+            if let Some(input) = self.search_input.cast::<HtmlInputElement>() {
+                let _ = input.focus();
+                input.select();
+            }
+        }
+        if let Some(scroll_top) = self.restore_scroll.take() {
+            if let Some(el) = self.transcript.cast::<web_sys::Element>() {
+                el.set_scroll_top(scroll_top);
+                self.scroll_top = scroll_top;
+            }
+            // Find out whether that left the view at the bottom.
+            ctx.link().send_message(Msg::Scrolled);
+        }
+        if let Some(id) = self.pending_jump.take() {
+            if let Some(element) = find_message_element(&id) {
+                a11y::scroll_into_view(&element);
+            }
+        }
+        if self.scroll_to_bottom {
+            self.scroll_to_bottom = false;
+            if let Some(el) = self.transcript.cast::<web_sys::Element>() {
+                el.set_scroll_top(el.scroll_height());
+            }
+        }
+        if let Some(anchor) = self.scroll_anchor.take() {
+            if let Some(el) = self.transcript.cast::<web_sys::Element>() {
+                el.set_scroll_top(el.scroll_height() - anchor);
+            }
+        }
+        if !first_render {
+            return;
+        }
+        if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+            // The draft left in the open conversation last time.
+            input.set_value(&self.input_text);
+        }
+        let document = match web_sys::window().and_then(|w| w.document()) {
+            Some(document) => document,
+            None => return,
+        };
+        let link = ctx.link().clone();
+        self._keydown = Some(EventListener::new(&document, "keydown", move |e| {
+            let e = match e.dyn_ref::<KeyboardEvent>() {
+                Some(e) => e,
+                None => return,
+            };
+            if let Some(shortcut) = shortcut_for(
+                &e.key(),
+                e.ctrl_key() || e.meta_key(),
+                e.alt_key(),
+                is_typing(e),
+            ) {
+                if shortcut != Shortcut::Dismiss {
+                    e.prevent_default();
                 }
-                false
+                link.send_message(Msg::Shortcut(shortcut));
             }
+        }));
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        if ctx.props().transport != self.transport {
+            self.transport = ctx.props().transport.clone();
+            self.disconnect();
+            self.connect(ctx);
         }
+        true
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        self._keydown = None;
+        if self.scroll_locked {
+            lock_page_scroll(false);
+        }
+        self._focus_listeners.clear();
+        self.clear_title_unread();
+        self.disconnect();
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let submit = ctx.link().callback(|_| Msg::SubmitMessage);
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::Input(input.value())
+        });
+        let onkeydown = ctx.link().callback(Msg::KeyDown);
+        let onpaste = ctx.link().callback(Msg::Paste);
+        // Without this the browser opens dropped files itself.
+        let ondragover = Callback::from(|e: DragEvent| e.prevent_default());
+        let ondrop = ctx.link().batch_callback(|e: DragEvent| {
+            e.prevent_default();
+            let (images, files): (Vec<File>, Vec<File>) = e
+                .data_transfer()
+                .map(|data| transferred_files(&data))
+                .unwrap_or_default()
+                .into_iter()
+                .partition(|file| file.type_().starts_with("image/"));
+            let mut msgs = Vec::new();
+            if !images.is_empty() {
+                msgs.push(Msg::AttachImages(images));
+            }
+            if !files.is_empty() {
+                msgs.push(Msg::AttachFiles(files));
+            }
+            msgs
+        });
+        let onfilechange = ctx.link().batch_callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let files = input.files().map(|list| {
+                (0..list.length())
+                    .filter_map(|i| list.get(i))
+                    .collect::<Vec<_>>()
+            });
+            // Lets the same file be picked again.
+            input.set_value("");
+            files
+                .filter(|files| !files.is_empty())
+                .map(Msg::AttachFiles)
+        });
+        let pick_file = {
+            let file_input = self.file_input.clone();
+            Callback::from(move |_| {
+                if let Some(input) = file_input.cast::<HtmlElement>() {
+                    input.click();
+                }
+            })
+        };
+        let input_chars = self.input_chars();
+        let over_limit = input_chars > MAX_MESSAGE_CHARS;
+        let toggle_settings = ctx.link().callback(|_| Msg::ToggleSettings);
+        let toggle_notifications = ctx.link().callback(|_| Msg::ToggleNotifications);
+        let toggle_sound = ctx.link().callback(|_| Msg::ToggleSound);
+        let reconnect = ctx.link().callback(|_| Msg::ReconnectNow);
+        let active = self.state.conversations.get(&self.state.active);
+        let trimmed = active.map(|c| c.messages.trimmed()).unwrap_or_default();
+        let loading_older = active.is_some_and(|c| c.loading_older);
+        let dark = self.theme.as_deref() == Some(&Theme::Dark);
+        let searching = self.search.is_some();
 
         html! {
-            <div class="flex w-screen h-screen font-sans text-gray-800">
-                // Sidebar Users List
-                <aside class="flex-none w-60 bg-gray-50 border-r border-gray-200 overflow-y-auto">
-                    <h2 class="text-2xl font-semibold p-4 border-b border-gray-200">{"Users"}</h2>
-                    <ul class="divide-y divide-gray-200">
-                        { for self.users.iter().map(|u| html! {
-                            <li class="flex items-center p-3 hover:bg-gray-100 cursor-pointer">
-                                <img
-                                    class="w-12 h-12 rounded-full mr-4"
-                                    src={u.avatar.clone()}
-                                    alt={format!("Avatar of {}", u.name)}
-                                />
-                                <div class="flex flex-col">
-                                    <span class="font-medium">{ &u.name }</span>
-                                    <span class="text-xs text-gray-500">{"Online"}</span>
-                                </div>
-                            </li>
-                        })}
-                    </ul>
-                </aside>
+            <div
+                class="flex w-screen h-dvh font-sans text-gray-800 dark:text-gray-100"
+                style={self.visual_height.map(|height| format!("height: {}px", height))}
+            >
+                { self.view_sidebar(ctx) }
 
                 // Chat Area
-                <main class="flex flex-col flex-grow bg-white">
-                    <header class="flex items-center justify-between p-4 border-b border-gray-200 bg-gray-100">
-                        <h1 class="text-xl font-semibold">{"💬 Chat!"}</h1>
-                    </header>
-
-                    <section class="flex-grow overflow-auto p-4 space-y-4 bg-gray-50">
-                        { for self.messages.iter().map(|m| {
-                            let user = self.users.iter().find(|u| u.name == m.from);
-
-                            html! {
-                                <div class="flex items-start space-x-3 max-w-xl">
-                                    {
-                                        if let Some(user) = user {
-                                            html! {
-                                                <img
-                                                    class="w-10 h-10 rounded-full"
-                                                    src={user.avatar.clone()}
-                                                    alt={format!("Avatar of {}", user.name)}
-                                                />
-                                            }
-                                        } else {
-                                            html! {
-                                                <div class="w-10 h-10 rounded-full bg-gray-300 flex items-center justify-center text-gray-600">
-                                                    {"?"}
-                                                </div>
-                                            }
-                                        }
+                <main {ondragover} {ondrop} class="relative flex flex-col flex-grow bg-white dark:bg-gray-800">
+                    <header class="relative flex flex-wrap items-center justify-between gap-2 p-2 md:p-4 border-b border-gray-200 bg-gray-100 dark:border-gray-700 dark:bg-gray-700">
+                        <div class="flex items-center space-x-3">
+                            {
+                                if ctx.props().sidebar_collapsed {
+                                    html! {}
+                                } else {
+                                    html! {
+                                        <button
+                                            onclick={ctx.link().callback(|_| Msg::ToggleDrawer)}
+                                            class="md:hidden text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                            aria-label={self.t("sidebar.open")}
+                                            aria-controls="chat-sidebar"
+                                            aria-expanded={(self.overlay == Some(Overlay::Drawer)).to_string()}
+                                        >
+                                            {"☰"}
+                                        </button>
                                     }
-
-                                    <div>
-                                        <div class="text-sm font-semibold">{ &m.from }</div>
-                                        <div class="mt-1 text-gray-700 text-sm max-w-prose break-words">
-                                            {
-                                                if m.message.ends_with(".gif") {
-                                                    html! {
-                                                        <img class="rounded-md max-w-xs" src={m.message.clone()} alt="gif" />
-                                                    }
-                                                } else {
-                                                    html! {
-                                                        <p>{ &m.message }</p>
-                                                    }
-                                                }
+                                }
+                            }
+                            { self.view_title(ctx) }
+                            <span class="text-xs text-gray-500 dark:text-gray-400" title={self.peak_label()}>
+                                { self.online_label(true) }
+                            </span>
+                            { self.view_connection_state() }
+                        </div>
+                        <div class="flex items-center space-x-2">
+                            <button
+                                onclick={ctx.link().callback(move |_| if searching { Msg::CloseSearch } else { Msg::OpenSearch })}
+                                class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                aria-label={self.t("search.open")}
+                                aria-pressed={searching.to_string()}
+                            >
+                                {"🔍"}
+                            </button>
+                            <button
+                                onclick={ctx.link().callback(|_| Msg::StartRename)}
+                                class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                aria-label={self.t("header.rename")}
+                                title={self.state.i18n.t_with("header.signed_in_as", &[("name", &self.state.username)])}
+                            >
+                                {"✎"}
+                            </button>
+                            <button
+                                onclick={ctx.link().callback(|_| Msg::Logout)}
+                                class="px-2 py-1 text-sm text-gray-500 hover:text-gray-800 rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                            >
+                                { self.t("header.log_out") }
+                            </button>
+                            <button
+                                onclick={ctx.link().callback(|_| Msg::ToggleTheme)}
+                                class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                aria-label={self.t(if dark { "header.theme_light" } else { "header.theme_dark" })}
+                                aria-pressed={dark.to_string()}
+                            >
+                                { if dark { "☀" } else { "🌙" } }
+                            </button>
+                            <button
+                                onclick={toggle_sound}
+                                class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                aria-label={self.t(if self.state.sound_enabled { "header.sound_off" } else { "header.sound_on" })}
+                                aria-pressed={self.state.sound_enabled.to_string()}
+                            >
+                                { if self.state.sound_enabled { "🔊" } else { "🔇" } }
+                            </button>
+                            <button
+                                onclick={ctx.link().callback(|_| Msg::ToggleFilter)}
+                                class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                aria-label={self.t(if self.state.filter_enabled { "header.filter_off" } else { "header.filter_on" })}
+                                aria-pressed={self.state.filter_enabled.to_string()}
+                            >
+                                { if self.state.filter_enabled { "🙊" } else { "💬" } }
+                            </button>
+                            <button
+                                onclick={toggle_notifications}
+                                class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                aria-label={self.t(if self.state.notifications_enabled { "header.notifications_off" } else { "header.notifications_on" })}
+                                aria-pressed={self.state.notifications_enabled.to_string()}
+                            >
+                                { if self.state.notifications_enabled { "🔔" } else { "🔕" } }
+                            </button>
+                            <button
+                                onclick={toggle_settings}
+                                class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 dark:text-gray-400 dark:hover:text-gray-100"
+                                aria-label={self.t("settings.title")}
+                                aria-expanded={(self.overlay == Some(Overlay::Settings)).to_string()}
+                            >
+                                {"⚙"}
+                            </button>
+                        </div>
+                        {
+                            if self.overlay == Some(Overlay::Settings) {
+                                html! {
+                                    <div
+                                        ref={self.overlay_root.clone()}
+                                        onkeydown={self.trap_focus()}
+                                        class="absolute right-4 top-full mt-2 w-80 p-4 bg-white border border-gray-200 rounded-md shadow-lg z-10 dark:bg-gray-800 dark:border-gray-700"
+                                        role="dialog"
+                                        aria-label={self.t("settings.title")}
+                                    >
+                                        <label class="block text-sm font-medium mb-1">{ self.t("settings.server_url") }</label>
+                                        <input
+                                            ref={self.settings_input.clone()}
+                                            type="text"
+                                            value={self.server_url.clone()}
+                                            class="w-full px-3 py-2 rounded-md border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                                        />
+                                        {
+                                            if let Some(error) = &self.settings_error {
+                                                html! { <p class="mt-1 text-xs text-red-600 dark:text-red-400">{ error }</p> }
+                                            } else {
+                                                html! {}
                                             }
-                                        </div>
+                                        }
+                                        <label class="block mt-3 text-sm font-medium mb-1">{ self.t("settings.language") }</label>
+                                        <select
+                                            onchange={ctx.link().callback(|e: Event| {
+                                                let select: HtmlSelectElement = e.target_unchecked_into();
+                                                Msg::SetLocale(select.value())
+                                            })}
+                                            class="w-full px-3 py-2 rounded-md border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                                        >
+                                            { for i18n::available().into_iter().map(|(tag, name)| html! {
+                                                <option value={tag} selected={tag == self.state.i18n.locale()}>{ name }</option>
+                                            })}
+                                        </select>
+                                        <label class="block mt-3 text-sm font-medium mb-1">{ self.t("settings.filter_words") }</label>
+                                        <input
+                                            type="text"
+                                            value={self.state.filter_words.join(", ")}
+                                            onchange={ctx.link().callback(|e: Event| {
+                                                let input: HtmlInputElement = e.target_unchecked_into();
+                                                Msg::SetFilterWords(input.value())
+                                            })}
+                                            placeholder={self.t("settings.filter_words_hint")}
+                                            class="w-full px-3 py-2 rounded-md border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                                        />
+                                        <button
+                                            onclick={reconnect}
+                                            class={self.accent(ctx, "mt-3 w-full bg-blue-600 hover:bg-blue-700 text-white rounded-md py-2 text-sm")}
+                                        >
+                                            { self.t("settings.reconnect") }
+                                        </button>
+                                        { self.view_diagnostics() }
                                     </div>
-                                </div>
+                                }
+                            } else {
+                                html! {}
                             }
+                        }
+                    </header>
+                    { self.view_pins(ctx) }
+                    { self.view_search(ctx) }
+
+                    // Rows come and go as the transcript scrolls, so instead of
+                    // being live itself it leaves announcing new messages to
+                    // the log after it.
+                    <section
+                        ref={self.transcript.clone()}
+                        onscroll={ctx.link().callback(|_| Msg::Scrolled)}
+                        onkeydown={ctx.link().batch_callback(|e: KeyboardEvent| {
+                            let find = (e.ctrl_key() || e.meta_key()) && e.key().eq_ignore_ascii_case("f");
+                            find.then(|| {
+                                e.prevent_default();
+                                Msg::OpenSearch
+                            })
                         })}
+                        class="flex-grow overflow-auto p-4 bg-gray-50 dark:bg-gray-900"
+                        role="log"
+                        aria-live="off"
+                        aria-label={self.t("chat.transcript")}
+                        aria-busy={loading_older.to_string()}
+                        tabindex="0"
+                    >
+                        {
+                            if trimmed > 0 {
+                                html! {
+                                    <div class="pb-4 text-center text-xs text-gray-400 dark:text-gray-500">
+                                        { self.state.i18n.plural("chat.trimmed", trimmed, &[]) }
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            if loading_older {
+                                html! {
+                                    <div
+                                        role="status"
+                                        class="pb-4 flex justify-center items-center gap-2 text-xs text-gray-400 dark:text-gray-500"
+                                    >
+                                        <span class="w-3 h-3 rounded-full border-2 border-gray-300 border-t-transparent animate-spin motion-reduce:animate-none dark:border-gray-600"></span>
+                                        { self.t("chat.loading_older") }
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        { self.view_transcript(ctx) }
                     </section>
+                    <div class="sr-only" role="log" aria-live="polite">
+                        { for self.state.announcements.iter().map(|text| html! { <p>{ text }</p> }) }
+                    </div>
+
+                    { self.view_typing() }
+                    { self.view_unseen_pill(ctx) }
+                    <ToastStack
+                        toasts={self.state.toasts.iter().cloned().collect::<Vec<_>>()}
+                        ondismiss={ctx.link().callback(Msg::DismissToast)}
+                        onretry={ctx.link().callback(Msg::RetrySend)}
+                    />
+                    {
+                        if self.state.throttled.is_empty() {
+                            html! {}
+                        } else {
+                            html! {
+                                <div class="px-4 py-1 border-t border-amber-200 bg-amber-50 text-xs text-amber-700 dark:border-amber-700 dark:bg-amber-900 dark:text-amber-300" aria-live="polite">
+                                    { self.t("compose.throttled") }
+                                </div>
+                            }
+                        }
+                    }
+                    { self.view_attachments(ctx) }
+                    { self.view_reply_preview(ctx) }
 
-                    <footer class="p-4 border-t border-gray-200 bg-white flex items-center space-x-3">
+                    <footer class="relative p-4 border-t border-gray-200 bg-white flex items-center space-x-3 dark:border-gray-700 dark:bg-gray-800">
                         <input
                             ref={self.chat_input.clone()}
+                            {oninput}
+                            {onkeydown}
+                            {onpaste}
                             type="text"
-                            placeholder="Type your message..."
-                            class="flex-grow px-4 py-2 rounded-full border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 focus:border-transparent"
+                            placeholder={self.t("compose.placeholder")}
+                            class="flex-grow px-4 py-2 rounded-full border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 focus:border-transparent dark:border-gray-600"
                             autocomplete="off"
                         />
+                        <input ref={self.file_input.clone()} type="file" multiple=true class="hidden" onchange={onfilechange} />
+                        <button
+                            onclick={pick_file}
+                            disabled={self.wss.is_none()}
+                            class="text-gray-500 hover:text-gray-800 text-xl rounded focus:outline-none focus:ring-2 focus:ring-blue-400 disabled:opacity-50 dark:text-gray-400 dark:hover:text-gray-100"
+                            aria-label={self.t("file.attach")}
+                        >
+                            {"📎"}
+                        </button>
+                        { self.view_emoji_picker(ctx) }
+                        { self.view_gif_picker(ctx) }
+                        {
+                            // Only shown once the limit is getting close.
+                            if input_chars * 5 >= MAX_MESSAGE_CHARS * 4 {
+                                html! {
+                                    <span
+                                        class={classes!(
+                                            "text-xs", "tabular-nums",
+                                            if over_limit { "text-red-600 font-semibold dark:text-red-400" } else { "text-gray-400 dark:text-gray-500" },
+                                        )}
+                                        aria-live="polite"
+                                    >
+                                        { format!("{}/{}", input_chars, MAX_MESSAGE_CHARS) }
+                                    </span>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
                         <button
                             onclick={submit}
-                            class="bg-blue-600 hover:bg-blue-700 text-white rounded-full w-12 h-12 flex items-center justify-center shadow-md transition-colors duration-200"
-                            aria-label="Send message"
+                            disabled={self.input_text.trim().is_empty() || over_limit || self.wss.is_none()}
+                            class={self.accent(ctx, "bg-blue-600 hover:bg-blue-700 text-white rounded-full w-12 h-12 flex items-center justify-center shadow-md transition-colors duration-200 motion-reduce:transition-none focus:outline-none focus:ring-2 focus:ring-blue-400 disabled:opacity-50 disabled:cursor-not-allowed")}
+                            aria-label={self.t("compose.send")}
                         >
                             <svg
                                 xmlns="http://www.w3.org/2000/svg"
@@ -238,6 +4860,14 @@ impl Component for Chat {
                         </button>
                     </footer>
                 </main>
+
+                { self.view_shortcuts(ctx) }
+                { self.view_user_popover(ctx) }
+                { self.view_message_menu(ctx) }
+                { self.view_lightbox(ctx) }
+                { self.view_frame_log(ctx) }
+                { self.view_version_banner(ctx) }
+                { self.view_refused(ctx) }
             </div>
         }
     }
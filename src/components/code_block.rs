@@ -0,0 +1,114 @@
+use gloo_timers::callback::Timeout;
+use yew::prelude::*;
+
+use crate::code::{highlight, Kind, Language};
+use crate::components::chat::COPIED_FEEDBACK_MS;
+use crate::i18n;
+use crate::services::clipboard;
+
+pub enum Msg {
+    Copy,
+    Copied,
+    CopiedEnded,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// The tag after the opening fence, shown as a badge.
+    #[prop_or_default]
+    pub lang: Option<String>,
+    pub code: String,
+}
+
+/// A fenced code block: monospace, scrolling sideways rather than
+/// wrapping, highlighted if we know its language, with a copy button.
+pub struct CodeBlock {
+    /// Set while the copy button shows that it worked.
+    copied: Option<Timeout>,
+}
+
+fn kind_class(kind: Kind) -> Option<&'static str> {
+    match kind {
+        Kind::Plain => None,
+        Kind::Keyword => Some("text-purple-700 dark:text-purple-300"),
+        Kind::Literal => Some("text-blue-700 dark:text-blue-300"),
+        Kind::Type => Some("text-teal-700 dark:text-teal-300"),
+        Kind::String => Some("text-green-700 dark:text-green-300"),
+        Kind::Number => Some("text-orange-700 dark:text-orange-300"),
+        Kind::Comment => Some("italic text-gray-500 dark:text-gray-400"),
+    }
+}
+
+impl Component for CodeBlock {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self { copied: None }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Copy => {
+                let link = ctx.link().clone();
+                clipboard::copy(ctx.props().code.clone(), move |copied| {
+                    if copied {
+                        link.send_message(Msg::Copied);
+                    } else {
+                        log::warn!("could not copy code block");
+                    }
+                });
+                false
+            }
+            Msg::Copied => {
+                let link = ctx.link().clone();
+                self.copied = Some(Timeout::new(COPIED_FEEDBACK_MS, move || {
+                    link.send_message(Msg::CopiedEnded)
+                }));
+                true
+            }
+            Msg::CopiedEnded => self.copied.take().is_some(),
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let props = ctx.props();
+        let i18n = i18n::of(ctx);
+        let language = props.lang.as_deref().and_then(Language::from_tag);
+        let copied = self.copied.is_some();
+
+        html! {
+            <div class="my-1 rounded-md border border-gray-200 bg-gray-50 dark:border-gray-700 dark:bg-gray-900">
+                <div class="flex items-center justify-between px-2 py-0.5 border-b border-gray-200 dark:border-gray-700">
+                    <span class="text-xs font-mono text-gray-500 dark:text-gray-400">
+                        { props.lang.clone().unwrap_or_else(|| i18n.t("code.plain")) }
+                    </span>
+                    <button
+                        onclick={ctx.link().callback(|_| Msg::Copy)}
+                        class={classes!(
+                            "text-xs", "rounded", "focus:outline-none", "focus:ring-2", "focus:ring-blue-400",
+                            if copied {
+                                "text-green-600 dark:text-green-400"
+                            } else {
+                                "text-gray-400 hover:text-blue-600 dark:text-gray-500"
+                            },
+                        )}
+                        aria-label={i18n.t(if copied { "code.copied" } else { "code.copy" })}
+                    >
+                        { i18n.t(if copied { "message.copied_button" } else { "message.copy_button" }) }
+                    </button>
+                </div>
+                <pre class="overflow-x-auto p-2 text-sm font-mono whitespace-pre">
+                    <code>
+                        { for highlight(language, &props.code).into_iter().map(|(kind, piece)| {
+                            match kind_class(kind) {
+                                Some(class) => html! { <span {class}>{ piece }</span> },
+                                None => html! { { piece } },
+                            }
+                        }) }
+                    </code>
+                </pre>
+            </div>
+        }
+    }
+}
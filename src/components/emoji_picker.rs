@@ -0,0 +1,325 @@
+use gloo_events::EventListener;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, HtmlInputElement, Node};
+use yew::prelude::*;
+
+use crate::a11y;
+use crate::i18n;
+use crate::services::storage;
+
+/// Emoji offered by the picker, grouped by category. Each comes with the
+/// words it can be found by; a category's heading is the `emoji.<name>`
+/// locale key, lowercased.
+const CATEGORIES: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Smileys",
+        &[
+            ("😀", "grinning smile happy"),
+            ("😂", "joy laugh tears"),
+            ("🙂", "slight smile"),
+            ("😉", "wink"),
+            ("😊", "blush smile"),
+            ("😍", "heart eyes love"),
+            ("😘", "kiss"),
+            ("😎", "cool sunglasses"),
+            ("🤔", "thinking"),
+            ("😐", "neutral"),
+            ("🙄", "eye roll"),
+            ("😴", "sleeping"),
+            ("😢", "cry sad"),
+            ("😭", "sob cry"),
+            ("😡", "angry rage"),
+            ("😱", "scream shock"),
+        ],
+    ),
+    (
+        "Gestures",
+        &[
+            ("👍", "thumbs up +1 yes"),
+            ("👎", "thumbs down -1 no"),
+            ("👏", "clap applause"),
+            ("🙌", "raised hands hooray"),
+            ("🙏", "pray please thanks"),
+            ("👋", "wave hello bye"),
+            ("🤝", "handshake deal"),
+            ("💪", "muscle strong"),
+        ],
+    ),
+    (
+        "Symbols",
+        &[
+            ("❤️", "heart love red"),
+            ("💔", "broken heart"),
+            ("🔥", "fire lit"),
+            ("✨", "sparkles"),
+            ("🎉", "tada party celebrate"),
+            ("💯", "hundred perfect"),
+            ("✅", "check done yes"),
+            ("❌", "cross no wrong"),
+            ("⚠️", "warning"),
+            ("❓", "question"),
+        ],
+    ),
+    (
+        "Things",
+        &[
+            ("☕", "coffee"),
+            ("🍕", "pizza"),
+            ("🍺", "beer"),
+            ("🎂", "cake birthday"),
+            ("🚀", "rocket ship launch"),
+            ("💻", "computer laptop"),
+            ("🐛", "bug"),
+            ("📌", "pin"),
+        ],
+    ),
+];
+
+/// Recently used emoji kept for the top row.
+const RECENT_CAP: usize = 20;
+/// Emoji per grid row, matching the `grid-cols-8` class.
+const COLUMNS: usize = 8;
+
+pub enum Msg {
+    Search(String),
+    Pick(String),
+    /// Moves the keyboard highlight by this many cells.
+    Move(isize),
+    PickHighlighted,
+    FocusGrid,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub on_select: Callback<String>,
+    pub on_close: Callback<()>,
+    /// The button that opens the picker. Clicks on it are left to the
+    /// button rather than treated as clicks outside.
+    #[prop_or_default]
+    pub anchor: NodeRef,
+}
+
+pub struct EmojiPicker {
+    query: String,
+    recent: Vec<String>,
+    highlighted: usize,
+    root: NodeRef,
+    grid: NodeRef,
+    _click_outside: Option<EventListener>,
+}
+
+impl EmojiPicker {
+    /// Sections to show, in order: recent picks and every category, or just
+    /// the matches while searching.
+    fn sections(&self) -> Vec<(&str, Vec<&str>)> {
+        let query = self.query.trim().to_lowercase();
+        if !query.is_empty() {
+            let matches = CATEGORIES
+                .iter()
+                .flat_map(|(_, emoji)| emoji.iter())
+                .filter(|(_, words)| words.contains(query.as_str()))
+                .map(|(emoji, _)| *emoji)
+                .collect();
+            return vec![("Results", matches)];
+        }
+
+        let mut sections = Vec::new();
+        if !self.recent.is_empty() {
+            sections.push(("Recent", self.recent.iter().map(String::as_str).collect()));
+        }
+        sections.extend(
+            CATEGORIES
+                .iter()
+                .map(|(name, emoji)| (*name, emoji.iter().map(|(e, _)| *e).collect())),
+        );
+        sections
+    }
+
+    fn visible(&self) -> Vec<String> {
+        self.sections()
+            .into_iter()
+            .flat_map(|(_, emoji)| emoji)
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Component for EmojiPicker {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            query: String::new(),
+            recent: storage::load_recent_emoji(),
+            highlighted: 0,
+            root: NodeRef::default(),
+            grid: NodeRef::default(),
+            _click_outside: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Search(query) => {
+                self.query = query;
+                self.highlighted = 0;
+                true
+            }
+            Msg::Pick(emoji) => {
+                self.recent.retain(|e| e != &emoji);
+                self.recent.insert(0, emoji.clone());
+                self.recent.truncate(RECENT_CAP);
+                storage::save_recent_emoji(&self.recent);
+                ctx.props().on_select.emit(emoji);
+                true
+            }
+            Msg::Move(by) => {
+                let count = self.visible().len();
+                if count == 0 {
+                    return false;
+                }
+                let target = self.highlighted as isize + by;
+                self.highlighted = target.clamp(0, count as isize - 1) as usize;
+                true
+            }
+            Msg::PickHighlighted => match self.visible().get(self.highlighted) {
+                Some(emoji) => {
+                    ctx.link().send_message(Msg::Pick(emoji.clone()));
+                    false
+                }
+                None => false,
+            },
+            Msg::FocusGrid => {
+                if let Some(grid) = self.grid.cast::<HtmlElement>() {
+                    let _ = grid.focus();
+                }
+                false
+            }
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if !first_render {
+            return;
+        }
+        if let Some(root) = self.root.cast::<Element>() {
+            a11y::focus_first(&root);
+        }
+        let document = match web_sys::window().and_then(|w| w.document()) {
+            Some(document) => document,
+            None => return,
+        };
+        let root = self.root.clone();
+        let anchor = ctx.props().anchor.clone();
+        let on_close = ctx.props().on_close.clone();
+        self._click_outside = Some(EventListener::new(&document, "mousedown", move |e| {
+            let target = e.target().and_then(|t| t.dyn_into::<Node>().ok());
+            let target = match target {
+                Some(target) => target,
+                None => return,
+            };
+            let inside = [&root, &anchor]
+                .iter()
+                .filter_map(|node| node.get())
+                .any(|node| node.contains(Some(&target)));
+            if !inside {
+                on_close.emit(());
+            }
+        }));
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let i18n = i18n::of(ctx);
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::Search(input.value())
+        });
+        let search_keydown = ctx
+            .link()
+            .batch_callback(|e: KeyboardEvent| match e.key().as_str() {
+                "ArrowDown" => {
+                    e.prevent_default();
+                    Some(Msg::FocusGrid)
+                }
+                "Enter" => {
+                    e.prevent_default();
+                    Some(Msg::PickHighlighted)
+                }
+                _ => None,
+            });
+        let grid_keydown = ctx.link().batch_callback(|e: KeyboardEvent| {
+            let msg = match e.key().as_str() {
+                "ArrowLeft" => Msg::Move(-1),
+                "ArrowRight" => Msg::Move(1),
+                "ArrowUp" => Msg::Move(-(COLUMNS as isize)),
+                "ArrowDown" => Msg::Move(COLUMNS as isize),
+                "Enter" | " " => Msg::PickHighlighted,
+                _ => return None,
+            };
+            e.prevent_default();
+            Some(msg)
+        });
+
+        let root = self.root.clone();
+        let trap_focus = Callback::from(move |e: KeyboardEvent| {
+            if let Some(root) = root.cast::<Element>() {
+                a11y::trap_focus(&root, &e);
+            }
+        });
+
+        let mut index = 0;
+        html! {
+            <div
+                ref={self.root.clone()}
+                onkeydown={trap_focus}
+                class="absolute bottom-20 right-4 w-80 max-h-96 flex flex-col bg-white border border-gray-200 rounded-md shadow-lg z-10 dark:bg-gray-800 dark:border-gray-700"
+                role="dialog"
+                aria-label={i18n.t("emoji.picker")}
+            >
+                <div class="p-2 border-b border-gray-200 dark:border-gray-700">
+                    <input
+                        {oninput}
+                        onkeydown={search_keydown}
+                        type="text"
+                        placeholder={i18n.t("emoji.search")}
+                        class="w-full px-3 py-1 rounded-md border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                    />
+                </div>
+                <div
+                    ref={self.grid.clone()}
+                    onkeydown={grid_keydown}
+                    tabindex="0"
+                    class="flex-grow overflow-y-auto p-2 focus:outline-none"
+                >
+                    { for self.sections().into_iter().map(|(name, emoji)| html! {
+                        <>
+                            <h3 class="mt-1 mb-1 text-xs font-semibold text-gray-500 dark:text-gray-400">{ i18n.t(&format!("emoji.{}", name.to_lowercase())) }</h3>
+                            <div class="grid grid-cols-8 gap-1">
+                                { for emoji.into_iter().map(|e| {
+                                    let highlighted = index == self.highlighted;
+                                    index += 1;
+                                    let emoji = e.to_string();
+                                    let onclick = ctx.link().callback(move |_| Msg::Pick(emoji.clone()));
+                                    html! {
+                                        <button
+                                            {onclick}
+                                            tabindex="-1"
+                                            class={classes!(
+                                                "text-xl", "rounded", "hover:bg-gray-100", "dark:hover:bg-gray-700",
+                                                highlighted.then_some("ring-2 ring-blue-400"),
+                                            )}
+                                            aria-label={e.to_string()}
+                                        >
+                                            { e }
+                                        </button>
+                                    }
+                                })}
+                            </div>
+                        </>
+                    })}
+                </div>
+            </div>
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use gloo_timers::callback::Interval;
+use js_sys::Date;
+use wasm_bindgen::JsValue;
+use yew::prelude::*;
+
+use crate::i18n;
+use crate::services::files;
+use crate::services::frame_log::{self, Direction, FrameRecord};
+
+/// How often the panel catches up with the log while open.
+const REFRESH_MS: u32 = 500;
+
+pub enum Msg {
+    Refresh,
+    Clear,
+    Export,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub on_close: Callback<()>,
+}
+
+/// Live view of the frames the websocket service recorded, newest first,
+/// with the connection's counters. Nothing is read from the log unless the
+/// panel is mounted.
+pub struct FrameLogPanel {
+    _refresh: Interval,
+}
+
+/// Time of day of `timestamp` down to the millisecond.
+fn clock(timestamp: u64) -> String {
+    let date = Date::new(&JsValue::from_f64(timestamp as f64));
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        date.get_hours(),
+        date.get_minutes(),
+        date.get_seconds(),
+        date.get_milliseconds()
+    )
+}
+
+fn view_record(record: &FrameRecord) -> Html {
+    let (arrow, class) = match record.direction {
+        Direction::In => ("↓", "text-green-600 dark:text-green-400"),
+        Direction::Out => ("↑", "text-blue-600 dark:text-blue-400"),
+    };
+    let kind = match &record.kind {
+        Ok(kind) => html! { { kind.name() } },
+        Err(error) => {
+            html! { <span class="text-red-600 dark:text-red-400" title={error.clone()}>{ error }</span> }
+        }
+    };
+
+    html! {
+        <tr class="align-top">
+            <td class={classes!("pr-2", class)}>{ arrow }</td>
+            <td class="pr-2 tabular-nums text-gray-500 dark:text-gray-400">{ clock(record.timestamp) }</td>
+            <td class="pr-2 break-all">{ kind }</td>
+            <td class="text-right tabular-nums">{ record.bytes }</td>
+        </tr>
+    }
+}
+
+impl Component for FrameLogPanel {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let link = ctx.link().clone();
+        Self {
+            _refresh: Interval::new(REFRESH_MS, move || link.send_message(Msg::Refresh)),
+        }
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Refresh => true,
+            Msg::Clear => {
+                frame_log::clear();
+                true
+            }
+            Msg::Export => {
+                match frame_log::with(serde_json::to_vec_pretty) {
+                    Ok(json) => {
+                        files::save_bytes(&json, "application/json", "yewchat-frames.json");
+                    }
+                    Err(e) => log::error!("could not export the frame log: {}", e),
+                }
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let i18n = i18n::of(ctx);
+        let button = "px-2 py-0.5 rounded border border-gray-300 hover:bg-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:hover:bg-gray-700";
+
+        frame_log::with(|log| {
+            let counters = log.counters;
+            html! {
+                <section
+                    class="fixed bottom-4 left-4 z-40 flex flex-col w-96 max-w-full max-h-96 bg-white border border-gray-200 rounded-md shadow-lg text-xs font-mono dark:bg-gray-800 dark:border-gray-700"
+                    aria-label={i18n.t("frame_log.title")}
+                >
+                    <header class="flex items-center gap-2 px-3 py-2 border-b border-gray-200 dark:border-gray-700">
+                        <h2 class="font-semibold font-sans text-sm mr-auto">{ i18n.t("frame_log.title") }</h2>
+                        <button onclick={ctx.link().callback(|_| Msg::Clear)} class={button}>
+                            { i18n.t("frame_log.clear") }
+                        </button>
+                        <button onclick={ctx.link().callback(|_| Msg::Export)} class={button}>
+                            { i18n.t("frame_log.export") }
+                        </button>
+                        <button
+                            onclick={ctx.props().on_close.reform(|_| ())}
+                            class="px-1 rounded text-gray-400 hover:text-gray-700 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:hover:text-gray-200"
+                            aria-label={i18n.t("frame_log.close")}
+                        >
+                            {"✕"}
+                        </button>
+                    </header>
+                    <dl class="flex gap-4 px-3 py-1 border-b border-gray-200 dark:border-gray-700">
+                        <div class="flex gap-1"><dt>{ i18n.t("frame_log.reconnects") }</dt><dd>{ counters.reconnects }</dd></div>
+                        <div class="flex gap-1"><dt>{ i18n.t("frame_log.dropped") }</dt><dd>{ counters.dropped }</dd></div>
+                        <div class="flex gap-1"><dt>{ i18n.t("frame_log.send_failures") }</dt><dd>{ counters.send_failures }</dd></div>
+                    </dl>
+                    <div class="overflow-y-auto px-3 py-1">
+                        <table class="w-full">
+                            <tbody>
+                                { for log.frames().rev().map(view_record) }
+                            </tbody>
+                        </table>
+                    </div>
+                </section>
+            }
+        })
+    }
+}
@@ -0,0 +1,375 @@
+use gloo_net::http::Request;
+use gloo_timers::callback::Timeout;
+use serde::Deserialize;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::config;
+use crate::i18n;
+use crate::sanitize::{safe_image_url, safe_web_url};
+
+/// GIF search API key, baked in at build time through `YEWCHAT_GIF_API_KEY`
+/// (or, as before, `YEWCHAT_TENOR_API_KEY`).
+const BUILD_API_KEY: Option<&str> = match option_env!("YEWCHAT_GIF_API_KEY") {
+    Some(key) => Some(key),
+    None => option_env!("YEWCHAT_TENOR_API_KEY"),
+};
+/// Search endpoint baked in at build time through `YEWCHAT_GIF_SEARCH_URL`.
+const BUILD_SEARCH_URL: Option<&str> = option_env!("YEWCHAT_GIF_SEARCH_URL");
+
+const DEFAULT_SEARCH_URL: &str = "https://tenor.googleapis.com/v2/search";
+const PAGE_SIZE: usize = 24;
+const DEBOUNCE_MS: u32 = 300;
+/// Distance from the bottom of the results at which the next page loads.
+const LOAD_MORE_THRESHOLD_PX: i32 = 120;
+
+/// Where GIFs are searched and with what key. Tenor's v2 search and
+/// Giphy's, or anything answering like either, will do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GifSearch {
+    pub endpoint: String,
+    pub key: String,
+}
+
+impl GifSearch {
+    /// The search configured the same way as the server address: at build
+    /// time, or else with `<meta name="gif-api-key">` and
+    /// `<meta name="gif-search-url">` tags. `None` without a key, which hides
+    /// the picker.
+    pub fn configured() -> Option<Self> {
+        let key = BUILD_API_KEY
+            .map(str::to_string)
+            .or_else(|| config::meta_content("gif-api-key"))
+            .filter(|key| !key.trim().is_empty())?;
+        let endpoint = BUILD_SEARCH_URL
+            .map(str::to_string)
+            .or_else(|| config::meta_content("gif-search-url"))
+            .and_then(|url| safe_web_url(&url))
+            .unwrap_or_else(|| DEFAULT_SEARCH_URL.to_string());
+        Some(Self { endpoint, key })
+    }
+
+    fn is_giphy(&self) -> bool {
+        self.endpoint.contains("giphy.com")
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Gif {
+    pub preview: String,
+    pub url: String,
+}
+
+pub struct GifPage {
+    gifs: Vec<Gif>,
+    next: Option<String>,
+}
+
+/// A page of results, in either API's shape.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SearchResponse {
+    Tenor(TenorResponse),
+    Giphy(GiphyResponse),
+}
+
+#[derive(Deserialize)]
+struct TenorResponse {
+    results: Vec<TenorResult>,
+    #[serde(default)]
+    next: String,
+}
+
+#[derive(Deserialize)]
+struct TenorResult {
+    media_formats: TenorFormats,
+}
+
+#[derive(Deserialize)]
+struct TenorFormats {
+    gif: TenorMedia,
+    tinygif: TenorMedia,
+}
+
+#[derive(Deserialize)]
+struct TenorMedia {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct GiphyResponse {
+    data: Vec<GiphyResult>,
+    pagination: GiphyPagination,
+}
+
+#[derive(Deserialize)]
+struct GiphyResult {
+    images: GiphyImages,
+}
+
+#[derive(Deserialize)]
+struct GiphyImages {
+    fixed_height: TenorMedia,
+    fixed_width_small: TenorMedia,
+}
+
+#[derive(Deserialize)]
+struct GiphyPagination {
+    offset: usize,
+    count: usize,
+    #[serde(default)]
+    total_count: usize,
+}
+
+impl From<SearchResponse> for GifPage {
+    fn from(response: SearchResponse) -> Self {
+        match response {
+            SearchResponse::Tenor(body) => GifPage {
+                gifs: body
+                    .results
+                    .into_iter()
+                    .map(|r| Gif {
+                        preview: r.media_formats.tinygif.url,
+                        url: r.media_formats.gif.url,
+                    })
+                    .collect(),
+                next: Some(body.next).filter(|n| !n.is_empty()),
+            },
+            SearchResponse::Giphy(body) => {
+                let end = body.pagination.offset + body.pagination.count;
+                GifPage {
+                    gifs: body
+                        .data
+                        .into_iter()
+                        .map(|r| Gif {
+                            preview: r.images.fixed_width_small.url,
+                            url: r.images.fixed_height.url,
+                        })
+                        .collect(),
+                    next: (body.pagination.count > 0 && end < body.pagination.total_count)
+                        .then(|| end.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// One page of results for `query`, starting at `pos` as given by the
+/// previous page. Errors are described for showing in the picker.
+async fn search(api: &GifSearch, query: &str, pos: Option<&str>) -> Result<GifPage, String> {
+    let limit = PAGE_SIZE.to_string();
+    let mut params = vec![("q", query), ("limit", &limit)];
+    if api.is_giphy() {
+        params.push(("api_key", &api.key));
+        if let Some(offset) = pos {
+            params.push(("offset", offset));
+        }
+    } else {
+        params.push(("key", &api.key));
+        params.push(("media_filter", "gif,tinygif"));
+        if let Some(pos) = pos {
+            params.push(("pos", pos));
+        }
+    }
+
+    let response = Request::get(&api.endpoint)
+        .query(params)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.ok() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let body: SearchResponse = response.json().await.map_err(|e| e.to_string())?;
+    Ok(body.into())
+}
+
+pub enum Msg {
+    Input(String),
+    Search,
+    LoadMore,
+    Loaded {
+        query: String,
+        page: Result<GifPage, String>,
+        append: bool,
+    },
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub search: GifSearch,
+    pub on_select: Callback<String>,
+    pub on_close: Callback<()>,
+}
+
+pub struct GifPicker {
+    query: String,
+    gifs: Vec<Gif>,
+    next: Option<String>,
+    loading: bool,
+    error: Option<String>,
+    debounce: Option<Timeout>,
+}
+
+impl GifPicker {
+    fn fetch(&mut self, ctx: &Context<Self>, pos: Option<String>) {
+        let api = ctx.props().search.clone();
+        self.loading = true;
+        self.error = None;
+
+        let query = self.query.clone();
+        let append = pos.is_some();
+        let link = ctx.link().clone();
+        spawn_local(async move {
+            let page = search(&api, &query, pos.as_deref()).await;
+            link.send_message(Msg::Loaded {
+                query,
+                page,
+                append,
+            });
+        });
+    }
+}
+
+impl Component for GifPicker {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            query: String::new(),
+            gifs: Vec::new(),
+            next: None,
+            loading: false,
+            error: None,
+            debounce: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Input(query) => {
+                self.query = query;
+                let link = ctx.link().clone();
+                self.debounce = Some(Timeout::new(DEBOUNCE_MS, move || {
+                    link.send_message(Msg::Search)
+                }));
+                false
+            }
+            Msg::Search => {
+                self.debounce = None;
+                self.gifs.clear();
+                self.next = None;
+                if self.query.trim().is_empty() {
+                    self.loading = false;
+                    return true;
+                }
+                self.fetch(ctx, None);
+                true
+            }
+            Msg::LoadMore => {
+                if self.loading || self.next.is_none() {
+                    return false;
+                }
+                let pos = self.next.clone();
+                self.fetch(ctx, pos);
+                true
+            }
+            Msg::Loaded {
+                query,
+                page,
+                append,
+            } => {
+                // A newer search has started since this request went out.
+                if query != self.query {
+                    return false;
+                }
+                self.loading = false;
+                match page {
+                    Ok(page) => {
+                        if !append {
+                            self.gifs.clear();
+                        }
+                        self.gifs.extend(page.gifs);
+                        self.next = page.next;
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::Input(input.value())
+        });
+        let load_more = ctx.link().callback(|_| Msg::LoadMore);
+        // Loads the next page before the end of the results comes into view.
+        let onscroll = ctx.link().batch_callback(|e: Event| {
+            let results: web_sys::Element = e.target_unchecked_into();
+            let left = results.scroll_height() - results.scroll_top() - results.client_height();
+            (left < LOAD_MORE_THRESHOLD_PX).then_some(Msg::LoadMore)
+        });
+        let i18n = i18n::of(ctx);
+        let close = ctx.props().on_close.reform(|_| ());
+
+        html! {
+            <div class="absolute bottom-20 right-4 w-96 max-h-96 flex flex-col bg-white border border-gray-200 rounded-md shadow-lg z-10 dark:bg-gray-800 dark:border-gray-700">
+                <div class="flex items-center p-2 border-b border-gray-200 dark:border-gray-700">
+                    <input
+                        {oninput}
+                        type="text"
+                        placeholder={i18n.t("gif.search")}
+                        class="flex-grow px-3 py-1 rounded-md border border-gray-300 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:bg-gray-700"
+                    />
+                    <button onclick={close} class="ml-2 text-gray-400 hover:text-gray-700 dark:text-gray-500 dark:hover:text-gray-200" aria-label={i18n.t("gif.close")}>
+                        {"✕"}
+                    </button>
+                </div>
+                <div {onscroll} class="flex-grow overflow-y-auto p-2">
+                    {
+                        if let Some(error) = &self.error {
+                            html! {
+                                <p class="text-xs text-red-600 mb-2 dark:text-red-400" role="alert">
+                                    { i18n.t_with("gif.error", &[("reason", error)]) }
+                                </p>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <div class="grid grid-cols-3 gap-2">
+                        { for self.gifs.iter().filter_map(|gif| Some((gif, safe_image_url(&gif.preview)?))).map(|(gif, preview)| {
+                            let url = gif.url.clone();
+                            let onclick = ctx.props().on_select.reform(move |_| url.clone());
+                            html! {
+                                <img
+                                    {onclick}
+                                    class="w-full h-24 object-cover rounded cursor-pointer hover:opacity-80"
+                                    src={preview}
+                                    alt="GIF"
+                                />
+                            }
+                        })}
+                    </div>
+                    {
+                        if self.loading {
+                            html! { <p class="text-center text-xs text-gray-500 py-2 dark:text-gray-400">{ i18n.t("gif.loading") }</p> }
+                        } else if self.next.is_some() {
+                            html! {
+                                <button onclick={load_more} class="w-full mt-2 py-1 text-xs text-blue-600 hover:underline dark:text-blue-400">
+                                    { i18n.t("gif.load_more") }
+                                </button>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                </div>
+            </div>
+        }
+    }
+}
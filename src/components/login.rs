@@ -3,37 +3,238 @@ use yew::functional::*;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+use crate::i18n::{use_i18n, I18n};
+use crate::protocol::{AvatarChoice, AVATAR_STYLES};
+use crate::services::storage;
 use crate::Route;
 use crate::User;
 
+const MIN_USERNAME_CHARS: usize = 3;
+const MAX_USERNAME_CHARS: usize = 20;
+
+/// Checks that `name` is 3–20 letters, digits, `_` or `-`.
+pub fn validate_username(i18n: &I18n, name: &str) -> Result<(), String> {
+    let len = name.chars().count();
+    if !(MIN_USERNAME_CHARS..=MAX_USERNAME_CHARS).contains(&len) {
+        return Err(i18n.t_with(
+            "login.length",
+            &[("min", &MIN_USERNAME_CHARS), ("max", &MAX_USERNAME_CHARS)],
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(i18n.t("login.characters"));
+    }
+    Ok(())
+}
+
 #[function_component(Login)]
 pub fn login() -> Html {
-    let username = use_state(|| String::new());
+    let i18n = use_i18n();
+    // Prefilled after logging out, but never submitted automatically.
+    let username = use_state(|| {
+        storage::load_username()
+            .or_else(storage::load_last_username)
+            .unwrap_or_default()
+    });
+    let saved_avatar = use_state(storage::load_avatar);
+    let avatar_style = {
+        let saved_avatar = saved_avatar.clone();
+        use_state(move || {
+            saved_avatar
+                .as_ref()
+                .map(|a| a.style.clone())
+                .unwrap_or_else(|| AVATAR_STYLES[0].to_string())
+        })
+    };
+    // A seed of our own, if one was rolled; otherwise the avatar follows
+    // the username.
+    let avatar_seed = {
+        let username = username.clone();
+        use_state(move || {
+            saved_avatar
+                .as_ref()
+                .map(|a| a.seed.clone())
+                .filter(|seed| *seed != *username)
+        })
+    };
     let user = use_context::<User>().expect("No context found.");
+    let rejection = {
+        let user = user.clone();
+        use_state(move || user.login_error.borrow_mut().take())
+    };
 
     let oninput = {
         let current_username = username.clone();
+        let rejection = rejection.clone();
 
         Callback::from(move |e: InputEvent| {
             let input: HtmlInputElement = e.target_unchecked_into();
             current_username.set(input.value());
+            rejection.set(None);
         })
     };
 
+    let avatar = AvatarChoice {
+        style: (*avatar_style).clone(),
+        seed: (*avatar_seed)
+            .clone()
+            .unwrap_or_else(|| (*username).clone()),
+    };
+
     let onclick = {
         let username = username.clone();
         let user = user.clone();
-        Callback::from(move |_| *user.username.borrow_mut() = (*username).clone())
+        let avatar = avatar.clone();
+        Callback::from(move |_| {
+            storage::save_username(&username);
+            storage::save_avatar(&avatar);
+            *user.username.borrow_mut() = (*username).clone();
+        })
     };
 
+    let randomize = {
+        let avatar_seed = avatar_seed.clone();
+        Callback::from(move |_| {
+            let seed = (js_sys::Math::random() * u32::MAX as f64) as u32;
+            avatar_seed.set(Some(format!("{:08x}", seed)));
+        })
+    };
+    let use_name = {
+        let avatar_seed = avatar_seed.clone();
+        Callback::from(move |_| avatar_seed.set(None))
+    };
+
+    let invalid = validate_username(&i18n, &username).err();
+    // Nothing typed yet isn't worth an error message.
+    let message = rejection
+        .as_ref()
+        .cloned()
+        .or_else(|| invalid.clone().filter(|_| !username.is_empty()));
+
     html! {
-       <div class="bg-gray-800 flex w-screen">
+       <div class="bg-gray-800 flex w-screen dark:bg-gray-900">
             <div class="container mx-auto flex flex-col justify-center items-center">
                 <form class="m-4 flex">
-                    <input {oninput} class="rounded-l-lg p-4 border-t mr-0 border-b border-l text-gray-800 border-gray-200 bg-white" placeholder="Username" />
-                    <Link<Route> to={Route::Chat}> <button {onclick} disabled={username.len()<1} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r" >{"Go Chatting!"}</button></Link<Route>>
+                    <input {oninput} value={(*username).clone()} class="rounded-l-lg p-4 border-t mr-0 border-b border-l text-gray-800 border-gray-200 bg-white dark:text-gray-100 dark:border-gray-700 dark:bg-gray-800" placeholder={i18n.t("login.username")} aria-invalid={message.is_some().to_string()} />
+                    <Link<Route> to={Route::Chat}> <button {onclick} disabled={invalid.is_some()} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r disabled:opacity-50 disabled:cursor-not-allowed" >{ i18n.t("login.submit") }</button></Link<Route>>
                 </form>
+                {
+                    if let Some(message) = message {
+                        html! { <p class="text-sm text-red-400" role="alert">{ message }</p> }
+                    } else {
+                        html! {}
+                    }
+                }
+                <fieldset class="m-4 p-4 rounded-lg bg-gray-700 text-white">
+                    <legend class="px-2 text-sm font-semibold">{ i18n.t("login.avatar") }</legend>
+                    <div class="grid grid-cols-4 gap-2" role="radiogroup">
+                        { for AVATAR_STYLES.iter().map(|style| {
+                            let selected = *avatar_style == *style;
+                            let preview = AvatarChoice {
+                                style: style.to_string(),
+                                seed: avatar.seed.clone(),
+                            };
+                            let onclick = {
+                                let avatar_style = avatar_style.clone();
+                                Callback::from(move |_| avatar_style.set(style.to_string()))
+                            };
+                            html! {
+                                <button
+                                    type="button"
+                                    {onclick}
+                                    role="radio"
+                                    aria-checked={selected.to_string()}
+                                    aria-label={style.to_string()}
+                                    title={style.to_string()}
+                                    class={classes!(
+                                        "p-1", "rounded-lg", "bg-white", "dark:bg-gray-800",
+                                        if selected { "ring-4 ring-violet-500" } else { "opacity-75 hover:opacity-100" },
+                                    )}
+                                >
+                                    <img class="w-14 h-14" src={preview.url()} alt="" />
+                                </button>
+                            }
+                        })}
+                    </div>
+                    <div class="mt-3 flex justify-center space-x-2 text-sm">
+                        <button type="button" onclick={randomize} class="px-3 py-1 rounded-md bg-gray-600 hover:bg-gray-500">
+                            { i18n.t("login.randomize") }
+                        </button>
+                        {
+                            if avatar_seed.is_some() {
+                                html! {
+                                    <button type="button" onclick={use_name} class="px-3 py-1 rounded-md bg-gray-600 hover:bg-gray-500">
+                                        { i18n.t("login.use_name") }
+                                    </button>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                    </div>
+                </fieldset>
             </div>
         </div>
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acceptable_names() {
+        let i18n = I18n::default();
+        for name in [
+            "bob",
+            "alice_smith",
+            "dash-ed",
+            "User42",
+            "José",
+            "日本語",
+            "a_b-c_d-e_f-g_h-i_j-",
+        ] {
+            assert_eq!(validate_username(&i18n, name), Ok(()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn names_of_the_wrong_length() {
+        let i18n = I18n::default();
+        let error = Err("Username must be 3 to 20 characters long".to_string());
+        for name in [
+            "",
+            "ab",
+            "日本",
+            "a".repeat(21).as_str(),
+            "ü".repeat(21).as_str(),
+        ] {
+            assert_eq!(validate_username(&i18n, name), error, "{}", name);
+        }
+        // Counted in characters, not bytes.
+        assert_eq!(validate_username(&i18n, &"ü".repeat(20)), Ok(()));
+    }
+
+    #[test]
+    fn names_with_other_characters() {
+        let i18n = I18n::default();
+        let error = Err("Username may only contain letters, digits, _ and -".to_string());
+        for name in [
+            "two words",
+            " bob",
+            "bob\t",
+            "a.b.c",
+            "<script>",
+            "bob@home",
+            "semi;colon",
+            "zero\u{200b}width",
+            "emoji🙂",
+            "new\nline",
+        ] {
+            assert_eq!(validate_username(&i18n, name), error, "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use gloo_events::EventListener;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, Node};
+use yew::prelude::*;
+
+use crate::a11y;
+use crate::components::user_popover::fit_in_viewport;
+use crate::i18n;
+
+/// What can be done to a message from its menu. Each maps onto the message
+/// the matching button in the transcript sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageAction {
+    Reply,
+    Copy,
+    /// Pin it, or unpin it if it already is.
+    TogglePin,
+    Edit,
+    Delete,
+    /// Put `@author` into the message input.
+    MentionAuthor,
+    React(&'static str),
+}
+
+pub enum Msg {
+    /// Moves the menu so that it fits in the window.
+    Place(i32, i32),
+    Action(MessageAction),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// The actions that apply to the message, in the order listed.
+    pub actions: Vec<MessageAction>,
+    /// Whether the message is pinned, for naming [`MessageAction::TogglePin`].
+    #[prop_or_default]
+    pub pinned: bool,
+    /// Where the click or press that opened the menu happened.
+    pub x: i32,
+    pub y: i32,
+    pub on_action: Callback<MessageAction>,
+    pub on_close: Callback<()>,
+}
+
+/// The actions for one message, opened by right-clicking or long-pressing
+/// it. Arrow keys move between them.
+pub struct MessageMenu {
+    /// The position last placed from.
+    opened_at: (i32, i32),
+    left: i32,
+    top: i32,
+    root: NodeRef,
+    _click_outside: Option<EventListener>,
+}
+
+impl MessageMenu {
+    /// Measures the rendered menu and moves it back inside the window if it
+    /// sticks out.
+    fn place(&self, ctx: &Context<Self>) {
+        let (root, window) = match (self.root.cast::<HtmlElement>(), web_sys::window()) {
+            (Some(root), Some(window)) => (root, window),
+            _ => return,
+        };
+        let dimension = |value: Result<wasm_bindgen::JsValue, _>| {
+            value.ok().and_then(|v| v.as_f64()).unwrap_or_default() as i32
+        };
+        let viewport = (
+            dimension(window.inner_width()),
+            dimension(window.inner_height()),
+        );
+        let size = (root.offset_width(), root.offset_height());
+        let (left, top) = fit_in_viewport((ctx.props().x, ctx.props().y), size, viewport);
+        ctx.link().send_message(Msg::Place(left, top));
+    }
+}
+
+impl Component for MessageMenu {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        Self {
+            opened_at: (ctx.props().x, ctx.props().y),
+            left: ctx.props().x,
+            top: ctx.props().y,
+            root: NodeRef::default(),
+            _click_outside: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Place(left, top) => {
+                let moved = (left, top) != (self.left, self.top);
+                self.left = left;
+                self.top = top;
+                moved
+            }
+            Msg::Action(action) => {
+                ctx.props().on_action.emit(action);
+                false
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        let point = (ctx.props().x, ctx.props().y);
+        if point != self.opened_at {
+            self.opened_at = point;
+            self.place(ctx);
+        }
+        true
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if !first_render {
+            return;
+        }
+        self.place(ctx);
+        if let Some(root) = self.root.cast::<Element>() {
+            a11y::focus_first(&root);
+        }
+
+        let document = match web_sys::window().and_then(|w| w.document()) {
+            Some(document) => document,
+            None => return,
+        };
+        let root = self.root.clone();
+        let on_close = ctx.props().on_close.clone();
+        // Touch too: a tap elsewhere sends no mousedown on every browser.
+        self._click_outside = Some(EventListener::new(&document, "pointerdown", move |e| {
+            let target = e.target().and_then(|t| t.dyn_into::<Node>().ok());
+            let inside = match (root.get(), target) {
+                (Some(root), Some(target)) => root.contains(Some(&target)),
+                _ => false,
+            };
+            if !inside {
+                on_close.emit(());
+            }
+        }));
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let props = ctx.props();
+        let i18n = i18n::of(ctx);
+        let root = self.root.clone();
+        let onkeydown = Callback::from(move |e: KeyboardEvent| {
+            if let Some(root) = root.cast::<Element>() {
+                a11y::navigate_menu(&root, &e);
+                a11y::trap_focus(&root, &e);
+            }
+        });
+        let (reactions, actions): (Vec<MessageAction>, Vec<MessageAction>) = props
+            .actions
+            .iter()
+            .partition(|a| matches!(a, MessageAction::React(_)));
+        let item = |action: MessageAction| {
+            let label = match action {
+                MessageAction::Reply => i18n.t("message.reply_button"),
+                MessageAction::Copy => i18n.t("message.copy_button"),
+                MessageAction::TogglePin => i18n.t(if props.pinned {
+                    "pins.unpin_button"
+                } else {
+                    "pins.pin_button"
+                }),
+                MessageAction::Edit => i18n.t("message.edit_button"),
+                MessageAction::Delete => i18n.t("message.delete_button"),
+                MessageAction::MentionAuthor => i18n.t("menu.mention"),
+                MessageAction::React(emoji) => emoji.to_string(),
+            };
+            let aria_label = match action {
+                MessageAction::React(emoji) => {
+                    Some(i18n.t_with("message.react", &[("emoji", &emoji)]))
+                }
+                _ => None,
+            };
+            let class = match action {
+                MessageAction::React(_) => {
+                    "px-2 py-1 rounded text-lg hover:bg-gray-100 focus:bg-gray-100 focus:outline-none dark:hover:bg-gray-700 dark:focus:bg-gray-700"
+                }
+                MessageAction::Delete => {
+                    "w-full px-3 py-1.5 text-left text-sm text-red-600 hover:bg-gray-100 focus:bg-gray-100 focus:outline-none dark:text-red-400 dark:hover:bg-gray-700 dark:focus:bg-gray-700"
+                }
+                _ => {
+                    "w-full px-3 py-1.5 text-left text-sm hover:bg-gray-100 focus:bg-gray-100 focus:outline-none dark:hover:bg-gray-700 dark:focus:bg-gray-700"
+                }
+            };
+            html! {
+                <button
+                    onclick={ctx.link().callback(move |_| Msg::Action(action))}
+                    {class}
+                    role="menuitem"
+                    aria-label={aria_label}
+                >
+                    { label }
+                </button>
+            }
+        };
+
+        html! {
+            <div
+                ref={self.root.clone()}
+                {onkeydown}
+                class="fixed z-30 w-48 py-1 bg-white border border-gray-200 rounded-md shadow-lg dark:bg-gray-800 dark:border-gray-700"
+                style={format!("left: {}px; top: {}px", self.left, self.top)}
+                role="menu"
+                aria-label={i18n.t("menu.label")}
+            >
+                {
+                    if reactions.is_empty() {
+                        html! {}
+                    } else {
+                        html! {
+                            <div class="flex justify-around px-1 pb-1 border-b border-gray-200 dark:border-gray-700">
+                                { for reactions.into_iter().map(item) }
+                            </div>
+                        }
+                    }
+                }
+                { for actions.into_iter().map(item) }
+            </div>
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo_timers::callback::Timeout;
+use wasm_bindgen::JsCast;
+use yew::prelude::*;
+
+use crate::components::avatar::Avatar;
+use crate::i18n::use_i18n;
+use crate::messages::MessageId;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub id: MessageId,
+    pub from: String,
+    /// Whether we wrote it: shown on the right, with the avatar on that
+    /// side, and as "You".
+    #[prop_or_default]
+    pub is_own: bool,
+    /// The sender's avatar image; left empty, their initials are shown.
+    #[prop_or_default]
+    pub avatar: String,
+    /// Briefly tinted after jumping to it.
+    #[prop_or_default]
+    pub highlighted: bool,
+    /// Shows the sender's card.
+    pub onuserclick: Callback<MouseEvent>,
+    /// Opens the message's menu at a point in the window, on a right-click
+    /// or a long press.
+    #[prop_or_default]
+    pub onmenu: Option<Callback<(i32, i32)>>,
+    /// Timestamp, receipt and actions, shown beside the name.
+    #[prop_or_default]
+    pub meta: Html,
+    /// The quote, body, reactions and send status, under the name.
+    #[prop_or_default]
+    pub children: Children,
+}
+
+/// How long a touch has to be held to open the menu.
+const LONG_PRESS_MS: u32 = 500;
+/// How far a touch may wander, in pixels, and still count as held rather
+/// than as the start of a scroll.
+const LONG_PRESS_SLOP_PX: i32 = 10;
+
+/// A touch being held: where it started, and the timeout that opens the
+/// menu if it is held long enough.
+type Press = Rc<RefCell<Option<((i32, i32), Timeout)>>>;
+
+/// Handlers opening the menu on right-click, the context menu key, or a
+/// touch held still for [`LONG_PRESS_MS`].
+struct MenuHandlers {
+    oncontextmenu: Callback<MouseEvent>,
+    onpointerdown: Callback<PointerEvent>,
+    onpointermove: Callback<PointerEvent>,
+    /// The touch lifted, was taken over by the browser, or left the row.
+    onpointerend: Callback<PointerEvent>,
+}
+
+fn menu_handlers(onmenu: &Callback<(i32, i32)>, press: &Press) -> MenuHandlers {
+    let oncontextmenu = {
+        let onmenu = onmenu.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            let (mut x, mut y) = (e.client_x(), e.client_y());
+            // Opened from the keyboard: anchor to the message instead.
+            if (x, y) == (0, 0) {
+                if let Some(row) = e
+                    .current_target()
+                    .and_then(|t| t.dyn_into::<web_sys::Element>().ok())
+                {
+                    let rect = row.get_bounding_client_rect();
+                    x = rect.left() as i32;
+                    y = rect.top() as i32;
+                }
+            }
+            onmenu.emit((x, y));
+        })
+    };
+    let onpointerdown = {
+        let (onmenu, press) = (onmenu.clone(), press.clone());
+        Callback::from(move |e: PointerEvent| {
+            // Mice have a right button for this.
+            if e.pointer_type() == "mouse" {
+                return;
+            }
+            let at = (e.client_x(), e.client_y());
+            let onmenu = onmenu.clone();
+            let held = press.clone();
+            let timeout = Timeout::new(LONG_PRESS_MS, move || {
+                held.borrow_mut().take();
+                onmenu.emit(at);
+            });
+            *press.borrow_mut() = Some((at, timeout));
+        })
+    };
+    let onpointermove = {
+        let press = press.clone();
+        Callback::from(move |e: PointerEvent| {
+            let scrolling = press.borrow().as_ref().is_some_and(|((x, y), _)| {
+                (e.client_x() - x).abs() > LONG_PRESS_SLOP_PX
+                    || (e.client_y() - y).abs() > LONG_PRESS_SLOP_PX
+            });
+            if scrolling {
+                press.borrow_mut().take();
+            }
+        })
+    };
+    let onpointerend = {
+        let press = press.clone();
+        Callback::from(move |_: PointerEvent| {
+            press.borrow_mut().take();
+        })
+    };
+    MenuHandlers {
+        oncontextmenu,
+        onpointerdown,
+        onpointermove,
+        onpointerend,
+    }
+}
+
+/// One message in the transcript: avatar, name line and body. Our own
+/// messages are mirrored to the right so they stand out when scanning.
+#[function_component(MessageRow)]
+pub fn message_row(props: &Props) -> Html {
+    let i18n = use_i18n();
+    let press: Press = use_mut_ref(|| None);
+    let handlers = props
+        .onmenu
+        .as_ref()
+        .map(|onmenu| menu_handlers(onmenu, &press));
+    let oncontextmenu = handlers.as_ref().map(|h| h.oncontextmenu.clone());
+    let onpointerdown = handlers.as_ref().map(|h| h.onpointerdown.clone());
+    let onpointermove = handlers.as_ref().map(|h| h.onpointermove.clone());
+    let onpointerup = handlers.as_ref().map(|h| h.onpointerend.clone());
+    let onpointercancel = onpointerup.clone();
+    let onpointerleave = onpointerup.clone();
+    let row_class = classes!(
+        "group",
+        "flex",
+        "items-start",
+        "md:max-w-xl",
+        "rounded-md",
+        "transition-colors",
+        "duration-500",
+        "motion-reduce:transition-none",
+        if props.is_own {
+            "ml-auto flex-row-reverse space-x-reverse space-x-3"
+        } else {
+            "space-x-3"
+        },
+        props
+            .highlighted
+            .then_some("bg-yellow-100 dark:bg-yellow-900"),
+    );
+    let name = if props.is_own {
+        i18n.t("message.you")
+    } else {
+        props.from.clone()
+    };
+
+    html! {
+        <div
+            class={row_class}
+            data-message-id={props.id.clone()}
+            {oncontextmenu}
+            {onpointerdown}
+            {onpointermove}
+            {onpointerup}
+            {onpointercancel}
+            {onpointerleave}
+        >
+            // Users who have left have no avatar to show, but still get
+            // their initials.
+            <Avatar
+                name={props.from.clone()}
+                url={props.avatar.clone()}
+                size={40}
+                class="cursor-pointer"
+                onclick={props.onuserclick.clone()}
+            />
+
+            <div class={classes!("flex", "flex-col", if props.is_own { "items-end" } else { "items-start" })}>
+                <div class={classes!(
+                    "flex", "items-center",
+                    if props.is_own { "flex-row-reverse space-x-reverse space-x-2" } else { "space-x-2" },
+                )}>
+                    <button
+                        onclick={props.onuserclick.clone()}
+                        class="text-sm font-semibold hover:underline rounded focus:outline-none focus:ring-2 focus:ring-blue-400"
+                        title={props.from.clone()}
+                    >
+                        { name }
+                    </button>
+                    { props.meta.clone() }
+                </div>
+                { for props.children.iter() }
+            </div>
+        </div>
+    }
+}
@@ -1,2 +1,13 @@
+pub mod avatar;
 pub mod chat;
+pub mod code_block;
+pub mod emoji_picker;
+#[cfg(feature = "frame-log")]
+pub mod frame_log;
+#[cfg(feature = "gif-picker")]
+pub mod gif_picker;
 pub mod login;
+pub mod message_menu;
+pub mod message_row;
+pub mod toast;
+pub mod user_popover;
@@ -0,0 +1,115 @@
+use yew::prelude::*;
+
+use crate::i18n::{use_i18n, I18n};
+use crate::messages::MessageId;
+use crate::toasts::{ErrorKind, Toast, ToastId};
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub toasts: Vec<Toast>,
+    pub ondismiss: Callback<ToastId>,
+    /// Sends the message again, from a failed send's notice.
+    pub onretry: Callback<MessageId>,
+}
+
+fn text(i18n: &I18n, kind: &ErrorKind) -> String {
+    match kind {
+        ErrorKind::SendFailed(_) => i18n.t("toast.send_failed"),
+        ErrorKind::ActionFailed => i18n.t("toast.action_failed"),
+        ErrorKind::Disconnected { reconnecting, .. } => i18n.t(if *reconnecting {
+            "connection.reconnecting"
+        } else {
+            "connection.lost"
+        }),
+        ErrorKind::Protocol(_) => i18n.t("toast.protocol"),
+        ErrorKind::Server(reason) => reason.clone(),
+    }
+}
+
+/// The stacked error notices, newest at the bottom. Each can be dismissed
+/// except the connection one, which goes once we are back.
+#[function_component(ToastStack)]
+pub fn toast_stack(props: &Props) -> Html {
+    let i18n = use_i18n();
+
+    let view_toast = |toast: &Toast| {
+        let persistent = toast.kind.persistent();
+        let detail = match &toast.kind {
+            ErrorKind::Disconnected { reason, .. } => reason.clone(),
+            ErrorKind::Protocol(reason) => Some(reason.clone()),
+            _ => None,
+        };
+        let retry = match &toast.kind {
+            ErrorKind::SendFailed(id) => {
+                let id = id.clone();
+                let onclick = props.onretry.reform(move |_| id.clone());
+                html! {
+                    <button {onclick} class="font-semibold underline hover:no-underline focus:outline-none focus:ring-2 focus:ring-red-400 rounded">
+                        { i18n.t("toast.retry") }
+                    </button>
+                }
+            }
+            _ => html! {},
+        };
+        let dismiss = if persistent {
+            html! {}
+        } else {
+            let id = toast.id;
+            html! {
+                <button
+                    onclick={props.ondismiss.reform(move |_| id)}
+                    class="ml-auto px-1 rounded opacity-60 hover:opacity-100 focus:outline-none focus:ring-2 focus:ring-red-400"
+                    aria-label={i18n.t("toast.dismiss")}
+                >
+                    { "✕" }
+                </button>
+            }
+        };
+        html! {
+            <div
+                key={toast.id.to_string()}
+                role={if persistent { "status" } else { "alert" }}
+                class={classes!(
+                    "pointer-events-auto", "rounded-md", "border", "shadow-md", "px-3", "py-2", "text-sm",
+                    if persistent {
+                        "border-yellow-200 bg-yellow-50 text-yellow-800 dark:border-yellow-700 dark:bg-yellow-900 dark:text-yellow-200"
+                    } else {
+                        "border-red-200 bg-red-50 text-red-700 dark:border-red-700 dark:bg-red-900 dark:text-red-300"
+                    },
+                )}
+            >
+                <div class="flex items-center gap-2">
+                    <span>{ text(&i18n, &toast.kind) }</span>
+                    {
+                        if toast.count > 1 {
+                            html! {
+                                <span class="rounded-full bg-red-200 px-1.5 text-xs font-semibold dark:bg-red-800">
+                                    { i18n.t_with("toast.count", &[("count", &toast.count)]) }
+                                </span>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    { retry }
+                    { dismiss }
+                </div>
+                {
+                    match detail {
+                        Some(detail) => html! { <p class="mt-0.5 text-xs opacity-80 break-words">{ detail }</p> },
+                        None => html! {},
+                    }
+                }
+            </div>
+        }
+    };
+
+    html! {
+        <div
+            class="fixed bottom-24 right-4 z-40 flex flex-col gap-2 w-80 max-w-full pointer-events-none"
+            aria-label={i18n.t("toast.region")}
+        >
+            { for props.toasts.iter().map(view_toast) }
+        </div>
+    }
+}
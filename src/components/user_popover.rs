@@ -0,0 +1,228 @@
+use gloo_events::EventListener;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, Node};
+use yew::prelude::*;
+
+use crate::a11y;
+use crate::components::avatar::Avatar;
+use crate::i18n;
+use crate::time::{self, format_duration};
+
+/// Gap kept between the popover and the edges of the window.
+const MARGIN_PX: i32 = 8;
+
+/// What can be done to a user from their popover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserAction {
+    /// Open a direct conversation with them.
+    Message,
+    /// Put `@name` into the message input.
+    Mention,
+    /// Collapse their messages and silence them, or stop doing so.
+    ToggleMute,
+}
+
+pub enum Msg {
+    /// Moves the popover so that it fits in the window.
+    Place(i32, i32),
+    Action(UserAction),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub name: String,
+    pub avatar: String,
+    /// When the user first showed up in a user list this session.
+    #[prop_or_default]
+    pub online_since: Option<u64>,
+    /// Whether this is us, in which case there is nothing to offer.
+    #[prop_or_default]
+    pub is_self: bool,
+    #[prop_or_default]
+    pub muted: bool,
+    /// Where the click that opened the popover happened.
+    pub x: i32,
+    pub y: i32,
+    pub on_action: Callback<UserAction>,
+    pub on_close: Callback<()>,
+}
+
+/// Card with a user's avatar, name and how long they have been around,
+/// opened by clicking them.
+pub struct UserPopover {
+    /// The click position last placed from.
+    opened_at: (i32, i32),
+    left: i32,
+    top: i32,
+    root: NodeRef,
+    _click_outside: Option<EventListener>,
+}
+
+/// Top-left corner for a box of `size` opened at `point`, moved up and left
+/// as needed to stay inside `viewport`.
+pub fn fit_in_viewport(point: (i32, i32), size: (i32, i32), viewport: (i32, i32)) -> (i32, i32) {
+    let fit = |at: i32, size: i32, room: i32| at.min(room - size - MARGIN_PX).max(MARGIN_PX);
+    (
+        fit(point.0, size.0, viewport.0),
+        fit(point.1, size.1, viewport.1),
+    )
+}
+
+impl UserPopover {
+    /// Measures the rendered popover and moves it back inside the window if
+    /// it sticks out.
+    fn place(&self, ctx: &Context<Self>) {
+        let (root, window) = match (self.root.cast::<HtmlElement>(), web_sys::window()) {
+            (Some(root), Some(window)) => (root, window),
+            _ => return,
+        };
+        let dimension = |value: Result<wasm_bindgen::JsValue, _>| {
+            value.ok().and_then(|v| v.as_f64()).unwrap_or_default() as i32
+        };
+        let viewport = (
+            dimension(window.inner_width()),
+            dimension(window.inner_height()),
+        );
+        let size = (root.offset_width(), root.offset_height());
+        let (left, top) = fit_in_viewport((ctx.props().x, ctx.props().y), size, viewport);
+        ctx.link().send_message(Msg::Place(left, top));
+    }
+}
+
+impl Component for UserPopover {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        Self {
+            opened_at: (ctx.props().x, ctx.props().y),
+            left: ctx.props().x,
+            top: ctx.props().y,
+            root: NodeRef::default(),
+            _click_outside: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Place(left, top) => {
+                let moved = (left, top) != (self.left, self.top);
+                self.left = left;
+                self.top = top;
+                moved
+            }
+            Msg::Action(action) => {
+                ctx.props().on_action.emit(action);
+                false
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        let point = (ctx.props().x, ctx.props().y);
+        if point != self.opened_at {
+            // Opened somewhere else: fit again around the new click.
+            self.opened_at = point;
+            self.place(ctx);
+        }
+        true
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if !first_render {
+            return;
+        }
+        self.place(ctx);
+        if let Some(root) = self.root.cast::<Element>() {
+            a11y::focus_first(&root);
+        }
+
+        let document = match web_sys::window().and_then(|w| w.document()) {
+            Some(document) => document,
+            None => return,
+        };
+        let root = self.root.clone();
+        let on_close = ctx.props().on_close.clone();
+        self._click_outside = Some(EventListener::new(&document, "mousedown", move |e| {
+            let target = e.target().and_then(|t| t.dyn_into::<Node>().ok());
+            let inside = match (root.get(), target) {
+                (Some(root), Some(target)) => root.contains(Some(&target)),
+                _ => false,
+            };
+            if !inside {
+                on_close.emit(());
+            }
+        }));
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let props = ctx.props();
+        let action = |action| ctx.link().callback(move |_| Msg::Action(action));
+        let i18n = i18n::of(ctx);
+        let online = props.online_since.map(|since| {
+            let duration = format_duration(&i18n, time::now().saturating_sub(since));
+            i18n.t_with("popover.online_for", &[("duration", &duration)])
+        });
+
+        let root = self.root.clone();
+        let trap_focus = Callback::from(move |e: KeyboardEvent| {
+            if let Some(root) = root.cast::<Element>() {
+                a11y::trap_focus(&root, &e);
+            }
+        });
+
+        html! {
+            <div
+                ref={self.root.clone()}
+                onkeydown={trap_focus}
+                class="fixed z-30 w-64 p-4 bg-white border border-gray-200 rounded-md shadow-lg dark:bg-gray-800 dark:border-gray-700"
+                style={format!("left: {}px; top: {}px", self.left, self.top)}
+                role="dialog"
+                aria-label={i18n.t_with("popover.label", &[("name", &props.name)])}
+            >
+                <div class="flex items-center space-x-3">
+                    <Avatar name={props.name.clone()} url={props.avatar.clone()} size={64} />
+                    <div class="flex flex-col">
+                        <span class="font-semibold">{ &props.name }</span>
+                        {
+                            if let Some(online) = online {
+                                html! { <span class="text-xs text-gray-500 dark:text-gray-400">{ online }</span> }
+                            } else {
+                                html! {}
+                            }
+                        }
+                    </div>
+                </div>
+                {
+                    if props.is_self {
+                        html! {}
+                    } else {
+                        html! {
+                            <div class="mt-3 flex space-x-2">
+                                <button
+                                    onclick={action(UserAction::Message)}
+                                    class="flex-grow px-2 py-1 text-sm rounded-md bg-blue-600 hover:bg-blue-700 text-white focus:outline-none focus:ring-2 focus:ring-blue-400"
+                                >
+                                    { i18n.t("popover.message") }
+                                </button>
+                                <button
+                                    onclick={action(UserAction::Mention)}
+                                    class="flex-grow px-2 py-1 text-sm rounded-md border border-gray-300 hover:bg-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:hover:bg-gray-700"
+                                >
+                                    { i18n.t("popover.mention") }
+                                </button>
+                                <button
+                                    onclick={action(UserAction::ToggleMute)}
+                                    class="flex-grow px-2 py-1 text-sm rounded-md border border-gray-300 hover:bg-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-400 dark:border-gray-600 dark:hover:bg-gray-700"
+                                    aria-pressed={props.muted.to_string()}
+                                >
+                                    { i18n.t(if props.muted { "popover.unmute" } else { "popover.mute" }) }
+                                </button>
+                            </div>
+                        }
+                    }
+                }
+            </div>
+        }
+    }
+}
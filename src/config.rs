@@ -0,0 +1,231 @@
+//! Where the chat server lives, as configured for this deployment.
+
+use web_sys::UrlSearchParams;
+
+use crate::messages::DEFAULT_MAX_MESSAGES;
+use crate::protocol::WireFormat;
+use crate::sanitize::safe_web_url;
+use crate::services::storage;
+use crate::services::websocket::{validate_ws_url, DEFAULT_WS_URL};
+
+/// Server address baked in at build time through `WS_URL`, or the older
+/// `YEWCHAT_WS_URL`. Takes precedence over the page's configuration, but
+/// not over the address saved in the settings panel.
+const BUILD_WS_URL: Option<&str> =
+    build_setting(option_env!("WS_URL"), option_env!("YEWCHAT_WS_URL"));
+
+/// Endpoint files are uploaded to, baked in at build time through
+/// `YEWCHAT_UPLOAD_URL`.
+const BUILD_UPLOAD_URL: Option<&str> = option_env!("YEWCHAT_UPLOAD_URL");
+
+/// Messages kept per conversation, baked in at build time through
+/// `YEWCHAT_MAX_MESSAGES`.
+const BUILD_MAX_MESSAGES: Option<&str> = option_env!("YEWCHAT_MAX_MESSAGES");
+
+/// Framing baked in at build time through `YEWCHAT_WIRE_FORMAT`.
+const BUILD_WIRE_FORMAT: Option<&str> = option_env!("YEWCHAT_WIRE_FORMAT");
+
+/// A build-time setting read from `preferred`, or else from the variable
+/// it replaced.
+const fn build_setting<'a>(
+    preferred: Option<&'a str>,
+    fallback: Option<&'a str>,
+) -> Option<&'a str> {
+    match preferred {
+        Some(value) => Some(value),
+        None => fallback,
+    }
+}
+
+/// Picks the server address from, in order: the build-time setting, the
+/// `<meta name="ws-url">` tag and the `?ws=` query parameter. Invalid values
+/// are skipped; if none is usable [`DEFAULT_WS_URL`] is returned.
+pub fn resolve_ws_url(meta: Option<&str>, query: Option<&str>) -> String {
+    first_ws_url([BUILD_WS_URL, meta, query])
+}
+
+/// The first of `candidates` that is a valid server address, or
+/// [`DEFAULT_WS_URL`].
+fn first_ws_url<'a>(candidates: impl IntoIterator<Item = Option<&'a str>>) -> String {
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|url| match validate_ws_url(url) {
+            Ok(url) => Some(url),
+            Err(e) => {
+                log::warn!("ignoring server url {:?}: {}", url, e);
+                None
+            }
+        })
+        .unwrap_or_else(|| DEFAULT_WS_URL.to_string())
+}
+
+/// The address saved in the settings panel if it is still valid, or else
+/// [`resolve_ws_url`] with the values found in the current page.
+pub fn ws_url() -> String {
+    storage::load_server_url()
+        .and_then(|saved| validate_ws_url(&saved).ok())
+        .unwrap_or_else(|| resolve_ws_url(meta_ws_url().as_deref(), query_param("ws").as_deref()))
+}
+
+fn meta_ws_url() -> Option<String> {
+    meta_content("ws-url")
+}
+
+/// Picks how many messages each conversation keeps the same way as the
+/// server address: the build-time setting, the `<meta name="max-messages">`
+/// tag, then the `?retention=` query parameter. Anything but a positive
+/// number is skipped; if none is usable [`DEFAULT_MAX_MESSAGES`] is
+/// returned.
+pub fn resolve_max_messages(meta: Option<&str>, query: Option<&str>) -> usize {
+    [BUILD_MAX_MESSAGES, meta, query]
+        .into_iter()
+        .flatten()
+        .find_map(|value| match value.trim().parse() {
+            Ok(cap) if cap > 0 => Some(cap),
+            _ => {
+                log::warn!("ignoring message cap {:?}", value);
+                None
+            }
+        })
+        .unwrap_or(DEFAULT_MAX_MESSAGES)
+}
+
+/// [`resolve_max_messages`] with the values found in the current page.
+pub fn max_messages() -> usize {
+    resolve_max_messages(
+        meta_content("max-messages").as_deref(),
+        query_param("retention").as_deref(),
+    )
+}
+
+/// Where files too big to send inline are uploaded, from the build-time
+/// setting or else the `<meta name="upload-url">` tag. Without one such
+/// files can't be sent.
+pub fn upload_url() -> Option<String> {
+    BUILD_UPLOAD_URL
+        .map(str::to_string)
+        .or_else(|| meta_content("upload-url"))
+        .and_then(|url| safe_web_url(&url))
+}
+
+/// How frames are encoded, from the build-time setting or else the
+/// `<meta name="wire-format">` tag (`json` or `msgpack`). `None`, the
+/// default, means following whatever the server sends first.
+pub fn wire_format() -> Option<WireFormat> {
+    let name = BUILD_WIRE_FORMAT
+        .map(str::to_string)
+        .or_else(|| meta_content("wire-format"))?;
+    let format = WireFormat::from_name(&name);
+    if format.is_none() && name.trim() != "auto" {
+        log::warn!("ignoring wire format {:?}", name);
+    }
+    format
+}
+
+/// Content of the page's `<meta name="{name}">` tag.
+pub fn meta_content(name: &str) -> Option<String> {
+    web_sys::window()?
+        .document()?
+        .query_selector(&format!("meta[name=\"{}\"]", name))
+        .ok()??
+        .get_attribute("content")
+}
+
+/// Value of `name` in the page's query string.
+pub fn query_param(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    UrlSearchParams::new_with_str(&search).ok()?.get(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_valid_candidate_wins() {
+        let url = first_ws_url([
+            Some("wss://build.example"),
+            Some("wss://saved.example"),
+            Some("wss://meta.example"),
+            Some("wss://query.example"),
+        ]);
+        assert_eq!(url, "wss://build.example");
+        let url = first_ws_url([
+            None,
+            Some("wss://saved.example"),
+            Some("wss://meta.example"),
+        ]);
+        assert_eq!(url, "wss://saved.example");
+        let url = first_ws_url([None, None, None, Some("/ws")]);
+        assert_eq!(url, "/ws");
+    }
+
+    #[test]
+    fn invalid_candidates_are_skipped() {
+        let url = first_ws_url([
+            Some("http://build.example"),
+            Some("wss://saved example"),
+            Some("wss://"),
+            Some(" wss://meta.example "),
+        ]);
+        assert_eq!(url, "wss://meta.example");
+    }
+
+    #[test]
+    fn default_when_nothing_is_usable() {
+        assert_eq!(first_ws_url([None, None]), DEFAULT_WS_URL);
+        assert_eq!(first_ws_url([Some("ftp://x"), Some("")]), DEFAULT_WS_URL);
+    }
+
+    #[test]
+    fn ws_url_beats_the_old_variable() {
+        assert_eq!(
+            build_setting(Some("wss://new.example"), Some("wss://old.example")),
+            Some("wss://new.example")
+        );
+        assert_eq!(
+            build_setting(None, Some("wss://old.example")),
+            Some("wss://old.example")
+        );
+        assert_eq!(build_setting(None, None), None);
+    }
+
+    #[test]
+    fn build_setting_beats_the_page() {
+        let url = resolve_ws_url(Some("wss://meta.example"), Some("wss://query.example"));
+        let expected = BUILD_WS_URL
+            .and_then(|url| validate_ws_url(url).ok())
+            .unwrap_or_else(|| "wss://meta.example".to_string());
+        assert_eq!(url, expected);
+    }
+
+    #[test]
+    fn page_values_are_validated() {
+        if BUILD_WS_URL.is_some() {
+            return;
+        }
+        assert_eq!(
+            resolve_ws_url(Some(""), Some("wss://query.example")),
+            "wss://query.example"
+        );
+        assert_eq!(
+            resolve_ws_url(Some("http://meta.example"), None),
+            DEFAULT_WS_URL
+        );
+        assert_eq!(resolve_ws_url(None, None), DEFAULT_WS_URL);
+    }
+
+    #[test]
+    fn max_messages_takes_the_first_positive_number() {
+        if BUILD_MAX_MESSAGES.is_some() {
+            return;
+        }
+        assert_eq!(resolve_max_messages(Some("0"), Some("300")), 300);
+        assert_eq!(resolve_max_messages(Some(" 50 "), Some("300")), 50);
+        assert_eq!(
+            resolve_max_messages(Some("lots"), None),
+            DEFAULT_MAX_MESSAGES
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! `:shortcode:` expansion for emoji.
+
+use std::collections::HashMap;
+
+/// Shortcodes understood in messages, in the spelling most chat apps use.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("smile", "😄"),
+    ("grinning", "😀"),
+    ("joy", "😂"),
+    ("slightly_smiling_face", "🙂"),
+    ("wink", "😉"),
+    ("blush", "😊"),
+    ("heart_eyes", "😍"),
+    ("kissing_heart", "😘"),
+    ("sunglasses", "😎"),
+    ("thinking", "🤔"),
+    ("neutral_face", "😐"),
+    ("roll_eyes", "🙄"),
+    ("sleeping", "😴"),
+    ("cry", "😢"),
+    ("sob", "😭"),
+    ("rage", "😡"),
+    ("scream", "😱"),
+    ("+1", "👍"),
+    ("thumbsup", "👍"),
+    ("-1", "👎"),
+    ("thumbsdown", "👎"),
+    ("clap", "👏"),
+    ("raised_hands", "🙌"),
+    ("pray", "🙏"),
+    ("wave", "👋"),
+    ("handshake", "🤝"),
+    ("muscle", "💪"),
+    ("heart", "❤️"),
+    ("broken_heart", "💔"),
+    ("fire", "🔥"),
+    ("sparkles", "✨"),
+    ("tada", "🎉"),
+    ("100", "💯"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("warning", "⚠️"),
+    ("question", "❓"),
+    ("coffee", "☕"),
+    ("pizza", "🍕"),
+    ("beer", "🍺"),
+    ("birthday", "🎂"),
+    ("rocket", "🚀"),
+    ("computer", "💻"),
+    ("bug", "🐛"),
+    ("pushpin", "📌"),
+];
+
+thread_local! {
+    static TABLE: HashMap<&'static str, &'static str> = SHORTCODES.iter().copied().collect();
+}
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')
+}
+
+/// Replaces known `:shortcode:`s in `text` with their emoji. Unknown codes,
+/// codes run into letters or digits (`1:100:2`) and anything inside
+/// `` `code` `` spans are left as typed.
+pub fn expand_shortcodes(text: &str) -> String {
+    TABLE.with(|table| {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        let mut in_code = false;
+
+        while let Some(i) = rest.find([':', '`']) {
+            out.push_str(&rest[..i]);
+            rest = &rest[i..];
+            if rest.starts_with('`') {
+                in_code = !in_code;
+                out.push('`');
+                rest = &rest[1..];
+                continue;
+            }
+
+            let code_len = rest[1..]
+                .find(|c: char| !is_shortcode_char(c))
+                .unwrap_or(rest.len() - 1);
+            let closed = rest[1 + code_len..].starts_with(':');
+            let before = text[..text.len() - rest.len()].chars().next_back();
+            let after = rest.get(code_len + 2..).and_then(|r| r.chars().next());
+            let apart = !before.is_some_and(char::is_alphanumeric)
+                && !after.is_some_and(char::is_alphanumeric);
+            match table.get(&rest[1..1 + code_len]) {
+                Some(emoji) if !in_code && closed && apart && code_len > 0 => {
+                    out.push_str(emoji);
+                    rest = &rest[code_len + 2..];
+                }
+                // Keep the colon and look for a code starting at the next one,
+                // so `:not::fire:` still finds `:fire:`.
+                _ => {
+                    out.push(':');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansion() {
+        let cases = [
+            ("", ""),
+            ("no codes", "no codes"),
+            (":smile:", "😄"),
+            ("hi :wave: there", "hi 👋 there"),
+            (":+1: and :-1:", "👍 and 👎"),
+            (":100:", "💯"),
+            ("(:tada:)", "(🎉)"),
+            ("done:white_check_mark:", "done:white_check_mark:"),
+            (":fire:!", "🔥!"),
+            // Adjacent codes.
+            (":fire::fire:", "🔥🔥"),
+            (":fire::fire::fire:", "🔥🔥🔥"),
+            (":not::fire:", ":not:🔥"),
+            ("::fire::", ":🔥:"),
+            // Unknown or unfinished codes stay as typed.
+            (":nope:", ":nope:"),
+            (":SMILE:", ":SMILE:"),
+            (":smile", ":smile"),
+            ("smile:", "smile:"),
+            (": smile :", ": smile :"),
+            ("::", "::"),
+            (":", ":"),
+            (":sm ile:", ":sm ile:"),
+            // Colons between letters or digits aren't shortcodes.
+            ("ratio 1:100:2", "ratio 1:100:2"),
+            ("a:x:b", "a:x:b"),
+            ("at 10:30:00", "at 10:30:00"),
+            ("日本:fire:語", "日本:fire:語"),
+            ("👋:wave:👋", "👋👋👋"),
+        ];
+        for (text, expanded) in cases {
+            assert_eq!(expand_shortcodes(text), expanded, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn code_spans_are_left_alone() {
+        assert_eq!(expand_shortcodes("`:fire:` :fire:"), "`:fire:` 🔥");
+        assert_eq!(
+            expand_shortcodes(":fire: `a :fire: b` :fire:"),
+            "🔥 `a :fire: b` 🔥"
+        );
+        assert_eq!(expand_shortcodes("`unclosed :fire:"), "`unclosed :fire:");
+    }
+
+    #[test]
+    fn every_listed_code_expands() {
+        for (code, emoji) in SHORTCODES {
+            assert_eq!(
+                expand_shortcodes(&format!(":{}:", code)),
+                *emoji,
+                "{}",
+                code
+            );
+        }
+    }
+}
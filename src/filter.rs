@@ -0,0 +1,226 @@
+//! Optional masking of rude words in displayed messages. Words only match
+//! whole, so innocent words containing one ("Scunthorpe", "class") are left
+//! alone; case and common look-alike symbols (`$h1t`) are seen through.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// Masked when the filter is on, on top of any words the user adds.
+pub const DEFAULT_WORDS: &[&str] = &[
+    "arse",
+    "arsehole",
+    "ass",
+    "asshole",
+    "bastard",
+    "bitch",
+    "bollocks",
+    "bullshit",
+    "crap",
+    "cunt",
+    "damn",
+    "dick",
+    "dickhead",
+    "fuck",
+    "motherfucker",
+    "piss",
+    "shit",
+    "slut",
+    "twat",
+    "wank",
+    "wanker",
+    "whore",
+];
+
+/// Endings a listed word still matches with (`fucking`, `pissed`).
+const SUFFIXES: &[&str] = &["s", "es", "ed", "er", "ers", "ing", "in"];
+
+/// Symbols and digits standing in for letters.
+fn unleet(c: char) -> Option<char> {
+    Some(match c {
+        '0' => 'o',
+        '1' | '!' | '|' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => return None,
+    })
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || unleet(c).is_some()
+}
+
+/// `word` lowercased, with look-alike symbols read as the letters they
+/// stand for.
+pub fn normalize(word: &str) -> String {
+    word.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| unleet(c).unwrap_or(c))
+        .collect()
+}
+
+/// The words to mask, normalized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordFilter {
+    words: HashSet<String>,
+}
+
+impl Default for WordFilter {
+    fn default() -> Self {
+        Self::new::<&str>(&[])
+    }
+}
+
+impl WordFilter {
+    /// The default words plus `extra`.
+    pub fn new<S: AsRef<str>>(extra: &[S]) -> Self {
+        let words = DEFAULT_WORDS
+            .iter()
+            .copied()
+            .chain(extra.iter().map(AsRef::as_ref))
+            .map(|word| normalize(word.trim()))
+            .filter(|word| !word.is_empty())
+            .collect();
+        Self { words }
+    }
+
+    fn matches(&self, word: &str) -> bool {
+        // Numbers alone are never words, however they read ("455").
+        if !word.chars().any(char::is_alphabetic) {
+            return false;
+        }
+        let word = normalize(word);
+        self.words.contains(&word)
+            || SUFFIXES.iter().any(|suffix| {
+                word.strip_suffix(suffix)
+                    .is_some_and(|stem| self.words.contains(stem))
+            })
+    }
+
+    /// Byte range of the part of the run of word characters `token` that
+    /// is a listed word, if any. Symbols at its ends only count when the
+    /// whole run is a word (`$hit`); otherwise they are read as punctuation
+    /// (`shit!`).
+    fn find(&self, token: &str) -> Option<(usize, usize)> {
+        if self.matches(token) {
+            return Some((0, token.len()));
+        }
+        let core = token.trim_matches(|c: char| !c.is_alphanumeric());
+        let start = token.find(core)?;
+        (!core.is_empty() && core.len() < token.len() && self.matches(core))
+            .then(|| (start, start + core.len()))
+    }
+
+    /// `text` with every listed word replaced by one asterisk per
+    /// character. Borrowed when there is nothing to mask.
+    pub fn censor<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut masked = String::new();
+        let mut copied = 0;
+        let mut rest = text.char_indices().peekable();
+        while let Some((start, c)) = rest.next() {
+            if !is_word_char(c) {
+                continue;
+            }
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, c)) = rest.peek() {
+                if !is_word_char(c) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                rest.next();
+            }
+            if let Some((from, to)) = self.find(&text[start..end]) {
+                let (from, to) = (start + from, start + to);
+                masked.push_str(&text[copied..from]);
+                masked.extend(text[from..to].chars().map(|_| '*'));
+                copied = to;
+            }
+        }
+        if copied == 0 {
+            return Cow::Borrowed(text);
+        }
+        masked.push_str(&text[copied..]);
+        Cow::Owned(masked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn censoring() {
+        let filter = WordFilter::default();
+        let cases = [
+            ("", ""),
+            ("hello there", "hello there"),
+            ("oh shit", "oh ****"),
+            ("SHIT", "****"),
+            ("Shit!", "****!"),
+            ("shit, shit.", "****, ****."),
+            ("(shit)", "(****)"),
+            ("\"damn\"", "\"****\""),
+            ("$hit", "****"),
+            ("sh1t", "****"),
+            ("5h!t", "****"),
+            ("@ss", "***"),
+            ("a$$", "***"),
+            ("fucking", "*******"),
+            ("pissed", "******"),
+            ("bitches", "*******"),
+            ("shit-faced", "****-faced"),
+            ("shit_faced", "****_faced"),
+            ("well,shit", "well,****"),
+            // Innocent words containing a listed one.
+            ("Scunthorpe", "Scunthorpe"),
+            ("class", "class"),
+            ("assassin", "assassin"),
+            ("passage", "passage"),
+            ("cocktail", "cocktail"),
+            ("Dickens", "Dickens"),
+            ("scrap", "scrap"),
+            ("shitake", "shitake"),
+            ("bass", "bass"),
+            ("grass!", "grass!"),
+            // Numbers never read as words.
+            ("455", "455"),
+            ("call 555-1234", "call 555-1234"),
+            ("!!!", "!!!"),
+            // Case folding beyond ASCII, and other scripts around a word.
+            ("Ünd shit", "Ünd ****"),
+            ("日本 shit 語", "日本 **** 語"),
+            ("shit👍", "****👍"),
+            ("shït", "shït"),
+        ];
+        for (text, shown) in cases {
+            assert_eq!(filter.censor(text), shown, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn clean_text_is_borrowed() {
+        let filter = WordFilter::default();
+        assert!(matches!(filter.censor("all fine"), Cow::Borrowed(_)));
+        assert!(matches!(filter.censor("oh crap"), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn added_words_are_normalized() {
+        let filter = WordFilter::new(&["  Heck ", "FR1CK", "", "Straße"]);
+        assert_eq!(filter.censor("what the heck"), "what the ****");
+        assert_eq!(filter.censor("HECKING"), "*******");
+        assert_eq!(filter.censor("frick off"), "***** off");
+        assert_eq!(filter.censor("STRASSE straße"), "STRASSE ******");
+        assert_eq!(filter.censor("shit"), "****");
+        assert_eq!(filter.censor("heckle"), "heckle");
+    }
+
+    #[test]
+    fn normalizing() {
+        assert_eq!(normalize("$H1T"), "shit");
+        assert_eq!(normalize("Ärger"), "ärger");
+        assert_eq!(normalize("İ"), "i\u{307}");
+        assert_eq!(normalize("@|3"), "aie");
+    }
+}
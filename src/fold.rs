@@ -0,0 +1,97 @@
+//! Folding long messages: past a size, only their head is shown until
+//! asked for the rest. The thresholds live here so they can be tuned in
+//! one place.
+
+use crate::code::Block;
+
+/// Characters of prose shown before a message is folded.
+pub const FOLD_CHARS: usize = 600;
+/// Lines, of prose and code together, shown before a message is folded.
+pub const FOLD_LINES: usize = 12;
+/// How far back from the cut, in characters, a space is looked for so that
+/// prose isn't folded mid-word.
+const WORD_BREAK_LOOKBACK: usize = 40;
+
+/// Where to cut `text` so that it keeps at most `chars` characters and
+/// `lines` lines, preferably at a space. `None` if it already fits.
+fn cut_prose(text: &str, chars: usize, lines: usize) -> Option<usize> {
+    let mut seen_lines = 1;
+    let mut last_space = None;
+    for (count, (at, c)) in text.char_indices().enumerate() {
+        if c == '\n' {
+            if seen_lines == lines {
+                return Some(at);
+            }
+            seen_lines += 1;
+        }
+        if count == chars {
+            let word_start =
+                last_space.filter(|&(space_count, _)| count - space_count <= WORD_BREAK_LOOKBACK);
+            return Some(word_start.map_or(at, |(_, space_at)| space_at));
+        }
+        if c.is_whitespace() {
+            last_space = Some((count, at));
+        }
+    }
+    None
+}
+
+/// Where to cut `code` so that it keeps at most `lines` whole lines.
+/// `None` if it already fits.
+fn cut_lines(code: &str, lines: usize) -> Option<usize> {
+    code.match_indices('\n')
+        .nth(lines.checked_sub(1)?)
+        .map(|(at, _)| at)
+}
+
+/// The head of a message split into `blocks`, if it is too long to show
+/// whole. Prose is cut by characters and lines, code only between lines so
+/// that highlighting never sees half a token.
+pub fn fold<'a>(blocks: &[Block<'a>]) -> Option<Vec<Block<'a>>> {
+    let mut chars = 0;
+    let mut lines = 0;
+    let mut head = Vec::new();
+    for &block in blocks {
+        let (chars_left, lines_left) = (FOLD_CHARS - chars, FOLD_LINES - lines);
+        let cut = match block {
+            Block::Text(text) => cut_prose(text, chars_left, lines_left)
+                .map(|end| Block::Text(text[..end].trim_end())),
+            Block::Code { lang, code } => cut_lines(code, lines_left).map(|end| Block::Code {
+                lang,
+                code: &code[..end],
+            }),
+        };
+        if let Some(cut) = cut {
+            let empty = match cut {
+                Block::Text(text) => text.trim().is_empty(),
+                Block::Code { code, .. } => code.is_empty(),
+            };
+            if !empty {
+                head.push(cut);
+            }
+            return Some(head);
+        }
+        match block {
+            Block::Text(text) => {
+                chars += text.chars().count();
+                lines += text.lines().count().max(1);
+            }
+            Block::Code { code, .. } => lines += code.lines().count().max(1),
+        }
+        head.push(block);
+        // What follows starts past the budget.
+        if chars >= FOLD_CHARS || lines >= FOLD_LINES {
+            return (head.len() < blocks.len()).then_some(head);
+        }
+    }
+    None
+}
+
+/// `count` shortened for a label: 950, 1.2k, 12k.
+pub fn short_count(count: usize) -> String {
+    match count {
+        0..=999 => count.to_string(),
+        1_000..=9_949 => format!("{:.1}k", count as f64 / 1_000.0),
+        _ => format!("{}k", (count + 500) / 1_000),
+    }
+}
@@ -0,0 +1,179 @@
+//! Translated UI strings. Each locale is a `locales/<tag>.lang` file of
+//! `key = text` lines, bundled at build time; `{name}` in a text is filled
+//! in from arguments. Plural forms are separate keys suffixed with the
+//! CLDR category (`.one`, `.few`, `.other`, …) picked by `Intl.PluralRules`.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use js_sys::{Array, Intl, Object};
+use wasm_bindgen::JsValue;
+use yew::prelude::*;
+
+use crate::services::storage;
+
+mod bundled {
+    include!(concat!(env!("OUT_DIR"), "/locales.rs"));
+}
+
+/// Used for anything the chosen locale lacks.
+pub const FALLBACK_LOCALE: &str = "en";
+
+/// The translations every view reads, and the settings panel replaces.
+pub type I18nContext = UseStateHandle<I18n>;
+
+/// Parses a locale file. Blank lines and lines starting with `#` are
+/// skipped; `\n` in a text stands for a line break.
+pub fn parse(source: &str) -> HashMap<String, String> {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, text)| (key.trim().to_string(), text.trim().replace("\\n", "\n")))
+        .collect()
+}
+
+/// Fills `{name}` placeholders in `text` from `args`. Unknown placeholders
+/// are left as they are.
+pub fn fill(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut text = text.to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// The bundled locale best matching `requested`: the exact tag, else its
+/// language alone (`id-ID` → `id`), else the fallback.
+pub fn best_match(requested: &str) -> &'static str {
+    let language = requested.split(['-', '_']).next().unwrap_or_default();
+    let tags = || bundled::LOCALES.iter().map(|(tag, _)| *tag);
+    tags()
+        .find(|tag| tag.eq_ignore_ascii_case(requested))
+        .or_else(|| tags().find(|tag| tag.eq_ignore_ascii_case(language)))
+        .unwrap_or(FALLBACK_LOCALE)
+}
+
+/// Every bundled locale as `(tag, name of the language in itself)`.
+pub fn available() -> Vec<(&'static str, String)> {
+    bundled::LOCALES
+        .iter()
+        .map(|(tag, source)| {
+            let name = parse(source).remove("locale.name");
+            (*tag, name.unwrap_or_else(|| tag.to_string()))
+        })
+        .collect()
+}
+
+/// The locale picked in the settings, or else the browser's.
+pub fn initial_locale() -> &'static str {
+    let requested =
+        storage::load_locale().or_else(|| web_sys::window().and_then(|w| w.navigator().language()));
+    best_match(requested.as_deref().unwrap_or(FALLBACK_LOCALE))
+}
+
+/// The translations provided to a function component, which re-renders when
+/// they change.
+pub fn use_i18n() -> I18n {
+    use_context::<I18nContext>()
+        .map(|handle| (*handle).clone())
+        .unwrap_or_default()
+}
+
+/// The translations provided to a struct component as of this render. Ones
+/// that must re-render on a switch subscribe to [`I18nContext`] instead.
+pub fn of<C: Component>(ctx: &Context<C>) -> I18n {
+    ctx.link()
+        .context::<I18nContext>(Callback::noop())
+        .map(|(handle, _)| (*handle).clone())
+        .unwrap_or_default()
+}
+
+/// Marks the page as being in `locale`, for screen readers and hyphenation.
+pub fn apply(locale: &str) {
+    if let Some(root) = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.document_element())
+    {
+        let _ = root.set_attribute("lang", locale);
+    }
+}
+
+/// The strings of one locale, with the fallback locale behind them.
+#[derive(Clone)]
+pub struct I18n {
+    locale: &'static str,
+    strings: Rc<HashMap<String, String>>,
+    fallback: Rc<HashMap<String, String>>,
+}
+
+impl PartialEq for I18n {
+    fn eq(&self, other: &Self) -> bool {
+        self.locale == other.locale
+    }
+}
+
+impl Default for I18n {
+    fn default() -> Self {
+        Self::new(FALLBACK_LOCALE)
+    }
+}
+
+impl I18n {
+    pub fn new(locale: &str) -> Self {
+        let locale = best_match(locale);
+        let load = |tag: &str| {
+            let source = bundled::LOCALES
+                .iter()
+                .find(|(t, _)| *t == tag)
+                .map_or("", |(_, source)| *source);
+            Rc::new(parse(source))
+        };
+        Self {
+            locale,
+            strings: load(locale),
+            fallback: load(FALLBACK_LOCALE),
+        }
+    }
+
+    pub fn locale(&self) -> &'static str {
+        self.locale
+    }
+
+    fn lookup(&self, key: &str) -> Option<&String> {
+        self.strings.get(key).or_else(|| self.fallback.get(key))
+    }
+
+    /// The text for `key`, or the key itself if no locale has it.
+    pub fn t(&self, key: &str) -> String {
+        self.lookup(key).cloned().unwrap_or_else(|| key.to_string())
+    }
+
+    /// The text for `key` with its placeholders filled from `args`.
+    pub fn t_with(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        fill(&self.t(key), args)
+    }
+
+    /// The plural form of `key` for `count`, with `{count}` and `args`
+    /// filled in.
+    pub fn plural(&self, key: &str, count: usize, args: &[(&str, &dyn Display)]) -> String {
+        let category = self.plural_category(count);
+        let text = self
+            .lookup(&format!("{}.{}", key, category))
+            .or_else(|| self.lookup(&format!("{}.other", key)))
+            .cloned()
+            .unwrap_or_else(|| key.to_string());
+        let mut all: Vec<(&str, &dyn Display)> = vec![("count", &count)];
+        all.extend_from_slice(args);
+        fill(&text, &all)
+    }
+
+    fn plural_category(&self, count: usize) -> String {
+        let locales = Array::of1(&JsValue::from_str(self.locale));
+        Intl::PluralRules::new(&locales, &Object::new())
+            .select(count as f64)
+            .into()
+    }
+}
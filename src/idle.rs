@@ -0,0 +1,169 @@
+//! Telling when the user has stepped away: no input for a while, or the tab
+//! left in the background for a while. Times are passed in, so the
+//! thresholds can be crossed without waiting for them.
+
+use crate::protocol::Presence;
+
+/// Without input for this long, the user is away.
+pub const IDLE_AFTER_MS: u64 = 5 * 60_000;
+/// With the tab hidden for this long, the user is away.
+pub const HIDDEN_AFTER_MS: u64 = 60_000;
+
+/// Tracks input and tab visibility and says when our presence changes.
+#[derive(Debug, Clone)]
+pub struct IdleTracker {
+    idle_after_ms: u64,
+    hidden_after_ms: u64,
+    last_activity: u64,
+    /// When the tab was hidden, while it is.
+    hidden_since: Option<u64>,
+    presence: Presence,
+}
+
+impl IdleTracker {
+    /// A tracker that starts online at `now`, going away after
+    /// `idle_after_ms` without input or `hidden_after_ms` in the background.
+    pub fn new(idle_after_ms: u64, hidden_after_ms: u64, now: u64) -> Self {
+        Self {
+            idle_after_ms,
+            hidden_after_ms,
+            last_activity: now,
+            hidden_since: None,
+            presence: Presence::Online,
+        }
+    }
+
+    pub fn presence(&self) -> Presence {
+        self.presence
+    }
+
+    /// The user typed or clicked at `now`. Returns the new presence if that
+    /// brought them back.
+    pub fn activity(&mut self, now: u64) -> Option<Presence> {
+        self.last_activity = now;
+        self.set(Presence::Online)
+    }
+
+    /// The tab was hidden or shown at `now`. Coming back to it counts as
+    /// activity.
+    pub fn visibility(&mut self, hidden: bool, now: u64) -> Option<Presence> {
+        if hidden {
+            self.hidden_since.get_or_insert(now);
+            self.tick(now)
+        } else {
+            self.hidden_since = None;
+            self.activity(now)
+        }
+    }
+
+    /// Checks the thresholds at `now`. Returns the new presence if one was
+    /// crossed.
+    pub fn tick(&mut self, now: u64) -> Option<Presence> {
+        let idle = now.saturating_sub(self.last_activity) >= self.idle_after_ms;
+        let hidden = self
+            .hidden_since
+            .is_some_and(|since| now.saturating_sub(since) >= self.hidden_after_ms);
+        if idle || hidden {
+            self.set(Presence::Away)
+        } else {
+            None
+        }
+    }
+
+    fn set(&mut self, presence: Presence) -> Option<Presence> {
+        (self.presence != presence).then(|| {
+            self.presence = presence;
+            presence
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Milliseconds since the tracker was made, moved by hand.
+    struct Clock(u64);
+
+    impl Clock {
+        fn advance(&mut self, ms: u64) -> u64 {
+            self.0 += ms;
+            self.0
+        }
+    }
+
+    fn tracker() -> (IdleTracker, Clock) {
+        (
+            IdleTracker::new(IDLE_AFTER_MS, HIDDEN_AFTER_MS, 0),
+            Clock(0),
+        )
+    }
+
+    #[test]
+    fn going_idle_makes_us_away_once() {
+        let (mut tracker, mut clock) = tracker();
+        assert_eq!(tracker.tick(clock.advance(IDLE_AFTER_MS - 1)), None);
+        assert_eq!(tracker.presence(), Presence::Online);
+        assert_eq!(tracker.tick(clock.advance(1)), Some(Presence::Away));
+        assert_eq!(tracker.tick(clock.advance(60_000)), None);
+        assert_eq!(tracker.presence(), Presence::Away);
+    }
+
+    #[test]
+    fn activity_brings_us_back_and_restarts_the_wait() {
+        let (mut tracker, mut clock) = tracker();
+        tracker.tick(clock.advance(IDLE_AFTER_MS));
+        assert_eq!(tracker.activity(clock.advance(5)), Some(Presence::Online));
+        assert_eq!(tracker.activity(clock.advance(5)), None);
+        assert_eq!(tracker.tick(clock.advance(IDLE_AFTER_MS - 1)), None);
+        assert_eq!(tracker.tick(clock.advance(1)), Some(Presence::Away));
+    }
+
+    #[test]
+    fn activity_keeps_us_online() {
+        let (mut tracker, mut clock) = tracker();
+        for _ in 0..10 {
+            assert_eq!(tracker.activity(clock.advance(IDLE_AFTER_MS - 1)), None);
+            assert_eq!(tracker.tick(clock.advance(0)), None);
+        }
+        assert_eq!(tracker.presence(), Presence::Online);
+    }
+
+    #[test]
+    fn a_hidden_tab_makes_us_away_sooner() {
+        let (mut tracker, mut clock) = tracker();
+        assert_eq!(tracker.visibility(true, clock.advance(1_000)), None);
+        assert_eq!(tracker.tick(clock.advance(HIDDEN_AFTER_MS - 1)), None);
+        assert_eq!(tracker.tick(clock.advance(1)), Some(Presence::Away));
+    }
+
+    #[test]
+    fn hiding_again_does_not_restart_the_wait() {
+        let (mut tracker, mut clock) = tracker();
+        tracker.visibility(true, clock.advance(0));
+        tracker.visibility(true, clock.advance(HIDDEN_AFTER_MS / 2));
+        assert_eq!(
+            tracker.visibility(true, clock.advance(HIDDEN_AFTER_MS / 2)),
+            Some(Presence::Away)
+        );
+    }
+
+    #[test]
+    fn showing_the_tab_brings_us_back() {
+        let (mut tracker, mut clock) = tracker();
+        tracker.visibility(true, clock.advance(0));
+        tracker.tick(clock.advance(HIDDEN_AFTER_MS));
+        assert_eq!(
+            tracker.visibility(false, clock.advance(10)),
+            Some(Presence::Online)
+        );
+        assert_eq!(tracker.tick(clock.advance(HIDDEN_AFTER_MS)), None);
+    }
+
+    #[test]
+    fn a_clock_going_backwards_is_not_idleness() {
+        let mut tracker = IdleTracker::new(IDLE_AFTER_MS, HIDDEN_AFTER_MS, 10_000);
+        assert_eq!(tracker.tick(0), None);
+        assert_eq!(tracker.presence(), Presence::Online);
+    }
+}
@@ -0,0 +1,178 @@
+//! What we sent, for bringing back into the message input with the arrow
+//! keys the way a shell does. Recalling only ever copies an entry out, so
+//! editing one and sending it adds a new entry instead of changing it.
+
+use std::collections::VecDeque;
+
+/// Sends remembered.
+pub const INPUT_HISTORY_CAP: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Recall {
+    /// The entry shown, counting back from the newest.
+    back: usize,
+    /// What was in the input when recall started.
+    draft: String,
+}
+
+/// Sent text, oldest first, and where recall is in it.
+#[derive(Debug, Clone)]
+pub struct InputHistory {
+    entries: VecDeque<String>,
+    cap: usize,
+    recall: Option<Recall>,
+}
+
+impl InputHistory {
+    /// A history holding `entries`, oldest first, keeping at most the
+    /// `cap` newest.
+    pub fn new(entries: Vec<String>, cap: usize) -> Self {
+        let skip = entries.len().saturating_sub(cap);
+        Self {
+            entries: entries.into_iter().skip(skip).collect(),
+            cap,
+            recall: None,
+        }
+    }
+
+    /// Remembers `text` as just sent, and stops recalling. Sending the
+    /// newest entry again doesn't repeat it.
+    pub fn push(&mut self, text: &str) {
+        self.recall = None;
+        if text.trim().is_empty() || self.entries.back().is_some_and(|last| last == text) {
+            return;
+        }
+        if self.entries.len() == self.cap {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(text.to_string());
+    }
+
+    pub fn is_recalling(&self) -> bool {
+        self.recall.is_some()
+    }
+
+    /// Steps to the entry before the one shown, or to the newest if recall
+    /// is starting, `current` being what the input holds. Returns the text
+    /// to put in the input, or `None` when there is nothing older.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let back = match &mut self.recall {
+            Some(recall) if recall.back + 1 < self.entries.len() => {
+                recall.back += 1;
+                recall.back
+            }
+            Some(_) => return None,
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.recall = Some(Recall {
+                    back: 0,
+                    draft: current.to_string(),
+                });
+                0
+            }
+        };
+        self.entries
+            .get(self.entries.len() - 1 - back)
+            .map(String::as_str)
+    }
+
+    /// Steps to the entry after the one shown. Past the newest, recall ends
+    /// and the draft comes back. Returns the text to put in the input, or
+    /// `None` when not recalling.
+    pub fn newer(&mut self) -> Option<String> {
+        let recall = self.recall.as_mut()?;
+        if recall.back == 0 {
+            return self.cancel();
+        }
+        recall.back -= 1;
+        let back = recall.back;
+        self.entries.get(self.entries.len() - 1 - back).cloned()
+    }
+
+    /// Stops recalling, returning the draft it started from.
+    pub fn cancel(&mut self) -> Option<String> {
+        self.recall.take().map(|recall| recall.draft)
+    }
+
+    /// Oldest first, for saving.
+    pub fn entries(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> InputHistory {
+        InputHistory::new(entries.iter().map(|e| e.to_string()).collect(), 3)
+    }
+
+    fn saved(history: &InputHistory) -> Vec<&str> {
+        history.entries().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn up_recalls_older_entries_then_stops() {
+        let mut history = history(&["one", "two", "three"]);
+        assert_eq!(history.older("draft"), Some("three"));
+        assert!(history.is_recalling());
+        assert_eq!(history.older("three"), Some("two"));
+        assert_eq!(history.older("two"), Some("one"));
+        assert_eq!(history.older("one"), None);
+        assert!(history.is_recalling());
+    }
+
+    #[test]
+    fn down_steps_back_and_returns_the_draft() {
+        let mut history = history(&["one", "two"]);
+        history.older("half typed");
+        history.older("two");
+        assert_eq!(history.newer().as_deref(), Some("two"));
+        assert_eq!(history.newer().as_deref(), Some("half typed"));
+        assert!(!history.is_recalling());
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn cancelling_returns_the_draft() {
+        let mut history = history(&["one"]);
+        assert_eq!(history.cancel(), None);
+        history.older("");
+        assert_eq!(history.cancel().as_deref(), Some(""));
+        assert!(!history.is_recalling());
+    }
+
+    #[test]
+    fn an_empty_history_recalls_nothing() {
+        let mut history = history(&[]);
+        assert_eq!(history.older("draft"), None);
+        assert!(!history.is_recalling());
+    }
+
+    #[test]
+    fn sending_ends_recall_and_skips_repeats_and_blanks() {
+        let mut history = history(&["one"]);
+        history.older("draft");
+        history.push("two");
+        assert!(!history.is_recalling());
+        history.push("two");
+        history.push("   ");
+        history.push("");
+        assert_eq!(saved(&history), ["one", "two"]);
+        history.push("one");
+        assert_eq!(saved(&history), ["one", "two", "one"]);
+    }
+
+    #[test]
+    fn only_the_newest_are_kept() {
+        let mut history = history(&["a", "b", "c", "d", "e"]);
+        assert_eq!(saved(&history), ["c", "d", "e"]);
+        history.push("f");
+        assert_eq!(saved(&history), ["d", "e", "f"]);
+        assert_eq!(history.older(""), Some("f"));
+        assert_eq!(history.older("f"), Some("e"));
+        assert_eq!(history.older("e"), Some("d"));
+        assert_eq!(history.older("d"), None);
+    }
+}
@@ -1,19 +1,81 @@
 #![recursion_limit = "512"]
+// The `html!` macro in yew 0.19 expands to code that trips these lints.
+#![allow(clippy::let_unit_value, clippy::unnecessary_operation)]
 
+#[cfg(feature = "web")]
+mod a11y;
+#[cfg(feature = "web")]
+mod attachments;
+#[cfg(feature = "web")]
+mod code;
+#[cfg(feature = "web")]
+mod commands;
+#[cfg(feature = "web")]
 mod components;
+#[cfg(feature = "web")]
+mod config;
+#[cfg(feature = "web")]
+mod emoji;
+#[cfg(feature = "web")]
+mod filter;
+#[cfg(feature = "web")]
+mod fold;
+#[cfg(feature = "web")]
+mod i18n;
+#[cfg(feature = "web")]
+mod idle;
+#[cfg(feature = "web")]
+mod input_history;
+#[cfg(feature = "web")]
+mod messages;
+pub mod protocol;
+#[cfg(feature = "web")]
+mod resync;
+#[cfg(feature = "web")]
+mod rich_text;
+#[cfg_attr(not(feature = "web"), allow(dead_code))]
+mod sanitize;
+#[cfg(feature = "web")]
+mod search;
+#[cfg(feature = "web")]
 mod services;
+#[cfg(feature = "web")]
+mod shortcuts;
+#[cfg(feature = "web")]
+mod state;
+#[cfg(feature = "web")]
+mod throttle;
+#[cfg(feature = "web")]
+mod time;
+#[cfg(feature = "web")]
+mod toasts;
+#[cfg(feature = "web")]
+mod viewport;
 
+#[cfg(feature = "web")]
 use std::cell::RefCell;
+#[cfg(feature = "web")]
 use std::rc::Rc;
 
+#[cfg(feature = "web")]
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "web")]
 use yew::functional::*;
+#[cfg(feature = "web")]
 use yew::prelude::*;
+#[cfg(feature = "web")]
 use yew_router::prelude::*;
 
-use components::chat::Chat;
+#[cfg(feature = "web")]
+pub use components::chat::{Chat, ChatProps};
+#[cfg(feature = "web")]
+pub use services::transport::TransportFactory;
+#[cfg(feature = "web")]
 use components::login::Login;
 
+#[cfg(feature = "mock-transport")]
+pub use services::transport::MockTransport;
+
 // When the `wee_alloc` feature is enabled, this uses `wee_alloc` as the global
 // allocator.
 //
@@ -22,6 +84,7 @@ use components::login::Login;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+#[cfg(feature = "web")]
 #[derive(Debug, Clone, Copy, PartialEq, Routable)]
 pub enum Route {
     #[at("/")]
@@ -33,32 +96,61 @@ pub enum Route {
     NotFound,
 }
 
+#[cfg(feature = "web")]
 pub type User = Rc<UserInner>;
 
+#[cfg(feature = "web")]
 #[derive(Debug, PartialEq)]
 pub struct UserInner {
     pub username: RefCell<String>,
+    /// Why the server turned the last login down, shown on the login screen.
+    pub login_error: RefCell<Option<String>>,
 }
 
+#[cfg(feature = "web")]
 #[function_component(Main)]
 fn main() -> Html {
+    let theme = use_state(services::theme::initial);
+    use_effect_with_deps(
+        |theme| {
+            services::theme::apply(*theme);
+            || ()
+        },
+        *theme,
+    );
+    let i18n = use_state(|| i18n::I18n::new(i18n::initial_locale()));
+    use_effect_with_deps(
+        |locale| {
+            i18n::apply(locale);
+            || ()
+        },
+        i18n.locale(),
+    );
     let ctx = use_state(|| {
         Rc::new(UserInner {
-            username: RefCell::new("initial".into()),
+            username: RefCell::new(
+                services::storage::load_username().unwrap_or_else(|| "initial".into()),
+            ),
+            login_error: RefCell::new(None),
         })
     });
 
     html! {
         <ContextProvider<User> context={(*ctx).clone()}>
-            <BrowserRouter>
-                <div class="flex w-screen h-screen">
-                    <Switch<Route> render={Switch::render(switch)}/>
-                </div>
-            </BrowserRouter>
+            <ContextProvider<services::theme::ThemeContext> context={theme}>
+                <ContextProvider<i18n::I18nContext> context={i18n}>
+                    <BrowserRouter>
+                        <div class="flex w-screen h-dvh">
+                            <Switch<Route> render={Switch::render(switch)}/>
+                        </div>
+                    </BrowserRouter>
+                </ContextProvider<i18n::I18nContext>>
+            </ContextProvider<services::theme::ThemeContext>>
         </ContextProvider<User>>
     }
 }
 
+#[cfg(feature = "web")]
 fn switch(selected_route: &Route) -> Html {
     match selected_route {
         Route::Login => html! {<Login />},
@@ -67,6 +159,7 @@ fn switch(selected_route: &Route) -> Html {
     }
 }
 
+#[cfg(feature = "web")]
 #[wasm_bindgen]
 pub fn run_app() -> Result<(), JsValue> {
     wasm_logger::init(wasm_logger::Config::default());
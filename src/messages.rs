@@ -0,0 +1,1028 @@
+//! Chat messages, the per-conversation store holding them, and pure helpers
+//! for shaping the list before it is rendered.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::{Deref, DerefMut};
+
+use crate::i18n::I18n;
+use crate::rich_text::image_url;
+use crate::sanitize::is_image_data_url;
+
+pub use crate::protocol::{MessageData, MessageId};
+
+/// Number of messages kept in memory per conversation unless configured
+/// otherwise.
+pub const DEFAULT_MAX_MESSAGES: usize = 2000;
+
+/// More than this many messages from one sender inside [`BURST_WINDOW_MS`]
+/// are collapsed into a single expandable group.
+pub const BURST_LIMIT: usize = 10;
+pub const BURST_WINDOW_MS: u64 = 2_000;
+
+/// Longest message the server accepts without truncating it.
+pub const MAX_MESSAGE_CHARS: usize = 500;
+
+/// Length of the excerpt shown when quoting a message.
+pub const QUOTE_EXCERPT_CHARS: usize = 80;
+
+/// Shown in place of the text of a deleted message.
+pub const DELETED_PLACEHOLDER: &str = "message deleted";
+
+/// How far one of our own messages has got on its way to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SendStatus {
+    /// Confirmed by the server, or received from someone else.
+    #[default]
+    Sent,
+    /// Sent while offline; held by the websocket service until it reconnects.
+    Queued,
+    /// On the wire, waiting for the server echo.
+    Pending,
+    /// No echo arrived in time, or the frame could not be handed over.
+    Failed,
+}
+
+/// A message as the chat holds it: what went over the wire, plus what only
+/// this client knows about it. The wire fields are reached through
+/// [`Deref`].
+#[derive(Debug, Clone, Default)]
+pub struct ChatMessage {
+    pub data: MessageData,
+    pub status: SendStatus,
+    /// When the frame actually went out, for matching the server echo.
+    pub sent_at: u64,
+    /// Generated locally (e.g. "bob joined") rather than sent by a user.
+    pub system: bool,
+    /// Loaded from history (the local cache or the server) rather than
+    /// received live.
+    pub restored: bool,
+}
+
+impl ChatMessage {
+    /// Replaces the text with an edit made by `from`, keeping the original.
+    /// Only the author may edit; returns whether the text changed.
+    pub fn apply_edit(&mut self, from: &str, text: String) -> bool {
+        let data = &mut self.data;
+        if from != data.from {
+            log::debug!("rejecting edit of {}'s message by {}", data.from, from);
+            return false;
+        }
+        if data.deleted || text == data.message {
+            return false;
+        }
+        let previous = std::mem::replace(&mut data.message, text);
+        data.original_text.get_or_insert(previous);
+        true
+    }
+
+    /// Clears the text if `from` wrote the message, leaving a placeholder
+    /// behind. Returns whether anything changed.
+    pub fn tombstone(&mut self, from: &str) -> bool {
+        let data = &mut self.data;
+        if from != data.from {
+            log::debug!("rejecting delete of {}'s message by {}", data.from, from);
+            return false;
+        }
+        if data.deleted {
+            return false;
+        }
+        data.deleted = true;
+        data.message.clear();
+        data.original_text = None;
+        data.file = None;
+        true
+    }
+
+    /// The text to show or quote.
+    pub fn display_text(&self) -> &str {
+        if self.deleted {
+            DELETED_PLACEHOLDER
+        } else {
+            &self.message
+        }
+    }
+}
+
+impl From<MessageData> for ChatMessage {
+    fn from(data: MessageData) -> Self {
+        Self {
+            data,
+            ..Default::default()
+        }
+    }
+}
+
+impl Deref for ChatMessage {
+    type Target = MessageData;
+
+    fn deref(&self) -> &MessageData {
+        &self.data
+    }
+}
+
+impl DerefMut for ChatMessage {
+    fn deref_mut(&mut self) -> &mut MessageData {
+        &mut self.data
+    }
+}
+
+/// Id for a message we are sending, unique as long as `counter` is never
+/// reused within the same millisecond.
+pub fn client_id(username: &str, timestamp: u64, counter: u64) -> MessageId {
+    format!("{}-{}-{}", username, timestamp, counter)
+}
+
+/// What [`MessageStore::merge`] did with a batch of history.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Merged {
+    /// Messages that weren't held before.
+    pub added: usize,
+    /// Our own sends the history showed the server got.
+    pub confirmed: Vec<MessageId>,
+    /// Some of the added messages went in ahead of ones already held.
+    pub above: bool,
+}
+
+/// Messages of one conversation, oldest first, capped in size. Pinned
+/// messages are kept past the cap.
+#[derive(Default)]
+pub struct MessageStore {
+    messages: Vec<ChatMessage>,
+    /// Older messages evicted to stay under the cap.
+    trimmed: usize,
+}
+
+impl From<Vec<ChatMessage>> for MessageStore {
+    fn from(messages: Vec<ChatMessage>) -> Self {
+        Self {
+            messages,
+            trimmed: 0,
+        }
+    }
+}
+
+impl MessageStore {
+    pub fn as_slice(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut ChatMessage> {
+        self.messages.iter_mut()
+    }
+
+    /// How many older messages have been evicted to respect the cap.
+    pub fn trimmed(&self) -> usize {
+        self.trimmed
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ChatMessage> {
+        self.messages
+            .iter()
+            .find(|m| !m.id.is_empty() && m.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut ChatMessage> {
+        self.messages
+            .iter_mut()
+            .find(|m| !m.id.is_empty() && m.id == id)
+    }
+
+    /// Appends `message` unless one with the same id is already held, then
+    /// evicts down to `cap`, sparing the ids `pinned` says are. Returns
+    /// whether it was added.
+    pub fn insert(
+        &mut self,
+        message: ChatMessage,
+        cap: usize,
+        pinned: impl Fn(&str) -> bool,
+    ) -> bool {
+        if self.get(&message.id).is_some() {
+            return false;
+        }
+        self.messages.push(message);
+        self.evict(cap, pinned);
+        true
+    }
+
+    /// Weaves server history into what is held, then evicts down to `cap`,
+    /// sparing the ids `pinned` says are. `history` may be an older page,
+    /// the gap missed while disconnected, or overlap either; it is sorted
+    /// by timestamp and slotted in without moving anything already held.
+    /// Ids held or repeated are skipped, except that a copy of one of our
+    /// unconfirmed sends replaces it where it stands, confirming it. Sends
+    /// the server doesn't have yet stay as they are.
+    pub fn merge(
+        &mut self,
+        mut history: Vec<ChatMessage>,
+        cap: usize,
+        pinned: impl Fn(&str) -> bool,
+    ) -> Merged {
+        let mut merged = Merged::default();
+        let held: HashMap<MessageId, usize> = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| !m.id.is_empty())
+            .map(|(at, m)| (m.id.clone(), at))
+            .collect();
+        history.sort_by_key(|m| m.timestamp);
+        let mut fresh = Vec::new();
+        let mut fresh_ids = HashSet::new();
+        for m in history {
+            match held.get(&m.id) {
+                Some(&at) if self.messages[at].status != SendStatus::Sent => {
+                    let sent = std::mem::replace(&mut self.messages[at], m);
+                    merged.confirmed.push(sent.data.id);
+                }
+                Some(_) => {}
+                None if !m.id.is_empty() && !fresh_ids.insert(m.id.clone()) => {}
+                None => fresh.push(m),
+            }
+        }
+        merged.added = fresh.len();
+        if !fresh.is_empty() {
+            let mut fresh = fresh.into_iter().peekable();
+            let mut woven = Vec::with_capacity(self.messages.len() + merged.added);
+            for m in std::mem::take(&mut self.messages) {
+                while let Some(earlier) = fresh.next_if(|f| f.timestamp < m.timestamp) {
+                    woven.push(earlier);
+                    merged.above = true;
+                }
+                woven.push(m);
+            }
+            woven.extend(fresh);
+            self.messages = woven;
+            self.evict(cap, pinned);
+        }
+        merged
+    }
+
+    /// Drops the oldest messages until at most `cap` are left, or only
+    /// pinned ones (as `pinned` says of an id) are left to drop. Replies to
+    /// a dropped message keep an excerpt of it to quote. Returns how many
+    /// were dropped.
+    pub fn evict(&mut self, cap: usize, pinned: impl Fn(&str) -> bool) -> usize {
+        let excess = self.messages.len().saturating_sub(cap);
+        if excess == 0 {
+            return 0;
+        }
+        let mut count = 0;
+        let mut quotes = HashMap::new();
+        self.messages.retain(|m| {
+            if count == excess || (!m.id.is_empty() && pinned(&m.id)) {
+                return true;
+            }
+            count += 1;
+            if !m.id.is_empty() {
+                quotes.insert(m.id.clone(), excerpt(m.display_text(), QUOTE_EXCERPT_CHARS));
+            }
+            false
+        });
+        for m in &mut self.messages {
+            if m.reply_excerpt.is_some() {
+                continue;
+            }
+            if let Some(quote) = m.reply_to.as_ref().and_then(|id| quotes.get(id)) {
+                m.reply_excerpt = Some(quote.clone());
+            }
+        }
+        self.trimmed += count;
+        count
+    }
+
+    /// The newest `limit` delivered messages, as kept across reloads. Sends
+    /// still in flight, notices and pasted images and files (which would
+    /// soon fill up the storage quota) are left out.
+    pub fn persisted(&self, limit: usize) -> Vec<&MessageData> {
+        let delivered: Vec<&MessageData> = self
+            .messages
+            .iter()
+            .filter(|m| {
+                m.status == SendStatus::Sent
+                    && !m.system
+                    && !is_image_data_url(&m.message)
+                    && m.file.as_ref().is_none_or(|f| f.body.is_none())
+            })
+            .map(|m| &m.data)
+            .collect();
+        let skip = delivered.len().saturating_sub(limit);
+        delivered.into_iter().skip(skip).collect()
+    }
+
+    /// Applies an edit by `from` to the message `id`, returning whether the
+    /// text changed.
+    pub fn apply_edit(&mut self, id: &str, from: &str, text: String) -> bool {
+        self.get_mut(id).is_some_and(|m| m.apply_edit(from, text))
+    }
+
+    /// Deletes the message `id` on behalf of `from`, returning whether it
+    /// changed.
+    pub fn tombstone(&mut self, id: &str, from: &str) -> bool {
+        self.get_mut(id).is_some_and(|m| m.tombstone(from))
+    }
+}
+
+/// The messages that are nothing but an image, oldest first, each with the
+/// image's URL. Deleted messages have dropped theirs.
+pub fn image_messages(messages: &[ChatMessage]) -> impl Iterator<Item = (&ChatMessage, &str)> {
+    messages
+        .iter()
+        .filter(|m| !m.deleted && m.file.is_none())
+        .filter_map(|m| image_url(&m.message).map(|url| (m, url)))
+}
+
+/// A renderable slice of the message list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    /// A single message, by index.
+    Single(usize),
+    /// `len` consecutive messages from one sender starting at `start`.
+    Burst { start: usize, len: usize },
+}
+
+impl Row {
+    /// The same row with its indices shifted by `by`, for rows computed over
+    /// a sub-slice.
+    pub fn offset(self, by: usize) -> Row {
+        match self {
+            Row::Single(i) => Row::Single(i + by),
+            Row::Burst { start, len } => Row::Burst {
+                start: start + by,
+                len,
+            },
+        }
+    }
+}
+
+/// Splits a list of `(sender, timestamp)` pairs into rows, collapsing runs of
+/// more than [`BURST_LIMIT`] messages from the same sender that all arrived
+/// within [`BURST_WINDOW_MS`] of the first one.
+pub fn group_bursts<'a>(messages: impl Iterator<Item = (&'a str, u64)>) -> Vec<Row> {
+    let messages: Vec<(&str, u64)> = messages.collect();
+    let mut rows = Vec::new();
+    let mut start = 0;
+
+    while start < messages.len() {
+        let (from, first_ts) = messages[start];
+        let len = messages[start..]
+            .iter()
+            .take_while(|(f, ts)| *f == from && ts.saturating_sub(first_ts) <= BURST_WINDOW_MS)
+            .count();
+
+        if len > BURST_LIMIT {
+            rows.push(Row::Burst { start, len });
+        } else {
+            rows.extend((start..start + len).map(Row::Single));
+        }
+        start += len;
+    }
+
+    rows
+}
+
+/// Indices at which a new calendar day starts, given the day each message
+/// was sent on. The first message always starts one.
+pub fn day_starts<T: PartialEq>(days: &[T]) -> Vec<usize> {
+    (0..days.len())
+        .filter(|&i| i == 0 || days[i] != days[i - 1])
+        .collect()
+}
+
+/// The first `max` characters of `text`, with an ellipsis if it was cut.
+pub fn excerpt(text: &str, max: usize) -> String {
+    let mut chars = text.chars();
+    let head: String = chars.by_ref().take(max).collect();
+    if chars.next().is_some() {
+        format!("{}…", head)
+    } else {
+        head
+    }
+}
+
+/// Furthest any recipient has got with one of our messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiptLevel {
+    /// Accepted by the server, but no client has confirmed it yet.
+    Sent,
+    Delivered,
+    Read,
+}
+
+/// Who has acknowledged one of our messages.
+#[derive(Default)]
+pub struct Receipts {
+    delivered: HashSet<String>,
+    read: HashSet<String>,
+}
+
+impl Receipts {
+    /// Records that `from` received the message, and with `read` that they
+    /// saw it. Returns whether anything changed.
+    pub fn record(&mut self, from: &str, read: bool) -> bool {
+        let mut changed = self.delivered.insert(from.to_string());
+        if read {
+            changed |= self.read.insert(from.to_string());
+        }
+        changed
+    }
+
+    pub fn level(&self) -> ReceiptLevel {
+        if !self.read.is_empty() {
+            ReceiptLevel::Read
+        } else if !self.delivered.is_empty() {
+            ReceiptLevel::Delivered
+        } else {
+            ReceiptLevel::Sent
+        }
+    }
+
+    /// Names of everyone who has seen the message, sorted.
+    pub fn readers(&self) -> Vec<&str> {
+        let mut readers: Vec<&str> = self.read.iter().map(String::as_str).collect();
+        readers.sort_unstable();
+        readers
+    }
+}
+
+/// Splits `text` into pieces of at most `max` characters (not bytes),
+/// breaking at whitespace where possible. Words longer than `max` are cut.
+pub fn split_message(text: &str, max: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let limit = match rest.char_indices().nth(max) {
+            Some((limit, _)) => limit,
+            None => {
+                parts.push(rest.to_string());
+                break;
+            }
+        };
+        let cut = if rest[limit..].starts_with(char::is_whitespace) {
+            limit
+        } else {
+            rest[..limit]
+                .rfind(char::is_whitespace)
+                .filter(|&i| i > 0)
+                .unwrap_or(limit)
+        };
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    parts
+}
+
+/// Ids remembered by [`SeenIds`] before the oldest ones are forgotten.
+pub const SEEN_IDS_CAP: usize = 1000;
+const SEEN_IDS_EVICT: usize = 100;
+
+/// Bounded record of message ids already received, used to drop replays.
+#[derive(Default)]
+pub struct SeenIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenIds {
+    /// Records `id`, returning `false` if it had been seen before.
+    pub fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+
+        if self.order.len() > SEEN_IDS_CAP {
+            for old in self.order.drain(..SEEN_IDS_EVICT) {
+                self.ids.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+/// Sentence shown under the transcript for the users currently typing, or
+/// `None` if nobody is. Past two names the rest are summarised as a count.
+pub fn typing_label(i18n: &I18n, names: &[&str]) -> Option<String> {
+    let label = match names {
+        [] => return None,
+        [one] => i18n.t_with("typing.one", &[("a", one)]),
+        [a, b] => i18n.t_with("typing.two", &[("a", a), ("b", b)]),
+        [a, b, c] => i18n.t_with("typing.three", &[("a", a), ("b", b), ("c", c)]),
+        [a, b, rest @ ..] => i18n.plural("typing.many", rest.len(), &[("a", a), ("b", b)]),
+    };
+    Some(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::FileData;
+    use crate::time::Day;
+
+    fn message(id: &str, timestamp: u64) -> ChatMessage {
+        ChatMessage::from(MessageData {
+            id: id.to_string(),
+            from: "alice".to_string(),
+            message: id.to_string(),
+            timestamp,
+            ..Default::default()
+        })
+    }
+
+    fn store(messages: &[(&str, u64)]) -> MessageStore {
+        messages
+            .iter()
+            .map(|&(id, timestamp)| message(id, timestamp))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn history(messages: &[(&str, u64)]) -> Vec<ChatMessage> {
+        messages
+            .iter()
+            .map(|&(id, timestamp)| message(id, timestamp))
+            .collect()
+    }
+
+    fn ids(store: &MessageStore) -> Vec<&str> {
+        store.as_slice().iter().map(|m| m.id.as_str()).collect()
+    }
+
+    const CAP: usize = 100;
+
+    #[test]
+    fn merge_skips_the_overlap() {
+        let mut store = store(&[("a", 1), ("b", 2), ("c", 3)]);
+        let merged = store.merge(history(&[("b", 2), ("c", 3), ("d", 4)]), CAP, |_| false);
+
+        assert_eq!(ids(&store), ["a", "b", "c", "d"]);
+        assert_eq!(merged.added, 1);
+        assert!(!merged.above);
+    }
+
+    #[test]
+    fn merge_drops_ids_repeated_within_the_history() {
+        let mut store = store(&[("a", 1)]);
+        let merged = store.merge(history(&[("b", 2), ("b", 2), ("c", 3)]), CAP, |_| false);
+
+        assert_eq!(ids(&store), ["a", "b", "c"]);
+        assert_eq!(merged.added, 2);
+    }
+
+    #[test]
+    fn merge_sorts_history_and_weaves_it_in() {
+        let mut store = store(&[("b", 20), ("d", 40)]);
+        let merged = store.merge(history(&[("e", 50), ("a", 10), ("c", 30)]), CAP, |_| false);
+
+        assert_eq!(ids(&store), ["a", "b", "c", "d", "e"]);
+        assert_eq!(merged.added, 3);
+        assert!(merged.above);
+    }
+
+    #[test]
+    fn merge_confirms_our_pending_sends_in_place() {
+        let mut pending = message("mine", 25);
+        pending.status = SendStatus::Pending;
+        let mut unsent = message("later", 26);
+        unsent.status = SendStatus::Queued;
+        let mut store = MessageStore::from(vec![message("a", 10), pending, unsent]);
+
+        let merged = store.merge(history(&[("mine", 30), ("b", 40)]), CAP, |_| false);
+
+        assert_eq!(ids(&store), ["a", "mine", "later", "b"]);
+        assert_eq!(merged.confirmed, ["mine"]);
+        assert_eq!(merged.added, 1);
+        assert_eq!(store.as_slice()[1].status, SendStatus::Sent);
+        assert_eq!(store.as_slice()[2].status, SendStatus::Queued);
+    }
+
+    #[test]
+    fn merge_of_a_gap_wider_than_the_cap_keeps_the_newest() {
+        let mut store = store(&[("a", 1), ("b", 2)]);
+        let gap: Vec<(String, u64)> = (0..10).map(|i| (format!("g{}", i), 10 + i)).collect();
+        let gap: Vec<(&str, u64)> = gap.iter().map(|(id, t)| (id.as_str(), *t)).collect();
+
+        let merged = store.merge(history(&gap), 4, |id| id == "a");
+
+        assert_eq!(merged.added, 10);
+        assert_eq!(ids(&store), ["a", "g7", "g8", "g9"]);
+        assert_eq!(store.trimmed(), 8);
+    }
+
+    #[test]
+    fn merge_of_nothing_new_changes_nothing() {
+        let mut store = store(&[("a", 1), ("b", 2)]);
+        let merged = store.merge(history(&[("a", 1)]), CAP, |_| false);
+
+        assert_eq!(merged, Merged::default());
+        assert_eq!(ids(&store), ["a", "b"]);
+    }
+
+    #[test]
+    fn eviction_drops_the_oldest_but_spares_pins() {
+        let mut store = store(&[("a", 1), ("b", 2), ("c", 3), ("d", 4), ("e", 5)]);
+
+        assert_eq!(store.evict(3, |id| id == "b"), 2);
+        assert_eq!(ids(&store), ["b", "d", "e"]);
+        assert_eq!(store.trimmed(), 2);
+
+        assert_eq!(store.evict(3, |_| false), 0);
+    }
+
+    #[test]
+    fn eviction_stops_when_only_pins_are_left_to_drop() {
+        let mut store = store(&[("a", 1), ("b", 2), ("c", 3)]);
+
+        assert_eq!(store.evict(1, |_| true), 0);
+        assert_eq!(ids(&store), ["a", "b", "c"]);
+        assert_eq!(store.trimmed(), 0);
+    }
+
+    #[test]
+    fn replies_to_evicted_messages_keep_a_quote() {
+        let mut asked = message("q", 1);
+        asked.message = "what time is the meeting?".to_string();
+        let mut gone = message("gone", 2);
+        gone.from = "bob".to_string();
+        gone.tombstone("bob");
+        let mut answer = message("r1", 3);
+        answer.reply_to = Some("q".to_string());
+        let mut quoted = message("r2", 4);
+        quoted.reply_to = Some("q".to_string());
+        quoted.reply_excerpt = Some("as the server quoted it".to_string());
+        let mut late = message("r3", 5);
+        late.reply_to = Some("gone".to_string());
+        let mut store = MessageStore::from(vec![asked, gone, answer, quoted, late]);
+
+        store.evict(3, |_| false);
+
+        assert_eq!(ids(&store), ["r1", "r2", "r3"]);
+        let excerpts: Vec<_> = store
+            .as_slice()
+            .iter()
+            .map(|m| m.reply_excerpt.as_deref())
+            .collect();
+        assert_eq!(
+            excerpts,
+            [
+                Some("what time is the meeting?"),
+                Some("as the server quoted it"),
+                Some(DELETED_PLACEHOLDER),
+            ]
+        );
+    }
+
+    #[test]
+    fn replies_to_kept_messages_are_left_alone() {
+        let mut reply = message("r", 3);
+        reply.reply_to = Some("pinned".to_string());
+        let mut store = MessageStore::from(vec![message("pinned", 1), message("b", 2), reply]);
+
+        store.evict(2, |id| id == "pinned");
+
+        assert_eq!(ids(&store), ["pinned", "r"]);
+        assert_eq!(store.as_slice()[1].reply_excerpt, None);
+    }
+
+    #[test]
+    fn inserting_evicts_down_to_the_cap() {
+        let mut store = MessageStore::default();
+        for i in 0..5 {
+            assert!(store.insert(message(&format!("m{}", i), i), 3, |id| id == "m0"));
+        }
+        assert_eq!(ids(&store), ["m0", "m3", "m4"]);
+        assert_eq!(store.trimmed(), 2);
+    }
+
+    #[test]
+    fn persisted_history_respects_the_limit() {
+        let mut store = MessageStore::default();
+        for i in 0..10 {
+            store.insert(message(&format!("m{}", i), i), 6, |_| false);
+        }
+        let persisted: Vec<&str> = store.persisted(4).iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(persisted, ["m6", "m7", "m8", "m9"]);
+        assert_eq!(store.persisted(100).len(), 6);
+        assert!(store.persisted(0).is_empty());
+    }
+
+    #[test]
+    fn persisted_history_leaves_out_what_should_not_be_cached() {
+        let mut pending = message("pending", 2);
+        pending.status = SendStatus::Pending;
+        let mut notice = message("notice", 3);
+        notice.system = true;
+        let mut pasted = message("pasted", 4);
+        pasted.message = "data:image/png;base64,iVBORw0KGgo=".to_string();
+        let mut inline = message("inline", 5);
+        inline.file = Some(Box::new(FileData {
+            body: Some("aGk=".to_string()),
+            ..Default::default()
+        }));
+        let mut uploaded = message("uploaded", 6);
+        uploaded.file = Some(Box::new(FileData {
+            url: Some("https://files.example/a.pdf".to_string()),
+            ..Default::default()
+        }));
+        let store = MessageStore::from(vec![
+            message("sent", 1),
+            pending,
+            notice,
+            pasted,
+            inline,
+            uploaded,
+        ]);
+
+        let persisted: Vec<&str> = store.persisted(100).iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(persisted, ["sent", "uploaded"]);
+    }
+
+    fn chars(parts: &[String]) -> Vec<usize> {
+        parts.iter().map(|p| p.chars().count()).collect()
+    }
+
+    #[test]
+    fn short_messages_are_not_split() {
+        assert_eq!(split_message("hello", 10), ["hello"]);
+        assert_eq!(split_message("  padded  ", 6), ["padded"]);
+        assert_eq!(split_message("🎉🎉🎉🎉🎉", 5), ["🎉🎉🎉🎉🎉"]);
+        assert!(split_message("", 10).is_empty());
+        assert!(split_message(" \n\t ", 10).is_empty());
+    }
+
+    #[test]
+    fn long_messages_split_between_words() {
+        assert_eq!(
+            split_message("the quick brown fox jumps", 10),
+            ["the quick", "brown fox", "jumps"]
+        );
+        assert_eq!(split_message("aaaa bbbb", 4), ["aaaa", "bbbb"]);
+        assert_eq!(split_message("one\ntwo\nthree", 8), ["one\ntwo", "three"]);
+    }
+
+    #[test]
+    fn words_longer_than_the_limit_are_cut() {
+        assert_eq!(split_message("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(
+            split_message("hi abcdefghij", 4),
+            ["hi", "abcd", "efgh", "ij"]
+        );
+    }
+
+    #[test]
+    fn emoji_count_as_one_character_each() {
+        let party = "🎉".repeat(12);
+        let parts = split_message(&party, 5);
+        assert_eq!(chars(&parts), [5, 5, 2]);
+        assert_eq!(parts.concat(), party);
+
+        assert_eq!(
+            split_message("😀😀😀 😎😎😎 🚀🚀🚀", 7),
+            ["😀😀😀 😎😎😎", "🚀🚀🚀"]
+        );
+        assert_eq!(
+            split_message("héllo wörld ñandú", 11),
+            ["héllo wörld", "ñandú"]
+        );
+    }
+
+    #[test]
+    fn emoji_heavy_text_keeps_every_word_under_the_limit() {
+        let words = ["👋🏽", "hello", "🇮🇩", "👨‍👩‍👧", "❤️", "日本語", "🎉🎉🎉", "ok"];
+        let text = words.repeat(40).join(" ");
+        for max in [1, 3, 7, 20, 500] {
+            let parts = split_message(&text, max);
+            assert!(chars(&parts).iter().all(|&n| n > 0 && n <= max), "{}", max);
+            let rejoined: String = parts.concat().split_whitespace().collect();
+            let original: String = text.split_whitespace().collect();
+            assert_eq!(rejoined, original, "{}", max);
+        }
+    }
+
+    #[test]
+    fn words_that_fit_are_never_broken() {
+        let words = ["👋🏽", "hello", "🇮🇩", "👨‍👩‍👧", "❤️", "日本語", "🎉🎉🎉", "ok"];
+        let text = words.repeat(40).join(" ");
+        for part in split_message(&text, 20) {
+            for word in part.split_whitespace() {
+                assert!(words.contains(&word), "{:?}", word);
+            }
+        }
+    }
+
+    fn rows(messages: &[(&str, u64)]) -> Vec<Row> {
+        group_bursts(messages.iter().copied())
+    }
+
+    #[test]
+    fn a_few_messages_stay_single() {
+        let limit: Vec<(&str, u64)> = (0..BURST_LIMIT as u64).map(|i| ("alice", i)).collect();
+        assert_eq!(
+            rows(&limit),
+            (0..BURST_LIMIT).map(Row::Single).collect::<Vec<_>>()
+        );
+        assert!(rows(&[]).is_empty());
+    }
+
+    #[test]
+    fn a_flood_from_one_sender_is_collapsed() {
+        let mut flood: Vec<(&str, u64)> = vec![("bob", 0)];
+        flood.extend((0..=BURST_LIMIT as u64).map(|i| ("alice", 100 + i)));
+        flood.push(("bob", 200));
+
+        assert_eq!(
+            rows(&flood),
+            [
+                Row::Single(0),
+                Row::Burst {
+                    start: 1,
+                    len: BURST_LIMIT + 1
+                },
+                Row::Single(BURST_LIMIT + 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_burst_ends_with_its_window() {
+        // Twelve messages, half a second apart: the first five fall inside
+        // the window of the first one, which is too few for a burst.
+        let slow: Vec<(&str, u64)> = (0..12).map(|i| ("alice", i * 500)).collect();
+        assert!(rows(&slow).iter().all(|row| matches!(row, Row::Single(_))));
+
+        let mut fast: Vec<(&str, u64)> = (0..11).map(|i| ("alice", i)).collect();
+        fast.push(("alice", BURST_WINDOW_MS));
+        fast.push(("alice", BURST_WINDOW_MS + 1));
+        assert_eq!(
+            rows(&fast),
+            [Row::Burst { start: 0, len: 12 }, Row::Single(12)]
+        );
+    }
+
+    #[test]
+    fn interleaved_senders_break_bursts() {
+        let chat: Vec<(&str, u64)> = (0..30)
+            .map(|i| (if i % 2 == 0 { "alice" } else { "bob" }, i))
+            .collect();
+        assert_eq!(rows(&chat).len(), 30);
+    }
+
+    #[test]
+    fn rows_over_a_slice_can_be_shifted() {
+        assert_eq!(Row::Single(2).offset(10), Row::Single(12));
+        assert_eq!(
+            Row::Burst { start: 1, len: 3 }.offset(10),
+            Row::Burst { start: 11, len: 3 }
+        );
+    }
+
+    #[test]
+    fn authors_edit_their_messages_in_place() {
+        let mut store = store(&[("a", 1), ("b", 2)]);
+
+        assert!(store.apply_edit("a", "alice", "first fix".to_string()));
+        assert!(store.apply_edit("a", "alice", "second fix".to_string()));
+
+        let edited = store.get("a").unwrap();
+        assert_eq!(edited.message, "second fix");
+        assert_eq!(edited.original_text.as_deref(), Some("a"));
+        assert_eq!(ids(&store), ["a", "b"]);
+    }
+
+    #[test]
+    fn edits_that_change_nothing_are_refused() {
+        let mut store = store(&[("a", 1)]);
+
+        assert!(!store.apply_edit("missing", "alice", "x".to_string()));
+        assert!(!store.apply_edit("a", "mallory", "pwned".to_string()));
+        assert!(!store.apply_edit("a", "alice", "a".to_string()));
+        assert!(!store.apply_edit("", "alice", "x".to_string()));
+
+        let message = store.get("a").unwrap();
+        assert_eq!(message.message, "a");
+        assert_eq!(message.original_text, None);
+    }
+
+    #[test]
+    fn deleted_messages_cannot_be_edited() {
+        let mut store = store(&[("a", 1)]);
+        assert!(store.tombstone("a", "alice"));
+        assert!(!store.apply_edit("a", "alice", "back".to_string()));
+        assert_eq!(store.get("a").unwrap().display_text(), DELETED_PLACEHOLDER);
+    }
+
+    #[test]
+    fn inserting_skips_ids_already_held() {
+        let mut store = MessageStore::default();
+        assert!(store.insert(message("a", 1), CAP, |_| false));
+        let mut replay = message("a", 1);
+        replay.message = "replayed".to_string();
+        assert!(!store.insert(replay, CAP, |_| false));
+
+        assert_eq!(ids(&store), ["a"]);
+        assert_eq!(store.get("a").unwrap().message, "a");
+    }
+
+    #[test]
+    fn messages_without_ids_are_all_kept_but_never_found() {
+        let mut store = MessageStore::default();
+        assert!(store.insert(message("", 1), CAP, |_| false));
+        assert!(store.insert(message("", 2), CAP, |_| false));
+
+        assert_eq!(store.as_slice().len(), 2);
+        assert!(store.get("").is_none());
+        assert!(store.get_mut("").is_none());
+        assert!(!store.tombstone("", "alice"));
+    }
+
+    #[test]
+    fn get_mut_changes_the_held_message() {
+        let mut store = store(&[("a", 1), ("b", 2)]);
+        store.get_mut("b").unwrap().status = SendStatus::Failed;
+        assert_eq!(store.get("b").unwrap().status, SendStatus::Failed);
+        assert_eq!(store.get("a").unwrap().status, SendStatus::Sent);
+        assert!(store.get_mut("c").is_none());
+    }
+
+    #[test]
+    fn tombstones_clear_the_message_once() {
+        let mut reply = message("r", 2);
+        reply.reply_to = Some("a".to_string());
+        let mut store = MessageStore::from(vec![message("a", 1), reply]);
+        store.apply_edit("a", "alice", "edited".to_string());
+
+        assert!(!store.tombstone("a", "mallory"));
+        assert!(store.tombstone("a", "alice"));
+        assert!(!store.tombstone("a", "alice"));
+        assert!(!store.tombstone("missing", "alice"));
+
+        let gone = store.get("a").unwrap();
+        assert!(gone.deleted);
+        assert_eq!(gone.message, "");
+        assert_eq!(gone.original_text, None);
+        assert_eq!(gone.display_text(), DELETED_PLACEHOLDER);
+        // Still held, so replies can point at the placeholder.
+        assert_eq!(ids(&store), ["a", "r"]);
+    }
+
+    #[test]
+    fn client_ids_differ_per_send() {
+        let first = client_id("alice", 1_000, 1);
+        assert_ne!(first, client_id("alice", 1_000, 2));
+        assert_ne!(first, client_id("bob", 1_000, 1));
+        assert_ne!(first, client_id("alice", 1_001, 1));
+    }
+
+    #[test]
+    fn seen_ids_drop_replays_and_forget_the_oldest() {
+        let mut seen = SeenIds::default();
+        assert!(seen.insert("a"));
+        assert!(!seen.insert("a"));
+
+        for i in 0..SEEN_IDS_CAP {
+            seen.insert(&i.to_string());
+        }
+        // "a" was among the oldest when the record overflowed.
+        assert!(seen.insert("a"));
+        assert!(!seen.insert(&(SEEN_IDS_CAP - 1).to_string()));
+    }
+
+    fn day(day: u32) -> Day {
+        Day {
+            year: 2024,
+            month: 12,
+            day,
+        }
+    }
+
+    #[test]
+    fn days_start_where_the_date_changes() {
+        assert!(day_starts::<Day>(&[]).is_empty());
+        assert_eq!(day_starts(&[day(1)]), [0]);
+        assert_eq!(day_starts(&[day(1), day(1), day(1)]), [0]);
+        assert_eq!(
+            day_starts(&[day(1), day(1), day(2), day(2), day(5)]),
+            [0, 2, 4]
+        );
+    }
+
+    #[test]
+    fn a_message_after_midnight_starts_a_new_day() {
+        let mut days = vec![day(30), day(31), day(31)];
+        assert_eq!(day_starts(&days), [0, 1]);
+
+        // The session runs past midnight into the new year.
+        days.push(Day {
+            year: 2025,
+            month: 1,
+            day: 1,
+        });
+        assert_eq!(day_starts(&days), [0, 1, 3]);
+    }
+
+    #[test]
+    fn a_day_coming_round_again_gets_another_heading() {
+        // Out of order, as with a skewed clock: each run is headed.
+        assert_eq!(day_starts(&[day(1), day(2), day(1)]), [0, 1, 2]);
+    }
+}
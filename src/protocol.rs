@@ -0,0 +1,900 @@
+//! Frames exchanged with the chat server and the payloads they carry.
+//!
+//! Nothing here touches the browser, so the module also builds without the
+//! `web` feature, for bots and tools that talk to the same server:
+//!
+//! ```toml
+//! yewchat = { path = "...", default-features = false }
+//! ```
+//!
+//! Field names are camelCase on the wire, in frames and payloads alike, and
+//! enum values are lowercase. Optional fields are left out rather than sent
+//! as `null`. Earlier builds wrote some [`MessageData`] fields in snake_case;
+//! those spellings are still read.
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::sanitize;
+
+/// Major version of the wire format spoken by this client, sent with every
+/// frame, registration included. Frames from a server that reports a
+/// higher one are not interpreted, and the user is asked to update.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Kind of a frame, sent as its `messageType`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MsgTypes {
+    Users,
+    Register,
+    Message,
+    Typing,
+    Private,
+    Join,
+    Leave,
+    History,
+    Reaction,
+    Edit,
+    Delete,
+    Delivered,
+    Read,
+    Rename,
+    Unregister,
+    /// A file, carried as [`FileData`]. Private when it has a recipient.
+    File,
+    /// Someone went away or came back, as [`StatusData`].
+    Status,
+    Error,
+    /// Heartbeats; answered and swallowed by the websocket service.
+    Ping,
+    Pong,
+    /// A type this client doesn't know, from a newer server. Such frames
+    /// are counted and otherwise ignored.
+    Unknown(String),
+}
+
+impl MsgTypes {
+    /// The type as sent on the wire.
+    pub fn name(&self) -> &str {
+        match self {
+            MsgTypes::Users => "users",
+            MsgTypes::Register => "register",
+            MsgTypes::Message => "message",
+            MsgTypes::Typing => "typing",
+            MsgTypes::Private => "private",
+            MsgTypes::Join => "join",
+            MsgTypes::Leave => "leave",
+            MsgTypes::History => "history",
+            MsgTypes::Reaction => "reaction",
+            MsgTypes::Edit => "edit",
+            MsgTypes::Delete => "delete",
+            MsgTypes::Delivered => "delivered",
+            MsgTypes::Read => "read",
+            MsgTypes::Rename => "rename",
+            MsgTypes::Unregister => "unregister",
+            MsgTypes::File => "file",
+            MsgTypes::Status => "status",
+            MsgTypes::Error => "error",
+            MsgTypes::Ping => "ping",
+            MsgTypes::Pong => "pong",
+            MsgTypes::Unknown(name) => name,
+        }
+    }
+
+    /// The type called `name` on the wire, or [`MsgTypes::Unknown`].
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "users" => MsgTypes::Users,
+            "register" => MsgTypes::Register,
+            "message" => MsgTypes::Message,
+            "typing" => MsgTypes::Typing,
+            "private" => MsgTypes::Private,
+            "join" => MsgTypes::Join,
+            "leave" => MsgTypes::Leave,
+            "history" => MsgTypes::History,
+            "reaction" => MsgTypes::Reaction,
+            "edit" => MsgTypes::Edit,
+            "delete" => MsgTypes::Delete,
+            "delivered" => MsgTypes::Delivered,
+            "read" => MsgTypes::Read,
+            "rename" => MsgTypes::Rename,
+            "unregister" => MsgTypes::Unregister,
+            "file" => MsgTypes::File,
+            "status" => MsgTypes::Status,
+            "error" => MsgTypes::Error,
+            "ping" => MsgTypes::Ping,
+            "pong" => MsgTypes::Pong,
+            other => MsgTypes::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl Serialize for MsgTypes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for MsgTypes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(MsgTypes::from_name(&name))
+    }
+}
+
+/// A frame as it travels over the socket. What `data` and `data_array`
+/// hold depends on [`MsgTypes`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketMessage {
+    #[serde(default = "default_version", deserialize_with = "major_version")]
+    pub version: u8,
+    pub message_type: MsgTypes,
+    pub data_array: Option<Vec<String>>,
+    pub data: Option<String>,
+    /// Id we picked for an outgoing message; servers that support it echo it
+    /// back in the message data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<MessageId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_excerpt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Recipient of a [`MsgTypes::Private`] message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Room a message or join/leave request is about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    /// On a [`MsgTypes::History`] request: only messages sent before this
+    /// timestamp are wanted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<u64>,
+    /// On a [`MsgTypes::History`] request: only messages sent after this
+    /// timestamp are wanted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    /// On a [`MsgTypes::History`] request: the most messages to send back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// On a [`MsgTypes::Register`] frame: the avatar picked at login.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<AvatarChoice>,
+}
+
+impl WebSocketMessage {
+    /// A frame of `message_type` carrying `data`, with every optional field
+    /// left empty.
+    pub fn new(message_type: MsgTypes, data: Option<String>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            message_type,
+            data_array: None,
+            data,
+            id: None,
+            reply_to: None,
+            reply_excerpt: None,
+            timestamp: None,
+            to: None,
+            room: None,
+            before: None,
+            since: None,
+            limit: None,
+            avatar: None,
+        }
+    }
+
+    /// Registration as `name`, the first frame a client sends.
+    pub fn register(name: &str) -> Self {
+        Self::new(MsgTypes::Register, Some(name.to_string()))
+    }
+
+    /// `text` said in the main room.
+    pub fn chat(text: &str) -> Self {
+        Self::new(MsgTypes::Message, Some(text.to_string()))
+    }
+
+    /// `text` said to `to` alone.
+    pub fn private(to: &str, text: &str) -> Self {
+        Self {
+            to: Some(to.to_string()),
+            ..Self::new(MsgTypes::Private, Some(text.to_string()))
+        }
+    }
+
+    /// `from` telling the others whether they are at their keyboard.
+    pub fn status(from: &str, status: Presence) -> Result<Self, serde_json::Error> {
+        let data = StatusData {
+            from: from.to_string(),
+            status,
+        };
+        Ok(Self::new(
+            MsgTypes::Status,
+            Some(serde_json::to_string(&data)?),
+        ))
+    }
+}
+
+/// Reads a JSON text frame.
+impl TryFrom<&str> for WebSocketMessage {
+    type Error = CodecError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        JsonCodec.decode(Frame::Text(text.to_string()))
+    }
+}
+
+/// Writes the frame as JSON text, the way [`JsonCodec`] sends it.
+impl fmt::Display for WebSocketMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// Frames from servers that predate versioning are treated as version 1.
+fn default_version() -> u8 {
+    1
+}
+
+/// Reads the major part of a frame's version. Servers may add a minor one,
+/// as `1.2` or `"1.2"`; minor versions only add to the protocol, so they
+/// don't matter to whether we understand the frame.
+fn major_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Version {
+        Number(u64),
+        Decimal(f64),
+        Text(String),
+    }
+
+    let major = match Version::deserialize(deserializer)? {
+        Version::Number(major) => major,
+        Version::Decimal(version) if version >= 0.0 => version.trunc() as u64,
+        Version::Text(version) => version
+            .trim()
+            .split('.')
+            .next()
+            .and_then(|major| major.parse().ok())
+            .ok_or_else(|| serde::de::Error::custom(format!("bad version {:?}", version)))?,
+        Version::Decimal(version) => {
+            return Err(serde::de::Error::custom(format!("bad version {}", version)))
+        }
+    };
+    // Anything past what fits is certainly newer than us.
+    Ok(major.min(u8::MAX as u64) as u8)
+}
+
+/// A frame as it goes over the socket, before decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[cfg(feature = "frame-log")]
+impl Frame {
+    /// Size on the wire, in bytes.
+    pub fn byte_len(&self) -> usize {
+        match self {
+            Frame::Text(text) => text.len(),
+            Frame::Binary(bytes) => bytes.len(),
+        }
+    }
+}
+
+/// Why a frame could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// The frame wasn't in the codec's format, or didn't describe a
+    /// [`WebSocketMessage`]; the text says what was wrong.
+    Malformed(String),
+    /// A binary codec was handed a text frame.
+    UnexpectedText,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Malformed(reason) => write!(f, "{}", reason),
+            CodecError::UnexpectedText => write!(f, "expected a binary frame, got text"),
+        }
+    }
+}
+
+/// Turns [`WebSocketMessage`]s into frames and back. Payloads inside
+/// `data` and `data_array` stay JSON whatever the codec; only the envelope
+/// changes.
+pub trait Codec {
+    fn encode(&self, msg: &WebSocketMessage) -> Frame;
+
+    fn decode(&self, frame: Frame) -> Result<WebSocketMessage, CodecError>;
+}
+
+/// Frames as JSON text, what servers speak unless told otherwise. Binary
+/// frames holding UTF-8 JSON are read too.
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode(&self, msg: &WebSocketMessage) -> Frame {
+        Frame::Text(serde_json::to_string(msg).unwrap())
+    }
+
+    fn decode(&self, frame: Frame) -> Result<WebSocketMessage, CodecError> {
+        let parsed = match &frame {
+            Frame::Text(text) => serde_json::from_str(text),
+            Frame::Binary(bytes) => serde_json::from_slice(bytes),
+        };
+        parsed.map_err(|e| CodecError::Malformed(e.to_string()))
+    }
+}
+
+/// Frames as binary MessagePack maps, keyed by the same field names as
+/// the JSON.
+pub struct MessagePackCodec;
+
+impl Codec for MessagePackCodec {
+    fn encode(&self, msg: &WebSocketMessage) -> Frame {
+        // Named, not positional: fields left out when empty would otherwise
+        // shift everything after them.
+        Frame::Binary(rmp_serde::to_vec_named(msg).unwrap())
+    }
+
+    fn decode(&self, frame: Frame) -> Result<WebSocketMessage, CodecError> {
+        match frame {
+            Frame::Binary(bytes) => {
+                rmp_serde::from_slice(&bytes).map_err(|e| CodecError::Malformed(e.to_string()))
+            }
+            Frame::Text(_) => Err(CodecError::UnexpectedText),
+        }
+    }
+}
+
+/// The framings we speak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Json,
+    MessagePack,
+}
+
+impl WireFormat {
+    /// The format named `name` (`json`, `msgpack`), if it is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(WireFormat::Json),
+            "msgpack" | "messagepack" => Some(WireFormat::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// The format `frame` is in, judged from the frame alone: text and
+    /// binary frames that look like a JSON object are JSON, any other
+    /// binary frame MessagePack.
+    pub fn detect(frame: &Frame) -> Self {
+        match frame {
+            Frame::Text(_) => WireFormat::Json,
+            Frame::Binary(bytes) if bytes.first() == Some(&b'{') => WireFormat::Json,
+            Frame::Binary(_) => WireFormat::MessagePack,
+        }
+    }
+
+    pub fn codec(self) -> &'static dyn Codec {
+        match self {
+            WireFormat::Json => &JsonCodec,
+            WireFormat::MessagePack => &MessagePackCodec,
+        }
+    }
+}
+
+/// A file sent as a message: its contents inline, base64-encoded, if small
+/// enough, or else where it was uploaded to. Payloads that can't be read
+/// come through with neither.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileData {
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Payload of a [`MsgTypes::Typing`] frame.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TypingData {
+    pub from: String,
+    pub typing: bool,
+}
+
+/// Whether someone is at their keyboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    /// Also what users we haven't heard a status from are taken to be.
+    #[default]
+    Online,
+    Away,
+}
+
+/// Payload of a [`MsgTypes::Status`] frame.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatusData {
+    pub from: String,
+    pub status: Presence,
+}
+
+/// Payload of a [`MsgTypes::Reaction`] frame.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionData {
+    pub message_id: MessageId,
+    pub emoji: String,
+    pub from: String,
+    /// Set when the reaction is taken back.
+    #[serde(default)]
+    pub retract: bool,
+}
+
+/// Payload of a [`MsgTypes::Edit`] frame.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditData {
+    pub message_id: MessageId,
+    pub from: String,
+    pub message: String,
+}
+
+/// Payload of a [`MsgTypes::Delete`] frame.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteData {
+    pub message_id: MessageId,
+    pub from: String,
+}
+
+/// Payload of [`MsgTypes::Delivered`] and [`MsgTypes::Read`] frames: `from`
+/// has received (or seen) the listed messages.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptData {
+    pub message_ids: Vec<MessageId>,
+    pub from: String,
+}
+
+/// Dicebear styles offered at login. The first is what users who never
+/// picked one get.
+pub const AVATAR_STYLES: &[&str] = &[
+    "adventurer-neutral",
+    "avataaars",
+    "big-smile",
+    "bottts",
+    "identicon",
+    "micah",
+    "pixel-art",
+    "thumbs",
+];
+
+/// An avatar: the Dicebear style and the seed it is generated from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AvatarChoice {
+    pub style: String,
+    pub seed: String,
+}
+
+impl AvatarChoice {
+    /// The avatar of someone who didn't pick one: the default style, seeded
+    /// with their name.
+    pub fn default_for(name: &str) -> Self {
+        Self {
+            style: AVATAR_STYLES[0].to_string(),
+            seed: name.to_string(),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        sanitize::avatar_url_for(&self.style, &self.seed)
+    }
+}
+
+/// One entry of a [`MsgTypes::Users`] list. Servers send either a bare name
+/// or, if they pass avatars along, a JSON object with both.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UserEntry {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<AvatarChoice>,
+}
+
+impl UserEntry {
+    pub fn parse(entry: &str) -> Self {
+        if entry.starts_with('{') {
+            if let Ok(parsed) = serde_json::from_str(entry) {
+                return parsed;
+            }
+        }
+        Self {
+            name: entry.to_string(),
+            avatar: None,
+        }
+    }
+}
+
+pub type MessageId = String;
+
+/// A chat message, as carried in the `data` of a message frame and in the
+/// `dataArray` of a history frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageData {
+    #[serde(default)]
+    pub id: String,
+    pub from: String,
+    pub message: String,
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default, alias = "reply_to", skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+    /// Short excerpt of the quoted message, for when we don't have it.
+    #[serde(
+        default,
+        alias = "reply_excerpt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub reply_excerpt: Option<String>,
+    /// Text before the first edit; `None` if the message was never edited.
+    #[serde(
+        default,
+        alias = "original_text",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub original_text: Option<String>,
+    /// What a [`MsgTypes::File`] message carries; its name is the text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<Box<FileData>>,
+    /// Removed by its author; the text has been cleared.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn to_json<T: Serialize>(value: &T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn message_fields_are_camel_case() {
+        let message = MessageData {
+            id: "a1".to_string(),
+            from: "alice".to_string(),
+            message: "hi".to_string(),
+            timestamp: 7,
+            reply_to: Some("b1".to_string()),
+            reply_excerpt: Some("hello".to_string()),
+            original_text: Some("ho".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            to_json(&message),
+            json!({
+                "id": "a1",
+                "from": "alice",
+                "message": "hi",
+                "timestamp": 7,
+                "replyTo": "b1",
+                "replyExcerpt": "hello",
+                "originalText": "ho",
+            })
+        );
+    }
+
+    #[test]
+    fn absent_message_fields_are_left_out() {
+        let message = MessageData {
+            from: "alice".to_string(),
+            message: "hi".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            to_json(&message),
+            json!({ "id": "", "from": "alice", "message": "hi", "timestamp": 0 })
+        );
+    }
+
+    #[test]
+    fn messages_round_trip_with_and_without_timestamps() {
+        let stamped = MessageData {
+            id: "a1".to_string(),
+            from: "alice".to_string(),
+            message: "hi".to_string(),
+            timestamp: 1_700_000_000_123,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&stamped).unwrap();
+        assert!(json.contains(r#""timestamp":1700000000123"#), "{}", json);
+        assert_eq!(serde_json::from_str::<MessageData>(&json).unwrap(), stamped);
+
+        // Servers from before timestamps; the receive time is filled in
+        // later, by whoever takes the message in.
+        let unstamped: MessageData =
+            serde_json::from_str(r#"{"from":"alice","message":"hi"}"#).unwrap();
+        assert_eq!(unstamped.timestamp, 0);
+        let json = serde_json::to_string(&unstamped).unwrap();
+        assert_eq!(
+            serde_json::from_str::<MessageData>(&json).unwrap(),
+            unstamped
+        );
+    }
+
+    /// Reads `json` as a `T` and writes it back, expecting the same JSON.
+    fn assert_round_trips<T: Serialize + for<'de> Deserialize<'de>>(json: Value) {
+        let value: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(to_json(&value), json);
+    }
+
+    #[test]
+    fn payloads_round_trip() {
+        assert_round_trips::<TypingData>(json!({ "from": "alice", "typing": true }));
+        assert_round_trips::<StatusData>(json!({ "from": "alice", "status": "away" }));
+        assert_round_trips::<ReactionData>(json!({
+            "messageId": "a1", "emoji": "👍", "from": "bob", "retract": true,
+        }));
+        assert_round_trips::<EditData>(json!({
+            "messageId": "a1", "from": "alice", "message": "fixed",
+        }));
+        assert_round_trips::<DeleteData>(json!({ "messageId": "a1", "from": "alice" }));
+        assert_round_trips::<ReceiptData>(json!({ "messageIds": ["a1", "a2"], "from": "bob" }));
+        assert_round_trips::<FileData>(json!({
+            "name": "notes.txt", "mime": "text/plain", "size": 2, "body": "aGk=",
+        }));
+        assert_round_trips::<FileData>(json!({
+            "name": "big.zip", "mime": "application/zip", "size": 1 << 30,
+            "url": "https://files.example/big.zip",
+        }));
+        assert_round_trips::<UserEntry>(json!({
+            "name": "alice", "avatar": { "style": "bottts", "seed": "a" },
+        }));
+        assert_round_trips::<MessageData>(json!({
+            "id": "a2", "from": "bob", "message": "sure", "timestamp": 5,
+            "replyTo": "a1", "replyExcerpt": "lunch?", "originalText": "sur",
+            "deleted": true,
+        }));
+    }
+
+    #[test]
+    fn payload_defaults_are_filled_in() {
+        let reaction: ReactionData =
+            serde_json::from_value(json!({ "messageId": "a1", "emoji": "👍", "from": "bob" }))
+                .unwrap();
+        assert!(!reaction.retract);
+        assert!(
+            serde_json::from_value::<StatusData>(json!({ "from": "a", "status": "busy" })).is_err()
+        );
+        assert!(serde_json::from_value::<EditData>(json!({ "messageId": "a1" })).is_err());
+    }
+
+    #[test]
+    fn user_entries_are_names_or_objects() {
+        assert_eq!(
+            UserEntry::parse("alice"),
+            UserEntry {
+                name: "alice".to_string(),
+                avatar: None
+            }
+        );
+        assert_eq!(
+            UserEntry::parse(r#"{"name":"bob","avatar":{"style":"micah","seed":"b"}}"#),
+            UserEntry {
+                name: "bob".to_string(),
+                avatar: Some(AvatarChoice {
+                    style: "micah".to_string(),
+                    seed: "b".to_string()
+                })
+            }
+        );
+        // Not an entry after all: taken as an odd name rather than lost.
+        assert_eq!(UserEntry::parse("{braces}").name, "{braces}");
+        assert_eq!(UserEntry::parse(r#"{"nom":"x"}"#).name, r#"{"nom":"x"}"#);
+    }
+
+    #[test]
+    fn snake_case_message_fields_are_still_read() {
+        let message: MessageData = serde_json::from_value(json!({
+            "from": "alice",
+            "message": "hi",
+            "reply_to": "b1",
+            "reply_excerpt": "hello",
+            "original_text": "ho",
+        }))
+        .unwrap();
+        assert_eq!(message.reply_to.as_deref(), Some("b1"));
+        assert_eq!(message.reply_excerpt.as_deref(), Some("hello"));
+        assert_eq!(message.original_text.as_deref(), Some("ho"));
+    }
+
+    #[test]
+    fn payload_fields_are_camel_case() {
+        let receipt = ReceiptData {
+            message_ids: vec!["a1".to_string()],
+            from: "bob".to_string(),
+        };
+        assert_eq!(
+            to_json(&receipt),
+            json!({ "messageIds": ["a1"], "from": "bob" })
+        );
+        let reaction = ReactionData {
+            message_id: "a1".to_string(),
+            emoji: "👍".to_string(),
+            from: "bob".to_string(),
+            retract: false,
+        };
+        assert_eq!(to_json(&reaction)["messageId"], "a1");
+        let frame = WebSocketMessage::new(MsgTypes::Users, None);
+        let frame = to_json(&frame);
+        assert_eq!(frame["messageType"], "users");
+        assert!(frame.get("dataArray").is_some());
+    }
+
+    #[test]
+    fn tags_are_lowercase() {
+        for (presence, tag) in [(Presence::Online, "online"), (Presence::Away, "away")] {
+            assert_eq!(to_json(&presence), tag);
+            assert_eq!(
+                serde_json::from_value::<Presence>(json!(tag)).unwrap(),
+                presence
+            );
+        }
+        for message_type in [MsgTypes::Delivered, MsgTypes::Unregister, MsgTypes::Status] {
+            let name = to_json(&message_type);
+            assert_eq!(name, message_type.name().to_lowercase());
+            assert_eq!(
+                serde_json::from_value::<MsgTypes>(name).unwrap(),
+                message_type
+            );
+        }
+    }
+
+    const ALL_TYPES: &[MsgTypes] = &[
+        MsgTypes::Users,
+        MsgTypes::Register,
+        MsgTypes::Message,
+        MsgTypes::Typing,
+        MsgTypes::Private,
+        MsgTypes::Join,
+        MsgTypes::Leave,
+        MsgTypes::History,
+        MsgTypes::Reaction,
+        MsgTypes::Edit,
+        MsgTypes::Delete,
+        MsgTypes::Delivered,
+        MsgTypes::Read,
+        MsgTypes::Rename,
+        MsgTypes::Unregister,
+        MsgTypes::File,
+        MsgTypes::Status,
+        MsgTypes::Error,
+        MsgTypes::Ping,
+        MsgTypes::Pong,
+    ];
+
+    /// A frame of `message_type` with every optional field set.
+    fn full_frame(message_type: MsgTypes) -> WebSocketMessage {
+        WebSocketMessage {
+            data_array: Some(vec!["alice".to_string(), "{\"x\":1}".to_string()]),
+            id: Some("me-1-1".to_string()),
+            reply_to: Some("bob-1-1".to_string()),
+            reply_excerpt: Some("quoted ✓".to_string()),
+            timestamp: Some(1_700_000_000_000),
+            to: Some("bob".to_string()),
+            room: Some("lobby".to_string()),
+            before: Some(5),
+            since: Some(3),
+            limit: Some(50),
+            avatar: Some(AvatarChoice::default_for("me")),
+            ..WebSocketMessage::new(message_type, Some("héllo 👋".to_string()))
+        }
+    }
+
+    fn round_trip(codec: &dyn Codec, frame: &WebSocketMessage) -> WebSocketMessage {
+        codec.decode(codec.encode(frame)).unwrap()
+    }
+
+    #[test]
+    fn every_frame_type_round_trips() {
+        for codec in [&JsonCodec as &dyn Codec, &MessagePackCodec] {
+            for message_type in ALL_TYPES {
+                let full = full_frame(message_type.clone());
+                assert_eq!(round_trip(codec, &full), full);
+                let bare = WebSocketMessage::new(message_type.clone(), None);
+                assert_eq!(round_trip(codec, &bare), bare);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_types_round_trip_by_name() {
+        for codec in [&JsonCodec as &dyn Codec, &MessagePackCodec] {
+            let frame = WebSocketMessage::new(MsgTypes::Unknown("poll".to_string()), None);
+            assert_eq!(round_trip(codec, &frame), frame);
+        }
+    }
+
+    #[test]
+    fn absent_optional_fields_are_not_written() {
+        let frame = WebSocketMessage::chat("hi");
+        assert_eq!(
+            frame.to_string(),
+            r#"{"version":1,"messageType":"message","dataArray":null,"data":"hi"}"#
+        );
+        let decoded =
+            WebSocketMessage::try_from(r#"{"messageType":"message","data":"hi"}"#).unwrap();
+        assert_eq!(decoded, frame);
+    }
+
+    #[test]
+    fn message_pack_frames_use_field_names() {
+        let frame = WebSocketMessage {
+            room: Some("lobby".to_string()),
+            ..WebSocketMessage::new(MsgTypes::Join, None)
+        };
+        let bytes = match MessagePackCodec.encode(&frame) {
+            Frame::Binary(bytes) => bytes,
+            Frame::Text(_) => panic!("expected a binary frame"),
+        };
+        let keys = String::from_utf8_lossy(&bytes);
+        assert!(keys.contains("messageType"));
+        assert!(keys.contains("room"));
+        assert!(!keys.contains("since"));
+    }
+
+    #[test]
+    fn codecs_refuse_what_they_cannot_read() {
+        assert!(matches!(
+            JsonCodec.decode(Frame::Text("{not json".to_string())),
+            Err(CodecError::Malformed(_))
+        ));
+        assert_eq!(
+            MessagePackCodec.decode(Frame::Text("{}".to_string())),
+            Err(CodecError::UnexpectedText)
+        );
+        assert!(matches!(
+            MessagePackCodec.decode(Frame::Binary(vec![0xc1])),
+            Err(CodecError::Malformed(_))
+        ));
+        // JSON sent in a binary frame is still read.
+        let json = WebSocketMessage::chat("hi").to_string().into_bytes();
+        assert_eq!(
+            JsonCodec.decode(Frame::Binary(json)).unwrap(),
+            WebSocketMessage::chat("hi")
+        );
+    }
+
+    #[test]
+    fn wire_format_follows_the_first_frame() {
+        assert_eq!(
+            WireFormat::detect(&Frame::Text("{}".to_string())),
+            WireFormat::Json
+        );
+        assert_eq!(
+            WireFormat::detect(&Frame::Binary(b"{}".to_vec())),
+            WireFormat::Json
+        );
+        assert_eq!(
+            WireFormat::detect(&Frame::Binary(vec![0x82])),
+            WireFormat::MessagePack
+        );
+        assert_eq!(
+            WireFormat::from_name(" MsgPack "),
+            Some(WireFormat::MessagePack)
+        );
+        assert_eq!(WireFormat::from_name("xml"), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! What a client has to tell the server every time the socket opens for
+//! the server to pick up where it left off: who we are, which rooms we are
+//! in, whether we are away, and which messages we missed.
+
+use crate::messages::{ChatMessage, SendStatus};
+use crate::protocol::{MsgTypes, Presence, WebSocketMessage};
+use crate::state::{ChatState, ConversationId};
+
+/// Timestamp of the newest message in `messages` the server is known to
+/// have sent us, history asked for after a reconnect starts past it. Our
+/// own unconfirmed sends and local notices don't count.
+pub fn since(messages: &[ChatMessage]) -> Option<u64> {
+    messages
+        .iter()
+        .filter(|m| !m.system && m.status == SendStatus::Sent)
+        .map(|m| m.timestamp)
+        .max()
+}
+
+/// The frames that restore our session, in the order they must go out:
+/// registration, room joins, our status, then a history request per room
+/// for what arrived while we were gone. They are control frames: the
+/// websocket service sends them first on every open, ahead of anything
+/// queued and outside the chat's send throttle.
+#[derive(Debug, Clone)]
+pub struct ResyncPlan {
+    frames: Vec<WebSocketMessage>,
+}
+
+impl ResyncPlan {
+    /// The plan for `state` as it is now, `presence` being ours.
+    pub fn new(state: &ChatState, presence: Presence) -> Self {
+        let register = WebSocketMessage {
+            avatar: state.own_avatar.clone(),
+            ..WebSocketMessage::register(&state.username)
+        };
+        let joins = state.rooms.iter().map(|room| WebSocketMessage {
+            room: Some(room.clone()),
+            ..WebSocketMessage::new(MsgTypes::Join, None)
+        });
+        // Without it we would show as online until our status next changes.
+        let status = WebSocketMessage::status(&state.username, presence)
+            .map_err(|e| log::error!("could not write our status: {}", e))
+            .ok();
+        // Direct conversations have no server history to catch up on.
+        let histories = state.rooms.iter().map(|room| {
+            let since = state
+                .conversations
+                .get(&ConversationId::Room(room.clone()))
+                .and_then(|c| since(c.messages.as_slice()));
+            WebSocketMessage {
+                room: Some(room.clone()),
+                since,
+                ..WebSocketMessage::new(MsgTypes::History, None)
+            }
+        });
+        Self {
+            frames: std::iter::once(register)
+                .chain(joins)
+                .chain(status)
+                .chain(histories)
+                .collect(),
+        }
+    }
+
+    pub fn into_frames(self) -> Vec<WebSocketMessage> {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ChatMessage, MessageData};
+
+    fn now() -> u64 {
+        1_000
+    }
+
+    fn message(from: &str, timestamp: u64, status: SendStatus) -> ChatMessage {
+        ChatMessage {
+            data: MessageData {
+                from: from.to_string(),
+                timestamp,
+                ..Default::default()
+            },
+            status,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn since_skips_unconfirmed_sends_and_notices() {
+        let mut notice = message("", 40, SendStatus::Sent);
+        notice.system = true;
+        let messages = [
+            message("alice", 10, SendStatus::Sent),
+            message("me", 20, SendStatus::Sent),
+            message("me", 30, SendStatus::Pending),
+            notice,
+        ];
+        assert_eq!(since(&messages), Some(20));
+        assert_eq!(since(&[]), None);
+    }
+
+    #[test]
+    fn plan_registers_joins_announces_then_catches_up() {
+        let mut state = ChatState::new("me".to_string(), now);
+        state.rooms.push("rust".to_string());
+        state.push_message(
+            &ConversationId::Room("rust".to_string()),
+            message("alice", 500, SendStatus::Sent),
+        );
+
+        let frames = ResyncPlan::new(&state, Presence::Away).into_frames();
+        let types: Vec<&MsgTypes> = frames.iter().map(|f| &f.message_type).collect();
+        assert_eq!(
+            types,
+            [
+                &MsgTypes::Register,
+                &MsgTypes::Join,
+                &MsgTypes::Join,
+                &MsgTypes::Status,
+                &MsgTypes::History,
+                &MsgTypes::History,
+            ]
+        );
+        assert_eq!(frames[0].data.as_deref(), Some("me"));
+        assert_eq!(
+            frames[3].data.as_deref(),
+            Some(r#"{"from":"me","status":"away"}"#)
+        );
+        assert_eq!(frames[4].room.as_deref(), Some("lobby"));
+        assert_eq!(frames[4].since, None);
+        assert_eq!(frames[5].room.as_deref(), Some("rust"));
+        assert_eq!(frames[5].since, Some(500));
+    }
+}
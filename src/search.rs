@@ -0,0 +1,265 @@
+//! Case-insensitive text search, for the user list filter and searching the
+//! transcript.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::filter::WordFilter;
+use crate::messages::ChatMessage;
+
+/// Byte range of the first occurrence of `needle` in `haystack`, comparing
+/// lowercased characters.
+pub fn find_ignore_case(haystack: &str, needle: &str) -> Option<Range<usize>> {
+    let needle: Vec<char> = needle.chars().flat_map(char::to_lowercase).collect();
+    if needle.is_empty() {
+        return Some(0..0);
+    }
+    haystack.char_indices().find_map(|(start, _)| {
+        let mut folded = Vec::new();
+        for (i, c) in haystack[start..].char_indices() {
+            folded.extend(c.to_lowercase());
+            if folded.len() >= needle.len() {
+                return (folded == needle).then(|| start..start + i + c.len_utf8());
+            }
+        }
+        None
+    })
+}
+
+/// Byte ranges of every non-overlapping occurrence of `query` in `text`,
+/// ignoring case. An empty query matches nothing.
+pub fn match_ranges(text: &str, query: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    if query.is_empty() {
+        return ranges;
+    }
+    let mut from = 0;
+    while let Some(found) = find_ignore_case(&text[from..], query) {
+        let range = from + found.start..from + found.end;
+        from = range.end;
+        ranges.push(range);
+    }
+    ranges
+}
+
+/// Indices of the `messages` whose text contains `query`, oldest first.
+/// Text is searched as shown, so through `filter` when it is on: masked
+/// words are not found, and can't be highlighted. Deleted messages have no
+/// text left to find.
+pub fn matching_messages(
+    messages: &[ChatMessage],
+    query: &str,
+    filter: Option<&WordFilter>,
+) -> Vec<usize> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| {
+            let shown = match filter {
+                Some(filter) => filter.censor(&m.message),
+                None => Cow::Borrowed(m.message.as_str()),
+            };
+            !m.deleted && find_ignore_case(&shown, query).is_some()
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Splits `piece`, which starts `offset` bytes into a text searched for
+/// `ranges`, into runs that are or aren't part of a match, in order.
+pub fn split_matches<'a>(
+    piece: &'a str,
+    offset: usize,
+    ranges: &[Range<usize>],
+) -> Vec<(&'a str, bool)> {
+    let end = offset + piece.len();
+    let mut runs = Vec::new();
+    let mut at = offset;
+    for range in ranges {
+        let start = range.start.clamp(at, end);
+        let stop = range.end.clamp(start, end);
+        if start == stop {
+            continue;
+        }
+        if start > at {
+            runs.push((&piece[at - offset..start - offset], false));
+        }
+        runs.push((&piece[start - offset..stop - offset], true));
+        at = stop;
+    }
+    if at < end {
+        runs.push((&piece[at - offset..], false));
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MessageData;
+
+    fn messages(texts: &[&str]) -> Vec<ChatMessage> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                ChatMessage::from(MessageData {
+                    id: i.to_string(),
+                    from: "alice".into(),
+                    message: text.to_string(),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finding_ignores_case() {
+        let cases = [
+            ("Hello World", "world", Some(6..11)),
+            ("Hello World", "HELLO", Some(0..5)),
+            ("Hello World", "o w", Some(4..7)),
+            ("Hello World", "worlds", None),
+            ("Hello", "", Some(0..0)),
+            ("", "a", None),
+            ("", "", Some(0..0)),
+            ("abcabc", "cab", Some(2..5)),
+            ("l'ÉCOLE", "école", Some(2..8)),
+            ("Привет мир", "ПРИВЕТ", Some(0..12)),
+            ("Привет мир", "МИР", Some(13..19)),
+            ("ΟΔΟΣ", "οδο", Some(0..6)),
+            ("Straße", "STRASSE", None),
+            ("İstanbul", "i\u{307}stanbul", Some(0..9)),
+            ("İstanbul", "stan", Some(2..6)),
+            ("日本語のテキスト", "テキスト", Some(12..24)),
+            ("👋 hi 👋", "HI", Some(5..7)),
+        ];
+        for (haystack, needle, found) in cases {
+            assert_eq!(
+                find_ignore_case(haystack, needle),
+                found,
+                "{:?} in {:?}",
+                needle,
+                haystack
+            );
+        }
+    }
+
+    #[test]
+    fn found_ranges_fall_on_char_boundaries() {
+        let haystack = "ÀÉÎÕÜ àéîõü";
+        for needle in ["é", "ÎÕ", "ü", " à"] {
+            let range = find_ignore_case(haystack, needle).unwrap();
+            assert!(haystack.is_char_boundary(range.start));
+            assert!(haystack.is_char_boundary(range.end));
+        }
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn every_match_is_ranged() {
+        assert_eq!(match_ranges("Ab ab AB", "ab"), [0..2, 3..5, 6..8]);
+        assert_eq!(match_ranges("aaaa", "aa"), [0..2, 2..4]);
+        assert_eq!(match_ranges("aaa", "aa"), [0..2]);
+        assert_eq!(match_ranges("éé", "É"), [0..2, 2..4]);
+        assert!(match_ranges("anything", "").is_empty());
+        assert!(match_ranges("", "x").is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn pieces_split_at_matches() {
+        type Runs = &'static [(&'static str, bool)];
+        let cases: &[(&str, usize, &[Range<usize>], Runs)] = &[
+            ("hello", 0, &[], &[("hello", false)]),
+            ("hello", 0, &[0..5], &[("hello", true)]),
+            (
+                "hello",
+                0,
+                &[1..3],
+                &[("h", false), ("el", true), ("lo", false)],
+            ),
+            (
+                "hello",
+                0,
+                &[0..1, 4..5],
+                &[("h", true), ("ell", false), ("o", true)],
+            ),
+            // `world` is bytes 6..11 of `hello world`.
+            (
+                "world",
+                6,
+                &[0..3, 7..9, 10..20],
+                &[("w", false), ("or", true), ("l", false), ("d", true)],
+            ),
+            ("world", 6, &[0..6], &[("world", false)]),
+            ("world", 6, &[11..13], &[("world", false)]),
+            ("world", 6, &[4..8], &[("wo", true), ("rld", false)]),
+            (
+                "héllo",
+                0,
+                &[1..3],
+                &[("h", false), ("é", true), ("llo", false)],
+            ),
+            ("", 3, &[0..10], &[]),
+        ];
+        for (piece, offset, ranges, runs) in cases {
+            assert_eq!(
+                split_matches(piece, *offset, ranges),
+                *runs,
+                "{:?} at {}",
+                piece,
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn pieces_rejoin_into_the_text() {
+        let text = "Search the WORD, then the word.";
+        let ranges = match_ranges(text, "word");
+        let (head, tail) = text.split_at(14);
+        let runs: Vec<_> = split_matches(head, 0, &ranges)
+            .into_iter()
+            .chain(split_matches(tail, 14, &ranges))
+            .collect();
+        let joined: String = runs.iter().map(|(run, _)| *run).collect();
+        assert_eq!(joined, text);
+        let matched: Vec<_> = runs
+            .iter()
+            .filter(|(_, hit)| *hit)
+            .map(|(r, _)| *r)
+            .collect();
+        assert_eq!(matched, ["WOR", "D", "word"]);
+    }
+
+    #[test]
+    fn matching_messages_in_order() {
+        let messages = messages(&["Hello", "nothing", "oh HELLO again", "hell"]);
+        assert_eq!(matching_messages(&messages, "hello", None), [0, 2]);
+        assert_eq!(matching_messages(&messages, "hell", None), [0, 2, 3]);
+        assert!(matching_messages(&messages, "", None).is_empty());
+        assert!(matching_messages(&messages, "bye", None).is_empty());
+    }
+
+    #[test]
+    fn deleted_messages_are_not_found() {
+        let mut messages = messages(&["find me", "find me too"]);
+        messages[0].deleted = true;
+        assert_eq!(matching_messages(&messages, "find", None), [1]);
+    }
+
+    #[test]
+    fn masked_words_are_not_found() {
+        let messages = messages(&["oh shit", "shitake mushrooms", "sh"]);
+        let filter = WordFilter::default();
+        assert_eq!(matching_messages(&messages, "shit", None), [0, 1]);
+        assert_eq!(matching_messages(&messages, "shit", Some(&filter)), [1]);
+        assert_eq!(matching_messages(&messages, "sh", Some(&filter)), [1, 2]);
+        assert_eq!(matching_messages(&messages, "oh **", Some(&filter)), [0]);
+        assert!(matching_messages(&messages, "oh **", None).is_empty());
+    }
+}
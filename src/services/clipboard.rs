@@ -0,0 +1,69 @@
+//! Copying text to the clipboard.
+
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{HtmlDocument, HtmlTextAreaElement};
+
+/// Puts `text` on the clipboard and calls `done` with whether that worked.
+/// Uses the async clipboard API, or a hidden textarea and `execCommand`
+/// where that is missing or refused, as on plain `http` origins.
+pub fn copy(text: String, done: impl FnOnce(bool) + 'static) {
+    spawn_local(async move {
+        let copied = write_text(&text).await || copy_with_textarea(&text);
+        done(copied);
+    });
+}
+
+async fn write_text(text: &str) -> bool {
+    // `Clipboard` is still behind web-sys' unstable APIs.
+    let promise = web_sys::window()
+        .and_then(|w| Reflect::get(&w.navigator(), &"clipboard".into()).ok())
+        .filter(|clipboard| !clipboard.is_undefined())
+        .and_then(|clipboard| {
+            let write = Reflect::get(&clipboard, &"writeText".into())
+                .ok()?
+                .dyn_into::<Function>()
+                .ok()?;
+            write
+                .call1(&clipboard, &JsValue::from_str(text))
+                .ok()?
+                .dyn_into::<Promise>()
+                .ok()
+        });
+    match promise {
+        Some(promise) => JsFuture::from(promise).await.is_ok(),
+        None => false,
+    }
+}
+
+fn copy_with_textarea(text: &str) -> bool {
+    let document = match web_sys::window().and_then(|w| w.document()) {
+        Some(document) => document,
+        None => return false,
+    };
+    let (body, textarea) = match (
+        document.body(),
+        document
+            .create_element("textarea")
+            .ok()
+            .and_then(|el| el.dyn_into::<HtmlTextAreaElement>().ok()),
+    ) {
+        (Some(body), Some(textarea)) => (body, textarea),
+        _ => return false,
+    };
+    textarea.set_value(text);
+    // Off screen, but still selectable.
+    let _ = textarea.set_attribute("style", "position: fixed; top: -1000px; opacity: 0");
+    let _ = textarea.set_attribute("readonly", "");
+    if body.append_child(&textarea).is_err() {
+        return false;
+    }
+    textarea.select();
+    let copied = document
+        .dyn_ref::<HtmlDocument>()
+        .and_then(|d| d.exec_command("copy").ok())
+        .unwrap_or(false);
+    textarea.remove();
+    copied
+}
@@ -1,10 +1,156 @@
-use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use yew_agent::{Agent, AgentLink, Context, HandlerId};
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum Request {
-    EventBusMsg(String),
+use crate::messages::MessageData;
+use crate::protocol::{
+    DeleteData, EditData, FileData, MsgTypes, ReactionData, ReceiptData, StatusData, TypingData,
+    UserEntry, WebSocketMessage, PROTOCOL_VERSION,
+};
+use crate::services::websocket::{CloseReason, ConnState};
+
+/// What travels over the bus: frames from the server, already parsed, and
+/// changes to the connection itself.
+#[derive(Debug, Clone)]
+pub enum ChatEvent {
+    /// A room or private message. `to` and `room` are as given in the frame.
+    MessageReceived {
+        message: MessageData,
+        private: bool,
+        to: Option<String>,
+        room: Option<String>,
+    },
+    UsersUpdated(Vec<UserEntry>),
+    Typing(TypingData),
+    StatusChanged(StatusData),
+    Reaction(ReactionData),
+    Edited(EditData),
+    Deleted(DeleteData),
+    /// `receipt.from` received the listed messages, and with `read` saw them.
+    Receipt {
+        receipt: ReceiptData,
+        read: bool,
+    },
+    /// Older messages of `room`, oldest first.
+    History {
+        room: Option<String>,
+        messages: Vec<MessageData>,
+    },
+    /// The server refused something we sent.
+    ServerError(String),
+    ConnectionChanged(ConnState),
+    /// The socket could not be opened; the text says why.
+    ConnectionError(String),
+    /// The server ended our session and won't take us back by reconnecting.
+    Refused(CloseReason),
+    /// A frame that could not be understood.
+    ProtocolError(String),
+    /// A frame of a type newer than this client, named here.
+    UnknownFrame(String),
+    /// The server speaks a newer major protocol version, given here, than
+    /// this client supports.
+    UnsupportedVersion(u8),
+}
+
+impl ChatEvent {
+    /// Interprets a frame from the server. Frames that mean nothing to
+    /// components (heartbeats, requests only clients send) give `None`.
+    pub fn from_frame(frame: WebSocketMessage) -> Option<ChatEvent> {
+        match Self::decode(frame) {
+            Ok(event) => event,
+            Err(error) => Some(ChatEvent::ProtocolError(error)),
+        }
+    }
+
+    fn decode(frame: WebSocketMessage) -> Result<Option<ChatEvent>, String> {
+        if frame.version > PROTOCOL_VERSION {
+            return Ok(Some(ChatEvent::UnsupportedVersion(frame.version)));
+        }
+
+        let event = match &frame.message_type {
+            MsgTypes::Users => ChatEvent::UsersUpdated(
+                frame
+                    .data_array
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|entry| UserEntry::parse(entry))
+                    .collect(),
+            ),
+            MsgTypes::Message | MsgTypes::Private | MsgTypes::File => {
+                let mut message: MessageData = payload(&frame)?;
+                if frame.message_type == MsgTypes::File {
+                    // Relayed like a message, with the file as its text.
+                    let file: FileData = serde_json::from_str(&message.message).unwrap_or_default();
+                    message.message = file.name.clone();
+                    message.file = Some(Box::new(file));
+                }
+                // Older servers put these on the frame rather than the
+                // message itself.
+                if message.id.is_empty() {
+                    message.id = frame.id.unwrap_or_default();
+                }
+                if message.timestamp == 0 {
+                    message.timestamp = frame.timestamp.unwrap_or_default();
+                }
+                if message.reply_to.is_none() {
+                    message.reply_to = frame.reply_to;
+                    message.reply_excerpt = frame.reply_excerpt;
+                }
+                ChatEvent::MessageReceived {
+                    message,
+                    private: frame.message_type == MsgTypes::Private
+                        || frame.message_type == MsgTypes::File && frame.to.is_some(),
+                    to: frame.to,
+                    room: frame.room,
+                }
+            }
+            MsgTypes::Typing => ChatEvent::Typing(payload(&frame)?),
+            MsgTypes::Status => ChatEvent::StatusChanged(payload(&frame)?),
+            MsgTypes::Reaction => ChatEvent::Reaction(payload(&frame)?),
+            MsgTypes::Edit => ChatEvent::Edited(payload(&frame)?),
+            MsgTypes::Delete => ChatEvent::Deleted(payload(&frame)?),
+            MsgTypes::Delivered | MsgTypes::Read => ChatEvent::Receipt {
+                receipt: payload(&frame)?,
+                read: frame.message_type == MsgTypes::Read,
+            },
+            MsgTypes::History => {
+                let messages = frame
+                    .data_array
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|data| serde_json::from_str::<MessageData>(data).ok())
+                    .collect();
+                ChatEvent::History {
+                    room: frame.room,
+                    messages,
+                }
+            }
+            MsgTypes::Error => ChatEvent::ServerError(
+                frame
+                    .data
+                    .unwrap_or_else(|| "The server rejected the request.".to_string()),
+            ),
+            MsgTypes::Register
+            | MsgTypes::Join
+            | MsgTypes::Leave
+            | MsgTypes::Rename
+            | MsgTypes::Unregister
+            | MsgTypes::Ping
+            | MsgTypes::Pong => return Ok(None),
+            MsgTypes::Unknown(name) => ChatEvent::UnknownFrame(name.clone()),
+        };
+        Ok(Some(event))
+    }
+}
+
+/// Parses the `data` of `frame`, or describes why it can't be.
+fn payload<T: serde::de::DeserializeOwned>(frame: &WebSocketMessage) -> Result<T, String> {
+    let data = frame.data.as_deref().unwrap_or_default();
+    serde_json::from_str(data).map_err(|e| {
+        format!(
+            "Could not read a {:?} frame from the server: {}",
+            frame.message_type, e
+        )
+    })
 }
 
 pub struct EventBus {
@@ -15,8 +161,8 @@ pub struct EventBus {
 impl Agent for EventBus {
     type Reach = Context<Self>;
     type Message = ();
-    type Input = Request;
-    type Output = String;
+    type Input = ChatEvent;
+    type Output = ChatEvent;
 
     fn create(link: AgentLink<Self>) -> Self {
         Self {
@@ -27,13 +173,9 @@ impl Agent for EventBus {
 
     fn update(&mut self, _msg: Self::Message) {}
 
-    fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
-        match msg {
-            Request::EventBusMsg(s) => {
-                for sub in self.subscribers.iter() {
-                    self.link.respond(*sub, s.clone())
-                }
-            }
+    fn handle_input(&mut self, event: Self::Input, _id: HandlerId) {
+        for sub in self.subscribers.iter() {
+            self.link.respond(*sub, event.clone())
         }
     }
 
@@ -45,3 +187,128 @@ impl Agent for EventBus {
         self.subscribers.remove(&id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(frame: &str) -> Option<ChatEvent> {
+        ChatEvent::from_frame(WebSocketMessage::try_from(frame).unwrap())
+    }
+
+    fn message(frame: &str) -> MessageData {
+        match event(frame) {
+            Some(ChatEvent::MessageReceived { message, .. }) => message,
+            other => panic!("expected a message, got {:?}", other),
+        }
+    }
+
+    const MESSAGE: &str =
+        r#""messageType":"message","data":"{\"from\":\"alice\",\"message\":\"hi\"}""#;
+
+    #[test]
+    fn frames_of_our_version_or_before_it_are_read() {
+        for version in ["", r#""version":1,"#, r#""version":0,"#] {
+            let frame = format!("{{{}{}}}", version, MESSAGE);
+            assert_eq!(message(&frame).message, "hi", "{}", frame);
+        }
+    }
+
+    #[test]
+    fn newer_minor_versions_are_read() {
+        for version in ["1.4", r#""1.4""#, r#""1""#] {
+            let frame = format!(r#"{{"version":{},{}}}"#, version, MESSAGE);
+            assert_eq!(message(&frame).message, "hi", "{}", frame);
+        }
+    }
+
+    #[test]
+    fn newer_major_versions_are_not_interpreted() {
+        for (version, major) in [("2", 2), ("2.0", 2), (r#""3.1""#, 3), ("999", u8::MAX)] {
+            let frame = format!(r#"{{"version":{},{}}}"#, version, MESSAGE);
+            assert!(
+                matches!(event(&frame), Some(ChatEvent::UnsupportedVersion(v)) if v == major),
+                "{}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn unreadable_versions_make_the_frame_malformed() {
+        for version in [r#""one""#, "-1", "null"] {
+            let frame = format!(r#"{{"version":{},{}}}"#, version, MESSAGE);
+            assert!(
+                WebSocketMessage::try_from(frame.as_str()).is_err(),
+                "{}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_types_are_named() {
+        assert!(matches!(
+            event(r#"{"messageType":"poll","data":"{}"}"#),
+            Some(ChatEvent::UnknownFrame(name)) if name == "poll"
+        ));
+    }
+
+    #[test]
+    fn unreadable_payloads_are_protocol_errors() {
+        for frame in [
+            r#"{"messageType":"message","data":"not json"}"#,
+            r#"{"messageType":"message","data":"{\"message\":\"no sender\"}"}"#,
+            r#"{"messageType":"typing"}"#,
+        ] {
+            assert!(
+                matches!(event(frame), Some(ChatEvent::ProtocolError(_))),
+                "{}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn optional_fields_may_be_missing() {
+        let m = message(&format!("{{{}}}", MESSAGE));
+        assert_eq!(m.id, "");
+        assert_eq!(m.timestamp, 0);
+        assert_eq!(m.reply_to, None);
+        assert!(!m.deleted);
+
+        match event(r#"{"messageType":"users"}"#) {
+            Some(ChatEvent::UsersUpdated(users)) => assert!(users.is_empty()),
+            other => panic!("expected users, got {:?}", other),
+        }
+        match event(r#"{"messageType":"history","room":"rust"}"#) {
+            Some(ChatEvent::History { room, messages }) => {
+                assert_eq!(room.as_deref(), Some("rust"));
+                assert!(messages.is_empty());
+            }
+            other => panic!("expected history, got {:?}", other),
+        }
+        assert!(matches!(
+            event(r#"{"messageType":"error"}"#),
+            Some(ChatEvent::ServerError(_))
+        ));
+    }
+
+    #[test]
+    fn frame_fields_fill_in_for_old_servers() {
+        let m = message(
+            r#"{"messageType":"message","id":"a1","timestamp":5,"replyTo":"b1","data":"{\"from\":\"alice\",\"message\":\"hi\"}"}"#,
+        );
+        assert_eq!(m.id, "a1");
+        assert_eq!(m.timestamp, 5);
+        assert_eq!(m.reply_to.as_deref(), Some("b1"));
+    }
+
+    #[test]
+    fn heartbeats_and_client_requests_mean_nothing() {
+        for message_type in ["ping", "pong", "register", "join"] {
+            let frame = format!(r#"{{"messageType":"{}"}}"#, message_type);
+            assert!(event(&frame).is_none(), "{}", frame);
+        }
+    }
+}
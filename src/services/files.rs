@@ -0,0 +1,150 @@
+//! Reading files picked by the user, uploading them, and saving received
+//! ones.
+
+use gloo_timers::callback::Timeout;
+use js_sys::{Array, ArrayBuffer, Promise, Uint8Array};
+use reqwasm::http::Request;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobPropertyBag, File, FileReader, HtmlAnchorElement, Url};
+
+use crate::attachments::{decode_file, encode_file};
+use crate::protocol::FileData;
+use crate::sanitize::safe_web_url;
+
+/// How long a saved file's object URL is kept around.
+const REVOKE_DELAY_MS: u32 = 10_000;
+
+/// Why a file couldn't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileError {
+    /// Over the inline limit, with nowhere to upload it.
+    TooLarge,
+    /// The browser couldn't read it.
+    Unreadable,
+    /// The upload endpoint refused it or gave back no usable address.
+    UploadFailed,
+}
+
+impl FileError {
+    /// Locale key describing the error.
+    pub fn key(self) -> &'static str {
+        match self {
+            FileError::TooLarge => "file.too_large",
+            FileError::Unreadable => "file.unreadable",
+            FileError::UploadFailed => "file.upload_failed",
+        }
+    }
+}
+
+/// `file` ready to send: inline if it is at most `inline_limit` bytes,
+/// else uploaded to `upload_url`.
+pub async fn prepare(
+    file: File,
+    inline_limit: usize,
+    upload_url: Option<String>,
+) -> Result<FileData, FileError> {
+    let mime = match file.type_() {
+        mime if mime.is_empty() => "application/octet-stream".to_string(),
+        mime => mime,
+    };
+    if file.size() as usize > inline_limit {
+        let endpoint = upload_url.ok_or(FileError::TooLarge)?;
+        let url = upload(&endpoint, &file, &mime)
+            .await
+            .ok_or(FileError::UploadFailed)?;
+        return Ok(FileData {
+            name: file.name(),
+            mime,
+            size: file.size() as u64,
+            body: None,
+            url: Some(url),
+        });
+    }
+    let bytes = read_bytes(&file).await.ok_or(FileError::Unreadable)?;
+    encode_file(&file.name(), &mime, &bytes, inline_limit).ok_or(FileError::TooLarge)
+}
+
+async fn read_bytes(file: &File) -> Option<Vec<u8>> {
+    let reader = FileReader::new().ok()?;
+    let loaded = Promise::new(&mut |resolve, reject| {
+        reader.set_onload(Some(&resolve));
+        reader.set_onerror(Some(&reject));
+    });
+    reader.read_as_array_buffer(file).ok()?;
+    JsFuture::from(loaded).await.ok()?;
+    let buffer: ArrayBuffer = reader.result().ok()?.dyn_into().ok()?;
+    Some(Uint8Array::new(&buffer).to_vec())
+}
+
+/// POSTs `file` to `endpoint`, which answers with the address it can be
+/// downloaded from.
+async fn upload(endpoint: &str, file: &File, mime: &str) -> Option<String> {
+    let response = Request::post(endpoint)
+        .header("Content-Type", mime)
+        .body(JsValue::from(file.clone()))
+        .send()
+        .await
+        .ok()?;
+    if !response.ok() {
+        log::warn!("upload of {} failed: {}", file.name(), response.status());
+        return None;
+    }
+    safe_web_url(&response.text().await.ok()?)
+}
+
+/// Saves `file`, inline or uploaded, under its name. Returns whether
+/// there was anything to save; inline contents over `inline_limit` are not
+/// trusted.
+pub fn download(file: &FileData, inline_limit: usize) -> bool {
+    if let Some(url) = file.url.as_deref().and_then(safe_web_url) {
+        return save_as(&url, &file.name);
+    }
+    match decode_file(file, inline_limit) {
+        Some(bytes) => save_bytes(&bytes, &file.mime, &file.name),
+        None => false,
+    }
+}
+
+/// Saves `bytes` of type `mime` as a file called `name`. Returns whether
+/// the browser took them.
+#[cfg_attr(not(feature = "frame-log"), allow(dead_code))]
+pub fn save_bytes(bytes: &[u8], mime: &str, name: &str) -> bool {
+    let parts = Array::of1(&Uint8Array::from(bytes));
+    let mut options = BlobPropertyBag::new();
+    options.type_(mime);
+    let url = match Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .and_then(|blob| Url::create_object_url_with_blob(&blob))
+    {
+        Ok(url) => url,
+        Err(e) => {
+            log::error!("could not save {}: {:?}", name, e);
+            return false;
+        }
+    };
+    let saved = save_as(&url, name);
+    // Some browsers only start reading the blob after the click returns.
+    Timeout::new(REVOKE_DELAY_MS, move || {
+        let _ = Url::revoke_object_url(&url);
+    })
+    .forget();
+    saved
+}
+
+/// Clicks a temporary `<a download>` pointing at `url`.
+fn save_as(url: &str, name: &str) -> bool {
+    let anchor = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.create_element("a").ok())
+        .and_then(|el| el.dyn_into::<HtmlAnchorElement>().ok());
+    match anchor {
+        Some(anchor) => {
+            anchor.set_href(url);
+            anchor.set_download(name);
+            anchor.set_rel("noopener");
+            anchor.click();
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,109 @@
+//! A record of the last frames that went over the websocket, both ways,
+//! heartbeats included, for working out what a misbehaving server sent.
+//! The websocket service writes to it; the diagnostics panel reads it.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::protocol::MsgTypes;
+use crate::time;
+
+/// Frames kept; older ones make way.
+pub const FRAME_LOG_CAP: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// One frame as it crossed the wire.
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameRecord {
+    pub direction: Direction,
+    pub timestamp: u64,
+    /// The frame's type, or why it couldn't be read.
+    #[serde(rename = "type")]
+    pub kind: Result<MsgTypes, String>,
+    /// Size on the wire, in bytes.
+    pub bytes: usize,
+}
+
+/// Things that went wrong with the connection, counted since the page
+/// loaded or the log was last cleared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Counters {
+    pub reconnects: u32,
+    /// Frames thrown away because the outbound queue was full.
+    pub dropped: u32,
+    /// Frames the socket or the service wouldn't take.
+    pub send_failures: u32,
+}
+
+/// The last frames, oldest first, and the counters.
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameLog {
+    frames: VecDeque<FrameRecord>,
+    pub counters: Counters,
+    #[serde(skip)]
+    cap: usize,
+}
+
+impl FrameLog {
+    /// An empty log keeping the last `cap` frames, allocated up front.
+    pub fn new(cap: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(cap),
+            counters: Counters::default(),
+            cap,
+        }
+    }
+
+    pub fn push(&mut self, record: FrameRecord) {
+        if self.frames.len() == self.cap {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(record);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.counters = Counters::default();
+    }
+
+    pub fn frames(&self) -> impl DoubleEndedIterator<Item = &FrameRecord> + ExactSizeIterator {
+        self.frames.iter()
+    }
+}
+
+thread_local! {
+    static LOG: RefCell<FrameLog> = RefCell::new(FrameLog::new(FRAME_LOG_CAP));
+}
+
+/// Notes a frame of `bytes` going `direction`, read as `kind`.
+pub fn record(direction: Direction, bytes: usize, kind: Result<&MsgTypes, String>) {
+    let record = FrameRecord {
+        direction,
+        timestamp: time::now(),
+        kind: kind.cloned(),
+        bytes,
+    };
+    LOG.with(|log| log.borrow_mut().push(record));
+}
+
+/// Bumps one of the counters.
+pub fn count(counter: impl FnOnce(&mut Counters) -> &mut u32) {
+    LOG.with(|log| *counter(&mut log.borrow_mut().counters) += 1);
+}
+
+/// Runs `f` on the log as it is now.
+pub fn with<T>(f: impl FnOnce(&FrameLog) -> T) -> T {
+    LOG.with(|log| f(&log.borrow()))
+}
+
+pub fn clear() {
+    LOG.with(|log| log.borrow_mut().clear());
+}
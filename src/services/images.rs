@@ -0,0 +1,138 @@
+//! Turning image files the user pastes or drops into something that can be
+//! sent: scaled down if large, and encoded as a `data:` URL.
+
+use js_sys::Promise;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{CanvasRenderingContext2d, File, FileReader, HtmlCanvasElement, HtmlImageElement};
+
+use crate::sanitize::IMAGE_MIME_TYPES;
+
+/// Images are scaled down until neither side is longer than this.
+pub const MAX_EDGE_PX: u32 = 1280;
+/// Largest image sent, once scaled down and encoded.
+pub const MAX_IMAGE_BYTES: usize = 2 * 1024 * 1024;
+/// Quality of JPEG and WebP images re-encoded after scaling.
+const QUALITY: f64 = 0.85;
+
+/// Why a file couldn't be turned into an image to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// Not one of the formats we send.
+    NotAnImage,
+    /// Over [`MAX_IMAGE_BYTES`] even after scaling down.
+    TooLarge,
+    /// The browser couldn't read or decode it.
+    Unreadable,
+}
+
+impl ImageError {
+    /// Locale key describing the error.
+    pub fn key(self) -> &'static str {
+        match self {
+            ImageError::NotAnImage => "image.not_an_image",
+            ImageError::TooLarge => "image.too_large",
+            ImageError::Unreadable => "image.unreadable",
+        }
+    }
+}
+
+/// Size of a `width`×`height` image scaled down, keeping its proportions,
+/// so that neither side exceeds `max_edge`. Smaller images keep their size.
+pub fn scaled_size(width: u32, height: u32, max_edge: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_edge {
+        return (width, height);
+    }
+    let scale = |side: u32| {
+        ((side as u64 * max_edge as u64 + longest as u64 / 2) / longest as u64).max(1) as u32
+    };
+    (scale(width), scale(height))
+}
+
+/// Bytes of data the base64 `data:` URL `url` decodes to.
+pub fn data_url_bytes(url: &str) -> usize {
+    let data = url.split_once(',').map_or("", |(_, data)| data);
+    let padding = data.bytes().rev().take_while(|&b| b == b'=').count();
+    (data.len() * 3 / 4).saturating_sub(padding)
+}
+
+/// `file` as a `data:` URL ready to send, scaled down to [`MAX_EDGE_PX`] if
+/// bigger. Images already small enough are sent as they are, so animated
+/// GIFs keep moving.
+pub async fn prepare(file: File) -> Result<String, ImageError> {
+    let mime = file.type_();
+    if !IMAGE_MIME_TYPES.contains(&mime.as_str()) {
+        return Err(ImageError::NotAnImage);
+    }
+    let original = read_as_data_url(&file)
+        .await
+        .ok_or(ImageError::Unreadable)?;
+    let image = load(&original).await.ok_or(ImageError::Unreadable)?;
+    let (width, height) = (image.natural_width(), image.natural_height());
+    let (scaled_width, scaled_height) = scaled_size(width, height, MAX_EDGE_PX);
+    let url = if (scaled_width, scaled_height) == (width, height)
+        && data_url_bytes(&original) <= MAX_IMAGE_BYTES
+    {
+        original
+    } else {
+        // Canvases can't write GIFs.
+        let output = if mime == "image/gif" {
+            "image/png"
+        } else {
+            &mime
+        };
+        draw_scaled(&image, scaled_width, scaled_height, output).ok_or(ImageError::Unreadable)?
+    };
+    if data_url_bytes(&url) > MAX_IMAGE_BYTES {
+        return Err(ImageError::TooLarge);
+    }
+    Ok(url)
+}
+
+async fn read_as_data_url(file: &File) -> Option<String> {
+    let reader = FileReader::new().ok()?;
+    let loaded = Promise::new(&mut |resolve, reject| {
+        reader.set_onload(Some(&resolve));
+        reader.set_onerror(Some(&reject));
+    });
+    reader.read_as_data_url(file).ok()?;
+    JsFuture::from(loaded).await.ok()?;
+    reader.result().ok()?.as_string()
+}
+
+/// `src` decoded into an image element.
+async fn load(src: &str) -> Option<HtmlImageElement> {
+    let image = HtmlImageElement::new().ok()?;
+    let loaded = Promise::new(&mut |resolve, reject| {
+        image.set_onload(Some(&resolve));
+        image.set_onerror(Some(&reject));
+    });
+    image.set_src(src);
+    JsFuture::from(loaded).await.ok()?;
+    Some(image)
+}
+
+fn draw_scaled(image: &HtmlImageElement, width: u32, height: u32, mime: &str) -> Option<String> {
+    let canvas: HtmlCanvasElement = web_sys::window()?
+        .document()?
+        .create_element("canvas")
+        .ok()?
+        .dyn_into()
+        .ok()?;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let context: CanvasRenderingContext2d = canvas.get_context("2d").ok()??.dyn_into().ok()?;
+    context
+        .draw_image_with_html_image_element_and_dw_and_dh(
+            image,
+            0.0,
+            0.0,
+            width as f64,
+            height as f64,
+        )
+        .ok()?;
+    canvas
+        .to_data_url_with_type_and_encoder_options(mime, &JsValue::from_f64(QUALITY))
+        .ok()
+}
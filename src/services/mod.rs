@@ -1,2 +1,12 @@
 pub mod websocket;
 pub mod event_bus;
+pub mod storage;
+pub mod notifications;
+pub mod sound;
+pub mod theme;
+pub mod transport;
+pub mod clipboard;
+pub mod images;
+pub mod files;
+#[cfg(feature = "frame-log")]
+pub mod frame_log;
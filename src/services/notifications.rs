@@ -0,0 +1,53 @@
+//! Desktop notifications through the web Notifications API. Every entry
+//! point checks that the API exists first, so browsers without it just get
+//! no notifications.
+
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{Notification, NotificationOptions, NotificationPermission, VisibilityState};
+
+fn supported() -> bool {
+    web_sys::window()
+        .map(|w| js_sys::Reflect::has(&w, &JsValue::from_str("Notification")).unwrap_or(false))
+        .unwrap_or(false)
+}
+
+/// Asks for permission unless the user has already answered.
+pub fn request_permission() {
+    if supported() && Notification::permission() == NotificationPermission::Default {
+        if let Err(e) = Notification::request_permission() {
+            log::error!("could not request notification permission: {:?}", e);
+        }
+    }
+}
+
+/// Whether the tab is currently in the background.
+pub fn page_hidden() -> bool {
+    web_sys::window()
+        .and_then(|w| w.document())
+        .map(|d| d.visibility_state() == VisibilityState::Hidden)
+        .unwrap_or(false)
+}
+
+/// Shows a notification if permission was granted. Clicking it brings the
+/// chat window to the front.
+pub fn notify(title: &str, body: &str) {
+    if !supported() || Notification::permission() != NotificationPermission::Granted {
+        return;
+    }
+    let notification =
+        match Notification::new_with_options(title, NotificationOptions::new().body(body)) {
+            Ok(notification) => notification,
+            Err(e) => {
+                log::error!("could not show notification: {:?}", e);
+                return;
+            }
+        };
+    let handle = notification.clone();
+    let onclick = Closure::once_into_js(move || {
+        if let Some(window) = web_sys::window() {
+            let _ = window.focus();
+        }
+        handle.close();
+    });
+    notification.set_onclick(Some(onclick.unchecked_ref()));
+}
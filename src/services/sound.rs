@@ -0,0 +1,30 @@
+//! The ping played for incoming messages.
+
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::HtmlAudioElement;
+
+/// Short clip shipped in `static/`.
+const PING_URL: &str = "ping.wav";
+
+thread_local! {
+    static PING: Option<HtmlAudioElement> = HtmlAudioElement::new_with_src(PING_URL).ok();
+}
+
+/// Plays the ping from the start. Browsers refuse playback until the user
+/// has interacted with the page; that rejection is expected and ignored.
+pub fn play_ping() {
+    PING.with(|ping| {
+        let ping = match ping {
+            Some(ping) => ping,
+            None => return,
+        };
+        ping.set_current_time(0.0);
+        if let Ok(promise) = ping.play() {
+            spawn_local(async move {
+                if JsFuture::from(promise).await.is_err() {
+                    log::debug!("ping blocked by the browser");
+                }
+            });
+        }
+    });
+}
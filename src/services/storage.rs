@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+
+use gloo_storage::{errors::StorageError, LocalStorage, Storage};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::protocol::AvatarChoice;
+use crate::services::theme::Theme;
+
+const SERVER_URL_KEY: &str = "yewchat.server_url";
+const USERNAME_KEY: &str = "yewchat.username";
+const LAST_USERNAME_KEY: &str = "yewchat.last_username";
+const HISTORY_KEY: &str = "yewchat.history";
+const NOTIFICATIONS_KEY: &str = "yewchat.notifications";
+const SOUND_KEY: &str = "yewchat.sound";
+const RECENT_EMOJI_KEY: &str = "yewchat.recent_emoji";
+const AVATAR_KEY: &str = "yewchat.avatar";
+const THEME_KEY: &str = "yewchat.theme";
+const LOCALE_KEY: &str = "yewchat.locale";
+const PINS_KEY: &str = "yewchat.pins";
+const MUTED_KEY: &str = "yewchat.muted";
+const FILTER_KEY: &str = "yewchat.filter";
+const FILTER_WORDS_KEY: &str = "yewchat.filter_words";
+const DRAFTS_KEY: &str = "yewchat.drafts";
+const SENT_HISTORY_KEY: &str = "yewchat.sent_history";
+const SIDEBAR_RAIL_KEY: &str = "yewchat.sidebar_rail";
+
+/// The server URL saved from the settings panel, if any.
+pub fn load_server_url() -> Option<String> {
+    LocalStorage::get(SERVER_URL_KEY).ok()
+}
+
+pub fn save_server_url(url: &str) {
+    if let Err(e) = LocalStorage::set(SERVER_URL_KEY, url) {
+        log::error!("could not persist server url: {:?}", e);
+    }
+}
+
+/// The name the user last logged in with, if any.
+pub fn load_username() -> Option<String> {
+    LocalStorage::get(USERNAME_KEY).ok()
+}
+
+pub fn save_username(username: &str) {
+    if let Err(e) = LocalStorage::set(USERNAME_KEY, username) {
+        log::error!("could not persist username: {:?}", e);
+    }
+}
+
+/// Forgets the logged-in name, remembering it only to prefill the login form.
+pub fn clear_username() {
+    if let Some(username) = load_username() {
+        if let Err(e) = LocalStorage::set(LAST_USERNAME_KEY, username) {
+            log::error!("could not persist last username: {:?}", e);
+        }
+    }
+    LocalStorage::delete(USERNAME_KEY);
+}
+
+/// The name used before logging out, if any.
+pub fn load_last_username() -> Option<String> {
+    LocalStorage::get(LAST_USERNAME_KEY).ok()
+}
+
+/// Whether desktop notifications are on. Defaults to on.
+pub fn load_notifications_enabled() -> bool {
+    LocalStorage::get(NOTIFICATIONS_KEY).unwrap_or(true)
+}
+
+pub fn save_notifications_enabled(enabled: bool) {
+    if let Err(e) = LocalStorage::set(NOTIFICATIONS_KEY, enabled) {
+        log::error!("could not persist notification setting: {:?}", e);
+    }
+}
+
+/// Whether the incoming message ping is on. Defaults to on.
+pub fn load_sound_enabled() -> bool {
+    LocalStorage::get(SOUND_KEY).unwrap_or(true)
+}
+
+pub fn save_sound_enabled(enabled: bool) {
+    if let Err(e) = LocalStorage::set(SOUND_KEY, enabled) {
+        log::error!("could not persist sound setting: {:?}", e);
+    }
+}
+
+/// Whether the sidebar was last left shrunk to a rail.
+pub fn load_sidebar_rail() -> bool {
+    LocalStorage::get(SIDEBAR_RAIL_KEY).unwrap_or(false)
+}
+
+pub fn save_sidebar_rail(rail: bool) {
+    if let Err(e) = LocalStorage::set(SIDEBAR_RAIL_KEY, rail) {
+        log::error!("could not persist sidebar setting: {:?}", e);
+    }
+}
+
+/// The avatar picked at login, if any.
+pub fn load_avatar() -> Option<AvatarChoice> {
+    LocalStorage::get(AVATAR_KEY).ok()
+}
+
+pub fn save_avatar(avatar: &AvatarChoice) {
+    if let Err(e) = LocalStorage::set(AVATAR_KEY, avatar) {
+        log::error!("could not persist avatar: {:?}", e);
+    }
+}
+
+/// The theme last chosen with the toggle, if it was ever used.
+pub fn load_theme() -> Option<Theme> {
+    LocalStorage::get(THEME_KEY).ok()
+}
+
+pub fn save_theme(theme: Theme) {
+    if let Err(e) = LocalStorage::set(THEME_KEY, theme) {
+        log::error!("could not persist theme: {:?}", e);
+    }
+}
+
+/// The locale picked in the settings, if one was ever picked.
+pub fn load_locale() -> Option<String> {
+    LocalStorage::get(LOCALE_KEY).ok()
+}
+
+pub fn save_locale(locale: &str) {
+    if let Err(e) = LocalStorage::set(LOCALE_KEY, locale) {
+        log::error!("could not persist locale: {:?}", e);
+    }
+}
+
+/// Emoji picked most recently, newest first.
+pub fn load_recent_emoji() -> Vec<String> {
+    LocalStorage::get(RECENT_EMOJI_KEY).unwrap_or_default()
+}
+
+pub fn save_recent_emoji(emoji: &[String]) {
+    if let Err(e) = LocalStorage::set(RECENT_EMOJI_KEY, emoji) {
+        log::error!("could not persist recent emoji: {:?}", e);
+    }
+}
+
+/// Chat history cached by [`save_history`]. Anything that no longer
+/// deserializes is discarded.
+pub fn load_history<T: DeserializeOwned>() -> Option<T> {
+    match LocalStorage::get(HISTORY_KEY) {
+        Ok(history) => Some(history),
+        Err(StorageError::KeyNotFound(_)) => None,
+        Err(e) => {
+            log::error!("discarding stored history: {:?}", e);
+            LocalStorage::delete(HISTORY_KEY);
+            None
+        }
+    }
+}
+
+/// Pinned messages saved by [`save_pins`]. Pins that no longer deserialize
+/// are dropped.
+pub fn load_pins<T: DeserializeOwned>() -> Vec<T> {
+    LocalStorage::get(PINS_KEY).unwrap_or_default()
+}
+
+pub fn save_pins<T: Serialize>(pins: &[T]) {
+    if let Err(e) = LocalStorage::set(PINS_KEY, pins) {
+        log::error!("could not persist pins: {:?}", e);
+    }
+}
+
+/// Names of the users muted on this device.
+pub fn load_muted() -> HashSet<String> {
+    LocalStorage::get(MUTED_KEY).unwrap_or_default()
+}
+
+pub fn save_muted(muted: &HashSet<String>) {
+    if let Err(e) = LocalStorage::set(MUTED_KEY, muted) {
+        log::error!("could not persist muted users: {:?}", e);
+    }
+}
+
+/// Whether rude words are masked in messages. Off unless turned on.
+pub fn load_filter_enabled() -> bool {
+    LocalStorage::get(FILTER_KEY).unwrap_or(false)
+}
+
+pub fn save_filter_enabled(enabled: bool) {
+    if let Err(e) = LocalStorage::set(FILTER_KEY, enabled) {
+        log::error!("could not persist filter setting: {:?}", e);
+    }
+}
+
+/// Words masked on top of the built-in list.
+pub fn load_filter_words() -> Vec<String> {
+    LocalStorage::get(FILTER_WORDS_KEY).unwrap_or_default()
+}
+
+pub fn save_filter_words(words: &[String]) {
+    if let Err(e) = LocalStorage::set(FILTER_WORDS_KEY, words) {
+        log::error!("could not persist filter words: {:?}", e);
+    }
+}
+
+/// Unsent input saved by [`save_drafts`], one entry per conversation.
+/// Entries that no longer deserialize are dropped.
+pub fn load_drafts<T: DeserializeOwned>() -> Vec<T> {
+    LocalStorage::get(DRAFTS_KEY).unwrap_or_default()
+}
+
+pub fn save_drafts<T: Serialize>(drafts: &[T]) {
+    if let Err(e) = LocalStorage::set(DRAFTS_KEY, drafts) {
+        log::error!("could not persist drafts: {:?}", e);
+    }
+}
+
+/// What we sent lately, oldest first, for recalling into the input.
+pub fn load_sent_history() -> Vec<String> {
+    LocalStorage::get(SENT_HISTORY_KEY).unwrap_or_default()
+}
+
+pub fn save_sent_history<'a>(sent: impl Iterator<Item = &'a String>) {
+    let sent: Vec<&String> = sent.collect();
+    if let Err(e) = LocalStorage::set(SENT_HISTORY_KEY, sent) {
+        log::error!("could not persist sent history: {:?}", e);
+    }
+}
+
+/// Caches `history`. If it doesn't fit (e.g. the quota is exceeded) the
+/// stale copy is removed instead, so it can't be restored later.
+pub fn save_history<T: Serialize>(history: &T) {
+    if let Err(e) = LocalStorage::set(HISTORY_KEY, history) {
+        log::error!("could not persist history: {:?}", e);
+        LocalStorage::delete(HISTORY_KEY);
+    }
+}
@@ -0,0 +1,52 @@
+//! Light and dark colour schemes, switched with the `dark` class on the
+//! document root so that Tailwind's `dark:` variants apply everywhere.
+
+use serde::{Deserialize, Serialize};
+use yew::UseStateHandle;
+
+use crate::services::storage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn toggled(self) -> Theme {
+        match self {
+            Theme::Light => Theme::Dark,
+            Theme::Dark => Theme::Light,
+        }
+    }
+}
+
+/// The theme every view reads and the header toggle sets.
+pub type ThemeContext = UseStateHandle<Theme>;
+
+/// The theme picked with the toggle, or else the one the system prefers.
+pub fn initial() -> Theme {
+    storage::load_theme().unwrap_or_else(|| {
+        let prefers_dark = web_sys::window()
+            .and_then(|w| w.match_media("(prefers-color-scheme: dark)").ok().flatten())
+            .is_some_and(|query| query.matches());
+        if prefers_dark {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    })
+}
+
+/// Puts the `dark` class on the document root, or takes it off.
+pub fn apply(theme: Theme) {
+    let root = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.document_element());
+    if let Some(root) = root {
+        let _ = root
+            .class_list()
+            .toggle_with_force("dark", theme == Theme::Dark);
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::config;
+use crate::protocol::WebSocketMessage;
+use crate::services::websocket::{ConnState, WebsocketService};
+
+/// Why a frame could not be handed to the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// Too many frames are already waiting to go out.
+    Full,
+    /// The transport has been shut down.
+    Closed,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Full => write!(f, "send buffer is full"),
+            SendError::Closed => write!(f, "connection is closed"),
+        }
+    }
+}
+
+/// Carries frames to the chat server, encoding them however it speaks to
+/// it. Whatever comes back is published as
+/// [`ChatEvent`](crate::services::event_bus::ChatEvent)s on the
+/// [`EventBus`](crate::services::event_bus::EventBus), so subscribing to
+/// inbound traffic works the same for every transport.
+pub trait MessageTransport {
+    fn send(&self, frame: WebSocketMessage) -> Result<(), SendError>;
+
+    fn state(&self) -> ConnState;
+
+    /// Sets the frames sent first every time the connection opens.
+    fn set_handshake(&self, frames: Vec<WebSocketMessage>);
+
+    /// Hangs up for good.
+    fn close(self: Box<Self>);
+}
+
+type Open = dyn Fn(&str) -> Result<Box<dyn MessageTransport>, String>;
+
+/// Opens a transport to a server address. The default opens a real
+/// websocket. Only clones of the same factory compare equal, which is enough
+/// for it to be passed as a property.
+#[derive(Clone)]
+pub struct TransportFactory(Rc<Open>);
+
+impl TransportFactory {
+    pub fn new(open: impl Fn(&str) -> Result<Box<dyn MessageTransport>, String> + 'static) -> Self {
+        Self(Rc::new(open))
+    }
+
+    pub fn open(&self, url: &str) -> Result<Box<dyn MessageTransport>, String> {
+        (self.0)(url)
+    }
+}
+
+impl Default for TransportFactory {
+    fn default() -> Self {
+        Self::new(|url| Ok(Box::new(WebsocketService::new(url, config::wire_format())?)))
+    }
+}
+
+impl PartialEq for TransportFactory {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(feature = "mock-transport")]
+pub use mock::MockTransport;
+
+#[cfg(feature = "mock-transport")]
+mod mock {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use yew_agent::{Dispatched, Dispatcher};
+
+    use super::{MessageTransport, SendError, TransportFactory};
+    use crate::protocol::{Codec, Frame, JsonCodec, WebSocketMessage};
+    use crate::services::event_bus::{ChatEvent, EventBus};
+    use crate::services::websocket::ConnState;
+
+    /// In-memory transport for driving `Chat` without a server. It records
+    /// every frame sent and publishes injected frames as if they had arrived
+    /// over a socket. Clones share the same state.
+    #[derive(Clone)]
+    pub struct MockTransport {
+        sent: Rc<RefCell<Vec<WebSocketMessage>>>,
+        handshake: Rc<RefCell<Vec<WebSocketMessage>>>,
+        state: Rc<Cell<ConnState>>,
+        event_bus: Rc<RefCell<Dispatcher<EventBus>>>,
+    }
+
+    impl Default for MockTransport {
+        fn default() -> Self {
+            Self {
+                sent: Rc::default(),
+                handshake: Rc::default(),
+                state: Rc::new(Cell::new(ConnState::Connecting)),
+                event_bus: Rc::new(RefCell::new(EventBus::dispatcher())),
+            }
+        }
+    }
+
+    impl MockTransport {
+        /// A factory handing out this transport whatever the address.
+        pub fn factory(&self) -> TransportFactory {
+            let transport = self.clone();
+            TransportFactory::new(move |_| Ok(Box::new(transport.clone())))
+        }
+
+        /// Everything sent so far, handshake included, oldest first.
+        pub fn sent(&self) -> Vec<WebSocketMessage> {
+            self.sent.borrow().clone()
+        }
+
+        /// Pretends the connection opened: the handshake is "sent" and the
+        /// state change published.
+        pub fn open(&self) {
+            let handshake = self.handshake.borrow().clone();
+            self.sent.borrow_mut().extend(handshake);
+            self.set_state(ConnState::Open);
+        }
+
+        /// Publishes `frame`, as JSON, as if the server had sent it.
+        pub fn receive(&self, frame: &str) {
+            let event = match JsonCodec.decode(Frame::Text(frame.to_string())) {
+                Ok(frame) => ChatEvent::from_frame(frame),
+                Err(e) => Some(ChatEvent::ProtocolError(format!(
+                    "Could not read a frame from the server: {}",
+                    e
+                ))),
+            };
+            if let Some(event) = event {
+                self.event_bus.borrow_mut().send(event);
+            }
+        }
+
+        fn set_state(&self, state: ConnState) {
+            if self.state.replace(state) != state {
+                self.event_bus
+                    .borrow_mut()
+                    .send(ChatEvent::ConnectionChanged(state));
+            }
+        }
+    }
+
+    impl MessageTransport for MockTransport {
+        fn send(&self, frame: WebSocketMessage) -> Result<(), SendError> {
+            match self.state.get() {
+                ConnState::Closed => Err(SendError::Closed),
+                _ => {
+                    self.sent.borrow_mut().push(frame);
+                    Ok(())
+                }
+            }
+        }
+
+        fn state(&self) -> ConnState {
+            self.state.get()
+        }
+
+        fn set_handshake(&self, frames: Vec<WebSocketMessage>) {
+            *self.handshake.borrow_mut() = frames;
+        }
+
+        fn close(self: Box<Self>) {
+            self.set_state(ConnState::Closed);
+        }
+    }
+}
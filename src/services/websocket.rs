@@ -1,53 +1,860 @@
-use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
-use reqwasm::websocket::{futures::WebSocket, Message};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::Poll;
+
+use futures::{
+    channel::mpsc::{self, Receiver, Sender},
+    future::{self, Either},
+    stream::{SplitSink, SplitStream},
+    Sink, SinkExt, Stream, StreamExt,
+};
+use gloo_timers::future::TimeoutFuture;
+use reqwasm::websocket::{futures::WebSocket, Message, State, WebSocketError};
+use serde::{Deserialize, Serialize};
 
 use wasm_bindgen_futures::spawn_local;
-use yew_agent::Dispatched;
+use yew_agent::{Dispatched, Dispatcher};
+
+use crate::protocol::{Frame, MsgTypes, WebSocketMessage, WireFormat};
+use crate::services::event_bus::{ChatEvent, EventBus};
+#[cfg(feature = "frame-log")]
+use crate::services::frame_log::{self, Direction as Traffic};
+use crate::services::notifications::page_hidden;
+use crate::services::transport::{MessageTransport, SendError};
+use crate::time;
+
+/// Server address used when nothing else has been configured; see
+/// [`crate::config`]. Without a scheme it follows the page, so an https
+/// deployment connects over wss://.
+pub const DEFAULT_WS_URL: &str = "127.0.0.1:8080";
+
+/// Close code sent when we hang up on purpose.
+const NORMAL_CLOSURE: u16 = 1000;
+/// Close code sent when the server stopped answering heartbeats.
+const HEARTBEAT_TIMEOUT_CLOSURE: u16 = 4000;
+
+/// How often a ping is sent while the socket is open.
+const HEARTBEAT_INTERVAL_MS: u32 = 30_000;
+/// Heartbeat intervals without any traffic after which the connection is
+/// considered dead.
+const MISSED_HEARTBEATS: u64 = 2;
+
+/// Longest pause between two reconnect attempts.
+const MAX_RECONNECT_DELAY_MS: u32 = 30_000;
+
+/// Frames held back while the socket is not open. Anything beyond this is
+/// refused.
+pub const OUTBOUND_QUEUE_CAP: usize = 100;
+
+/// Whether a frame of `message_type` sent while the socket is down is still
+/// worth sending once it is back: messages and changes to them are. Typing
+/// notices and receipts would be stale by then, and registration, joins and
+/// our status go out again with the handshake anyway.
+pub fn outlasts_disconnects(message_type: &MsgTypes) -> bool {
+    matches!(
+        message_type,
+        MsgTypes::Message
+            | MsgTypes::Private
+            | MsgTypes::File
+            | MsgTypes::Edit
+            | MsgTypes::Delete
+            | MsgTypes::Reaction
+    )
+}
+
+/// Why the server ended our session for good.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    Kicked,
+    Banned,
+    /// Someone else is using our name.
+    NameTaken,
+}
+
+/// Close codes after which reconnecting would only be refused again. Any
+/// other code, such as a server restart (1001, 1012) or a dropped network
+/// (1006), is retried.
+const TERMINAL_CLOSE_CODES: &[(u16, CloseReason)] = &[
+    (4001, CloseReason::Kicked),
+    (4002, CloseReason::Banned),
+    (4003, CloseReason::NameTaken),
+];
+
+/// Phrases in an `Error` frame that mean the server is about to close on
+/// us for good, matched case-insensitively. Other errors leave the session
+/// up.
+const TERMINAL_ERRORS: &[(&str, CloseReason)] = &[
+    ("kicked", CloseReason::Kicked),
+    ("banned", CloseReason::Banned),
+    ("name taken", CloseReason::NameTaken),
+    ("name already taken", CloseReason::NameTaken),
+    ("name already in use", CloseReason::NameTaken),
+];
+
+/// Why the server closed with `code`, if reconnecting is pointless.
+pub fn terminal_close(code: u16) -> Option<CloseReason> {
+    TERMINAL_CLOSE_CODES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, reason)| *reason)
+}
+
+/// Why the server is ending the session, if `error` (the text of an
+/// `Error` frame) says it is.
+pub fn terminal_error(error: &str) -> Option<CloseReason> {
+    let error = error.to_lowercase();
+    TERMINAL_ERRORS
+        .iter()
+        .find(|(phrase, _)| error.contains(phrase))
+        .map(|(_, reason)| *reason)
+}
+
+/// Lifecycle of the socket, as broadcast over the [`EventBus`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnState {
+    /// First connection attempt is in flight.
+    Connecting,
+    Open,
+    /// The socket is gone and no further attempts will be made.
+    Closed,
+    /// The connection dropped and a new attempt is pending.
+    Reconnecting,
+}
+
+/// Checks that `url` looks like a websocket address and returns the trimmed
+/// value. Accepted are `ws://host`, `wss://host`, a bare `host` (scheme taken
+/// from the page) and a path such as `/ws` on the page's own host.
+pub fn validate_ws_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    if url.contains(char::is_whitespace) {
+        return Err("Server URL must not contain spaces".to_string());
+    }
+    if url.starts_with('/') && !url.starts_with("//") {
+        return Ok(url.to_string());
+    }
+
+    let rest = match url.split_once("://") {
+        Some(("ws" | "wss", rest)) => rest,
+        Some(_) => return Err("Server URL must use ws:// or wss://".to_string()),
+        None => url.trim_start_matches("//"),
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if host.is_empty() {
+        return Err("Server URL is missing a host".to_string());
+    }
+
+    Ok(url.to_string())
+}
+
+/// Turns a validated server address into the absolute URL to open from a
+/// page served with `page_protocol` (`"http:"`, `"https:"`) by `page_host`.
+/// A scheme in `url` is kept as is; otherwise `wss://` is used on https pages
+/// and `ws://` everywhere else, and paths are resolved against `page_host`.
+pub fn absolute_ws_url(url: &str, page_protocol: &str, page_host: &str) -> String {
+    if url.contains("://") {
+        return url.to_string();
+    }
+    let scheme = if page_protocol == "https:" {
+        "wss"
+    } else {
+        "ws"
+    };
+    match url.strip_prefix("//") {
+        Some(rest) => format!("{}://{}", scheme, rest),
+        None if url.starts_with('/') => format!("{}://{}{}", scheme, page_host, url),
+        None => format!("{}://{}", scheme, url),
+    }
+}
 
-use crate::services::event_bus::{EventBus, Request};
+/// [`absolute_ws_url`] for the page we are running in.
+fn page_ws_url(url: &str) -> String {
+    let location = web_sys::window().map(|w| w.location());
+    let protocol = location.as_ref().and_then(|l| l.protocol().ok());
+    let host = location.as_ref().and_then(|l| l.host().ok());
+    absolute_ws_url(
+        url,
+        protocol.as_deref().unwrap_or_default(),
+        host.as_deref().unwrap_or_default(),
+    )
+}
+
+/// Exponential backoff for the `attempt`th reconnect (starting at 1).
+pub fn reconnect_delay_ms(attempt: u32) -> u32 {
+    1_000u32
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_RECONNECT_DELAY_MS)
+}
+
+/// A bare frame of `message_type`, for heartbeats.
+fn heartbeat(message_type: MsgTypes) -> WebSocketMessage {
+    WebSocketMessage::new(message_type, None)
+}
+
+fn to_message(frame: Frame) -> Message {
+    match frame {
+        Frame::Text(text) => Message::Text(text),
+        Frame::Binary(bytes) => Message::Bytes(bytes),
+    }
+}
+
+/// Why [`Connection::pump`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stopped {
+    /// Every sender is gone; we hung up.
+    Dropped,
+    /// The socket was closed from the other end.
+    Closed,
+    /// The server ended the session in a way reconnecting can't undo.
+    Refused(CloseReason),
+    /// Nothing arrived for [`MISSED_HEARTBEATS`] intervals.
+    TimedOut,
+}
+
+/// Frames sent while the socket was not open, waiting to be flushed in order.
+pub struct OutboundQueue {
+    frames: VecDeque<WebSocketMessage>,
+    cap: usize,
+}
+
+impl OutboundQueue {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            cap,
+        }
+    }
+
+    /// Appends `frame`, returning `false` (and dropping it) if the queue is
+    /// already full.
+    pub fn push(&mut self, frame: WebSocketMessage) -> bool {
+        if self.frames.len() >= self.cap {
+            log::warn!("outbound queue full, dropping frame: {:?}", frame);
+            #[cfg(feature = "frame-log")]
+            frame_log::count(|c| &mut c.dropped);
+            return false;
+        }
+        self.frames.push_back(frame);
+        true
+    }
+
+    /// Queues `frame` if it [outlasts disconnects](outlasts_disconnects).
+    /// Fails with [`SendError::Closed`] if it doesn't and with
+    /// [`SendError::Full`] if there is no room left.
+    pub fn hold(&mut self, frame: WebSocketMessage) -> Result<(), SendError> {
+        if !outlasts_disconnects(&frame.message_type) {
+            log::debug!("not connected, dropping frame: {:?}", frame);
+            return Err(SendError::Closed);
+        }
+        if self.push(frame) {
+            Ok(())
+        } else {
+            Err(SendError::Full)
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<WebSocketMessage> {
+        self.frames.pop_front()
+    }
+}
 
 pub struct WebsocketService {
-    pub tx: Sender<String>,
+    tx: Sender<WebSocketMessage>,
+    state: Rc<Cell<ConnState>>,
+    handshake: Rc<RefCell<Vec<WebSocketMessage>>>,
+    /// Shared with the connection task, which flushes it on every open.
+    queue: Rc<RefCell<OutboundQueue>>,
 }
 
 impl WebsocketService {
-    pub fn new() -> Self {
-        let ws = WebSocket::open("ws://127.0.0.1:8080").unwrap();
+    /// Connects to `url`, encoding frames as `format`. Without one, frames
+    /// go out as JSON until the server's first frame shows what it speaks,
+    /// on every connection.
+    pub fn new(url: &str, format: Option<WireFormat>) -> Result<Self, String> {
+        let url = page_ws_url(&validate_ws_url(url)?);
+        let ws =
+            WebSocket::open(&url).map_err(|e| format!("Could not connect to {}: {}", url, e))?;
+
+        let (in_tx, in_rx) = futures::channel::mpsc::channel::<WebSocketMessage>(1000);
+        let state = Rc::new(Cell::new(ConnState::Connecting));
+        let handshake = Rc::new(RefCell::new(Vec::new()));
+        let queue = Rc::new(RefCell::new(OutboundQueue::new(OUTBOUND_QUEUE_CAP)));
+        let connection = Connection {
+            url,
+            state: state.clone(),
+            handshake: handshake.clone(),
+            queue: queue.clone(),
+            event_bus: EventBus::dispatcher(),
+            format: format.unwrap_or(WireFormat::Json),
+            negotiate: format.is_none(),
+        };
 
-        let (mut write, mut read) = ws.split();
+        spawn_local(connection.run(ws, in_rx));
 
-        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<String>(1000);
-        let mut event_bus = EventBus::dispatcher();
+        Ok(Self {
+            tx: in_tx,
+            state,
+            handshake,
+            queue,
+        })
+    }
+}
 
-        spawn_local(async move {
-            while let Some(s) = in_rx.next().await {
-                log::debug!("got event from channel! {}", s);
-                write.send(Message::Text(s)).await.unwrap();
+impl MessageTransport for WebsocketService {
+    /// Sends `frame` if the socket is open. Otherwise it is
+    /// [held](OutboundQueue::hold) until the socket opens again, so a full
+    /// queue or a frame not worth holding fails right away.
+    fn send(&self, frame: WebSocketMessage) -> Result<(), SendError> {
+        match self.state.get() {
+            ConnState::Open => {}
+            ConnState::Closed => return Err(SendError::Closed),
+            ConnState::Connecting | ConnState::Reconnecting => {
+                return self.queue.borrow_mut().hold(frame);
             }
-        });
+        }
+        self.tx.clone().try_send(frame).map_err(|e| {
+            #[cfg(feature = "frame-log")]
+            frame_log::count(|c| &mut c.send_failures);
+            if e.is_full() {
+                SendError::Full
+            } else {
+                SendError::Closed
+            }
+        })
+    }
 
-        spawn_local(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(data)) => {
-                        log::debug!("from websocket: {}", data);
-                        event_bus.send(Request::EventBusMsg(data));
+    fn state(&self) -> ConnState {
+        self.state.get()
+    }
+
+    /// Sets the frames sent first every time the socket opens, ahead of
+    /// anything queued while it was down.
+    fn set_handshake(&self, frames: Vec<WebSocketMessage>) {
+        *self.handshake.borrow_mut() = frames;
+    }
+
+    /// Hangs up. Frames already sent are still written, then the socket is
+    /// closed normally (code 1000) and the connection task ends instead of
+    /// reconnecting. Dropping the service has the same effect.
+    fn close(self: Box<Self>) {
+        // The task notices once every sender is gone.
+        drop(self.tx);
+    }
+}
+
+/// State owned by the background task driving the socket.
+struct Connection {
+    url: String,
+    state: Rc<Cell<ConnState>>,
+    handshake: Rc<RefCell<Vec<WebSocketMessage>>>,
+    queue: Rc<RefCell<OutboundQueue>>,
+    event_bus: Dispatcher<EventBus>,
+    /// How frames are encoded, as configured or last negotiated.
+    format: WireFormat,
+    /// Whether `format` follows the server's first frame on each connection.
+    negotiate: bool,
+}
+
+impl Connection {
+    fn set_state(&mut self, state: ConnState) {
+        if self.state.replace(state) != state {
+            self.event_bus.send(ChatEvent::ConnectionChanged(state));
+        }
+    }
+
+    /// Tells subscribers why the connection is not working.
+    fn report(&mut self, error: String) {
+        log::error!("ws: {}", error);
+        self.event_bus.send(ChatEvent::ConnectionError(error));
+    }
+
+    /// Keeps a socket open to `self.url`, reconnecting with backoff whenever
+    /// it drops. Returns once the sending half has been dropped, or the
+    /// server refused us for good.
+    async fn run(mut self, mut ws: WebSocket, mut in_rx: Receiver<WebSocketMessage>) {
+        let mut attempt = 0;
+        self.set_state(ConnState::Connecting);
+
+        loop {
+            let opened = match self
+                .buffer_while(wait_until_open(&mut ws), &mut in_rx)
+                .await
+            {
+                Some(opened) => opened,
+                None => return,
+            };
+            if opened {
+                attempt = 0;
+                self.set_state(ConnState::Open);
+                let (write, read) = ws.split();
+                match self.pump(write, read, &mut in_rx).await {
+                    Stopped::Dropped => return,
+                    Stopped::Refused(reason) => {
+                        log::warn!("ws: server ended the session: {:?}", reason);
+                        self.event_bus.send(ChatEvent::Refused(reason));
+                        self.set_state(ConnState::Closed);
+                        return;
                     }
-                    Ok(Message::Bytes(b)) => {
-                        let decoded = std::str::from_utf8(&b);
-                        if let Ok(val) = decoded {
-                            log::debug!("from websocket: {}", val);
-                            event_bus.send(Request::EventBusMsg(val.into()));
+                    Stopped::Closed | Stopped::TimedOut => {}
+                }
+            } else {
+                self.report(format!("Could not reach the server at {}", self.url));
+            }
+
+            attempt += 1;
+            #[cfg(feature = "frame-log")]
+            frame_log::count(|c| &mut c.reconnects);
+            // Sent while we were open but never written; they go ahead of
+            // anything sent from now on, which is queued directly.
+            while let Ok(frame) = in_rx.try_recv() {
+                let _ = self.queue.borrow_mut().hold(frame);
+            }
+            self.set_state(ConnState::Reconnecting);
+            let delay = TimeoutFuture::new(reconnect_delay_ms(attempt));
+            if self.buffer_while(delay, &mut in_rx).await.is_none() {
+                return;
+            }
+
+            ws = match WebSocket::open(&self.url) {
+                Ok(ws) => ws,
+                Err(e) => {
+                    self.report(format!("Could not connect to {}: {}", self.url, e));
+                    self.set_state(ConnState::Closed);
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Drives `fut` to completion while queueing anything sent in the
+    /// meantime. Returns `None` if the sending half was dropped first.
+    async fn buffer_while<F: Future>(
+        &mut self,
+        fut: F,
+        in_rx: &mut Receiver<WebSocketMessage>,
+    ) -> Option<F::Output> {
+        let queue = &self.queue;
+        let buffering = async {
+            while let Some(frame) = in_rx.next().await {
+                let _ = queue.borrow_mut().hold(frame);
+            }
+        };
+
+        futures::pin_mut!(fut, buffering);
+        match future::select(fut, buffering).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+
+    /// Forwards frames in both directions until the socket closes, starting
+    /// with the handshake and whatever was queued while offline. A ping goes
+    /// out every [`HEARTBEAT_INTERVAL_MS`] and pings from the server are
+    /// answered here. Returns why it stopped. If nothing can be sent
+    /// anymore, or the server announced it is ending the session, the
+    /// socket is closed normally; a socket that went quiet is closed too.
+    async fn pump(
+        &mut self,
+        mut write: SplitSink<WebSocket, Message>,
+        mut read: SplitStream<WebSocket>,
+        in_rx: &mut Receiver<WebSocketMessage>,
+    ) -> Stopped {
+        let event_bus = &mut self.event_bus;
+        let queue = &self.queue;
+        let handshake = self.handshake.borrow().clone();
+        let format = Cell::new(self.format);
+        let negotiated = Cell::new(!self.negotiate);
+        // Heartbeat frames, sent ahead of anything else waiting.
+        let (control_tx, mut control_rx) = mpsc::unbounded::<WebSocketMessage>();
+        let last_seen = Cell::new(time::now());
+
+        let writer = async {
+            let encode = |frame: WebSocketMessage| {
+                let encoded = format.get().codec().encode(&frame);
+                #[cfg(feature = "frame-log")]
+                frame_log::record(Traffic::Out, encoded.byte_len(), Ok(&frame.message_type));
+                to_message(encoded)
+            };
+            for frame in handshake
+                .into_iter()
+                .chain(std::iter::from_fn(|| queue.borrow_mut().pop()))
+            {
+                if let Err(e) = write.send(encode(frame)).await {
+                    log::error!("ws: {:?}", e);
+                    #[cfg(feature = "frame-log")]
+                    frame_log::count(|c| &mut c.send_failures);
+                }
+            }
+            while let (Some(frame), _) = future::select(control_rx.next(), in_rx.next())
+                .await
+                .factor_first()
+            {
+                log::debug!("got event from channel! {:?}", frame);
+                if let Err(e) = write.send(encode(frame)).await {
+                    log::error!("ws: {:?}", e);
+                    #[cfg(feature = "frame-log")]
+                    frame_log::count(|c| &mut c.send_failures);
+                }
+            }
+        };
+
+        // Resolves to why the server ended the session, if it said.
+        let reader = async {
+            while let Some(msg) = read.next().await {
+                let data = match msg {
+                    Ok(Message::Text(data)) => Frame::Text(data),
+                    Ok(Message::Bytes(bytes)) => Frame::Binary(bytes),
+                    Err(WebSocketError::ConnectionClose(close)) => {
+                        log::debug!("ws: closed with {} {:?}", close.code, close.reason);
+                        if let Some(reason) = terminal_close(close.code) {
+                            return Some(reason);
                         }
+                        continue;
                     }
                     Err(e) => {
-                        log::error!("ws: {:?}", e)
+                        log::error!("ws: {:?}", e);
+                        continue;
+                    }
+                };
+                log::debug!("from websocket: {:?}", data);
+                last_seen.set(time::now());
+                if !negotiated.replace(true) {
+                    format.set(WireFormat::detect(&data));
+                    log::debug!("ws: server speaks {:?}", format.get());
+                }
+                #[cfg(feature = "frame-log")]
+                let bytes = data.byte_len();
+                let decoded = format.get().codec().decode(data);
+                #[cfg(feature = "frame-log")]
+                frame_log::record(
+                    Traffic::In,
+                    bytes,
+                    decoded
+                        .as_ref()
+                        .map(|f| &f.message_type)
+                        .map_err(|e| e.to_string()),
+                );
+                let frame = match decoded {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        let error = format!("Could not read a frame from the server: {}", e);
+                        event_bus.send(ChatEvent::ProtocolError(error));
+                        continue;
                     }
+                };
+                if frame.message_type == MsgTypes::Ping {
+                    let _ = control_tx.unbounded_send(heartbeat(MsgTypes::Pong));
+                    continue;
+                }
+                // Said right before the server closes; no need to wait.
+                if frame.message_type == MsgTypes::Error {
+                    if let Some(reason) = frame.data.as_deref().and_then(terminal_error) {
+                        return Some(reason);
+                    }
+                }
+                if let Some(event) = ChatEvent::from_frame(frame) {
+                    event_bus.send(event);
                 }
             }
             log::debug!("WebSocket Closed");
-        });
+            None
+        };
+
+        // Dropping this future (when the pump stops) cancels its timer.
+        let heartbeat = async {
+            let timeout = u64::from(HEARTBEAT_INTERVAL_MS) * MISSED_HEARTBEATS;
+            loop {
+                TimeoutFuture::new(HEARTBEAT_INTERVAL_MS).await;
+                // Don't keep a mobile radio awake for a tab nobody looks at;
+                // the server may drop us, and we reconnect once visible.
+                if page_hidden() {
+                    last_seen.set(time::now());
+                    continue;
+                }
+                if time::now().saturating_sub(last_seen.get()) >= timeout {
+                    log::warn!("ws: no traffic for {} ms, reconnecting", timeout);
+                    return;
+                }
+                let _ = control_tx.unbounded_send(heartbeat(MsgTypes::Ping));
+            }
+        };
+
+        let stopped = {
+            futures::pin_mut!(writer, reader, heartbeat);
+            match future::select(writer, future::select(reader, heartbeat)).await {
+                Either::Left(_) => Stopped::Dropped,
+                Either::Right((Either::Left((Some(reason), _)), _)) => Stopped::Refused(reason),
+                Either::Right((Either::Left((None, _)), _)) => Stopped::Closed,
+                Either::Right((Either::Right(_), _)) => Stopped::TimedOut,
+            }
+        };
+        self.format = format.get();
+        let code = match stopped {
+            Stopped::Dropped | Stopped::Refused(_) => NORMAL_CLOSURE,
+            Stopped::TimedOut => HEARTBEAT_TIMEOUT_CLOSURE,
+            Stopped::Closed => return stopped,
+        };
+        if let Ok(ws) = write.reunite(read) {
+            if let Err(e) = ws.close(Some(code), None) {
+                log::error!("ws: {:?}", e);
+            }
+        }
+        stopped
+    }
+}
+
+/// Waits for `ws` to leave the connecting state, returning whether it opened.
+/// Any error or close event that arrives first means the attempt failed.
+async fn wait_until_open(ws: &mut WebSocket) -> bool {
+    let ready = future::poll_fn(|cx| {
+        if Pin::new(&mut *ws).poll_next(cx).is_ready() {
+            return Poll::Ready(false);
+        }
+        Pin::new(&mut *ws).poll_ready(cx).map(|r| r.is_ok())
+    })
+    .await;
+
+    ready && matches!(ws.state(), State::Open)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepted_server_urls() {
+        for (url, validated) in [
+            ("ws://chat.example", "ws://chat.example"),
+            (
+                "wss://chat.example:8443/ws?room=a",
+                "wss://chat.example:8443/ws?room=a",
+            ),
+            ("  wss://chat.example  ", "wss://chat.example"),
+            ("127.0.0.1:8080", "127.0.0.1:8080"),
+            ("//chat.example/ws", "//chat.example/ws"),
+            ("/ws", "/ws"),
+        ] {
+            assert_eq!(validate_ws_url(url).as_deref(), Ok(validated), "{}", url);
+        }
+    }
+
+    #[test]
+    fn rejected_server_urls() {
+        for url in [
+            "http://chat.example",
+            "javascript://chat.example",
+            "wss://",
+            "wss:///ws",
+            "//",
+            "",
+            "wss://chat example",
+        ] {
+            assert!(validate_ws_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn refusals_stop_reconnecting() {
+        assert_eq!(terminal_close(4001), Some(CloseReason::Kicked));
+        assert_eq!(terminal_close(4002), Some(CloseReason::Banned));
+        assert_eq!(terminal_close(4003), Some(CloseReason::NameTaken));
+    }
+
+    #[test]
+    fn other_closes_are_retried() {
+        // Normal, going away, abnormal, internal error, restart, and the
+        // application codes either side of ours.
+        for code in [1000, 1001, 1006, 1011, 1012, 3000, 4000, 4004, 4999] {
+            assert_eq!(terminal_close(code), None, "{}", code);
+        }
+    }
+
+    #[test]
+    fn error_frames_announcing_the_end() {
+        let cases = [
+            ("You were kicked by an admin", Some(CloseReason::Kicked)),
+            ("BANNED for spam", Some(CloseReason::Banned)),
+            ("Name taken", Some(CloseReason::NameTaken)),
+            ("Name already in use", Some(CloseReason::NameTaken)),
+            ("name already taken: alice", Some(CloseReason::NameTaken)),
+            ("Message too long", None),
+            ("Rate limited, slow down", None),
+            ("Unknown room", None),
+            ("", None),
+        ];
+        for (error, reason) in cases {
+            assert_eq!(terminal_error(error), reason, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn reconnects_back_off_up_to_a_cap() {
+        let delays: Vec<u32> = (1..=7).map(reconnect_delay_ms).collect();
+        assert_eq!(delays, [1_000, 2_000, 4_000, 8_000, 16_000, 30_000, 30_000]);
+        assert_eq!(reconnect_delay_ms(0), 1_000);
+        assert_eq!(reconnect_delay_ms(100), MAX_RECONNECT_DELAY_MS);
+        assert_eq!(reconnect_delay_ms(u32::MAX), MAX_RECONNECT_DELAY_MS);
+    }
+
+    #[test]
+    fn server_urls_follow_the_page_scheme() {
+        // (configured, on an http page, on an https page)
+        let cases = [
+            (
+                "ws://chat.example/ws",
+                "ws://chat.example/ws",
+                "ws://chat.example/ws",
+            ),
+            (
+                "wss://chat.example/ws",
+                "wss://chat.example/ws",
+                "wss://chat.example/ws",
+            ),
+            (
+                "//chat.example/ws",
+                "ws://chat.example/ws",
+                "wss://chat.example/ws",
+            ),
+            (
+                "chat.example:8080/ws",
+                "ws://chat.example:8080/ws",
+                "wss://chat.example:8080/ws",
+            ),
+            (
+                "/ws",
+                "ws://page.example:3000/ws",
+                "wss://page.example:3000/ws",
+            ),
+            (
+                "/ws?room=a#b",
+                "ws://page.example:3000/ws?room=a#b",
+                "wss://page.example:3000/ws?room=a#b",
+            ),
+        ];
+        for (url, http, https) in cases {
+            assert_eq!(
+                absolute_ws_url(url, "http:", "page.example:3000"),
+                http,
+                "{}",
+                url
+            );
+            assert_eq!(
+                absolute_ws_url(url, "https:", "page.example:3000"),
+                https,
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn pages_without_a_web_scheme_use_plain_websockets() {
+        for protocol in ["file:", ""] {
+            assert_eq!(
+                absolute_ws_url("//chat.example", protocol, ""),
+                "ws://chat.example",
+                "{:?}",
+                protocol
+            );
+        }
+    }
+
+    #[test]
+    fn validated_urls_resolve_to_websockets() {
+        for url in [
+            "wss://chat.example",
+            "//chat.example",
+            "chat.example",
+            "/ws",
+        ] {
+            let validated = validate_ws_url(url).unwrap();
+            for protocol in ["http:", "https:"] {
+                let absolute = absolute_ws_url(&validated, protocol, "page.example");
+                assert!(
+                    absolute.starts_with("ws://") || absolute.starts_with("wss://"),
+                    "{} on {}: {}",
+                    url,
+                    protocol,
+                    absolute
+                );
+            }
+        }
+    }
+
+    fn numbered(n: usize) -> WebSocketMessage {
+        WebSocketMessage::new(MsgTypes::Message, Some(n.to_string()))
+    }
+
+    fn drain(queue: &mut OutboundQueue) -> Vec<String> {
+        std::iter::from_fn(|| queue.pop())
+            .filter_map(|frame| frame.data)
+            .collect()
+    }
+
+    #[test]
+    fn the_outbound_queue_keeps_order() {
+        let mut queue = OutboundQueue::new(OUTBOUND_QUEUE_CAP);
+        for n in 0..5 {
+            assert!(queue.push(numbered(n)));
+        }
+        assert_eq!(drain(&mut queue), ["0", "1", "2", "3", "4"]);
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn the_outbound_queue_drops_what_does_not_fit() {
+        let mut queue = OutboundQueue::new(OUTBOUND_QUEUE_CAP);
+        for n in 0..OUTBOUND_QUEUE_CAP {
+            assert!(queue.push(numbered(n)));
+        }
+        assert!(!queue.push(numbered(OUTBOUND_QUEUE_CAP)));
+
+        let flushed = drain(&mut queue);
+        assert_eq!(flushed.len(), OUTBOUND_QUEUE_CAP);
+        assert_eq!(flushed.first().map(String::as_str), Some("0"));
+        assert_eq!(flushed.last(), Some(&(OUTBOUND_QUEUE_CAP - 1).to_string()));
+
+        // Room again once flushed.
+        assert!(queue.push(numbered(0)));
+    }
+
+    #[test]
+    fn only_messages_outlast_disconnects() {
+        for message_type in [
+            MsgTypes::Message,
+            MsgTypes::Private,
+            MsgTypes::File,
+            MsgTypes::Edit,
+            MsgTypes::Delete,
+            MsgTypes::Reaction,
+        ] {
+            assert!(outlasts_disconnects(&message_type), "{:?}", message_type);
+        }
+        for message_type in [
+            MsgTypes::Typing,
+            MsgTypes::Delivered,
+            MsgTypes::Read,
+            MsgTypes::Status,
+            MsgTypes::Register,
+            MsgTypes::Join,
+            MsgTypes::Leave,
+            MsgTypes::Ping,
+            MsgTypes::Pong,
+        ] {
+            assert!(!outlasts_disconnects(&message_type), "{:?}", message_type);
+        }
+    }
 
-        Self { tx: in_tx }
+    #[test]
+    fn holding_refuses_control_frames_and_overflow() {
+        let mut queue = OutboundQueue::new(2);
+        let typing = WebSocketMessage::new(MsgTypes::Typing, None);
+        assert!(matches!(queue.hold(typing), Err(SendError::Closed)));
+        assert!(queue.hold(numbered(0)).is_ok());
+        assert!(queue.hold(numbered(1)).is_ok());
+        assert!(matches!(queue.hold(numbered(2)), Err(SendError::Full)));
+        assert_eq!(drain(&mut queue), ["0", "1"]);
     }
 }
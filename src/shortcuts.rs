@@ -0,0 +1,59 @@
+//! Document-wide keyboard shortcuts for the chat view.
+
+/// An action triggered from the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shortcut {
+    /// Close the topmost open panel, or clear the message input.
+    Dismiss,
+    /// Move focus to the message input.
+    FocusInput,
+    /// Show or hide the shortcut cheatsheet.
+    ToggleHelp,
+    /// Open the conversation before or after the current one: rooms in
+    /// sidebar order, then direct conversations.
+    CycleConversation(Direction),
+    /// Scroll the transcript by about a screenful.
+    ScrollPage(Direction),
+    /// Show or hide the record of websocket traffic.
+    #[cfg(feature = "frame-log")]
+    ToggleFrameLog,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// Key combinations listed in the cheatsheet, in display order, with the
+/// locale key describing each.
+pub const CHEATSHEET: &[(&str, &str)] = &[
+    ("Ctrl+K", "shortcuts.focus_input"),
+    ("Esc", "shortcuts.dismiss"),
+    ("Alt+↑ / Alt+↓", "shortcuts.cycle_conversation"),
+    ("PgUp / PgDn", "shortcuts.scroll_page"),
+    ("Ctrl+/", "shortcuts.toggle_help"),
+];
+
+/// Maps a keydown to a shortcut. `ctrl` also stands for Cmd. `typing` is
+/// true when the event target is a text field, in which case plain keys
+/// are left to it except the ones that cannot be typed (Escape, PageUp and
+/// PageDown).
+pub fn shortcut_for(key: &str, ctrl: bool, alt: bool, typing: bool) -> Option<Shortcut> {
+    match (key, ctrl, alt) {
+        ("Escape", false, false) => Some(Shortcut::Dismiss),
+        ("PageUp", false, false) => Some(Shortcut::ScrollPage(Direction::Up)),
+        ("PageDown", false, false) => Some(Shortcut::ScrollPage(Direction::Down)),
+        ("k" | "K", true, false) => Some(Shortcut::FocusInput),
+        ("/", true, false) => Some(Shortcut::ToggleHelp),
+        // Ctrl+Shift+D; not listed, it is for debugging servers.
+        #[cfg(feature = "frame-log")]
+        ("D", true, false) => Some(Shortcut::ToggleFrameLog),
+        ("ArrowUp", false, true) => Some(Shortcut::CycleConversation(Direction::Up)),
+        ("ArrowDown", false, true) => Some(Shortcut::CycleConversation(Direction::Down)),
+        _ if typing || ctrl || alt => None,
+        ("/", _, _) => Some(Shortcut::FocusInput),
+        ("?", _, _) => Some(Shortcut::ToggleHelp),
+        _ => None,
+    }
+}
@@ -0,0 +1,1882 @@
+//! What the chat knows about users and conversations, and how events from
+//! the server change it. Nothing here touches the DOM or the socket: changes
+//! that need the outside world are returned as [`Effect`]s for the component
+//! to carry out.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::filter::WordFilter;
+use crate::i18n::I18n;
+use crate::input_history::{InputHistory, INPUT_HISTORY_CAP};
+use crate::messages::{
+    client_id, excerpt, ChatMessage, MessageData, MessageId, MessageStore, Receipts, SeenIds,
+    SendStatus, DEFAULT_MAX_MESSAGES, QUOTE_EXCERPT_CHARS,
+};
+use crate::protocol::{
+    AvatarChoice, DeleteData, EditData, FileData, MsgTypes, Presence, ReactionData, ReceiptData,
+    TypingData, UserEntry, WebSocketMessage,
+};
+use crate::search::find_ignore_case;
+use crate::services::event_bus::ChatEvent;
+use crate::services::websocket::{CloseReason, ConnState};
+use crate::throttle::{Decision, RateLimiter};
+use crate::toasts::{ErrorKind, Toasts};
+
+/// Room every user joins on registering. Messages that don't name a room
+/// belong here.
+pub const DEFAULT_ROOM: &str = "lobby";
+
+/// Our own messages not echoed back by the server within this time are
+/// shown as failed.
+pub const PENDING_TIMEOUT_MS: u64 = 5_000;
+
+/// Messages asked for per page when scrolling back through history.
+pub const HISTORY_PAGE_SIZE: usize = 50;
+
+/// Incoming messages kept in the screen reader announcer at once.
+pub const ANNOUNCED_MESSAGES: usize = 5;
+
+/// Chat messages allowed per [`RATE_WINDOW_MS`]; more are held back so the
+/// server's flood protection doesn't kick us. Control frames (typing,
+/// receipts, reactions) are not counted.
+pub const MESSAGES_PER_WINDOW: usize = 5;
+pub const RATE_WINDOW_MS: u64 = 10_000;
+
+/// While typing, a notice is sent at most this often.
+pub const TYPING_SEND_INTERVAL_MS: u64 = 3_000;
+
+/// A message pinned to the strip under the header. What it said is kept
+/// with it, so the strip still reads well once the message itself is gone
+/// from memory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pin {
+    pub id: MessageId,
+    pub conversation: ConversationId,
+    pub from: String,
+    pub excerpt: String,
+}
+
+/// Which transcript a message belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConversationId {
+    /// A named room.
+    Room(String),
+    /// Direct messages with the named user.
+    Direct(String),
+}
+
+impl ConversationId {
+    /// The `to` and `room` fields addressing a frame to this conversation.
+    pub fn target(&self) -> (Option<String>, Option<String>) {
+        match self {
+            ConversationId::Room(room) => (None, Some(room.clone())),
+            ConversationId::Direct(peer) => (Some(peer.clone()), None),
+        }
+    }
+}
+
+/// Messages exchanged in one conversation.
+#[derive(Default)]
+pub struct Conversation {
+    pub messages: MessageStore,
+    /// Messages received while another conversation was open.
+    pub unread: usize,
+    /// A page of older history has been asked for and not yet arrived.
+    pub loading_older: bool,
+    /// The server has nothing older than what we hold.
+    pub reached_start: bool,
+    /// First message that arrived while the window was in the background,
+    /// above which the "new messages" line goes.
+    pub new_since: Option<MessageId>,
+}
+
+#[derive(Clone)]
+pub struct UserProfile {
+    pub name: String,
+    pub avatar: String,
+    pub departing: bool,
+}
+
+/// Puts `me` first and everyone else in alphabetical order, ignoring case
+/// (and then by exact spelling, so the order never depends on the server's).
+pub fn sort_users(users: &mut [UserProfile], me: &str) {
+    users.sort_by(|a, b| {
+        (a.name != me)
+            .cmp(&(b.name != me))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+/// The users whose name contains `query`, ignoring case, each with the byte
+/// range of the match for highlighting. An empty query matches everyone.
+pub fn filter_users<'a>(
+    users: &'a [UserProfile],
+    query: &str,
+) -> Vec<(&'a UserProfile, Range<usize>)> {
+    users
+        .iter()
+        .filter_map(|u| Some((u, find_ignore_case(&u.name, query)?)))
+        .collect()
+}
+
+/// Frames from the server this client couldn't use, for the diagnostics in
+/// the settings panel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolStats {
+    /// Frames of types this client doesn't know.
+    pub unknown: usize,
+    /// Frames that couldn't be read.
+    pub malformed: usize,
+    /// What was wrong with the latest of either.
+    pub last: Option<String>,
+}
+
+/// Something the component has to do after the state changed.
+#[derive(Debug)]
+pub enum Effect {
+    SendFrame(Box<WebSocketMessage>),
+    /// Something visible changed.
+    Render,
+    ScrollToBottom,
+    /// Messages were inserted above what the user is reading; keep their
+    /// place.
+    KeepScrollPosition,
+    /// The server echoed our message, so it no longer needs retrying.
+    Confirmed(MessageId),
+    /// Someone else wrote to us, for a notification if the tab is hidden.
+    Notify {
+        from: String,
+        text: String,
+    },
+    /// Someone else wrote while the latest messages were out of view.
+    PlaySound,
+    /// [`ChatState::title_unread`] changed.
+    UpdateTitle,
+    /// The user left; remove them once they have faded out.
+    UserDeparted(String),
+    /// A departing user came back before being removed.
+    UserReturned(String),
+    /// The server accepted our new name.
+    Renamed(String),
+    /// The server refused to register us.
+    RejectLogin(String),
+    /// Pins were updated or dropped to match their messages.
+    PinsChanged,
+    ShowError(ErrorKind),
+}
+
+pub struct ChatState {
+    pub username: String,
+    pub users: Vec<UserProfile>,
+    /// Whether a user list has arrived since the socket last opened.
+    pub users_seeded: bool,
+    /// Most users online at once this session.
+    pub peak_users: usize,
+    /// When each listed user first appeared in a user list.
+    pub first_seen: HashMap<String, u64>,
+    /// Avatar we picked at login, sent when registering.
+    pub own_avatar: Option<AvatarChoice>,
+    /// Name we asked the server to switch to, until a user list shows it.
+    pub pending_rename: Option<String>,
+    pub conversations: HashMap<ConversationId, Conversation>,
+    pub active: ConversationId,
+    /// Rooms we are a member of, in the order they were joined.
+    pub rooms: Vec<String>,
+    pub max_messages: usize,
+    pub seen_ids: SeenIds,
+    /// Bumped for every id we generate, so ids made in the same millisecond
+    /// stay distinct.
+    pub id_counter: u64,
+    /// Acknowledgements others sent for our messages.
+    pub receipts: HashMap<MessageId, Receipts>,
+    /// Pinned messages of every conversation, oldest pin first.
+    pub pins: Vec<Pin>,
+    /// Users whose messages are collapsed and who are never heard typing or
+    /// pinging.
+    pub muted: HashSet<String>,
+    /// Messages from muted users opened up anyway.
+    pub shown_muted: HashSet<MessageId>,
+    /// Statuses users last sent. Anyone missing is online.
+    pub presence: HashMap<String, Presence>,
+    /// Messages from others we haven't told the sender we've seen yet.
+    pub unread_receipts: HashMap<ConversationId, Vec<MessageId>>,
+    /// Reactions by message id, then emoji, listing who reacted.
+    pub reactions: HashMap<MessageId, HashMap<String, Vec<String>>>,
+    /// Messages we got reactions for before the message itself, and when.
+    pub orphan_reactions: HashMap<MessageId, u64>,
+    /// Deletes that arrived before their message: who sent them and when.
+    pub tombstones: HashMap<MessageId, (String, u64)>,
+    /// Who is typing, and when we last heard so.
+    pub typing: HashMap<String, u64>,
+    /// Whether the latest messages of the open conversation are on screen.
+    pub at_bottom: bool,
+    pub window_focused: bool,
+    /// Messages that arrived in the open conversation while scrolled up.
+    pub unseen: usize,
+    /// Messages that arrived while the window was in the background.
+    pub title_unread: usize,
+    pub protocol_stats: ProtocolStats,
+    /// Protocol version of a server too new for this client, once one was
+    /// heard from.
+    pub unsupported_version: Option<u8>,
+    /// Why the server ended our session, once it has for good.
+    pub refused: Option<CloseReason>,
+    pub conn_state: ConnState,
+    /// Why the last connection attempt failed, until one succeeds.
+    pub conn_error: Option<String>,
+    /// Frames of our messages not yet echoed back, kept for retrying.
+    pub outgoing: HashMap<MessageId, WebSocketMessage>,
+    pub rate_limiter: RateLimiter,
+    /// Our messages held back by the rate limiter, in sending order.
+    pub throttled: VecDeque<MessageId>,
+    /// Pieces of an over-long paste still to be sent, in order.
+    pub chunks: VecDeque<String>,
+    /// Seen messages waiting for the next batched Read frame.
+    pub read_batch: HashMap<ConversationId, Vec<MessageId>>,
+    /// When we last told the others we are typing.
+    pub typing_sent_at: Option<u64>,
+    /// Error notices up.
+    pub toasts: Toasts,
+    /// Latest incoming messages, read out by screen readers.
+    pub announcements: VecDeque<String>,
+    pub replying_to: Option<MessageId>,
+    /// Our own message whose text the input currently holds for editing.
+    pub editing: Option<MessageId>,
+    /// Our message whose delete button has been clicked once.
+    pub confirm_delete: Option<MessageId>,
+    /// What we sent, for recalling with the arrow keys.
+    pub sent: InputHistory,
+    /// Text typed and not sent, per conversation. Kept while editing a
+    /// message, and put back once the edit is done.
+    pub drafts: HashMap<ConversationId, String>,
+    pub notifications_enabled: bool,
+    pub sound_enabled: bool,
+    pub filter_enabled: bool,
+    /// Words added in the settings, as typed.
+    pub filter_words: Vec<String>,
+    pub word_filter: WordFilter,
+    /// Language the notices we write ourselves are in.
+    pub i18n: I18n,
+    /// Current time in milliseconds; replaceable so the state can be driven
+    /// outside a browser.
+    pub clock: fn() -> u64,
+    effects: Vec<Effect>,
+}
+
+impl ChatState {
+    pub fn new(username: String, clock: fn() -> u64) -> Self {
+        Self {
+            username,
+            users: Vec::new(),
+            users_seeded: false,
+            peak_users: 0,
+            first_seen: HashMap::new(),
+            own_avatar: None,
+            pending_rename: None,
+            conversations: HashMap::new(),
+            active: ConversationId::Room(DEFAULT_ROOM.to_string()),
+            rooms: vec![DEFAULT_ROOM.to_string()],
+            max_messages: DEFAULT_MAX_MESSAGES,
+            seen_ids: SeenIds::default(),
+            id_counter: 0,
+            receipts: HashMap::new(),
+            pins: Vec::new(),
+            muted: HashSet::new(),
+            shown_muted: HashSet::new(),
+            presence: HashMap::new(),
+            unread_receipts: HashMap::new(),
+            reactions: HashMap::new(),
+            orphan_reactions: HashMap::new(),
+            tombstones: HashMap::new(),
+            typing: HashMap::new(),
+            at_bottom: true,
+            window_focused: true,
+            unseen: 0,
+            title_unread: 0,
+            protocol_stats: ProtocolStats::default(),
+            unsupported_version: None,
+            refused: None,
+            conn_state: ConnState::Connecting,
+            conn_error: None,
+            outgoing: HashMap::new(),
+            rate_limiter: RateLimiter::new(MESSAGES_PER_WINDOW, RATE_WINDOW_MS),
+            throttled: VecDeque::new(),
+            chunks: VecDeque::new(),
+            read_batch: HashMap::new(),
+            typing_sent_at: None,
+            toasts: Toasts::default(),
+            announcements: VecDeque::new(),
+            replying_to: None,
+            editing: None,
+            confirm_delete: None,
+            sent: InputHistory::new(Vec::new(), INPUT_HISTORY_CAP),
+            drafts: HashMap::new(),
+            notifications_enabled: true,
+            sound_enabled: true,
+            filter_enabled: false,
+            filter_words: Vec::new(),
+            word_filter: WordFilter::new::<String>(&[]),
+            i18n: I18n::default(),
+            clock,
+            effects: Vec::new(),
+        }
+    }
+
+    /// Applies an event from the server and returns what has to happen as a
+    /// result, including anything left over from direct calls since the last
+    /// [`ChatState::take_effects`].
+    pub fn apply(&mut self, event: ChatEvent) -> Vec<Effect> {
+        if self.handle(event) {
+            self.effects.push(Effect::Render);
+        }
+        self.take_effects()
+    }
+
+    /// Effects queued by methods called outside of [`ChatState::apply`].
+    pub fn take_effects(&mut self) -> Vec<Effect> {
+        std::mem::take(&mut self.effects)
+    }
+
+    fn handle(&mut self, event: ChatEvent) -> bool {
+        match event {
+            ChatEvent::UsersUpdated(names) => self.update_users(names),
+            ChatEvent::MessageReceived {
+                mut message,
+                private,
+                to,
+                room,
+            } => {
+                if !message.id.is_empty() && !self.seen_ids.insert(&message.id) {
+                    log::debug!("dropping duplicate message {}", message.id);
+                    return false;
+                }
+                if message.timestamp == 0 {
+                    message.timestamp = (self.clock)();
+                }
+                // Only ids the sender knows are worth acknowledging.
+                let acknowledge = !message.id.is_empty() && message.from != self.username;
+                if message.id.is_empty() {
+                    message.id = self.next_id(&message.from, message.timestamp);
+                }
+                self.typing.remove(&message.from);
+                // Our own private messages come back addressed to the peer,
+                // or else go to whoever we sent them to; everyone else's are
+                // keyed by sender.
+                let conversation = match (private, to) {
+                    (true, Some(to)) if message.from == self.username => ConversationId::Direct(to),
+                    (true, None) if message.from == self.username => {
+                        match self.outgoing.get(&message.id).and_then(|f| f.to.clone()) {
+                            Some(to) => ConversationId::Direct(to),
+                            None => return false,
+                        }
+                    }
+                    (true, _) => ConversationId::Direct(message.from.clone()),
+                    (false, _) => self.room_conversation(room),
+                };
+                if message.from != self.username && !self.is_muted(&message.from) {
+                    let text = excerpt(&message.message, QUOTE_EXCERPT_CHARS);
+                    self.announce(&message.from, &text);
+                    self.effects.push(Effect::Notify {
+                        from: message.from.clone(),
+                        text,
+                    });
+                    if !self.window_focused || !self.at_bottom {
+                        self.effects.push(Effect::PlaySound);
+                    }
+                }
+                if acknowledge {
+                    let id = message.id.clone();
+                    self.send_receipt(MsgTypes::Delivered, &conversation, vec![id.clone()]);
+                    self.unread_receipts
+                        .entry(conversation.clone())
+                        .or_default()
+                        .push(id);
+                }
+                self.confirm_or_push(conversation, message.into());
+                true
+            }
+            ChatEvent::Typing(data) => {
+                if data.from == self.username || self.is_muted(&data.from) {
+                    false
+                } else if data.typing {
+                    self.typing.insert(data.from, (self.clock)()).is_none()
+                } else {
+                    self.typing.remove(&data.from).is_some()
+                }
+            }
+            ChatEvent::StatusChanged(status) => {
+                self.presence.insert(status.from, status.status) != Some(status.status)
+            }
+            ChatEvent::Edited(edit) => self.apply_edit(edit),
+            ChatEvent::Deleted(delete) => self.apply_delete(delete),
+            ChatEvent::Receipt { receipt, read } => {
+                if receipt.from == self.username {
+                    return false;
+                }
+                let mut changed = false;
+                for id in receipt.message_ids {
+                    changed |= self
+                        .receipts
+                        .entry(id)
+                        .or_default()
+                        .record(&receipt.from, read);
+                }
+                changed
+            }
+            ChatEvent::Reaction(reaction) => self.apply_reaction(reaction),
+            ChatEvent::ServerError(reason) => {
+                if let Some(name) = self.pending_rename.take() {
+                    self.push_notice(
+                        self.active.clone(),
+                        self.i18n.t_with(
+                            "notice.rename_failed",
+                            &[("name", &name), ("reason", &reason)],
+                        ),
+                    );
+                    return true;
+                }
+                // Until our name shows up in a user list, an error means the
+                // server refused to register us.
+                if !self.users.iter().any(|u| u.name == self.username) {
+                    self.effects.push(Effect::RejectLogin(reason));
+                    return false;
+                }
+                self.effects
+                    .push(Effect::ShowError(ErrorKind::Server(reason)));
+                false
+            }
+            ChatEvent::ProtocolError(error) => {
+                self.protocol_stats.malformed += 1;
+                self.protocol_stats.last = Some(error.clone());
+                self.effects
+                    .push(Effect::ShowError(ErrorKind::Protocol(error)));
+                false
+            }
+            ChatEvent::UnknownFrame(name) => {
+                log::warn!("ignoring frame of unknown type {:?}", name);
+                self.protocol_stats.unknown += 1;
+                self.protocol_stats.last = Some(format!("unknown frame type {:?}", name));
+                false
+            }
+            ChatEvent::UnsupportedVersion(version) => {
+                self.unsupported_version.replace(version) != Some(version)
+            }
+            ChatEvent::Refused(reason) => self.refused.replace(reason) != Some(reason),
+            ChatEvent::History { room, messages } => {
+                let conversation = self.room_conversation(room);
+                let added = self.merge_history(conversation.clone(), messages);
+                let entry = self.conversations.entry(conversation).or_default();
+                let requested = std::mem::take(&mut entry.loading_older);
+                // A page we asked for that brings nothing new means there is
+                // nothing older left. Unprompted history (the one sent on
+                // connect) often overlaps what we restored, so it doesn't count.
+                if requested && !added {
+                    entry.reached_start = true;
+                }
+                added || requested
+            }
+            ChatEvent::ConnectionChanged(_) | ChatEvent::ConnectionError(_) => false,
+        }
+    }
+
+    /// Conversations in the order they are cycled through: rooms as they
+    /// are listed, then direct conversations by peer name.
+    pub fn conversation_order(&self) -> Vec<ConversationId> {
+        let mut direct: Vec<&String> = self
+            .conversations
+            .keys()
+            .filter_map(|id| match id {
+                ConversationId::Direct(peer) => Some(peer),
+                ConversationId::Room(_) => None,
+            })
+            .collect();
+        direct.sort_unstable();
+        self.rooms
+            .iter()
+            .cloned()
+            .map(ConversationId::Room)
+            .chain(direct.into_iter().cloned().map(ConversationId::Direct))
+            .collect()
+    }
+
+    /// The conversation `step` places after the open one in
+    /// [`conversation_order`](Self::conversation_order), wrapping around at
+    /// either end.
+    pub fn cycle_conversation(&self, step: isize) -> Option<ConversationId> {
+        let order = self.conversation_order();
+        let len = order.len() as isize;
+        let current = order.iter().position(|id| *id == self.active)? as isize;
+        let next = (current + step).rem_euclid(len);
+        (next != current).then(|| order[next as usize].clone())
+    }
+
+    /// Messages of the open conversation.
+    pub fn messages(&self) -> &[ChatMessage] {
+        self.conversations
+            .get(&self.active)
+            .map(|c| c.messages.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// What `name` last said their status was.
+    pub fn presence_of(&self, name: &str) -> Presence {
+        self.presence.get(name).copied().unwrap_or_default()
+    }
+
+    /// Whether we wrote `m`. Our earlier messages are moved to our new name
+    /// on a rename, so this holds across them.
+    pub fn is_own(&self, m: &ChatMessage) -> bool {
+        !m.system && m.from == self.username
+    }
+
+    /// Whether `name` is muted. Their messages are still kept, but shown
+    /// collapsed.
+    pub fn is_muted(&self, name: &str) -> bool {
+        self.muted.contains(name)
+    }
+
+    /// Mutes or unmutes `name`. Returns whether that changed anything.
+    pub fn set_muted(&mut self, name: &str, muted: bool) -> bool {
+        if !muted {
+            return self.muted.remove(name);
+        }
+        self.typing.remove(name);
+        self.muted.insert(name.to_string())
+    }
+
+    /// Whether `m` is shown collapsed: its sender is muted and it hasn't
+    /// been opened up.
+    pub fn is_collapsed(&self, m: &ChatMessage) -> bool {
+        !m.system && self.is_muted(&m.from) && !self.shown_muted.contains(&m.id)
+    }
+
+    /// Opens up collapsed message `id`. Returns whether it was collapsed.
+    pub fn show_muted(&mut self, id: MessageId) -> bool {
+        self.shown_muted.insert(id)
+    }
+
+    /// Whether message `id` is pinned.
+    pub fn is_pinned(&self, id: &str) -> bool {
+        self.pins.iter().any(|pin| pin.id == id)
+    }
+
+    /// Pins message `id` of the open conversation, or unpins it if it
+    /// already is. Returns whether the pins changed.
+    pub fn toggle_pin(&mut self, id: &str) -> bool {
+        if self.is_pinned(id) {
+            self.pins.retain(|pin| pin.id != id);
+            return true;
+        }
+        let message = match self.messages().iter().find(|m| m.id == id) {
+            Some(m) if !m.deleted => m,
+            _ => return false,
+        };
+        let pin = Pin {
+            id: message.id.clone(),
+            conversation: self.active.clone(),
+            from: message.from.clone(),
+            excerpt: excerpt(&message.message, QUOTE_EXCERPT_CHARS),
+        };
+        self.pins.push(pin);
+        true
+    }
+
+    /// Brings the pin of message `id`, if it has one, in line with the
+    /// message: an edit shows in the excerpt, and a delete unpins it.
+    fn refresh_pin(&mut self, id: &str) {
+        let at = match self.pins.iter().position(|pin| pin.id == id) {
+            Some(at) => at,
+            None => return,
+        };
+        let message = self
+            .conversations
+            .get(&self.pins[at].conversation)
+            .and_then(|c| c.messages.get(id));
+        match message {
+            Some(m) if m.deleted => {
+                self.pins.remove(at);
+            }
+            Some(m) => {
+                let excerpt = excerpt(&m.message, QUOTE_EXCERPT_CHARS);
+                if self.pins[at].excerpt == excerpt {
+                    return;
+                }
+                self.pins[at].excerpt = excerpt;
+            }
+            None => return,
+        }
+        self.effects.push(Effect::PinsChanged);
+    }
+
+    /// Queues `text` for the screen reader announcer, dropping the oldest
+    /// past [`ANNOUNCED_MESSAGES`].
+    fn announce(&mut self, from: &str, text: &str) {
+        let announcement = self
+            .i18n
+            .t_with("chat.announce", &[("name", &from), ("text", &text)]);
+        if self.announcements.len() == ANNOUNCED_MESSAGES {
+            self.announcements.pop_front();
+        }
+        self.announcements.push_back(announcement);
+    }
+
+    /// Puts up an error notice for `kind`, counting it on the one already
+    /// up if it is the same failure again.
+    pub fn show_error(&mut self, kind: ErrorKind) {
+        self.toasts.push(kind, (self.clock)());
+    }
+
+    /// Keeps the connection notice up, and up to date, for as long as the
+    /// connection isn't open.
+    pub fn sync_connection_toast(&mut self) {
+        if self.conn_state == ConnState::Open {
+            self.toasts
+                .dismiss_where(|t| matches!(t.kind, ErrorKind::Disconnected { .. }));
+        } else {
+            let kind = ErrorKind::Disconnected {
+                reconnecting: self.conn_state != ConnState::Closed,
+                reason: self.conn_error.clone(),
+            };
+            self.show_error(kind);
+        }
+    }
+
+    /// Takes in a change of connection state. Once the socket is open
+    /// again, the messages queued while offline have gone out behind the
+    /// handshake and start waiting for their echo; their ids are returned.
+    pub fn connection_changed(&mut self, conn: ConnState) -> Vec<MessageId> {
+        let mut flushed = Vec::new();
+        if conn == ConnState::Open {
+            self.users_seeded = false;
+            let now = (self.clock)();
+            let throttled = &self.throttled;
+            for conversation in self.conversations.values_mut() {
+                // A page requested over the old connection may never be
+                // answered.
+                conversation.loading_older = false;
+                let queued = conversation
+                    .messages
+                    .iter_mut()
+                    .filter(|m| m.status == SendStatus::Queued && !throttled.contains(&m.id));
+                for m in queued {
+                    m.status = SendStatus::Pending;
+                    m.sent_at = now;
+                    flushed.push(m.id.clone());
+                }
+            }
+            self.conn_error = None;
+        }
+        self.conn_state = conn;
+        self.sync_connection_toast();
+        flushed
+    }
+
+    /// Notes why the connection failed, for the connection notice.
+    pub fn connection_error(&mut self, error: String) {
+        self.conn_error = Some(error);
+        self.sync_connection_toast();
+    }
+
+    /// Our own message, `text` or `file`, to the open conversation, replying
+    /// to whatever was being replied to. It is shown right away as pending
+    /// and kept in [`outgoing`](Self::outgoing) until the server echoes it.
+    /// Returns its frame.
+    pub fn compose(&mut self, text: String, file: Option<FileData>) -> WebSocketMessage {
+        let message_type = match (&self.active, &file) {
+            (_, Some(_)) => MsgTypes::File,
+            (ConversationId::Room(_), None) => MsgTypes::Message,
+            (ConversationId::Direct(_), None) => MsgTypes::Private,
+        };
+        let data = match &file {
+            Some(file) => serde_json::to_string(file).unwrap(),
+            None => text.clone(),
+        };
+        let (to, room) = self.active.target();
+        let reply_to = self.replying_to.take();
+        let reply_excerpt = reply_to.as_ref().and_then(|id| {
+            self.messages()
+                .iter()
+                .find(|m| &m.id == id)
+                .map(|m| excerpt(m.display_text(), QUOTE_EXCERPT_CHARS))
+        });
+        let timestamp = (self.clock)();
+        let id = self.next_id(&self.username.clone(), timestamp);
+        let message = WebSocketMessage {
+            id: Some(id.clone()),
+            reply_to,
+            reply_excerpt,
+            timestamp: Some(timestamp),
+            to,
+            room,
+            ..WebSocketMessage::new(message_type, Some(data))
+        };
+        let conversation = self.active.clone();
+        self.push_message(
+            &conversation,
+            ChatMessage {
+                data: MessageData {
+                    id: id.clone(),
+                    from: self.username.clone(),
+                    message: text,
+                    timestamp,
+                    reply_to: message.reply_to.clone(),
+                    reply_excerpt: message.reply_excerpt.clone(),
+                    file: file.map(Box::new),
+                    ..Default::default()
+                },
+                status: SendStatus::Pending,
+                ..Default::default()
+            },
+        );
+        self.outgoing.insert(id, message.clone());
+        message
+    }
+
+    fn set_status(&mut self, id: &str, status: SendStatus) {
+        let now = (self.clock)();
+        if let Some(m) = self
+            .conversations
+            .values_mut()
+            .find_map(|c| c.messages.get_mut(id))
+        {
+            m.status = status;
+            m.sent_at = now;
+        }
+    }
+
+    /// Asks the rate limiter whether our message `id` may go out now. If
+    /// not, it is held back behind any messages already waiting and shown
+    /// as queued; the error then says in how long to drain the held
+    /// messages, unless a drain is already due.
+    pub fn admit(&mut self, id: MessageId) -> Result<(), Option<u64>> {
+        let mut drain_in = None;
+        if self.throttled.is_empty() {
+            match self.rate_limiter.try_acquire((self.clock)()) {
+                Decision::Allow => return Ok(()),
+                Decision::Wait(ms) => drain_in = Some(ms),
+            }
+        }
+        self.set_status(&id, SendStatus::Queued);
+        self.throttled.push_back(id);
+        Err(drain_in)
+    }
+
+    /// Takes the held-back messages the rate limit now lets out, in order,
+    /// with how long until the rest may be drained.
+    pub fn drain_throttled(&mut self) -> (Vec<MessageId>, Option<u64>) {
+        let mut ready = Vec::new();
+        while let Some(id) = self.throttled.front() {
+            match self.rate_limiter.try_acquire((self.clock)()) {
+                Decision::Allow => ready.push(id.clone()),
+                Decision::Wait(ms) => return (ready, Some(ms)),
+            }
+            self.throttled.pop_front();
+        }
+        (ready, None)
+    }
+
+    /// Notes how handing our message `id` to the websocket service went:
+    /// on the wire waiting for its echo, queued until the connection is
+    /// back, or failed. Returns the status it now has.
+    pub fn handed_over(&mut self, id: &str, accepted: bool) -> SendStatus {
+        let status = match accepted {
+            false => {
+                self.show_error(ErrorKind::SendFailed(id.to_string()));
+                SendStatus::Failed
+            }
+            true if self.conn_state == ConnState::Open => SendStatus::Pending,
+            true => SendStatus::Queued,
+        };
+        self.set_status(id, status);
+        status
+    }
+
+    /// Gives up on the echo of our message `id` if it is still pending,
+    /// showing it as failed. Returns whether it was.
+    pub fn send_timed_out(&mut self, id: &str) -> bool {
+        let failed = match self
+            .conversations
+            .values_mut()
+            .find_map(|c| c.messages.get_mut(id))
+        {
+            Some(m) if m.status == SendStatus::Pending => {
+                m.status = SendStatus::Failed;
+                true
+            }
+            _ => false,
+        };
+        if failed {
+            self.show_error(ErrorKind::SendFailed(id.to_string()));
+        }
+        failed
+    }
+
+    /// The notice telling the others whether we are typing, if one is due.
+    /// Start notices go out at most once per [`TYPING_SEND_INTERVAL_MS`],
+    /// stop notices only after a start.
+    pub fn typing_notice(&mut self, typing: bool) -> Option<WebSocketMessage> {
+        let now = (self.clock)();
+        if typing {
+            if self
+                .typing_sent_at
+                .is_some_and(|sent_at| now.saturating_sub(sent_at) < TYPING_SEND_INTERVAL_MS)
+            {
+                return None;
+            }
+            self.typing_sent_at = Some(now);
+        } else {
+            self.typing_sent_at.take()?;
+        }
+        let data = TypingData {
+            from: self.username.clone(),
+            typing,
+        };
+        Some(WebSocketMessage {
+            timestamp: Some(now),
+            ..WebSocketMessage::new(
+                MsgTypes::Typing,
+                Some(serde_json::to_string(&data).unwrap()),
+            )
+        })
+    }
+
+    /// Collects Read receipts for the open conversation if its latest
+    /// messages are on screen. Returns whether any were added to
+    /// [`read_batch`](Self::read_batch).
+    pub fn mark_read(&mut self) -> bool {
+        if !self.at_bottom || !self.window_focused {
+            return false;
+        }
+        match self.unread_receipts.remove(&self.active) {
+            Some(ids) => {
+                self.read_batch
+                    .entry(self.active.clone())
+                    .or_default()
+                    .extend(ids);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends the Read receipts collected so far, a frame per conversation.
+    pub fn flush_reads(&mut self) {
+        for (conversation, ids) in std::mem::take(&mut self.read_batch) {
+            self.send_receipt(MsgTypes::Read, &conversation, ids);
+        }
+    }
+
+    /// Keeps `text` as the open conversation's draft, or forgets the draft
+    /// if it is blank. Text being edited is not a draft. Returns whether
+    /// the drafts changed.
+    pub fn set_draft(&mut self, text: &str) -> bool {
+        if self.editing.is_some() {
+            return false;
+        }
+        if text.trim().is_empty() {
+            self.drafts.remove(&self.active).is_some()
+        } else {
+            self.drafts.insert(self.active.clone(), text.to_string());
+            true
+        }
+    }
+
+    /// Changes how many messages each conversation keeps, evicting at once
+    /// whatever is over it. Returns how many messages went.
+    pub fn set_max_messages(&mut self, cap: usize) -> usize {
+        self.max_messages = cap;
+        let pins = &self.pins;
+        self.conversations
+            .values_mut()
+            .map(|c| c.messages.evict(cap, |id| pins.iter().any(|p| p.id == id)))
+            .sum()
+    }
+
+    /// Appends `message` to `conversation`, trimming it back to the cap.
+    pub fn push_message(&mut self, conversation: &ConversationId, message: ChatMessage) {
+        let entry = self.conversations.entry(conversation.clone()).or_default();
+        let pins = &self.pins;
+        entry.messages.insert(message, self.max_messages, |id| {
+            pins.iter().any(|p| p.id == id)
+        });
+    }
+
+    /// A fresh id for a message sent by `from` at `timestamp`.
+    pub fn next_id(&mut self, from: &str, timestamp: u64) -> MessageId {
+        self.id_counter += 1;
+        client_id(from, timestamp, self.id_counter)
+    }
+
+    /// The conversation for messages addressed to `room` (the default room if
+    /// unset). Rooms we haven't joined are added to the list rather than
+    /// having their messages dropped.
+    pub fn room_conversation(&mut self, room: Option<String>) -> ConversationId {
+        let room = room.unwrap_or_else(|| DEFAULT_ROOM.to_string());
+        if !self.rooms.contains(&room) {
+            log::debug!("message for room {} we haven't joined", room);
+            self.rooms.push(room.clone());
+        }
+        ConversationId::Room(room)
+    }
+
+    /// Asks the server for the page of history before the oldest message of
+    /// the open room, unless one is already on its way, there is nothing
+    /// older, or the room is at its message cap (older messages would be
+    /// trimmed straight away). Returns whether a request was sent.
+    pub fn request_older(&mut self) -> bool {
+        let room = match &self.active {
+            ConversationId::Room(room) => room.clone(),
+            ConversationId::Direct(_) => return false,
+        };
+        let max_messages = self.max_messages;
+        let entry = self.conversations.entry(self.active.clone()).or_default();
+        if entry.loading_older
+            || entry.reached_start
+            || entry.messages.as_slice().len() >= max_messages
+        {
+            return false;
+        }
+        let before = entry
+            .messages
+            .as_slice()
+            .iter()
+            .find(|m| !m.system)
+            .map(|m| m.timestamp);
+        entry.loading_older = true;
+        self.effects
+            .push(Effect::SendFrame(Box::new(WebSocketMessage {
+                room: Some(room),
+                before,
+                limit: Some(HISTORY_PAGE_SIZE),
+                ..WebSocketMessage::new(MsgTypes::History, None)
+            })));
+        true
+    }
+
+    /// Merges server history into what `conversation` already holds,
+    /// skipping anything received before and confirming our own sends it
+    /// turns up. Returns whether anything changed.
+    pub fn merge_history(
+        &mut self,
+        conversation: ConversationId,
+        history: Vec<MessageData>,
+    ) -> bool {
+        let entry = self.conversations.entry(conversation.clone()).or_default();
+        let mut kept: Vec<ChatMessage> = Vec::new();
+        // Pinned messages we hold may have been edited or deleted while we
+        // were away; the server's copy says how they stand now.
+        let mut repinned = Vec::new();
+        for m in history {
+            if self.pins.iter().any(|pin| pin.id == m.id) {
+                if let Some(held) = entry.messages.get_mut(&m.id) {
+                    let changed = if m.deleted {
+                        held.tombstone(&m.from)
+                    } else {
+                        held.apply_edit(&m.from, m.message.clone())
+                    };
+                    if changed {
+                        repinned.push(m.id.clone());
+                    }
+                }
+            }
+            let mut m = ChatMessage::from(m);
+            let duplicate = if m.id.is_empty() {
+                entry.messages.as_slice().iter().chain(&kept).any(|other| {
+                    other.from == m.from
+                        && other.message == m.message
+                        && other.timestamp == m.timestamp
+                })
+            } else {
+                !self.seen_ids.insert(&m.id)
+            };
+            if !duplicate {
+                if m.id.is_empty() {
+                    // Servers that don't propagate ids still need one for
+                    // replies and reactions to refer to.
+                    self.id_counter += 1;
+                    m.id = client_id(&m.from, m.timestamp, self.id_counter);
+                } else if let Some((from, _)) = self.tombstones.remove(&m.id) {
+                    m.tombstone(&from);
+                }
+                m.restored = true;
+                kept.push(m);
+            }
+        }
+        let pins_changed = !repinned.is_empty();
+        for id in repinned {
+            self.refresh_pin(&id);
+        }
+        if kept.is_empty() {
+            return pins_changed;
+        }
+
+        let entry = self.conversations.entry(conversation.clone()).or_default();
+        let pins = &self.pins;
+        let merged = entry.messages.merge(kept, self.max_messages, |id| {
+            pins.iter().any(|p| p.id == id)
+        });
+        let confirmed = !merged.confirmed.is_empty();
+        for id in &merged.confirmed {
+            self.outgoing.remove(id);
+            self.toasts
+                .dismiss_where(|t| t.kind == ErrorKind::SendFailed(id.clone()));
+        }
+        self.effects
+            .extend(merged.confirmed.into_iter().map(Effect::Confirmed));
+        if merged.added == 0 {
+            return confirmed || pins_changed;
+        }
+
+        if conversation == self.active {
+            if self.at_bottom {
+                self.effects.push(Effect::ScrollToBottom);
+            } else if merged.above {
+                self.effects.push(Effect::KeepScrollPosition);
+            }
+        }
+        true
+    }
+
+    /// Applies an edit to whichever conversation holds the message. Edits of
+    /// messages we don't have are ignored.
+    pub fn apply_edit(&mut self, edit: EditData) -> bool {
+        let changed = self
+            .conversations
+            .values_mut()
+            .find(|c| c.messages.get(&edit.message_id).is_some())
+            .is_some_and(|c| {
+                c.messages
+                    .apply_edit(&edit.message_id, &edit.from, edit.message)
+            });
+        if changed {
+            self.refresh_pin(&edit.message_id);
+        }
+        changed
+    }
+
+    /// Queues a Delivered or Read receipt for `message_ids` from us.
+    pub fn send_receipt(
+        &mut self,
+        message_type: MsgTypes,
+        conversation: &ConversationId,
+        message_ids: Vec<MessageId>,
+    ) {
+        let receipt = ReceiptData {
+            message_ids,
+            from: self.username.clone(),
+        };
+        let (to, room) = conversation.target();
+        let message = WebSocketMessage {
+            to,
+            room,
+            ..WebSocketMessage::new(message_type, Some(serde_json::to_string(&receipt).unwrap()))
+        };
+        self.effects.push(Effect::SendFrame(Box::new(message)));
+    }
+
+    /// Deletes a message wherever it is. Deletes of messages we don't have
+    /// yet are held until they arrive.
+    pub fn apply_delete(&mut self, delete: DeleteData) -> bool {
+        let holder = self
+            .conversations
+            .values_mut()
+            .find(|c| c.messages.get(&delete.message_id).is_some());
+        match holder {
+            Some(c) => {
+                let changed = c.messages.tombstone(&delete.message_id, &delete.from);
+                if changed {
+                    self.refresh_pin(&delete.message_id);
+                }
+                changed
+            }
+            None => {
+                self.tombstones
+                    .insert(delete.message_id, (delete.from, (self.clock)()));
+                false
+            }
+        }
+    }
+
+    /// Records or retracts a reaction, returning whether anything changed.
+    /// Applying the same reaction twice is a no-op, so our own reactions can
+    /// be shown before the server echoes them.
+    pub fn apply_reaction(&mut self, reaction: ReactionData) -> bool {
+        let known = self
+            .conversations
+            .values()
+            .any(|c| c.messages.get(&reaction.message_id).is_some());
+        if !known {
+            let now = (self.clock)();
+            self.orphan_reactions
+                .entry(reaction.message_id.clone())
+                .or_insert(now);
+        }
+
+        let by_emoji = self.reactions.entry(reaction.message_id).or_default();
+        let users = by_emoji.entry(reaction.emoji.clone()).or_default();
+        let had = users.contains(&reaction.from);
+        if reaction.retract {
+            users.retain(|u| u != &reaction.from);
+            if users.is_empty() {
+                by_emoji.remove(&reaction.emoji);
+            }
+        } else if !had {
+            users.push(reaction.from);
+        }
+        had == reaction.retract && known
+    }
+
+    /// Adds a locally generated notice to the default room, where everyone
+    /// is present.
+    pub fn push_system(&mut self, text: String) {
+        self.push_notice(ConversationId::Room(DEFAULT_ROOM.to_string()), text);
+    }
+
+    /// Adds a locally generated notice to `conversation`.
+    pub fn push_notice(&mut self, conversation: ConversationId, text: String) {
+        self.push_message(
+            &conversation,
+            ChatMessage {
+                data: MessageData {
+                    message: text,
+                    timestamp: (self.clock)(),
+                    ..Default::default()
+                },
+                system: true,
+                ..Default::default()
+            },
+        );
+        if conversation == self.active && self.at_bottom {
+            self.effects.push(Effect::ScrollToBottom);
+        }
+    }
+
+    /// Replaces our own pending entry in `conversation` with the server echo
+    /// of it, or appends the message if it doesn't correspond to anything we
+    /// sent.
+    pub fn confirm_or_push(&mut self, conversation: ConversationId, mut message: ChatMessage) {
+        if let Some((from, _)) = self.tombstones.remove(&message.id) {
+            message.tombstone(&from);
+        }
+        if message.from == self.username {
+            let now = (self.clock)();
+            let pending = self.conversations.get_mut(&conversation).and_then(|c| {
+                // Prefer the id we sent; older servers only echo the text.
+                if c.messages
+                    .get(&message.id)
+                    .is_some_and(|m| m.status != SendStatus::Sent)
+                {
+                    return c.messages.get_mut(&message.id);
+                }
+                c.messages.iter_mut().find(|m| {
+                    m.status == SendStatus::Pending
+                        && m.message == message.message
+                        && now.saturating_sub(m.sent_at) <= PENDING_TIMEOUT_MS
+                })
+            });
+            if let Some(entry) = pending {
+                let sent = std::mem::replace(entry, message);
+                self.outgoing.remove(&sent.id);
+                self.toasts
+                    .dismiss_where(|t| t.kind == ErrorKind::SendFailed(sent.id.clone()));
+                self.effects.push(Effect::Confirmed(sent.data.id));
+                return;
+            }
+        }
+        self.orphan_reactions.remove(&message.id);
+        let unseen = !self.window_focused && message.from != self.username;
+        if unseen {
+            self.title_unread += 1;
+            self.effects.push(Effect::UpdateTitle);
+        }
+        let id = message.id.clone();
+        self.push_message(&conversation, message);
+        if unseen && !id.is_empty() {
+            if let Some(c) = self.conversations.get_mut(&conversation) {
+                c.new_since.get_or_insert(id);
+            }
+        }
+        if conversation != self.active {
+            if let Some(c) = self.conversations.get_mut(&conversation) {
+                c.unread += 1;
+            }
+        } else if self.at_bottom {
+            self.effects.push(Effect::ScrollToBottom);
+        } else {
+            self.unseen += 1;
+        }
+    }
+
+    /// Replaces the user list with `names`. Users missing from it are kept
+    /// around, marked as departing, until the component removes them.
+    /// Returns false when the broadcast changes nothing, which servers do a
+    /// lot.
+    pub fn update_users(&mut self, entries: Vec<UserEntry>) -> bool {
+        let names: Vec<String> = entries.iter().map(|e| e.name.clone()).collect();
+        let completes_rename = self
+            .pending_rename
+            .as_ref()
+            .is_some_and(|name| names.contains(name));
+        let profiles: Vec<UserProfile> = entries
+            .into_iter()
+            .map(|entry| UserProfile {
+                avatar: self.avatar_url(&entry),
+                name: entry.name,
+                departing: false,
+            })
+            .collect();
+        if self.users_seeded && !completes_rename {
+            let mut online: Vec<(&str, &str)> = self
+                .users
+                .iter()
+                .filter(|u| !u.departing)
+                .map(|u| (u.name.as_str(), u.avatar.as_str()))
+                .collect();
+            let mut incoming: Vec<(&str, &str)> = profiles
+                .iter()
+                .map(|u| (u.name.as_str(), u.avatar.as_str()))
+                .collect();
+            online.sort_unstable();
+            incoming.sort_unstable();
+            if online == incoming {
+                return false;
+            }
+        }
+
+        let old_name = match self.pending_rename.take() {
+            Some(name) if names.contains(&name) => Some(self.rename(name)),
+            pending => {
+                self.pending_rename = pending;
+                None
+            }
+        };
+
+        // The first list after connecting is everyone already here, not news.
+        if self.users_seeded {
+            let online: Vec<&str> = self
+                .users
+                .iter()
+                .filter(|u| !u.departing)
+                .map(|u| u.name.as_str())
+                .collect();
+            let joined = names
+                .iter()
+                .filter(|n| !online.contains(&n.as_str()) && **n != self.username)
+                .map(|n| self.i18n.t_with("notice.joined", &[("name", n)]));
+            let left = online
+                .iter()
+                .filter(|n| !names.iter().any(|name| name == *n) && old_name.as_deref() != Some(*n))
+                .map(|n| self.i18n.t_with("notice.left", &[("name", n)]));
+            let renamed = old_name.as_ref().map(|old| {
+                self.i18n
+                    .t_with("notice.renamed", &[("old", old), ("new", &self.username)])
+            });
+            let notices: Vec<String> = renamed.into_iter().chain(joined).chain(left).collect();
+            for notice in notices {
+                self.push_system(notice);
+            }
+        }
+        self.users_seeded = true;
+
+        let mut users = profiles;
+
+        for old in std::mem::take(&mut self.users) {
+            // Our old name hasn't left; it is us, under the new one.
+            if old_name.as_ref() == Some(&old.name) {
+                continue;
+            }
+            if names.contains(&old.name) {
+                if old.departing {
+                    self.effects.push(Effect::UserReturned(old.name));
+                }
+                continue;
+            }
+            if !old.departing {
+                self.effects.push(Effect::UserDeparted(old.name.clone()));
+            }
+            users.push(UserProfile {
+                departing: true,
+                ..old
+            });
+        }
+
+        sort_users(&mut users, &self.username);
+        let now = (self.clock)();
+        self.first_seen
+            .retain(|name, _| users.iter().any(|u| &u.name == name));
+        for name in names {
+            self.first_seen.entry(name).or_insert(now);
+        }
+        self.users = users;
+        self.peak_users = self.peak_users.max(self.online_count());
+        true
+    }
+
+    /// Where the avatar of a listed user comes from: what the server passed
+    /// along, else our own pick if it's us, else the default for the name.
+    fn avatar_url(&self, entry: &UserEntry) -> String {
+        let own = self
+            .own_avatar
+            .as_ref()
+            .filter(|_| entry.name == self.username);
+        match entry.avatar.as_ref().or(own) {
+            Some(avatar) => avatar.url(),
+            None => AvatarChoice::default_for(&entry.name).url(),
+        }
+    }
+
+    /// Users in the latest list, not counting those shown as leaving.
+    pub fn online_count(&self) -> usize {
+        self.users.iter().filter(|u| !u.departing).count()
+    }
+
+    /// Switches to `name` once the server has accepted it, attributing our
+    /// earlier messages to it. Returns the old name.
+    fn rename(&mut self, name: String) -> String {
+        let old = std::mem::replace(&mut self.username, name.clone());
+        for m in self
+            .conversations
+            .values_mut()
+            .flat_map(|c| c.messages.iter_mut())
+            .filter(|m| m.from == old && !m.system)
+        {
+            m.from = name.clone();
+        }
+        self.effects.push(Effect::Renamed(name));
+        old
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::services::websocket::OutboundQueue;
+
+    thread_local! {
+        static NOW: Cell<u64> = const { Cell::new(1_000) };
+    }
+
+    fn now() -> u64 {
+        NOW.with(Cell::get)
+    }
+
+    fn advance(ms: u64) {
+        NOW.with(|now| now.set(now.get() + ms));
+    }
+
+    fn state() -> ChatState {
+        ChatState::new("me".to_string(), now)
+    }
+
+    fn message(id: &str, from: &str, text: &str) -> MessageData {
+        MessageData {
+            id: id.to_string(),
+            from: from.to_string(),
+            message: text.to_string(),
+            timestamp: now(),
+            ..Default::default()
+        }
+    }
+
+    fn received(message: MessageData) -> ChatEvent {
+        ChatEvent::MessageReceived {
+            message,
+            private: false,
+            to: None,
+            room: None,
+        }
+    }
+
+    fn users(names: &[&str]) -> ChatEvent {
+        ChatEvent::UsersUpdated(names.iter().map(|n| UserEntry::parse(n)).collect())
+    }
+
+    fn texts(state: &ChatState) -> Vec<&str> {
+        state
+            .messages()
+            .iter()
+            .map(|m| m.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn receiving_appends_notifies_and_acknowledges() {
+        let mut state = state();
+        let effects = state.apply(received(message("a1", "alice", "hi")));
+
+        assert_eq!(texts(&state), ["hi"]);
+        assert!(effects
+            .iter()
+            .any(|e| matches!(e, Effect::Notify { from, .. } if from == "alice")));
+        assert!(effects.iter().any(|e| matches!(
+            e,
+            Effect::SendFrame(frame) if frame.message_type == MsgTypes::Delivered
+        )));
+        assert!(matches!(effects.last(), Some(Effect::Render)));
+        assert_eq!(state.announcements.len(), 1);
+        assert_eq!(state.unread_receipts[&state.active], ["a1"]);
+    }
+
+    #[test]
+    fn messages_keep_arrival_order_and_duplicates_are_dropped() {
+        let mut state = state();
+        state.apply(received(message("a1", "alice", "one")));
+        advance(10);
+        state.apply(received(message("b1", "bob", "two")));
+        let effects = state.apply(received(message("a1", "alice", "one")));
+
+        assert!(effects.is_empty());
+        assert_eq!(texts(&state), ["one", "two"]);
+    }
+
+    #[test]
+    fn messages_without_id_or_timestamp_get_both() {
+        let mut state = state();
+        let mut m = message("", "alice", "hi");
+        m.timestamp = 0;
+        let effects = state.apply(received(m));
+
+        let stored = &state.messages()[0];
+        assert!(!stored.id.is_empty());
+        assert_eq!(stored.timestamp, now());
+        // Nothing the sender could match a receipt against.
+        assert!(!effects.iter().any(|e| matches!(e, Effect::SendFrame(_))));
+    }
+
+    #[test]
+    fn only_the_author_may_edit() {
+        let mut state = state();
+        state.apply(received(message("a1", "alice", "helo")));
+
+        let edit = |from: &str| {
+            ChatEvent::Edited(EditData {
+                message_id: "a1".to_string(),
+                from: from.to_string(),
+                message: "hello".to_string(),
+            })
+        };
+        assert!(state.apply(edit("bob")).is_empty());
+        assert_eq!(texts(&state), ["helo"]);
+
+        assert!(matches!(state.apply(edit("alice"))[..], [Effect::Render]));
+        let edited = &state.messages()[0];
+        assert_eq!(edited.message, "hello");
+        assert_eq!(edited.original_text.as_deref(), Some("helo"));
+    }
+
+    #[test]
+    fn delete_tombstones_and_early_deletes_wait_for_their_message() {
+        let mut state = state();
+        state.apply(received(message("a1", "alice", "oops")));
+        state.apply(ChatEvent::Deleted(DeleteData {
+            message_id: "a1".to_string(),
+            from: "alice".to_string(),
+        }));
+        assert!(state.messages()[0].deleted);
+
+        let effects = state.apply(ChatEvent::Deleted(DeleteData {
+            message_id: "a2".to_string(),
+            from: "alice".to_string(),
+        }));
+        assert!(effects.is_empty());
+        state.apply(received(message("a2", "alice", "too late")));
+        assert!(state.messages()[1].deleted);
+    }
+
+    #[test]
+    fn rename_completes_when_a_user_list_has_the_new_name() {
+        let mut state = state();
+        state.apply(users(&["me", "alice"]));
+        state.push_message(&state.active.clone(), message("m1", "me", "mine").into());
+        state.pending_rename = Some("neo".to_string());
+
+        let effects = state.apply(users(&["neo", "alice"]));
+
+        assert_eq!(state.username, "neo");
+        assert!(state.pending_rename.is_none());
+        assert!(effects
+            .iter()
+            .any(|e| matches!(e, Effect::Renamed(name) if name == "neo")));
+        assert_eq!(state.messages()[0].from, "neo");
+        // Our old name doesn't show up as someone leaving.
+        assert!(!effects.iter().any(|e| matches!(e, Effect::UserDeparted(_))));
+        assert!(!state.users.iter().any(|u| u.name == "me"));
+    }
+
+    #[test]
+    fn failed_rename_is_reported_in_the_open_conversation() {
+        let mut state = state();
+        state.apply(users(&["me"]));
+        state.pending_rename = Some("taken".to_string());
+
+        state.apply(ChatEvent::ServerError("name in use".to_string()));
+
+        assert!(state.pending_rename.is_none());
+        assert_eq!(state.username, "me");
+        assert!(state.messages()[0].system);
+    }
+
+    #[test]
+    fn private_echo_confirms_the_pending_message() {
+        let mut state = state();
+        state.active = ConversationId::Direct("alice".to_string());
+        let frame = state.compose("psst".to_string(), None);
+        let id = frame.id.clone().unwrap();
+        assert_eq!(frame.message_type, MsgTypes::Private);
+        assert_eq!(state.messages()[0].status, SendStatus::Pending);
+
+        let effects = state.apply(ChatEvent::MessageReceived {
+            message: message(&id, "me", "psst"),
+            private: true,
+            to: Some("alice".to_string()),
+            room: None,
+        });
+
+        assert!(effects
+            .iter()
+            .any(|e| matches!(e, Effect::Confirmed(confirmed) if *confirmed == id)));
+        assert_eq!(state.messages().len(), 1);
+        assert_eq!(state.messages()[0].status, SendStatus::Sent);
+        assert!(state.outgoing.is_empty());
+        assert!(!state.send_timed_out(&id));
+    }
+
+    #[test]
+    fn own_private_echo_without_recipient_confirms_the_pending_message() {
+        let mut state = state();
+        state.active = ConversationId::Direct("alice".to_string());
+        let id = state.compose("psst".to_string(), None).id.unwrap();
+
+        let effects = state.apply(ChatEvent::MessageReceived {
+            message: message(&id, "me", "psst"),
+            private: true,
+            to: None,
+            room: None,
+        });
+        assert!(effects
+            .iter()
+            .any(|e| matches!(e, Effect::Confirmed(confirmed) if *confirmed == id)));
+        assert_eq!(state.messages().len(), 1);
+        assert_eq!(state.messages()[0].status, SendStatus::Sent);
+        assert!(state.outgoing.is_empty());
+        assert!(!state
+            .conversations
+            .contains_key(&ConversationId::Direct("me".to_string())));
+
+        advance(PENDING_TIMEOUT_MS);
+        assert!(!state.send_timed_out(&id));
+    }
+
+    #[test]
+    fn own_private_echo_of_an_unknown_message_without_recipient_is_dropped() {
+        let mut state = state();
+        let effects = state.apply(ChatEvent::MessageReceived {
+            message: message("elsewhere", "me", "from another tab"),
+            private: true,
+            to: None,
+            room: None,
+        });
+        assert!(effects.is_empty());
+        assert!(state
+            .conversations
+            .values()
+            .all(|c| c.messages.as_slice().is_empty()));
+    }
+
+    #[test]
+    fn others_private_messages_are_keyed_by_sender() {
+        let mut state = state();
+        state.apply(ChatEvent::MessageReceived {
+            message: message("a1", "alice", "hey"),
+            private: true,
+            to: Some("me".to_string()),
+            room: None,
+        });
+
+        let direct = &state.conversations[&ConversationId::Direct("alice".to_string())];
+        assert_eq!(direct.messages.as_slice().len(), 1);
+        assert_eq!(direct.unread, 1);
+    }
+
+    #[test]
+    fn eviction_spares_pinned_messages() {
+        let mut state = state();
+        for i in 0..4 {
+            state.apply(received(message(
+                &format!("a{}", i),
+                "alice",
+                &i.to_string(),
+            )));
+        }
+        assert!(state.toggle_pin("a0"));
+
+        assert_eq!(state.set_max_messages(2), 2);
+        assert_eq!(texts(&state), ["0", "3"]);
+
+        state.apply(received(message("a4", "alice", "4")));
+        assert_eq!(texts(&state), ["0", "4"]);
+    }
+
+    #[test]
+    fn user_list_changes_announce_departures_and_returns() {
+        let mut state = state();
+        let effects = state.apply(users(&["me", "alice", "bob"]));
+        // The first list is who was already here.
+        assert!(state.messages().is_empty());
+        assert!(!effects.iter().any(|e| matches!(e, Effect::UserDeparted(_))));
+
+        let effects = state.apply(users(&["me", "alice"]));
+        assert!(effects
+            .iter()
+            .any(|e| matches!(e, Effect::UserDeparted(name) if name == "bob")));
+        assert!(state.users.iter().any(|u| u.name == "bob" && u.departing));
+        assert_eq!(state.online_count(), 2);
+
+        let effects = state.apply(users(&["me", "alice", "bob"]));
+        assert!(effects
+            .iter()
+            .any(|e| matches!(e, Effect::UserReturned(name) if name == "bob")));
+        assert_eq!(state.messages().len(), 2);
+
+        // The same list again changes nothing.
+        assert!(state.apply(users(&["alice", "bob", "me"])).is_empty());
+    }
+
+    #[test]
+    fn peak_users_only_grows() {
+        let mut state = state();
+        state.apply(users(&["me", "alice", "bob"]));
+        state.apply(users(&["me"]));
+        assert_eq!(state.online_count(), 1);
+        assert_eq!(state.peak_users, 3);
+        state.apply(users(&["me", "alice", "bob", "carol"]));
+        assert_eq!(state.peak_users, 4);
+
+        state.connection_changed(ConnState::Reconnecting);
+        state.connection_changed(ConnState::Open);
+        state.apply(users(&["me"]));
+        assert_eq!(state.peak_users, 4);
+    }
+
+    #[test]
+    fn departing_users_are_not_counted() {
+        let mut state = state();
+        state.apply(users(&["me", "alice", "bob"]));
+        state.apply(users(&["me", "bob"]));
+
+        assert!(state.users.iter().any(|u| u.name == "alice" && u.departing));
+        assert_eq!(state.online_count(), 2);
+        state.apply(users(&["me", "bob", "carol"]));
+        assert_eq!(state.online_count(), 3);
+        assert_eq!(state.peak_users, 3);
+    }
+
+    #[test]
+    fn muted_users_are_kept_but_not_heard() {
+        let mut state = state();
+        state.apply(ChatEvent::Typing(TypingData {
+            from: "troll".to_string(),
+            typing: true,
+        }));
+        assert!(state.set_muted("troll", true));
+        assert!(state.typing.is_empty());
+
+        let effects = state.apply(received(message("t1", "troll", "spam")));
+        assert_eq!(texts(&state), ["spam"]);
+        assert!(!effects.iter().any(|e| matches!(e, Effect::Notify { .. })));
+        assert!(state.announcements.is_empty());
+
+        let typing = ChatEvent::Typing(TypingData {
+            from: "troll".to_string(),
+            typing: true,
+        });
+        assert!(state.apply(typing).is_empty());
+        assert!(state.set_muted("troll", false));
+        assert!(!state.set_muted("troll", false));
+    }
+
+    #[test]
+    fn unmuting_reveals_collapsed_messages() {
+        let mut state = state();
+        state.apply(received(message("t1", "troll", "spam")));
+        state.apply(received(message("t2", "troll", "more spam")));
+        state.apply(received(message("a1", "alice", "hi")));
+        state.set_muted("troll", true);
+        let collapsed = |state: &ChatState| -> Vec<String> {
+            state
+                .messages()
+                .iter()
+                .filter(|m| state.is_collapsed(m))
+                .map(|m| m.id.clone())
+                .collect()
+        };
+        assert_eq!(collapsed(&state), ["t1", "t2"]);
+
+        assert!(state.show_muted("t1".to_string()));
+        assert!(!state.show_muted("t1".to_string()));
+        assert_eq!(collapsed(&state), ["t2"]);
+
+        state.set_muted("troll", false);
+        assert!(collapsed(&state).is_empty());
+        state.set_muted("troll", true);
+        assert_eq!(collapsed(&state), ["t2"]);
+    }
+
+    #[test]
+    fn history_brings_pins_up_to_date() {
+        let mut state = state();
+        state.apply(received(message("a1", "alice", "draft")));
+        state.apply(received(message("a2", "alice", "secret")));
+        state.toggle_pin("a1");
+        state.toggle_pin("a2");
+
+        let mut edited = message("a1", "alice", "final");
+        edited.original_text = Some("draft".to_string());
+        let mut deleted = message("a2", "alice", "");
+        deleted.deleted = true;
+        let effects = state.apply(ChatEvent::History {
+            room: None,
+            messages: vec![edited, deleted],
+        });
+
+        assert!(effects.iter().any(|e| matches!(e, Effect::PinsChanged)));
+        assert_eq!(texts(&state), ["final", ""]);
+        assert!(state.messages()[1].deleted);
+        assert_eq!(state.pins.len(), 1);
+        assert_eq!(state.pins[0].excerpt, "final");
+    }
+
+    #[test]
+    fn a_flood_of_messages_is_trimmed_to_the_cap() {
+        let mut state = state();
+        let flood = DEFAULT_MAX_MESSAGES + 500;
+        for i in 0..flood {
+            state.apply(received(message(
+                &format!("m{}", i),
+                "alice",
+                &i.to_string(),
+            )));
+        }
+
+        let messages = state.messages();
+        assert_eq!(messages.len(), DEFAULT_MAX_MESSAGES);
+        assert_eq!(messages[0].message, "500");
+        assert_eq!(
+            messages[messages.len() - 1].message,
+            (flood - 1).to_string()
+        );
+        assert_eq!(state.conversations[&state.active].messages.trimmed(), 500);
+    }
+
+    #[test]
+    fn messages_sent_offline_flush_in_order_on_reconnect() {
+        let mut state = state();
+        state.connection_changed(ConnState::Reconnecting);
+        let ids: Vec<MessageId> = ["one", "two", "three"]
+            .iter()
+            .map(|text| {
+                let id = state.compose(text.to_string(), None).id.unwrap();
+                assert_eq!(state.handed_over(&id, true), SendStatus::Queued);
+                advance(1);
+                id
+            })
+            .collect();
+        assert!(state
+            .messages()
+            .iter()
+            .all(|m| m.status == SendStatus::Queued));
+
+        assert_eq!(state.connection_changed(ConnState::Open), ids);
+        assert_eq!(texts(&state), ["one", "two", "three"]);
+        assert!(state
+            .messages()
+            .iter()
+            .all(|m| m.status == SendStatus::Pending));
+        assert!(state.connection_changed(ConnState::Open).is_empty());
+    }
+
+    #[test]
+    fn messages_the_full_queue_refuses_fail() {
+        let mut state = state();
+        state.connection_changed(ConnState::Reconnecting);
+        // What the websocket service does with frames while offline.
+        let mut queue = OutboundQueue::new(1);
+        let mut hand_over = |state: &mut ChatState, text: &str| {
+            let frame = state.compose(text.to_string(), None);
+            let id = frame.id.clone().unwrap();
+            let status = state.handed_over(&id, queue.hold(frame).is_ok());
+            (id, status)
+        };
+        let (kept, status) = hand_over(&mut state, "kept");
+        assert_eq!(status, SendStatus::Queued);
+
+        let (dropped, status) = hand_over(&mut state, "dropped");
+        assert_eq!(status, SendStatus::Failed);
+        assert!(state
+            .toasts
+            .iter()
+            .any(|t| t.kind == ErrorKind::SendFailed(dropped.clone())));
+        assert_eq!(state.connection_changed(ConnState::Open), [kept]);
+    }
+
+    fn profiles(names: &[&str]) -> Vec<UserProfile> {
+        names
+            .iter()
+            .map(|name| UserProfile {
+                name: name.to_string(),
+                avatar: String::new(),
+                departing: false,
+            })
+            .collect()
+    }
+
+    fn filtered<'a>(users: &'a [UserProfile], query: &str) -> Vec<(&'a str, Range<usize>)> {
+        filter_users(users, query)
+            .into_iter()
+            .map(|(u, range)| (u.name.as_str(), range))
+            .collect()
+    }
+
+    #[test]
+    fn filtering_users() {
+        let users = profiles(&["Alice", "bob", "Ünal", "carol_ALI", "李雷"]);
+
+        assert_eq!(
+            filtered(&users, "ali"),
+            [("Alice", 0..3), ("carol_ALI", 6..9)]
+        );
+        assert_eq!(filtered(&users, "B"), [("bob", 0..1)]);
+        assert_eq!(filtered(&users, "ün"), [("Ünal", 0..3)]);
+        assert_eq!(filtered(&users, "雷"), [("李雷", 3..6)]);
+        assert!(filtered(&users, "zed").is_empty());
+        assert_eq!(filtered(&users, "").len(), users.len());
+    }
+
+    #[test]
+    fn sorting_users_puts_me_first() {
+        let mut users = profiles(&["carol", "me", "Bob", "alice", "bob"]);
+        sort_users(&mut users, "me");
+        let names: Vec<&str> = users.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(names, ["me", "alice", "Bob", "bob", "carol"]);
+    }
+
+    #[test]
+    fn a_fresh_user_list_is_filtered_the_same_way() {
+        let mut state = state();
+        state.apply(users(&["me", "alice", "bob"]));
+        assert_eq!(filtered(&state.users, "o").len(), 1);
+
+        state.apply(users(&["me", "alice", "bob", "bonnie", "otto"]));
+        let names: Vec<&str> = filtered(&state.users, "o")
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["bob", "bonnie", "otto"]);
+    }
+}
@@ -0,0 +1,103 @@
+//! Client-side rate limiting for outgoing frames.
+
+use std::collections::VecDeque;
+
+/// Outcome of asking a [`RateLimiter`] for permission to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// The budget is spent; try again in this many milliseconds.
+    Wait(u64),
+}
+
+/// Allows at most `limit` sends in any rolling window of `window_ms`.
+pub struct RateLimiter {
+    limit: usize,
+    window_ms: u64,
+    /// When each send inside the current window happened, oldest first.
+    sent: VecDeque<u64>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window_ms: u64) -> Self {
+        Self {
+            limit,
+            window_ms,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Takes one send from the budget at `now` (milliseconds) if there is
+    /// one left.
+    pub fn try_acquire(&mut self, now: u64) -> Decision {
+        while let Some(&oldest) = self.sent.front() {
+            if now.saturating_sub(oldest) < self.window_ms {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        match self.sent.front() {
+            Some(&oldest) if self.sent.len() >= self.limit => {
+                Decision::Wait(oldest + self.window_ms - now)
+            }
+            _ => {
+                self.sent.push_back(now);
+                Decision::Allow
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_up_to_the_limit_then_says_how_long_to_wait() {
+        let mut limiter = RateLimiter::new(3, 10_000);
+        assert_eq!(limiter.try_acquire(1_000), Decision::Allow);
+        assert_eq!(limiter.try_acquire(2_000), Decision::Allow);
+        assert_eq!(limiter.try_acquire(3_000), Decision::Allow);
+        assert_eq!(limiter.try_acquire(4_000), Decision::Wait(7_000));
+        assert_eq!(limiter.try_acquire(10_999), Decision::Wait(1));
+        assert_eq!(limiter.try_acquire(11_000), Decision::Allow);
+    }
+
+    #[test]
+    fn waiting_does_not_use_up_the_budget() {
+        let mut limiter = RateLimiter::new(1, 1_000);
+        assert_eq!(limiter.try_acquire(0), Decision::Allow);
+        for now in [1, 500, 999] {
+            assert_eq!(limiter.try_acquire(now), Decision::Wait(1_000 - now));
+        }
+        assert_eq!(limiter.try_acquire(1_000), Decision::Allow);
+        assert_eq!(limiter.try_acquire(1_001), Decision::Wait(999));
+    }
+
+    #[test]
+    fn a_burst_is_cut_off_at_the_limit() {
+        let mut limiter = RateLimiter::new(5, 10_000);
+        let decisions: Vec<Decision> = (0..8).map(|_| limiter.try_acquire(0)).collect();
+        assert_eq!(&decisions[..5], [Decision::Allow; 5]);
+        assert_eq!(&decisions[5..], [Decision::Wait(10_000); 3]);
+    }
+
+    #[test]
+    fn the_budget_refills_as_sends_leave_the_window() {
+        let mut limiter = RateLimiter::new(3, 10_000);
+        for now in [0, 1_000, 2_000] {
+            assert_eq!(limiter.try_acquire(now), Decision::Allow);
+        }
+        // One slot back each time one of the sends gets 10s old.
+        assert_eq!(limiter.try_acquire(10_000), Decision::Allow);
+        assert_eq!(limiter.try_acquire(10_500), Decision::Wait(500));
+        assert_eq!(limiter.try_acquire(11_000), Decision::Allow);
+        assert_eq!(limiter.try_acquire(12_000), Decision::Allow);
+        assert_eq!(limiter.try_acquire(12_000), Decision::Wait(8_000));
+        // After a quiet spell the whole budget is back.
+        for now in [60_000, 60_001, 60_002] {
+            assert_eq!(limiter.try_acquire(now), Decision::Allow);
+        }
+        assert_eq!(limiter.try_acquire(60_003), Decision::Wait(9_997));
+    }
+}
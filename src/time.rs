@@ -0,0 +1,291 @@
+//! Helpers for turning millisecond timestamps into labels.
+
+use js_sys::{Array, Date, Function, Intl, Object, Reflect};
+use wasm_bindgen::JsValue;
+
+use crate::i18n::I18n;
+
+thread_local! {
+    static CLOCK_FORMAT: Function = {
+        let options = Object::new();
+        let _ = Reflect::set(&options, &"hour".into(), &"2-digit".into());
+        let _ = Reflect::set(&options, &"minute".into(), &"2-digit".into());
+        Intl::DateTimeFormat::new(&Array::new(), &options).format()
+    };
+    static DAY_FORMAT: Function = day_format(false);
+    static DAY_YEAR_FORMAT: Function = day_format(true);
+    static FULL_FORMAT: Function = {
+        let options = Object::new();
+        let _ = Reflect::set(&options, &"dateStyle".into(), &"full".into());
+        let _ = Reflect::set(&options, &"timeStyle".into(), &"medium".into());
+        Intl::DateTimeFormat::new(&Array::new(), &options).format()
+    };
+}
+
+fn day_format(with_year: bool) -> Function {
+    let options = Object::new();
+    let _ = Reflect::set(&options, &"month".into(), &"long".into());
+    let _ = Reflect::set(&options, &"day".into(), &"numeric".into());
+    if with_year {
+        let _ = Reflect::set(&options, &"year".into(), &"numeric".into());
+    }
+    Intl::DateTimeFormat::new(&Array::new(), &options).format()
+}
+
+/// A calendar day in the browser's time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Day {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Day {
+    /// The local day `timestamp` falls on.
+    pub fn of(timestamp: u64) -> Day {
+        Day::from_date(&Date::new(&JsValue::from_f64(timestamp as f64)))
+    }
+
+    fn from_date(date: &Date) -> Day {
+        Day {
+            year: date.get_full_year(),
+            month: date.get_month() + 1,
+            day: date.get_date(),
+        }
+    }
+
+    /// The day before. Stepping the calendar rather than subtracting 24h
+    /// keeps this right across daylight saving changes.
+    pub fn previous(self) -> Day {
+        match (self.month, self.day) {
+            (1, 1) => Day {
+                year: self.year - 1,
+                month: 12,
+                day: 31,
+            },
+            (month, 1) => Day {
+                month: month - 1,
+                day: days_in_month(self.year, month - 1),
+                ..self
+            },
+            (_, day) => Day {
+                day: day - 1,
+                ..self
+            },
+        }
+    }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// How the heading over a day's messages names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DayHeading {
+    Today,
+    Yesterday,
+    /// The date, without the year.
+    ThisYear,
+    /// The date with the year.
+    Dated,
+}
+
+fn day_heading(day: Day, today: Day) -> DayHeading {
+    if day == today {
+        DayHeading::Today
+    } else if day == today.previous() {
+        DayHeading::Yesterday
+    } else if day.year == today.year {
+        DayHeading::ThisYear
+    } else {
+        DayHeading::Dated
+    }
+}
+
+/// Messages younger than this are labelled relative to now.
+pub const RELATIVE_FOR_MS: u64 = 60 * 60 * 1_000;
+
+/// Current time in milliseconds since the epoch.
+pub fn now() -> u64 {
+    Date::now() as u64
+}
+
+/// Hour and minute of `timestamp`, formatted for the browser's locale.
+pub fn format_clock(timestamp: u64) -> String {
+    let date = Date::new(&JsValue::from_f64(timestamp as f64));
+    CLOCK_FORMAT
+        .with(|format| format.call1(&JsValue::NULL, &date).ok())
+        .and_then(|label| label.as_string())
+        .unwrap_or_else(|| format!("{:02}:{:02}", date.get_hours(), date.get_minutes()))
+}
+
+/// Date and time of `timestamp` in full, for tooltips.
+pub fn format_full(timestamp: u64) -> String {
+    let date = Date::new(&JsValue::from_f64(timestamp as f64));
+    FULL_FORMAT
+        .with(|format| format.call1(&JsValue::NULL, &date).ok())
+        .and_then(|label| label.as_string())
+        .unwrap_or_else(|| String::from(date.to_string()))
+}
+
+/// Heading for the messages sent on the day of `timestamp`: "Today",
+/// "Yesterday", or the date, with the year only when it isn't this one.
+pub fn format_day(i18n: &I18n, timestamp: u64, now: u64) -> String {
+    let day = Day::of(timestamp);
+    let format = match day_heading(day, Day::of(now)) {
+        DayHeading::Today => return i18n.t("time.today"),
+        DayHeading::Yesterday => return i18n.t("time.yesterday"),
+        DayHeading::ThisYear => &DAY_FORMAT,
+        DayHeading::Dated => &DAY_YEAR_FORMAT,
+    };
+    let date = Date::new(&JsValue::from_f64(timestamp as f64));
+    format
+        .with(|format| format.call1(&JsValue::NULL, &date).ok())
+        .and_then(|label| label.as_string())
+        .unwrap_or_else(|| format!("{}-{:02}-{:02}", day.year, day.month, day.day))
+}
+
+/// "just now" or "N min ago" for a `timestamp` less than an hour before
+/// `now`; `None` once it should be shown as a clock time instead. Timestamps
+/// slightly in the future (clock skew) count as just now.
+pub fn relative_label(i18n: &I18n, timestamp: u64, now: u64) -> Option<String> {
+    recent_age(timestamp, now).map(|age| match age {
+        Age::JustNow => i18n.t("time.just_now"),
+        Age::Minutes(minutes) => i18n.plural("time.minutes_ago", minutes as usize, &[]),
+    })
+}
+
+/// How long before now a recent timestamp is, as [`relative_label`] puts it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Age {
+    JustNow,
+    /// Whole minutes, from one up to the hour.
+    Minutes(u64),
+}
+
+fn recent_age(timestamp: u64, now: u64) -> Option<Age> {
+    let age = now.saturating_sub(timestamp);
+    if age >= RELATIVE_FOR_MS {
+        None
+    } else if age < 60_000 {
+        Some(Age::JustNow)
+    } else {
+        Some(Age::Minutes(age / 60_000))
+    }
+}
+
+/// A length of time in words, rounded down to the minute: "less than a
+/// minute", "5 min", "2 h 10 min".
+pub fn format_duration(i18n: &I18n, ms: u64) -> String {
+    let minutes = ms / 60_000;
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => i18n.t("duration.under_minute"),
+        (0, m) => i18n.t_with("duration.minutes", &[("minutes", &m)]),
+        (h, 0) => i18n.t_with("duration.hours", &[("hours", &h)]),
+        (h, m) => i18n.t_with("duration.hours_minutes", &[("hours", &h), ("minutes", &m)]),
+    }
+}
+
+/// Label for a message sent at `timestamp`: relative while recent, the
+/// clock time after that.
+pub fn format_timestamp(i18n: &I18n, timestamp: u64, now: u64) -> String {
+    relative_label(i18n, timestamp, now).unwrap_or_else(|| format_clock(timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60_000;
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn day(year: u32, month: u32, day: u32) -> Day {
+        Day { year, month, day }
+    }
+
+    #[test]
+    fn recent_ages() {
+        let cases = [
+            (0, Some(Age::JustNow)),
+            (59_000, Some(Age::JustNow)),
+            (MINUTE - 1, Some(Age::JustNow)),
+            (MINUTE, Some(Age::Minutes(1))),
+            (61_000, Some(Age::Minutes(1))),
+            (2 * MINUTE - 1, Some(Age::Minutes(1))),
+            (2 * MINUTE, Some(Age::Minutes(2))),
+            (RELATIVE_FOR_MS - 1, Some(Age::Minutes(59))),
+            (RELATIVE_FOR_MS, None),
+            (24 * RELATIVE_FOR_MS, None),
+            (400 * 24 * RELATIVE_FOR_MS, None),
+        ];
+        for (age, label) in cases {
+            assert_eq!(recent_age(NOW - age, NOW), label, "{}", age);
+        }
+    }
+
+    #[test]
+    fn the_future_is_just_now() {
+        assert_eq!(recent_age(NOW + 5_000, NOW), Some(Age::JustNow));
+    }
+
+    #[test]
+    fn durations() {
+        let i18n = I18n::default();
+        let cases = [
+            (0, "less than a minute"),
+            (59_000, "less than a minute"),
+            (61_000, "1 min"),
+            (59 * MINUTE, "59 min"),
+            (60 * MINUTE, "1 h"),
+            (130 * MINUTE + 59_000, "2 h 10 min"),
+            (48 * 60 * MINUTE, "48 h"),
+        ];
+        for (ms, text) in cases {
+            assert_eq!(format_duration(&i18n, ms), text, "{}", ms);
+        }
+    }
+
+    #[test]
+    fn the_day_before() {
+        let cases = [
+            (day(2024, 5, 15), day(2024, 5, 14)),
+            (day(2024, 5, 1), day(2024, 4, 30)),
+            (day(2024, 4, 1), day(2024, 3, 31)),
+            (day(2024, 3, 1), day(2024, 2, 29)),
+            (day(2023, 3, 1), day(2023, 2, 28)),
+            (day(2000, 3, 1), day(2000, 2, 29)),
+            (day(1900, 3, 1), day(1900, 2, 28)),
+            (day(2024, 1, 1), day(2023, 12, 31)),
+        ];
+        for (today, yesterday) in cases {
+            assert_eq!(today.previous(), yesterday, "{:?}", today);
+        }
+    }
+
+    #[test]
+    fn day_headings() {
+        let today = day(2024, 1, 1);
+        let cases = [
+            (day(2024, 1, 1), DayHeading::Today),
+            (day(2023, 12, 31), DayHeading::Yesterday),
+            (day(2023, 12, 30), DayHeading::Dated),
+            (day(2023, 1, 1), DayHeading::Dated),
+        ];
+        for (day, heading) in cases {
+            assert_eq!(day_heading(day, today), heading, "{:?}", day);
+        }
+        let today = day(2024, 6, 10);
+        assert_eq!(day_heading(day(2024, 6, 9), today), DayHeading::Yesterday);
+        assert_eq!(day_heading(day(2024, 6, 8), today), DayHeading::ThisYear);
+        assert_eq!(day_heading(day(2024, 1, 1), today), DayHeading::ThisYear);
+        assert_eq!(day_heading(day(2023, 6, 10), today), DayHeading::Dated);
+    }
+}
@@ -0,0 +1,280 @@
+//! Notices about things that went wrong, stacked over the chat. The same
+//! failure happening again bumps a counter on the notice already up rather
+//! than stacking another. Times are passed in, so expiry can be checked
+//! without waiting for it.
+
+use std::mem::discriminant;
+
+use crate::messages::MessageId;
+
+/// How long a notice stays up unless dismissed.
+pub const TOAST_TTL_MS: u64 = 6_000;
+/// Most notices shown at once; the oldest make way.
+pub const MAX_TOASTS: usize = 4;
+
+pub type ToastId = u64;
+
+/// What went wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Our message with this id couldn't be handed to the socket, or wasn't
+    /// echoed back in time.
+    SendFailed(MessageId),
+    /// An edit, delete, reaction or rename couldn't be sent.
+    ActionFailed,
+    /// The connection is down; `reconnecting` while another attempt is
+    /// coming.
+    Disconnected {
+        reconnecting: bool,
+        reason: Option<String>,
+    },
+    /// The server sent a frame we couldn't read.
+    Protocol(String),
+    /// The server reported an error.
+    Server(String),
+}
+
+impl ErrorKind {
+    /// Whether `other` is this failure again, to be counted on one notice.
+    fn same_as(&self, other: &ErrorKind) -> bool {
+        match (self, other) {
+            (ErrorKind::SendFailed(a), ErrorKind::SendFailed(b)) => a == b,
+            (ErrorKind::Server(a), ErrorKind::Server(b)) => a == b,
+            _ => discriminant(self) == discriminant(other),
+        }
+    }
+
+    /// Whether the notice stays up until its cause goes away, rather than
+    /// expiring or being dismissed.
+    pub fn persistent(&self) -> bool {
+        matches!(self, ErrorKind::Disconnected { .. })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toast {
+    pub id: ToastId,
+    /// The latest occurrence; details such as a protocol error's reason are
+    /// from the last one.
+    pub kind: ErrorKind,
+    /// How many times it happened while the notice was up.
+    pub count: u32,
+    expires_at: Option<u64>,
+}
+
+/// The notices up, oldest first.
+#[derive(Debug, Clone)]
+pub struct Toasts {
+    shown: Vec<Toast>,
+    next_id: ToastId,
+    ttl_ms: u64,
+    max: usize,
+}
+
+impl Default for Toasts {
+    fn default() -> Self {
+        Self::new(TOAST_TTL_MS, MAX_TOASTS)
+    }
+}
+
+impl Toasts {
+    /// An empty stack whose notices expire after `ttl_ms`, at most `max` of
+    /// them up at once.
+    pub fn new(ttl_ms: u64, max: usize) -> Self {
+        Self {
+            shown: Vec::new(),
+            next_id: 0,
+            ttl_ms,
+            max,
+        }
+    }
+
+    /// Shows `kind` as of `now`. If the same failure is already up, its
+    /// notice is updated, counted and kept up longer instead. Returns the
+    /// notice's id.
+    pub fn push(&mut self, kind: ErrorKind, now: u64) -> ToastId {
+        let expires_at = (!kind.persistent()).then_some(now + self.ttl_ms);
+        if let Some(toast) = self.shown.iter_mut().find(|t| t.kind.same_as(&kind)) {
+            // A persistent notice changing state isn't the failure repeating.
+            if !kind.persistent() {
+                toast.count += 1;
+            }
+            toast.kind = kind;
+            toast.expires_at = expires_at;
+            return toast.id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.shown.push(Toast {
+            id,
+            kind,
+            count: 1,
+            expires_at,
+        });
+        while self.shown.len() > self.max {
+            match self.shown.iter().position(|t| !t.kind.persistent()) {
+                Some(oldest) => self.shown.remove(oldest),
+                None => break,
+            };
+        }
+        id
+    }
+
+    /// Takes down notice `id`. Returns whether it was up.
+    pub fn dismiss(&mut self, id: ToastId) -> bool {
+        self.dismiss_where(|t| t.id == id)
+    }
+
+    /// Takes down the notices matching `pred`, e.g. once their cause is
+    /// resolved. Returns whether any were up.
+    pub fn dismiss_where(&mut self, mut pred: impl FnMut(&Toast) -> bool) -> bool {
+        let before = self.shown.len();
+        self.shown.retain(|t| !pred(t));
+        self.shown.len() != before
+    }
+
+    /// Takes down the notices expired at `now`. Returns whether there were
+    /// any.
+    pub fn expire(&mut self, now: u64) -> bool {
+        self.dismiss_where(|t| t.expires_at.is_some_and(|at| at <= now))
+    }
+
+    /// When the next notice expires, if any will.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.shown.iter().filter_map(|t| t.expires_at).min()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Toast> {
+        self.shown.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: u64 = 1_000;
+
+    fn disconnected(reconnecting: bool) -> ErrorKind {
+        ErrorKind::Disconnected {
+            reconnecting,
+            reason: None,
+        }
+    }
+
+    fn kinds(toasts: &Toasts) -> Vec<&ErrorKind> {
+        toasts.iter().map(|t| &t.kind).collect()
+    }
+
+    #[test]
+    fn different_failures_stack_in_order() {
+        let mut toasts = Toasts::new(TTL, 4);
+        let a = toasts.push(ErrorKind::ActionFailed, 0);
+        let b = toasts.push(ErrorKind::SendFailed("m1".into()), 0);
+        let c = toasts.push(ErrorKind::SendFailed("m2".into()), 0);
+        let d = toasts.push(ErrorKind::Server("full".into()), 0);
+
+        assert_eq!(
+            toasts.iter().map(|t| t.id).collect::<Vec<_>>(),
+            [a, b, c, d]
+        );
+        assert!(toasts.iter().all(|t| t.count == 1));
+    }
+
+    #[test]
+    fn a_repeated_failure_is_counted_on_one_notice() {
+        let mut toasts = Toasts::new(TTL, 4);
+        let first = toasts.push(ErrorKind::Protocol("bad json".into()), 0);
+        for at in 1..50 {
+            assert_eq!(
+                toasts.push(ErrorKind::Protocol(format!("bad {}", at)), at),
+                first
+            );
+        }
+
+        let toast = toasts.iter().next().unwrap();
+        assert_eq!(toasts.iter().count(), 1);
+        assert_eq!(toast.count, 50);
+        assert_eq!(toast.kind, ErrorKind::Protocol("bad 49".into()));
+        assert_eq!(toasts.next_expiry(), Some(49 + TTL));
+    }
+
+    #[test]
+    fn notices_expire_after_their_time() {
+        let mut toasts = Toasts::new(TTL, 4);
+        toasts.push(ErrorKind::ActionFailed, 0);
+        toasts.push(ErrorKind::Server("x".into()), 500);
+        assert_eq!(toasts.next_expiry(), Some(TTL));
+
+        assert!(!toasts.expire(TTL - 1));
+        assert!(toasts.expire(TTL));
+        assert_eq!(kinds(&toasts), [&ErrorKind::Server("x".into())]);
+        assert_eq!(toasts.next_expiry(), Some(500 + TTL));
+
+        assert!(toasts.expire(500 + TTL));
+        assert_eq!(toasts.iter().count(), 0);
+        assert_eq!(toasts.next_expiry(), None);
+        assert!(!toasts.expire(10 * TTL));
+    }
+
+    #[test]
+    fn repeating_keeps_a_notice_up_longer() {
+        let mut toasts = Toasts::new(TTL, 4);
+        toasts.push(ErrorKind::ActionFailed, 0);
+        toasts.push(ErrorKind::ActionFailed, 800);
+        assert!(!toasts.expire(TTL));
+        assert!(toasts.expire(800 + TTL));
+    }
+
+    #[test]
+    fn dismissing() {
+        let mut toasts = Toasts::new(TTL, 4);
+        let a = toasts.push(ErrorKind::ActionFailed, 0);
+        toasts.push(ErrorKind::SendFailed("m1".into()), 0);
+        toasts.push(ErrorKind::SendFailed("m2".into()), 0);
+
+        assert!(toasts.dismiss(a));
+        assert!(!toasts.dismiss(a));
+        assert!(toasts.dismiss_where(|t| t.kind == ErrorKind::SendFailed("m1".into())));
+        assert!(!toasts.dismiss_where(|t| t.kind == ErrorKind::ActionFailed));
+        assert_eq!(kinds(&toasts), [&ErrorKind::SendFailed("m2".into())]);
+
+        // A dismissed failure happening again gets a fresh notice.
+        let again = toasts.push(ErrorKind::ActionFailed, 0);
+        assert_ne!(again, a);
+    }
+
+    #[test]
+    fn the_connection_notice_stays_until_resolved() {
+        let mut toasts = Toasts::new(TTL, 4);
+        let id = toasts.push(disconnected(true), 0);
+        assert_eq!(toasts.next_expiry(), None);
+        assert!(!toasts.expire(1_000 * TTL));
+
+        // Changing state updates the notice without counting a repeat.
+        assert_eq!(toasts.push(disconnected(false), 5), id);
+        let toast = toasts.iter().next().unwrap();
+        assert_eq!(toast.count, 1);
+        assert_eq!(toast.kind, disconnected(false));
+
+        assert!(toasts.dismiss_where(|t| t.kind.persistent()));
+        assert_eq!(toasts.iter().count(), 0);
+    }
+
+    #[test]
+    fn the_oldest_make_way_but_not_the_connection_notice() {
+        let mut toasts = Toasts::new(TTL, 3);
+        toasts.push(disconnected(true), 0);
+        for i in 0..5 {
+            toasts.push(ErrorKind::SendFailed(format!("m{}", i)), i);
+        }
+        assert_eq!(
+            kinds(&toasts),
+            [
+                &disconnected(true),
+                &ErrorKind::SendFailed("m3".into()),
+                &ErrorKind::SendFailed("m4".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Picking which rows of a long list to put in the DOM, so that only what is
+//! on screen (plus a margin) gets rendered.
+
+/// Height assumed for rows that haven't been measured yet.
+pub const ESTIMATED_ROW_PX: i32 = 64;
+
+/// Rows rendered beyond each edge of the viewport, so that fast scrolling
+/// doesn't reveal empty space before the next render.
+pub const OVERSCAN_ROWS: usize = 8;
+
+/// Rows `start..end` are rendered; the ones left out are replaced by spacers
+/// of `space_above` and `space_below` pixels to keep the scrollbar honest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Window {
+    pub start: usize,
+    pub end: usize,
+    pub space_above: i32,
+    pub space_below: i32,
+}
+
+/// The rows of a list with the given `heights` that overlap
+/// `scroll_top..scroll_top + viewport`, widened by `overscan` rows on either
+/// side. Offsets beyond either end of the list are clamped to it.
+pub fn visible_window(heights: &[i32], scroll_top: i32, viewport: i32, overscan: usize) -> Window {
+    let total: i32 = heights.iter().sum();
+    let scroll_top = scroll_top.min(total - viewport).max(0);
+    let mut first = None;
+    let mut last = heights.len();
+    let mut offset = 0;
+    for (i, height) in heights.iter().enumerate() {
+        if offset >= scroll_top + viewport {
+            last = i;
+            break;
+        }
+        if first.is_none() && offset + height > scroll_top {
+            first = Some(i);
+        }
+        offset += height;
+    }
+    let first = first.unwrap_or_else(|| heights.len().saturating_sub(1));
+
+    let end = (last + overscan).min(heights.len());
+    let start = first.saturating_sub(overscan).min(end);
+    Window {
+        start,
+        end,
+        space_above: heights[..start].iter().sum(),
+        space_below: heights[end..].iter().sum(),
+    }
+}
+
+/// Distance from the top of the list to the row at `index`.
+pub fn offset_of(heights: &[i32], index: usize) -> i32 {
+    heights[..index.min(heights.len())].iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(heights: &[i32], window: Window) -> i32 {
+        heights[window.start..window.end].iter().sum()
+    }
+
+    #[test]
+    fn the_top_of_the_list() {
+        let heights = [50; 100];
+        assert_eq!(
+            visible_window(&heights, 0, 200, 2),
+            Window {
+                start: 0,
+                end: 6,
+                space_above: 0,
+                space_below: 94 * 50,
+            }
+        );
+    }
+
+    #[test]
+    fn the_middle_of_the_list() {
+        let heights = [50; 100];
+        // Rows 20..24 are on screen, plus two either side.
+        let window = visible_window(&heights, 1_000, 200, 2);
+        assert_eq!((window.start, window.end), (18, 26));
+        assert_eq!(window.space_above, 18 * 50);
+        assert_eq!(window.space_below, 74 * 50);
+
+        // A row peeking in by a pixel counts.
+        let window = visible_window(&heights, 1_001, 200, 0);
+        assert_eq!((window.start, window.end), (20, 25));
+    }
+
+    #[test]
+    fn scrolling_past_either_end_is_clamped() {
+        let heights = [50; 100];
+        let bottom = visible_window(&heights, 5_000 - 200, 200, 2);
+        assert_eq!(visible_window(&heights, 1_000_000, 200, 2), bottom);
+        assert_eq!((bottom.start, bottom.end, bottom.space_below), (94, 100, 0));
+        assert_eq!(
+            visible_window(&heights, -500, 200, 2),
+            visible_window(&heights, 0, 200, 2)
+        );
+    }
+
+    #[test]
+    fn short_and_empty_lists_are_rendered_whole() {
+        let heights = [40, 60, 80];
+        assert_eq!(
+            visible_window(&heights, 0, 1_000, OVERSCAN_ROWS),
+            Window {
+                start: 0,
+                end: 3,
+                space_above: 0,
+                space_below: 0,
+            }
+        );
+        assert_eq!(
+            visible_window(&[], 0, 1_000, OVERSCAN_ROWS),
+            Window::default()
+        );
+    }
+
+    #[test]
+    fn measured_rows_of_different_heights() {
+        let heights = [10, 300, 10, 10, 300, 10];
+        // 315..335 shows the end of row 2, row 3 and the top of row 4.
+        let window = visible_window(&heights, 315, 20, 0);
+        assert_eq!((window.start, window.end), (2, 5));
+        assert_eq!(window.space_above, 310);
+        assert_eq!(window.space_below, 10);
+    }
+
+    #[test]
+    fn ten_thousand_messages_render_a_screenful() {
+        let heights = vec![ESTIMATED_ROW_PX; 10_000];
+        let total: i32 = heights.iter().sum();
+        let viewport = 800;
+        let most = viewport as usize / ESTIMATED_ROW_PX as usize + 2 + 2 * OVERSCAN_ROWS;
+        for scroll_top in (0..total).step_by(7_919).chain([total - viewport]) {
+            let window = visible_window(&heights, scroll_top, viewport, OVERSCAN_ROWS);
+            assert!(window.end - window.start <= most, "{:?}", window);
+            assert_eq!(
+                window.space_above + rendered(&heights, window) + window.space_below,
+                total
+            );
+            assert_eq!(window.space_above, offset_of(&heights, window.start));
+            assert!(window.space_above <= scroll_top.min(total - viewport));
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        let heights = [10, 20, 30];
+        assert_eq!(offset_of(&heights, 0), 0);
+        assert_eq!(offset_of(&heights, 2), 30);
+        assert_eq!(offset_of(&heights, 3), 60);
+        assert_eq!(offset_of(&heights, 99), 60);
+    }
+}
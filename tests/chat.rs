@@ -0,0 +1,309 @@
+//! Drives the chat component against a [`MockTransport`] in a browser:
+//!
+//! ```sh
+//! wasm-pack test --headless --firefox -- --features mock-transport
+//! ```
+//!
+//! The accessibility pass needs a copy of axe-core's `axe.min.js` the test
+//! browser can load, given as `AXE_CORE_URL` at build time; without one it
+//! is skipped.
+
+// The `html!` macro in yew 0.19 expands to code that trips these lints.
+#![allow(clippy::let_unit_value, clippy::unnecessary_operation)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo_timers::future::TimeoutFuture;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+use web_sys::{Element, Event, HtmlElement, HtmlInputElement, KeyboardEvent, KeyboardEventInit};
+use yew::prelude::*;
+use yewchat::protocol::{MsgTypes, WebSocketMessage};
+use yewchat::{Chat, MockTransport, TransportFactory, User, UserInner};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Where the axe-core script is loaded from, such as a pinned copy served
+/// next to the tests. Nothing is fetched from elsewhere.
+const AXE_CORE_URL: Option<&str> = option_env!("AXE_CORE_URL");
+
+#[wasm_bindgen(inline_js = r#"
+export function load_axe(src) {
+    if (window.axe) {
+        return Promise.resolve();
+    }
+    return new Promise((resolve, reject) => {
+        const script = document.createElement("script");
+        script.src = src;
+        script.onload = resolve;
+        script.onerror = () => reject(new Error(`could not load axe-core from ${src}`));
+        document.head.appendChild(script);
+    });
+}
+
+export async function axe_violations(root) {
+    // The chat is mounted in a bare test page, so the page-level landmark
+    // rule would only be complaining about the harness.
+    const results = await window.axe.run(root, { rules: { region: { enabled: false } } });
+    return results.violations
+        .map((v) => `${v.id}: ${v.help} (${v.nodes.map((n) => n.target).join(", ")})`)
+        .join("\n");
+}
+"#)]
+extern "C" {
+    /// Adds the axe-core script at `src` to the page, unless it is there.
+    fn load_axe(src: &str) -> js_sys::Promise;
+    /// Runs axe-core over `root`, resolving to one line per violation.
+    fn axe_violations(root: &Element) -> js_sys::Promise;
+}
+
+#[derive(Properties, PartialEq)]
+struct HostProps {
+    transport: TransportFactory,
+}
+
+/// `Chat` as the app mounts it, logged in as "tester".
+#[function_component(Host)]
+fn host(props: &HostProps) -> Html {
+    let user = use_state(|| {
+        Rc::new(UserInner {
+            username: RefCell::new("tester".to_string()),
+            login_error: RefCell::new(None),
+        })
+    });
+    html! {
+        <ContextProvider<User> context={(*user).clone()}>
+            <Chat transport={props.transport.clone()} />
+        </ContextProvider<User>>
+    }
+}
+
+/// Mounts a chat in a fresh element, with the connection open.
+async fn mount() -> (MockTransport, Element) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let root = document.create_element("div").unwrap();
+    document.body().unwrap().append_child(&root).unwrap();
+    let transport = MockTransport::default();
+    yew::start_app_with_props_in_element::<Host>(
+        root.clone(),
+        HostProps {
+            transport: transport.factory(),
+        },
+    );
+    transport.open();
+    settle().await;
+    (transport, root)
+}
+
+/// Lets the event bus deliver and the component re-render.
+async fn settle() {
+    TimeoutFuture::new(20).await;
+}
+
+/// Types `text` into the message input, as far as the chat can tell.
+async fn type_into(root: &Element, text: &str) -> HtmlInputElement {
+    let input: HtmlInputElement = root
+        .query_selector("footer input[type=text]")
+        .unwrap()
+        .unwrap()
+        .unchecked_into();
+    input.set_value(text);
+    input.dispatch_event(&Event::new("input").unwrap()).unwrap();
+    settle().await;
+    input
+}
+
+/// Presses Enter in `input`, with the modifiers and composition state
+/// `init` sets.
+async fn press_enter(input: &HtmlInputElement, init: &mut KeyboardEventInit) {
+    init.key("Enter").bubbles(true).cancelable(true);
+    let event = KeyboardEvent::new_with_keyboard_event_init_dict("keydown", init).unwrap();
+    input.dispatch_event(&event).unwrap();
+    settle().await;
+}
+
+fn send_button(root: &Element) -> HtmlElement {
+    root.query_selector("footer button[aria-label='Send message']")
+        .unwrap()
+        .unwrap()
+        .unchecked_into()
+}
+
+fn sent_of(transport: &MockTransport, message_type: MsgTypes) -> Vec<WebSocketMessage> {
+    transport
+        .sent()
+        .into_iter()
+        .filter(|frame| frame.message_type == message_type)
+        .collect()
+}
+
+#[wasm_bindgen_test]
+async fn registers_on_create() {
+    let (transport, _root) = mount().await;
+
+    let sent = transport.sent();
+    assert_eq!(sent[0].message_type, MsgTypes::Register);
+    assert_eq!(sent[0].data.as_deref(), Some("tester"));
+}
+
+#[wasm_bindgen_test]
+async fn users_frame_fills_the_user_list() {
+    let (transport, root) = mount().await;
+
+    transport.receive(r#"{"messageType":"users","dataArray":["tester","alice","bob"]}"#);
+    settle().await;
+
+    let sidebar = root.query_selector("#chat-sidebar").unwrap().unwrap();
+    let text = sidebar.text_content().unwrap_or_default();
+    assert!(text.contains("alice"), "{}", text);
+    assert!(text.contains("bob"), "{}", text);
+}
+
+#[wasm_bindgen_test]
+async fn malformed_frames_do_not_break_the_chat() {
+    let (transport, root) = mount().await;
+
+    transport.receive("{not json");
+    transport.receive(r#"{"messageType":"users","dataArray":"alice"}"#);
+    settle().await;
+
+    transport.receive(r#"{"messageType":"users","dataArray":["tester","alice"]}"#);
+    settle().await;
+    let text = root.text_content().unwrap_or_default();
+    assert!(text.contains("alice"), "{}", text);
+}
+
+#[wasm_bindgen_test]
+async fn submitting_sends_a_message_frame() {
+    let (transport, root) = mount().await;
+
+    let input = type_into(&root, "hello there").await;
+    send_button(&root).click();
+    settle().await;
+
+    let messages = sent_of(&transport, MsgTypes::Message);
+    assert_eq!(messages.len(), 1);
+    let frame = &messages[0];
+    assert_eq!(frame.data.as_deref(), Some("hello there"));
+    assert_eq!(frame.room.as_deref(), Some("lobby"));
+    assert!(frame.id.is_some());
+    assert_eq!(input.value(), "");
+}
+
+#[wasm_bindgen_test]
+async fn enter_sends_the_input() {
+    let (transport, root) = mount().await;
+
+    let input = type_into(&root, "sent with enter").await;
+    press_enter(&input, &mut KeyboardEventInit::new()).await;
+
+    let messages = sent_of(&transport, MsgTypes::Message);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].data.as_deref(), Some("sent with enter"));
+    assert_eq!(input.value(), "");
+}
+
+#[wasm_bindgen_test]
+async fn shift_enter_does_not_send() {
+    let (transport, root) = mount().await;
+
+    let input = type_into(&root, "not yet").await;
+    press_enter(&input, KeyboardEventInit::new().shift_key(true)).await;
+
+    assert!(sent_of(&transport, MsgTypes::Message).is_empty());
+    assert_eq!(input.value(), "not yet");
+}
+
+#[wasm_bindgen_test]
+async fn enter_while_composing_does_not_send() {
+    let (transport, root) = mount().await;
+
+    let input = type_into(&root, "にほんご").await;
+    press_enter(&input, KeyboardEventInit::new().is_composing(true)).await;
+
+    assert!(sent_of(&transport, MsgTypes::Message).is_empty());
+    assert_eq!(input.value(), "にほんご");
+}
+
+#[wasm_bindgen_test]
+async fn blank_input_sends_nothing_either_way() {
+    let (transport, root) = mount().await;
+
+    let input = type_into(&root, "   ").await;
+    assert!(send_button(&root).has_attribute("disabled"));
+    press_enter(&input, &mut KeyboardEventInit::new()).await;
+    assert!(sent_of(&transport, MsgTypes::Message).is_empty());
+
+    type_into(&root, "now there is text").await;
+    assert!(!send_button(&root).has_attribute("disabled"));
+}
+
+#[wasm_bindgen_test]
+async fn incoming_messages_are_announced() {
+    let (transport, root) = mount().await;
+
+    transport.receive(
+        r#"{"messageType":"message","data":"{\"from\":\"alice\",\"message\":\"hello\"}"}"#,
+    );
+    settle().await;
+
+    let live = root
+        .query_selector("[role=log][aria-live=polite]")
+        .unwrap()
+        .unwrap();
+    let text = live.text_content().unwrap_or_default();
+    assert!(text.contains("alice: hello"), "{}", text);
+}
+
+#[wasm_bindgen_test]
+async fn user_rows_are_reachable_by_tab() {
+    let (transport, root) = mount().await;
+
+    transport.receive(r#"{"messageType":"users","dataArray":["tester","alice"]}"#);
+    settle().await;
+
+    let buttons = root.query_selector_all("#chat-sidebar li button").unwrap();
+    let alice = (0..buttons.length())
+        .filter_map(|i| buttons.item(i))
+        .map(|node| node.unchecked_into::<HtmlElement>())
+        .find(|button| button.text_content().unwrap_or_default().contains("alice"))
+        .expect("a button for alice");
+    assert!(alice.tab_index() >= 0);
+    alice.focus().unwrap();
+    let active = web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .active_element();
+    assert_eq!(active.as_ref(), Some(alice.as_ref()));
+}
+
+#[wasm_bindgen_test]
+async fn axe_finds_no_violations() {
+    let src = match AXE_CORE_URL {
+        Some(src) => src,
+        None => {
+            console_log!("skipping the axe pass: AXE_CORE_URL is not set");
+            return;
+        }
+    };
+    if let Err(e) = JsFuture::from(load_axe(src)).await {
+        console_log!("skipping the axe pass: {:?}", e);
+        return;
+    }
+    let (transport, root) = mount().await;
+
+    transport.receive(r#"{"messageType":"users","dataArray":["tester","alice","bob"]}"#);
+    transport.receive(
+        r#"{"messageType":"message","data":"{\"from\":\"alice\",\"message\":\"hello\"}"}"#,
+    );
+    settle().await;
+    type_into(&root, "a draft").await;
+
+    let violations = JsFuture::from(axe_violations(&root)).await.unwrap();
+    let violations = violations.as_string().unwrap_or_default();
+    assert!(violations.is_empty(), "{}", violations);
+}